@@ -2,7 +2,7 @@ use proc_macro::TokenStream;
 use quote::quote;
 #[cfg(feature = "jwt")]
 use syn::{Data, DeriveInput, Fields};
-use syn::{ItemFn, parse_macro_input};
+use syn::{ItemFn, LitStr, parse_macro_input};
 
 /// Derive macro for implementing the `Claim` trait for JWT claims.
 ///
@@ -13,6 +13,8 @@ use syn::{ItemFn, parse_macro_input};
 ///
 /// - `#[required]` - Mark a field as required (must not be empty)
 /// - `#[exp]` - Mark a field as the expiration timestamp (checks against current time)
+/// - `#[roles]` - Mark the field holding the token's roles or scopes, for `#[jwt_required(roles(...))]`.
+///   A `Vec<String>`, or a `String` of space separated scopes, optionally in an `Option`
 ///
 /// # Example: Simple Claims
 ///
@@ -54,6 +56,18 @@ use syn::{ItemFn, parse_macro_input};
 /// }
 /// ```
 ///
+/// # Example: With Roles
+///
+/// ```rust,ignore
+/// #[derive(Claim, Clone)]
+/// struct StaffClaims {
+///     #[required]
+///     sub: String,
+///     #[roles]
+///     roles: Vec<String>,
+/// }
+/// ```
+///
 /// # How It Works
 ///
 /// The macro generates a `validate()` method that:
@@ -68,11 +82,12 @@ use syn::{ItemFn, parse_macro_input};
 /// - [`SimpleClaims`](https://docs.rs/feather/latest/feather/jwt/struct.SimpleClaims.html) for a built-in claims struct
 /// - [Authentication Guide](https://docs.rs/feather/latest/feather/guides/authentication/) for JWT patterns
 #[cfg(feature = "jwt")]
-#[proc_macro_derive(Claim, attributes(required, exp))]
+#[proc_macro_derive(Claim, attributes(required, exp, roles))]
 pub fn derive_claim(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let name = &input.ident;
     let mut checks = Vec::new();
+    let mut roles = None;
 
     if let Data::Struct(data_struct) = &input.data {
         if let Fields::Named(fields) = &data_struct.fields {
@@ -86,6 +101,13 @@ pub fn derive_claim(input: TokenStream) -> TokenStream {
                             }
                         });
                     }
+                    if attr.path().is_ident("roles") {
+                        roles = Some(quote! {
+                            fn roles(&self) -> Vec<&str> {
+                                feather::jwt::RoleList::role_list(&self.#field_name)
+                            }
+                        });
+                    }
                     if attr.path().is_ident("exp") {
                         checks.push(quote! {
                             if self.#field_name < ::std::time::SystemTime::now().duration_since(::std::time::UNIX_EPOCH).unwrap().as_secs() as usize {
@@ -104,6 +126,8 @@ pub fn derive_claim(input: TokenStream) -> TokenStream {
                 #(#checks)*
                 Ok(())
             }
+
+            #roles
         }
     };
     TokenStream::from(expanded)
@@ -121,7 +145,7 @@ pub fn derive_claim(input: TokenStream) -> TokenStream {
 /// - `res: &mut Response` - The HTTP response
 /// - `ctx: &AppContext` - Application context for accessing state
 ///
-/// Your function must return `Outcome` (which is `Result<MiddlewareResult, Box<dyn Error>>`).
+/// Your function must return `Outcome` (which is `Result<MiddlewareResult, feather::Error>`).
 ///
 /// # Basic Example
 ///
@@ -245,6 +269,292 @@ pub fn middleware_fn(_attr: TokenStream, item: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
+/// Attribute macro adapting a function with typed parameters into a middleware.
+///
+/// Parameters are read from the request with `FromRequest` before the body runs, in order, so the function
+/// states what it needs instead of parsing it. Parameters of type `&mut Response`, `&mut Request` and
+/// `&AppContext` are passed through and can appear anywhere. The function returns an `Outcome`.
+///
+/// When a parameter can't be extracted, the request is answered with its `Rejection` and the body
+/// doesn't run.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::extract::{Json, Path};
+/// use feather::handler;
+///
+/// #[handler]
+/// fn update_user(Path(id): Path<u64>, Json(user): Json<UserUpdate>, res: &mut Response, ctx: &AppContext) -> Outcome {
+///     ctx.get_state::<Users>().update(id, user)?;
+///     res.finish_text("Updated")
+/// }
+///
+/// app.put("/users/:id", update_user);
+/// ```
+///
+/// # See Also
+///
+/// - The [`extract`](https://docs.rs/feather/latest/feather/extract/) module for the built-in extractors
+/// - [`#[middleware_fn]`](attr.middleware_fn.html) for handlers that read the request themselves
+#[proc_macro_attribute]
+pub fn handler(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as ItemFn);
+    let vis = &input.vis;
+    let fn_name = &input.sig.ident;
+    let output = &input.sig.output;
+    let block = &input.block;
+    if let Some(receiver) = input.sig.receiver() {
+        return syn::Error::new_spanned(receiver, "#[handler] functions can't take `self`").to_compile_error().into();
+    }
+    let parameters = parameters(&input.sig.inputs);
+
+    let expanded = quote! {
+        #vis fn #fn_name(__req: &mut feather::Request, __res: &mut feather::Response, __ctx: &feather::AppContext) #output {
+            #(#parameters)*
+            #block
+        }
+    };
+    TokenStream::from(expanded)
+}
+
+/// The statements binding the typed parameters of a `#[handler]` or a `#[resource]` action, skipping
+/// the receiver. Extracted ones come first, so the references passed through are free to use after.
+fn parameters(inputs: &syn::punctuated::Punctuated<syn::FnArg, syn::Token![,]>) -> Vec<syn::Stmt> {
+    let mut extracted = Vec::new();
+    let mut passed = Vec::new();
+    for arg in inputs {
+        let syn::FnArg::Typed(arg) = arg else {
+            continue;
+        };
+        let (pat, ty) = (&arg.pat, &arg.ty);
+        match passed_through(ty) {
+            Some("Request") => passed.push(syn::parse_quote! { let #pat: #ty = __req; }),
+            Some("Response") => passed.push(syn::parse_quote! { let #pat: #ty = __res; }),
+            Some(_) => passed.push(syn::parse_quote! { let #pat: #ty = __ctx; }),
+            None => extracted.push(syn::parse_quote! {
+                let #pat: #ty = match <#ty as feather::extract::FromRequest>::from_request(__req, __ctx) {
+                    Ok(value) => value,
+                    Err(rejection) => return rejection.respond(__req, __res),
+                };
+            }),
+        }
+    }
+    extracted.extend(passed);
+    extracted
+}
+
+/// The name of `ty` if it's one of the references a `#[handler]` passes through.
+fn passed_through(ty: &syn::Type) -> Option<&'static str> {
+    let syn::Type::Reference(reference) = ty else {
+        return None;
+    };
+    let syn::Type::Path(path) = &*reference.elem else {
+        return None;
+    };
+    let name = path.path.segments.last()?.ident.to_string();
+    match (name.as_str(), reference.mutability.is_some()) {
+        ("Request", true) => Some("Request"),
+        ("Response", true) => Some("Response"),
+        ("AppContext", false) => Some("AppContext"),
+        _ => None,
+    }
+}
+
+/// Attribute macro turning an `impl` block into a `Resource`, for `app.resource("/posts", Posts)`.
+///
+/// The methods named `index`, `show`, `create`, `update` and `delete` become the actions of the resource
+/// and take typed parameters like a [`#[handler]`](attr.handler.html), after `&self`. A `PARAM` constant
+/// renames the id parameter. Other items stay on the type, so helpers can live next to the actions.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::extract::{Json, Path};
+/// use feather::resource;
+///
+/// struct Posts {
+///     db: Pool,
+/// }
+///
+/// #[resource]
+/// impl Posts {
+///     fn index(&self, res: &mut Response) -> Outcome {
+///         res.finish_json(&self.db.all_posts()?)
+///     }
+///
+///     fn show(&self, Path(id): Path<u64>, res: &mut Response) -> Outcome {
+///         res.finish_json(&self.find(id)?)
+///     }
+///
+///     fn create(&self, Json(post): Json<NewPost>, res: &mut Response) -> Outcome {
+///         let id = self.db.insert_post(post)?;
+///         res.set_status(201).finish_text(id.to_string())
+///     }
+///
+///     fn find(&self, id: u64) -> Result<Post, HttpError> {
+///         self.db.post(id).ok_or_else(|| HttpError::not_found(format!("no post {id}")))
+///     }
+/// }
+///
+/// app.resource("/posts", Posts { db: pool });
+/// ```
+#[proc_macro_attribute]
+pub fn resource(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::ItemImpl);
+    if let Some((_, trait_, _)) = &input.trait_ {
+        return syn::Error::new_spanned(trait_, "#[resource] goes on an `impl` block without a trait").to_compile_error().into();
+    }
+    let (impl_generics, _, where_clause) = input.generics.split_for_impl();
+    let self_ty = &input.self_ty;
+
+    let mut actions = Vec::new();
+    let mut others = Vec::new();
+    for item in &input.items {
+        match item {
+            syn::ImplItem::Fn(method) if matches!(method.sig.ident.to_string().as_str(), "index" | "show" | "create" | "update" | "delete") => {
+                if let Some(receiver) = method.sig.receiver()
+                    && (receiver.reference.is_none() || receiver.mutability.is_some())
+                {
+                    return syn::Error::new_spanned(receiver, "resource actions take `&self`").to_compile_error().into();
+                }
+                let attrs = &method.attrs;
+                let name = &method.sig.ident;
+                let output = &method.sig.output;
+                let block = &method.block;
+                let parameters = parameters(&method.sig.inputs);
+                actions.push(quote! {
+                    #(#attrs)*
+                    fn #name(&self, __req: &mut feather::Request, __res: &mut feather::Response, __ctx: &feather::AppContext) #output {
+                        #(#parameters)*
+                        #block
+                    }
+                });
+            }
+            syn::ImplItem::Const(constant) if constant.ident == "PARAM" => {
+                let (ty, expr) = (&constant.ty, &constant.expr);
+                actions.push(quote! { const PARAM: #ty = #expr; });
+            }
+            other => others.push(other),
+        }
+    }
+
+    let inherent = (!others.is_empty()).then(|| {
+        quote! {
+            impl #impl_generics #self_ty #where_clause {
+                #(#others)*
+            }
+        }
+    });
+    let expanded = quote! {
+        impl #impl_generics feather::Resource for #self_ty #where_clause {
+            #(#actions)*
+        }
+
+        #inherent
+    };
+    TokenStream::from(expanded)
+}
+
+/// Declares the route of a handler next to it, registered with `register!`.
+///
+/// `#[get]`, `#[post]`, `#[put]`, `#[patch]`, `#[delete]`, `#[head]` and `#[options]` take the path of the
+/// route and go above `#[middleware_fn]`, or above a function that already has the middleware signature.
+/// The function is kept as it is, so it can still be passed to `app.get(..)` or called directly.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::{get, post, middleware_fn, register};
+///
+/// #[get("/users/:id")]
+/// #[middleware_fn]
+/// fn show_user() {
+///     let id = req.param("id").unwrap_or_default();
+///     res.finish_text(format!("User {id}"))
+/// }
+///
+/// #[post("/users")]
+/// #[middleware_fn]
+/// fn create_user() {
+///     res.set_status(201).finish_text("Created")
+/// }
+///
+/// let mut app = App::new();
+/// register!(app, show_user, create_user);
+/// ```
+///
+/// # How It Works
+///
+/// Next to the function the macro declares a hidden type of the same name implementing
+/// `AttributeRoute`, which holds the method and the path. `register!` reads them from the type and
+/// routes the function, so both have to be in scope where it's used.
+#[proc_macro_attribute]
+pub fn get(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route_attribute("GET", attr, item)
+}
+
+/// Declares a `POST` route, see [`#[get]`](macro@get).
+#[proc_macro_attribute]
+pub fn post(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route_attribute("POST", attr, item)
+}
+
+/// Declares a `PUT` route, see [`#[get]`](macro@get).
+#[proc_macro_attribute]
+pub fn put(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route_attribute("PUT", attr, item)
+}
+
+/// Declares a `PATCH` route, see [`#[get]`](macro@get).
+#[proc_macro_attribute]
+pub fn patch(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route_attribute("PATCH", attr, item)
+}
+
+/// Declares a `DELETE` route, see [`#[get]`](macro@get).
+#[proc_macro_attribute]
+pub fn delete(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route_attribute("DELETE", attr, item)
+}
+
+/// Declares a `HEAD` route, see [`#[get]`](macro@get).
+#[proc_macro_attribute]
+pub fn head(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route_attribute("HEAD", attr, item)
+}
+
+/// Declares an `OPTIONS` route, see [`#[get]`](macro@get).
+#[proc_macro_attribute]
+pub fn options(attr: TokenStream, item: TokenStream) -> TokenStream {
+    route_attribute("OPTIONS", attr, item)
+}
+
+fn route_attribute(method: &str, attr: TokenStream, item: TokenStream) -> TokenStream {
+    let path = parse_macro_input!(attr as LitStr);
+    let input = parse_macro_input!(item as ItemFn);
+    if !path.value().starts_with('/') {
+        return syn::Error::new_spanned(&path, "route paths start with `/`").to_compile_error().into();
+    }
+    let vis = &input.vis;
+    let fn_name = &input.sig.ident;
+    let method = syn::Ident::new(method, path.span());
+
+    let expanded = quote! {
+        #input
+
+        #[doc(hidden)]
+        #[allow(non_camel_case_types, dead_code)]
+        #vis struct #fn_name {}
+
+        impl feather::internals::AttributeRoute for #fn_name {
+            const METHOD: feather::internals::Method = feather::internals::Method::#method;
+            const PATH: &'static str = #path;
+        }
+    };
+    TokenStream::from(expanded)
+}
+
 /// Attribute macro for creating JWT-protected middleware.
 ///
 /// Combines with `#[middleware_fn]` to automatically extract and validate JWT claims
@@ -256,10 +566,15 @@ pub fn middleware_fn(_attr: TokenStream, item: TokenStream) -> TokenStream {
 /// 1. Extracts the JWT token from the `Authorization: Bearer <token>` header
 /// 2. Decodes and validates the token using the app's JWT manager
 /// 3. Validates claims using the `Claim` trait
-/// 4. Injects the decoded claims into your function
+/// 4. Marks the request as `Authenticated`, so `CachePolicy` keeps the response private
+/// 5. Injects the decoded claims into your function
 ///
 /// If any step fails, it returns a 401 Unauthorized response automatically.
 ///
+/// With `roles(...)` the token must also grant at least one of the roles, checked against
+/// [`Claim::roles`](https://docs.rs/feather/latest/feather/jwt/trait.Claim.html#method.roles),
+/// otherwise the response is 403 Forbidden.
+///
 /// # Syntax
 ///
 /// ```rust,ignore
@@ -294,6 +609,17 @@ pub fn middleware_fn(_attr: TokenStream, item: TokenStream) -> TokenStream {
 /// app.get("/profile", protected_profile);
 /// ```
 ///
+/// # Example: Requiring a Role
+///
+/// ```rust,ignore
+/// #[jwt_required(roles("admin", "ops"))]
+/// #[middleware_fn]
+/// fn delete_user(claims: StaffClaims) {
+///     res.send_text(format!("Deleted by {}", claims.sub));
+///     next!()
+/// }
+/// ```
+///
 /// # Example: With SimpleClaims
 ///
 /// ```rust,ignore
@@ -344,6 +670,8 @@ pub fn middleware_fn(_attr: TokenStream, item: TokenStream) -> TokenStream {
 /// - Token is invalid or expired
 /// - Claims fail validation
 ///
+/// A 403 response is sent if the token has none of the required `roles(...)`.
+///
 /// To customize error responses, use `#[middleware_fn]` with manual JWT handling.
 ///
 /// # See Also
@@ -353,7 +681,19 @@ pub fn middleware_fn(_attr: TokenStream, item: TokenStream) -> TokenStream {
 /// - [Authentication Guide](https://docs.rs/feather/latest/feather/guides/authentication/) - JWT patterns and examples
 #[cfg(feature = "jwt")]
 #[proc_macro_attribute]
-pub fn jwt_required(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn jwt_required(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut roles = Vec::new();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("roles") {
+            let content;
+            syn::parenthesized!(content in meta.input);
+            roles.extend(content.parse_terminated(|input| input.parse::<syn::LitStr>(), syn::Token![,])?);
+            Ok(())
+        } else {
+            Err(meta.error("expected `roles(\"...\")`"))
+        }
+    });
+    parse_macro_input!(attr with parser);
     let input = parse_macro_input!(item as ItemFn);
     let fn_name = &input.sig.ident;
     let vis = &input.vis;
@@ -379,6 +719,15 @@ pub fn jwt_required(_attr: TokenStream, item: TokenStream) -> TokenStream {
         }
     };
 
+    let authorize = (!roles.is_empty()).then(|| {
+        quote! {
+            if !feather::jwt::Claim::has_any_role(&#claims_name, &[#(#roles),*]) {
+                manager.reject(req, res, feather::jwt::JwtFailure::Forbidden);
+                return feather::next!();
+            }
+        }
+    });
+
     let expanded = quote! {
         #vis fn #fn_name(req: &mut feather::Request, res: &mut feather::Response, ctx: &feather::AppContext) -> feather::Outcome {
             let manager = ctx.jwt();
@@ -389,26 +738,25 @@ pub fn jwt_required(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 .and_then(|h| h.strip_prefix("Bearer ")) {
                     Some(t) => t,
                     None => {
-                        res.set_status(401);
-                        res.send_text("Missing or invalid Authorization header");
+                        manager.reject(req, res, feather::jwt::JwtFailure::MissingToken);
                         return feather::next!();
                     }
                 };
 
             let #claims_name: #claims_type = match manager.decode(token) {
                 Ok(c) => c,
-                Err(_) => {
-                    res.set_status(401);
-                    res.send_text("Invalid or expired token");
+                Err(e) => {
+                    manager.reject(req, res, feather::jwt::JwtFailure::InvalidToken(e));
                     return feather::next!();
                 }
             };
 
-            if let Err(_) = #claims_name.validate() {
-                res.set_status(401);
-                res.send_text("Invalid or expired token");
+            if let Err(e) = #claims_name.validate() {
+                manager.reject(req, res, feather::jwt::JwtFailure::InvalidToken(e));
                 return feather::next!();
             }
+            req.extensions.insert(feather::builtins::Authenticated);
+            #authorize
 
             #block
         }
@@ -0,0 +1,47 @@
+//! Benchmarks for the request parsing hot path.
+//!
+//! Run with `cargo bench -p feather-runtime --bench parse`.
+
+use bytes::Bytes;
+use criterion::{Criterion, Throughput, black_box, criterion_group, criterion_main};
+use feather_runtime::http::{Request, find_header_end};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+const ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+const SMALL: &[u8] = b"GET /users/42?sort=asc HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n";
+
+fn browser_request() -> Vec<u8> {
+    let mut raw = b"POST /api/v1/items HTTP/1.1\r\nHost: example.com\r\n".to_vec();
+    raw.extend_from_slice(b"User-Agent: Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0\r\n");
+    raw.extend_from_slice(b"Accept: text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8\r\n");
+    raw.extend_from_slice(b"Accept-Language: en-US,en;q=0.5\r\nAccept-Encoding: gzip, deflate, br\r\n");
+    raw.extend_from_slice(format!("Cookie: session={}; theme=dark\r\n", "a".repeat(256)).as_bytes());
+    raw.extend_from_slice(b"Content-Type: application/json\r\nContent-Length: 18\r\n\r\n{\"name\":\"feather\"}");
+    raw
+}
+
+fn header_scan(c: &mut Criterion) {
+    let large = browser_request();
+    let mut group = c.benchmark_group("find_header_end");
+    for (name, raw) in [("small", SMALL), ("browser", large.as_slice())] {
+        group.throughput(Throughput::Bytes(raw.len() as u64));
+        group.bench_function(name, |b| b.iter(|| find_header_end(black_box(raw))));
+    }
+    group.finish();
+}
+
+fn request_parse(c: &mut Criterion) {
+    let large = browser_request();
+    let mut group = c.benchmark_group("Request::parse");
+    for (name, raw) in [("small", SMALL), ("browser", large.as_slice())] {
+        let end = find_header_end(raw).unwrap();
+        let body = Bytes::copy_from_slice(&raw[end..]);
+        group.throughput(Throughput::Bytes(raw.len() as u64));
+        group.bench_function(name, |b| b.iter(|| Request::parse(black_box(&raw[..end]), body.clone(), ADDR).unwrap()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, header_scan, request_parse);
+criterion_main!(benches);
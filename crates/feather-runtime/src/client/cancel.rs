@@ -0,0 +1,86 @@
+use crate::runtime::MayStream;
+use parking_lot::Mutex;
+use std::net::Shutdown;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Aborts the requests of a [Client](super::Client) it was given to, from any coroutine or thread.
+///
+/// Cancelling shuts down the connections of the requests in flight, so they fail with
+/// [`ClientError::Cancelled`](super::ClientError::Cancelled) right away instead of waiting for the server.
+/// Requests started after it was cancelled fail before connecting. Clones cancel together.
+///
+/// ```rust,ignore
+/// let cancel = Cancel::new();
+/// let watchdog = cancel.clone();
+/// spawn(move || {
+///     sleep(Duration::from_secs(1));
+///     watchdog.cancel();
+/// });
+/// let result = client.send_cancellable(&ClientRequest::get(url), &cancel);
+/// ```
+#[derive(Clone, Default)]
+pub struct Cancel(Arc<State>);
+
+#[derive(Default)]
+struct State {
+    cancelled: AtomicBool,
+    /// The connections in flight, a slot is emptied when its request is done
+    streams: Mutex<Vec<Option<MayStream>>>,
+}
+
+impl Cancel {
+    /// A cancel that wasn't triggered yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Abort the requests in flight and the ones started later.
+    pub fn cancel(&self) {
+        let mut streams = self.0.streams.lock();
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        for stream in streams.iter_mut().filter_map(Option::take) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    /// Whether [cancel](Self::cancel) was called.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Track the connection of a request until the returned guard is dropped, `None` if already cancelled.
+    pub(super) fn register(&self, stream: &MayStream) -> Option<Registration<'_>> {
+        let handle = stream.try_clone().ok();
+        let mut streams = self.0.streams.lock();
+        if self.is_cancelled() {
+            return None;
+        }
+        let slot = match streams.iter().position(Option::is_none) {
+            Some(slot) => {
+                streams[slot] = handle;
+                slot
+            }
+            None => {
+                streams.push(handle);
+                streams.len() - 1
+            }
+        };
+        Some(Registration {
+            cancel: self,
+            slot,
+        })
+    }
+}
+
+/// Stops tracking a connection when its request is done.
+pub(super) struct Registration<'a> {
+    cancel: &'a Cancel,
+    slot: usize,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.cancel.0.streams.lock()[self.slot] = None;
+    }
+}
@@ -0,0 +1,141 @@
+use super::cancel::{Cancel, Registration};
+use super::{Client, ClientError, Target};
+use crate::runtime::MayStream;
+use crate::runtime::run_blocking;
+use std::io::{self, Read, Write};
+use std::net::ToSocketAddrs;
+use std::time::{Duration, Instant};
+
+/// The connection of one request, closed when dropped.
+pub(super) struct Connection<'a> {
+    inner: Inner,
+    _registration: Option<Registration<'a>>,
+}
+
+enum Inner {
+    Plain(Deadline),
+    #[cfg(feature = "tls")]
+    Tls(Box<rustls::StreamOwned<rustls::ClientConnection, Deadline>>),
+}
+
+/// A stream whose every read and write gets the time left until the deadline of the request.
+struct Deadline {
+    stream: MayStream,
+    deadline: Instant,
+}
+
+impl Deadline {
+    fn remaining(&self) -> io::Result<Duration> {
+        // A zero timeout means none to the socket
+        match self.deadline.checked_duration_since(Instant::now()) {
+            Some(left) if !left.is_zero() => Ok(left),
+            _ => Err(io::ErrorKind::TimedOut.into()),
+        }
+    }
+}
+
+impl Read for Deadline {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.set_read_timeout(Some(self.remaining()?))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for Deadline {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.set_write_timeout(Some(self.remaining()?))?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Resolve and connect to `target`, registering the connection with `cancel`.
+pub(super) fn connect<'a>(target: &Target, deadline: Instant, cancel: Option<&'a Cancel>, client: &Client) -> Result<Connection<'a>, ClientError> {
+    if !cfg!(feature = "tls") && target.secure {
+        return Err(ClientError::TlsUnavailable);
+    }
+    let (host, port) = (target.host.clone(), target.port);
+    let addrs: Vec<_> = run_blocking(move || (host, port).to_socket_addrs().map(Iterator::collect))?;
+    let mut last = io::Error::new(io::ErrorKind::NotFound, format!("{} has no address", target.host));
+    let mut connected = None;
+    for addr in addrs {
+        if cancel.is_some_and(Cancel::is_cancelled) {
+            return Err(ClientError::Cancelled);
+        }
+        match deadline.checked_duration_since(Instant::now()).filter(|left| !left.is_zero()) {
+            None => return Err(ClientError::TimedOut),
+            Some(left) => match MayStream::connect_timeout(&addr, left) {
+                Ok(stream) => {
+                    connected = Some(stream);
+                    break;
+                }
+                Err(e) => last = e,
+            },
+        }
+    }
+    let stream = connected.ok_or(last)?;
+    let registration = match cancel {
+        Some(cancel) => Some(cancel.register(&stream).ok_or(ClientError::Cancelled)?),
+        None => None,
+    };
+    let stream = Deadline {
+        stream,
+        deadline,
+    };
+    let inner = if target.secure {
+        tls(target, stream, client)?
+    } else {
+        Inner::Plain(stream)
+    };
+    Ok(Connection {
+        inner,
+        _registration: registration,
+    })
+}
+
+#[cfg(feature = "tls")]
+fn tls(target: &Target, stream: Deadline, client: &Client) -> Result<Inner, ClientError> {
+    let name = rustls::pki_types::ServerName::try_from(target.host.clone()).map_err(|_| ClientError::InvalidUrl(target.host.clone()))?;
+    let connection = rustls::ClientConnection::new(client.tls_config(), name).map_err(io::Error::other)?;
+    Ok(Inner::Tls(Box::new(rustls::StreamOwned::new(connection, stream))))
+}
+
+#[cfg(not(feature = "tls"))]
+fn tls(_target: &Target, _stream: Deadline, _client: &Client) -> Result<Inner, ClientError> {
+    Err(ClientError::TlsUnavailable)
+}
+
+impl Read for Connection<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            Inner::Plain(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Inner::Tls(stream) => match stream.read(buf) {
+                // Servers often close without a TLS close_notify, the framing tells whether the body is complete
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+                result => result,
+            },
+        }
+    }
+}
+
+impl Write for Connection<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            Inner::Plain(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Inner::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            Inner::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Inner::Tls(stream) => stream.flush(),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use super::{ClientError, ClientResponse};
+use bytes::Bytes;
+use http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header};
+use std::io::{self, BufRead, BufReader, Read};
+
+/// The longest response head read, status line and headers together.
+const MAX_HEAD: usize = 64 * 1024;
+const MAX_HEADERS: usize = 100;
+
+/// Read the response to a `method` request, its body up to `max_body` bytes.
+pub(super) fn response(stream: impl Read, method: &Method, max_body: u64) -> Result<ClientResponse, ClientError> {
+    let mut reader = BufReader::new(stream);
+    let (status, headers) = loop {
+        let (status, headers) = head(&mut reader)?;
+        // `100 Continue` and other interim responses come before the final one
+        if !status.is_informational() || status == StatusCode::SWITCHING_PROTOCOLS {
+            break (status, headers);
+        }
+    };
+    let body = if *method == Method::HEAD || status.is_informational() || status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED {
+        Bytes::new()
+    } else {
+        body(&mut reader, &headers, max_body)?
+    };
+    Ok(ClientResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn invalid(message: impl Into<String>) -> ClientError {
+    ClientError::InvalidResponse(message.into())
+}
+
+fn head(reader: &mut impl BufRead) -> Result<(StatusCode, HeaderMap), ClientError> {
+    let mut head = Vec::with_capacity(1024);
+    loop {
+        let start = head.len();
+        if reader.take((MAX_HEAD - start) as u64).read_until(b'\n', &mut head)? == 0 {
+            return Err(invalid("the connection closed before the response head ended"));
+        }
+        if !head.ends_with(b"\n") {
+            return Err(invalid(format!("the response head is larger than {MAX_HEAD} bytes")));
+        }
+        // A blank line ends the head, leading ones are tolerated
+        if matches!(&head[start..], b"\r\n" | b"\n") && start > 0 {
+            break;
+        }
+        if head.iter().all(|byte| matches!(byte, b'\r' | b'\n')) {
+            head.clear();
+        }
+    }
+    let mut parsed = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut response = httparse::Response::new(&mut parsed);
+    match response.parse(&head) {
+        Ok(httparse::Status::Complete(_)) => {}
+        Ok(httparse::Status::Partial) => return Err(invalid("incomplete response head")),
+        Err(e) => return Err(invalid(e.to_string())),
+    }
+    let status = response.code.and_then(|code| StatusCode::from_u16(code).ok()).ok_or_else(|| invalid("invalid status code"))?;
+    let mut headers = HeaderMap::with_capacity(response.headers.len());
+    for parsed in response.headers.iter() {
+        let name = HeaderName::from_bytes(parsed.name.as_bytes()).map_err(|_| invalid(format!("invalid header name `{}`", parsed.name)))?;
+        let value = HeaderValue::from_bytes(parsed.value).map_err(|_| invalid(format!("invalid value for `{name}`")))?;
+        headers.append(name, value);
+    }
+    Ok((status, headers))
+}
+
+fn body(reader: &mut impl BufRead, headers: &HeaderMap, limit: u64) -> Result<Bytes, ClientError> {
+    if let Some(coding) = headers.get(header::TRANSFER_ENCODING) {
+        let coding = coding.to_str().map_err(|_| invalid("invalid Transfer-Encoding"))?;
+        if coding.rsplit(',').next().is_some_and(|last| last.trim().eq_ignore_ascii_case("chunked")) {
+            return chunked(reader, limit);
+        }
+        // Another final coding is ended by closing the connection
+        return to_end(reader, limit);
+    }
+    let mut lengths = headers.get_all(header::CONTENT_LENGTH).iter();
+    let Some(length) = lengths.next() else {
+        return to_end(reader, limit);
+    };
+    if lengths.any(|other| other != length) {
+        return Err(invalid("conflicting Content-Length headers"));
+    }
+    let length: u64 = length.to_str().ok().and_then(|length| length.trim().parse().ok()).ok_or_else(|| invalid("invalid Content-Length"))?;
+    if length > limit {
+        return Err(ClientError::TooLarge {
+            limit,
+        });
+    }
+    let mut body = vec![0; length as usize];
+    reader.read_exact(&mut body).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => invalid("the connection closed before the body ended"),
+        _ => e.into(),
+    })?;
+    Ok(body.into())
+}
+
+/// A body ended by closing the connection.
+fn to_end(reader: &mut impl BufRead, limit: u64) -> Result<Bytes, ClientError> {
+    let mut body = Vec::new();
+    // One byte past the limit tells a body of exactly `limit` bytes from a longer one
+    reader.take(limit.saturating_add(1)).read_to_end(&mut body)?;
+    if body.len() as u64 > limit {
+        return Err(ClientError::TooLarge {
+            limit,
+        });
+    }
+    Ok(body.into())
+}
+
+fn chunked(reader: &mut impl BufRead, limit: u64) -> Result<Bytes, ClientError> {
+    let mut body = Vec::new();
+    let mut line = Vec::new();
+    loop {
+        read_line(reader, &mut line)?;
+        let size = line.split(|byte| *byte == b';').next().unwrap_or_default();
+        let size = std::str::from_utf8(size).ok().and_then(|size| u64::from_str_radix(size.trim(), 16).ok()).ok_or_else(|| invalid("invalid chunk size"))?;
+        if size == 0 {
+            break;
+        }
+        if (body.len() as u64).saturating_add(size) > limit {
+            return Err(ClientError::TooLarge {
+                limit,
+            });
+        }
+        let start = body.len();
+        body.resize(start + size as usize, 0);
+        reader.read_exact(&mut body[start..]).map_err(|_| invalid("the connection closed inside a chunk"))?;
+        read_line(reader, &mut line)?;
+        if !line.is_empty() {
+            return Err(invalid("chunk data is longer than its size"));
+        }
+    }
+    // Trailers are dropped, a blank line ends them
+    loop {
+        read_line(reader, &mut line)?;
+        if line.is_empty() {
+            return Ok(body.into());
+        }
+    }
+}
+
+/// One line without its line break into `line`.
+fn read_line(reader: &mut impl BufRead, line: &mut Vec<u8>) -> Result<(), ClientError> {
+    line.clear();
+    reader.take(MAX_HEAD as u64).read_until(b'\n', line)?;
+    if !line.ends_with(b"\n") {
+        return Err(invalid("the connection closed inside the chunked body"));
+    }
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(())
+}
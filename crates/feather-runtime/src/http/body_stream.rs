@@ -0,0 +1,61 @@
+use bytes::Bytes;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// A response body read while it is written, set with [`Response::send_reader`](super::Response::send_reader).
+///
+/// Only one chunk of the body is in memory at a time, so large files are sent without loading them whole.
+pub struct BodyStream {
+    reader: Box<dyn Read + Send>,
+    len: u64,
+    chunk_size: usize,
+}
+
+impl BodyStream {
+    pub(crate) fn new(reader: impl Read + Send + 'static, len: u64, chunk_size: usize) -> Self {
+        Self {
+            reader: Box::new(reader.take(len)),
+            len,
+            chunk_size: chunk_size.max(1),
+        }
+    }
+
+    /// Write the body to `writer`, one chunk at a time.
+    ///
+    /// # Errors
+    ///
+    /// When reading or writing fails, or when the reader ends before the length of the body, which the
+    /// client was already told.
+    pub fn write_to(mut self, writer: &mut impl Write) -> io::Result<()> {
+        let mut chunk = vec![0; self.chunk_size.min(usize::try_from(self.len).unwrap_or(usize::MAX))];
+        let mut remaining = self.len;
+        while remaining > 0 {
+            let n = match self.reader.read(&mut chunk) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the body ended before its length")),
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            writer.write_all(&chunk[..n])?;
+            remaining -= n as u64;
+        }
+        Ok(())
+    }
+
+    /// Read the whole body into memory.
+    ///
+    /// # Errors
+    ///
+    /// Like [write_to](Self::write_to).
+    pub fn into_bytes(self) -> io::Result<Bytes> {
+        let mut body = Vec::new();
+        self.write_to(&mut body)?;
+        Ok(Bytes::from(body))
+    }
+}
+
+impl fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyStream").field("len", &self.len).field("chunk_size", &self.chunk_size).finish_non_exhaustive()
+    }
+}
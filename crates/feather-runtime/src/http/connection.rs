@@ -0,0 +1,15 @@
+/// Statistics of the connection a request arrived on, see [`Request::connection_info`](super::Request::connection_info).
+///
+/// Useful to debug keep-alive behaviour or to rate limit per connection instead of per address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConnectionInfo {
+    /// Identifies the connection, unique for the lifetime of the server.
+    pub id: u64,
+    /// Position of the request on the connection, starting at 1.
+    pub sequence: u64,
+    /// Bytes read from the connection so far, this request included.
+    pub bytes_read: u64,
+    /// Whether the connection stays open after this request.
+    pub keep_alive: bool,
+}
@@ -8,3 +8,14 @@ pub enum HeaderError {
     #[error("Invalid Header Value")]
     InvalidHeaderValue(#[from] InvalidHeaderValue),
 }
+
+/// Returned by [`Request::parse_with_max_headers`](super::Request::parse_with_max_headers) when a request has more headers than allowed.
+#[derive(Debug, Error)]
+#[error("Too many headers, at most {0} are allowed")]
+pub struct TooManyHeaders(pub usize);
+
+/// Returned by [`Response::set_status_with_reason`](super::Response::set_status_with_reason) for a reason phrase
+/// with line breaks or other control characters.
+#[derive(Debug, Error)]
+#[error("Invalid reason phrase {0:?}")]
+pub struct InvalidReasonPhrase(pub String);
@@ -0,0 +1,33 @@
+use crate::runtime::ShutdownSignal;
+use bytes::Bytes;
+use may::net::TcpStream;
+use std::fmt;
+
+/// A connection taken over with [`Response::hijack`](super::Response::hijack).
+pub struct Hijacked {
+    /// The client connection. The server's read and write timeouts still apply, change them as needed.
+    pub stream: TcpStream,
+    /// Bytes the client sent after the request that the server already read, like the first frames of the new protocol.
+    pub read_ahead: Bytes,
+    /// Triggered when the server shuts down, which waits for the handler to return.
+    pub shutdown: ShutdownSignal,
+}
+
+/// The callback that receives a hijacked connection.
+pub(crate) struct HijackHandler(Box<dyn FnOnce(Hijacked) + Send>);
+
+impl HijackHandler {
+    pub(crate) fn new(handler: impl FnOnce(Hijacked) + Send + 'static) -> Self {
+        Self(Box::new(handler))
+    }
+
+    pub(crate) fn call(self, connection: Hijacked) {
+        (self.0)(connection)
+    }
+}
+
+impl fmt::Debug for HijackHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("HijackHandler")
+    }
+}
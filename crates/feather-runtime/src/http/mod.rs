@@ -1,6 +1,15 @@
+mod body_stream;
+mod connection;
 mod errors;
+mod hijack;
 mod request;
 mod response;
 
-pub use request::Request;
+pub use body_stream::BodyStream;
+pub use connection::ConnectionInfo;
+pub use errors::{InvalidReasonPhrase, TooManyHeaders};
+pub(crate) use hijack::HijackHandler;
+pub use hijack::Hijacked;
+pub(crate) use request::ArenaSlot;
+pub use request::{Request, RequestBuilder, find_header_end};
 pub use response::Response;
@@ -3,12 +3,28 @@ use std::net::SocketAddr;
 /// Simple alias for error results in this module.
 /// We use a boxed std error to avoid depending on the removed crate error type.
 pub type Error = Box<dyn std::error::Error>;
+use super::errors::HeaderError;
+use super::{ConnectionInfo, Response, TooManyHeaders};
+use bumpalo::Bump;
 use bytes::Bytes;
 use http::{Extensions, HeaderMap, Method, Uri, Version};
+use memchr::memmem;
 use std::str::FromStr;
+use parking_lot::Mutex;
+use std::sync::{Arc, LazyLock};
 use std::{borrow::Cow, collections::HashMap, fmt};
 use urlencoding::decode;
 
+static HEADER_END: LazyLock<memmem::Finder<'static>> = LazyLock::new(|| memmem::Finder::new(b"\r\n\r\n"));
+
+/// Returns the length of the request head (up to and including the blank line), or `None` if it's incomplete.
+///
+/// Uses a SIMD accelerated search, so it's cheap to call again every time more bytes arrive.
+#[inline]
+pub fn find_header_end(buf: &[u8]) -> Option<usize> {
+    HEADER_END.find(buf).map(|pos| pos + 4)
+}
+
 /// Contains a incoming Http Request
 #[derive(Debug)]
 pub struct Request {
@@ -29,20 +45,67 @@ pub struct Request {
     addr: SocketAddr,
     /// The route parameters of the request.
     params: HashMap<String, String>,
+    /// The response of the previous request on the same connection, cleared for reuse.
+    recycled: Option<Response>,
+    /// Scratch memory that lives as long as the request.
+    arena: Bump,
+    /// Where the arena goes back to when the request is dropped.
+    arena_slot: Option<Arc<ArenaSlot>>,
+    /// Statistics of the connection, set by the server.
+    connection: Option<ConnectionInfo>,
+}
+
+/// Keeps a connection's arena between requests.
+///
+/// The request hands the arena back when dropped, reset but with its memory kept, so keep-alive
+/// connections allocate it only once.
+#[derive(Debug, Default)]
+pub(crate) struct ArenaSlot(Mutex<Option<Bump>>);
+
+impl ArenaSlot {
+    /// Takes the stored arena, or a new one if the last request is still alive.
+    pub(crate) fn take(&self) -> Bump {
+        self.0.lock().take().unwrap_or_default()
+    }
 }
 
 impl Request {
+    /// How many headers [`parse`](Self::parse) accepts.
+    pub const DEFAULT_MAX_HEADERS: usize = 64;
+
     /// Parses a Request from raw bytes if parsing fails returns a error
+    ///
+    /// At most [`DEFAULT_MAX_HEADERS`](Self::DEFAULT_MAX_HEADERS) headers are accepted.
     pub fn parse(headers_raw: &[u8], body: Bytes, incoming_addr: SocketAddr) -> Result<Request, Error> {
-        let mut headers = [httparse::EMPTY_HEADER; 64];
-        let mut request = httparse::Request::new(&mut headers);
+        Self::parse_with_max_headers(headers_raw, body, incoming_addr, Self::DEFAULT_MAX_HEADERS)
+    }
 
-        request.parse(headers_raw).map_err(|e| -> Error { Box::new(io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse request: {}", e))) })?;
+    /// Like [`parse`](Self::parse), but accepts up to `max_headers` headers.
+    ///
+    /// Fails with a [`TooManyHeaders`] error when the request has more.
+    pub fn parse_with_max_headers(headers_raw: &[u8], body: Bytes, incoming_addr: SocketAddr, max_headers: usize) -> Result<Request, Error> {
+        // Common limits stay on the stack
+        let mut inline = [httparse::EMPTY_HEADER; Self::DEFAULT_MAX_HEADERS];
+        let mut spilled = Vec::new();
+        let headers = if max_headers <= inline.len() {
+            &mut inline[..max_headers]
+        } else {
+            spilled.resize(max_headers, httparse::EMPTY_HEADER);
+            &mut spilled[..]
+        };
+        let mut request = httparse::Request::new(headers);
+
+        request.parse(headers_raw).map_err(|e| -> Error {
+            match e {
+                httparse::Error::TooManyHeaders => Box::new(TooManyHeaders(max_headers)),
+                e => Box::new(io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse request: {}", e))),
+            }
+        })?;
 
         // Get the method string, ensuring it exists
         let method_str = request.method.ok_or_else(|| -> Error { Box::new(io::Error::new(io::ErrorKind::InvalidData, "Missing HTTP method")) })?;
 
-        // Validate method against known HTTP methods
+        // Any token is a valid method, extension methods like PURGE are routed like the standard ones
         let method = Method::from_str(method_str).map_err(|_| -> Error { Box::new(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid HTTP method: {}", method_str))) })?;
         let path = request.path.ok_or_else(|| -> Error { Box::new(io::Error::new(io::ErrorKind::InvalidData, "Failed to parse URI")) })?;
         let uri: Uri = path.parse().map_err(|e| -> Error { Box::new(io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse URI: {}", e))) })?;
@@ -52,7 +115,7 @@ impl Request {
             Some(1) => Version::HTTP_11,
             _ => Version::HTTP_11,
         };
-        let mut header_map = HeaderMap::new();
+        let mut header_map = HeaderMap::with_capacity(request.headers.len());
         for header in request.headers.iter() {
             let name = http::header::HeaderName::from_bytes(header.name.as_bytes()).map_err(|e| -> Error { Box::new(io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse header name: {}", e))) })?;
             let value = http::header::HeaderValue::from_bytes(header.value).map_err(|e| -> Error { Box::new(io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse header value: {}", e))) })?;
@@ -70,20 +133,33 @@ impl Request {
             addr: incoming_addr,
             extensions,
             params: HashMap::new(),
+            recycled: None,
+            arena: Bump::new(),
+            arena_slot: None,
+            connection: None,
         })
     }
 
+    /// Start building a request by hand, like a test or mock request for a middleware.
+    ///
+    /// ```rust,ignore
+    /// let request = Request::builder().method(Method::POST).uri("/auth").header("Authorization", "Bearer token").json(&login).build()?;
+    /// ```
+    pub fn builder() -> RequestBuilder {
+        RequestBuilder::default()
+    }
+
     /// Parses the body of the request as Serde JSON Value. Returns an error if the body is not valid JSON.  
     /// This method is useful for parsing JSON payloads in requests.  
     #[cfg(feature = "json")]
-    pub fn json(&self) -> Result<serde_json::Value, Error> {
-        serde_json::from_slice(&self.body).map_err(|e| -> Error { Box::new(io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse JSON body: {}", e))) })
+    pub fn json(&self) -> Result<serde_json::Value, io::Error> {
+        serde_json::from_slice(&self.body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse JSON body: {}", e)))
     }
     /// Returns a Hashmap of the query parameters of the Request.  
     /// Returns a Error if parsing fails
-    pub fn query(&self) -> Result<HashMap<String, String>, Error> {
+    pub fn query(&self) -> Result<HashMap<String, String>, io::Error> {
         if let Some(query) = self.uri.query() {
-            serde_urlencoded::from_str(query).map_err(|e| -> Error { Box::new(io::Error::new(io::ErrorKind::InvalidData, format!("Failed to Parse Query parameters {}", e))) })
+            serde_urlencoded::from_str(query).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Failed to Parse Query parameters {}", e)))
         } else {
             Ok(HashMap::new())
         }
@@ -97,6 +173,51 @@ impl Request {
         self.params.get(key).map(|v| &**v)
     }
 
+    /// The route parameters of the request, by name.
+    pub fn params(&self) -> &HashMap<String, String> {
+        &self.params
+    }
+
+    /// Takes the response recycled from the previous request on this connection, or a new one.
+    ///
+    /// Services should build their response from it, so keep-alive connections don't reallocate the headers on every request.
+    pub fn take_response(&mut self) -> Response {
+        self.recycled.take().unwrap_or_default()
+    }
+
+    pub(crate) fn recycle_response(&mut self, mut response: Response) {
+        response.reset();
+        self.recycled = Some(response);
+    }
+
+    /// A bump arena for scratch allocations that only need to live as long as the request.
+    ///
+    /// Allocating from it is a pointer bump and everything is freed at once when the request is done,
+    /// the memory itself is reused by the next request on the same connection.
+    ///
+    /// ```rust,ignore
+    /// let upper = req.arena().alloc_str(&name.to_uppercase());
+    /// ```
+    pub fn arena(&self) -> &Bump {
+        &self.arena
+    }
+
+    pub(crate) fn set_arena(&mut self, slot: Arc<ArenaSlot>) {
+        self.arena = slot.take();
+        self.arena_slot = Some(slot);
+    }
+
+    /// Statistics of the connection the request arrived on.
+    ///
+    /// Returns `None` for requests that weren't read by the server, e.g. in tests.
+    pub fn connection_info(&self) -> Option<ConnectionInfo> {
+        self.connection
+    }
+
+    pub(crate) fn set_connection_info(&mut self, info: ConnectionInfo) {
+        self.connection = Some(info);
+    }
+
     /// Returns the path of the Request
     pub fn path(&self) -> Cow<'_, str> {
         decode(self.uri.path()).unwrap()
@@ -107,8 +228,164 @@ impl Request {
     }
 }
 
+impl Drop for Request {
+    fn drop(&mut self) {
+        if let Some(slot) = self.arena_slot.take() {
+            let mut arena = std::mem::take(&mut self.arena);
+            arena.reset();
+            *slot.0.lock() = Some(arena);
+        }
+    }
+}
+
+/// Copies everything the client sent, the route parameters and the extensions.
+///
+/// The copy starts with an empty [arena](Request::arena) and without the recycled response, so it can outlive the
+/// request's handler, like in a WebSocket handler that runs after the upgrade.
+impl Clone for Request {
+    fn clone(&self) -> Self {
+        Request {
+            method: self.method.clone(),
+            uri: self.uri.clone(),
+            version: self.version,
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+            extensions: self.extensions.clone(),
+            addr: self.addr,
+            params: self.params.clone(),
+            recycled: None,
+            arena: Bump::new(),
+            arena_slot: None,
+            connection: self.connection,
+        }
+    }
+}
+
 impl fmt::Display for Request {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{} {}", self.method, self.uri.path())
     }
 }
+
+/// Builds a [`Request`] without parsing raw bytes, see [`Request::builder`].
+///
+/// Requests start as `GET /` over HTTP/1.1 from `127.0.0.1:0`. An invalid part is reported by
+/// [build](Self::build), so the calls can be chained without checking each one.
+pub struct RequestBuilder {
+    request: Result<Request, Error>,
+}
+
+impl Default for RequestBuilder {
+    fn default() -> Self {
+        Self {
+            request: Ok(Request {
+                method: Method::GET,
+                uri: Uri::from_static("/"),
+                version: Version::HTTP_11,
+                headers: HeaderMap::new(),
+                body: Bytes::new(),
+                extensions: Extensions::new(),
+                addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+                params: HashMap::new(),
+                recycled: None,
+                arena: Bump::new(),
+                arena_slot: None,
+                connection: None,
+            }),
+        }
+    }
+}
+
+impl RequestBuilder {
+    fn and_then(mut self, f: impl FnOnce(&mut Request) -> Result<(), Error>) -> Self {
+        if let Ok(request) = &mut self.request
+            && let Err(e) = f(request)
+        {
+            self.request = Err(e);
+        }
+        self
+    }
+
+    /// Set the method.
+    pub fn method(self, method: Method) -> Self {
+        self.and_then(|request| {
+            request.method = method;
+            Ok(())
+        })
+    }
+
+    /// Set the target, a path with an optional query string like `/search?q=feather`.
+    pub fn uri(self, uri: &str) -> Self {
+        self.and_then(|request| {
+            request.uri = uri.parse().map_err(|e| -> Error { Box::new(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid URI {uri:?}: {e}"))) })?;
+            Ok(())
+        })
+    }
+
+    /// Set the HTTP version.
+    pub fn version(self, version: Version) -> Self {
+        self.and_then(|request| {
+            request.version = version;
+            Ok(())
+        })
+    }
+
+    /// Add a header, keeping the ones with the same name.
+    pub fn header(self, name: &str, value: &str) -> Self {
+        self.and_then(|request| {
+            let name = http::HeaderName::from_bytes(name.as_bytes()).map_err(HeaderError::from)?;
+            let value = http::HeaderValue::from_str(value).map_err(HeaderError::from)?;
+            request.headers.append(name, value);
+            Ok(())
+        })
+    }
+
+    /// Set the body and its `Content-Length`.
+    pub fn body(self, body: impl Into<Bytes>) -> Self {
+        self.and_then(|request| {
+            request.body = body.into();
+            request.headers.insert(http::header::CONTENT_LENGTH, request.body.len().into());
+            Ok(())
+        })
+    }
+
+    /// Set the body to `value` as JSON, along with its `Content-Type`.
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize>(self, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => self.header("Content-Type", "application/json").body(body),
+            Err(e) => Self {
+                request: Err(Box::new(e)),
+            },
+        }
+    }
+
+    /// Set a route parameter, as if the request matched a route like `/users/:id`.
+    pub fn param(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.and_then(|request| {
+            request.params.insert(key.into(), value.into());
+            Ok(())
+        })
+    }
+
+    /// Add an extension, like the ones earlier middleware leave for later ones.
+    pub fn extension<T: Clone + Send + Sync + 'static>(self, extension: T) -> Self {
+        self.and_then(|request| {
+            request.extensions.insert(extension);
+            Ok(())
+        })
+    }
+
+    /// Set the address the request comes from.
+    pub fn remote_addr(self, addr: SocketAddr) -> Self {
+        self.and_then(|request| {
+            request.addr = addr;
+            Ok(())
+        })
+    }
+
+    /// Finish the request, failing with the first invalid part.
+    pub fn build(self) -> Result<Request, Error> {
+        self.request
+    }
+}
@@ -1,9 +1,17 @@
-use super::errors::HeaderError;
+use super::body_stream::BodyStream;
+use super::errors::{HeaderError, InvalidReasonPhrase};
+use super::hijack::{HijackHandler, Hijacked};
 use bytes::{Bytes, BytesMut};
 use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
 #[cfg(feature = "json")]
 use serde::Serialize;
-use std::{fs::File, io::Read, str::FromStr};
+use std::{
+    borrow::Cow,
+    fs::{File, Metadata},
+    io::{self, IoSlice, Read, Write},
+    str::FromStr,
+    time::UNIX_EPOCH,
+};
 
 #[derive(Debug, Default)]
 pub struct Response {
@@ -17,8 +25,16 @@ pub struct Response {
     /// This is the content that is sent back to the client.
     /// The body is represented as a `Bytes` object for efficient handling of binary data.
     pub body: Option<Bytes>,
+    /// Written after the head instead of `body`, see [send_reader](Self::send_reader).
+    stream: Option<Box<BodyStream>>,
     /// The HTTP version of the response.
     pub version: http::Version,
+    /// Takes over the connection once the response is written.
+    hijack: Option<HijackHandler>,
+    /// Sent instead of the canonical reason of the status.
+    reason: Option<Cow<'static, str>>,
+    /// Written without the Content-Length [write_head](Self::write_head) adds, for HEAD and close-delimited bodies.
+    unframed: bool,
 }
 
 impl Response {
@@ -32,15 +48,129 @@ impl Response {
         self.headers.insert(HeaderName::from_static("content-length"), Self::len_to_header_value(len));
     }
 
+    /// 1xx, 204 and 304 responses never carry a body, nor a length for it.
+    fn may_have_body(&self) -> bool {
+        !(self.status.is_informational() || self.status == StatusCode::NO_CONTENT || self.status == StatusCode::NOT_MODIFIED)
+    }
+
+    /// Frames the response for an HTTP/1.0 client.
+    ///
+    /// The status line says `HTTP/1.0`, which has no transfer codings. A body only in chunked coding is decoded
+    /// and sent with a `Content-Length`. Any other coding is dropped and the body is delimited by closing the
+    /// connection instead, returns `true` when the caller must close it.
+    pub(crate) fn frame_for_http10(&mut self) -> bool {
+        self.version = http::Version::HTTP_10;
+        let Some(coding) = self.headers.remove(http::header::TRANSFER_ENCODING) else {
+            return false;
+        };
+        let coding = coding.to_str().unwrap_or_default();
+        let chunked = coding.rsplit(',').next().is_some_and(|last| last.trim().eq_ignore_ascii_case("chunked"));
+        let decoded = chunked.then(|| self.body.as_ref().and_then(|body| decode_chunked(body))).flatten();
+        let identity = decoded.is_some() && coding.trim().eq_ignore_ascii_case("chunked");
+        if let Some(body) = decoded {
+            self.body = Some(body);
+        }
+        if identity {
+            let len = self.body.as_ref().map_or(0, |body| body.len());
+            self.headers.insert(http::header::CONTENT_LENGTH, Self::len_to_header_value(len));
+        } else {
+            self.headers.remove(http::header::CONTENT_LENGTH);
+            self.unframed = true;
+        }
+        !identity
+    }
+
+    /// Drops the body of a response to `HEAD`, keeping the headers.
+    ///
+    /// A `Content-Length` set by the handler is kept, but none is made up from the missing body.
+    pub(crate) fn frame_for_head(&mut self) {
+        self.body = None;
+        self.unframed = true;
+    }
+
+    /// Clears the response so it can be reused for another request, keeping the memory of its headers.
+    pub fn reset(&mut self) {
+        self.status = StatusCode::OK;
+        self.headers.clear();
+        self.body = None;
+        self.stream = None;
+        self.version = http::Version::default();
+        self.hijack = None;
+        self.reason = None;
+        self.unframed = false;
+    }
+
+    /// Take over the connection once this response is written, to switch to another protocol
+    /// after a `101 Switching Protocols` or to hold the connection for long polling.
+    ///
+    /// The server writes the response, stops speaking HTTP on the connection and calls `handler` with it.
+    /// The handler runs on the connection's coroutine, and the connection counts as in flight during
+    /// graceful shutdown until it returns.
+    ///
+    /// Only plain TCP connections can be handed over. Over TLS or local sockets the client gets
+    /// `501 Not Implemented` instead and the handler is never called.
+    ///
+    /// ```rust,ignore
+    /// res.set_status(101);
+    /// res.add_header("Upgrade", "my-protocol")?;
+    /// res.add_header("Connection", "Upgrade")?;
+    /// res.hijack(|mut conn| {
+    ///     conn.stream.write_all(b"hello over the raw connection").ok();
+    /// });
+    /// ```
+    pub fn hijack(&mut self, handler: impl FnOnce(Hijacked) + Send + 'static) -> &mut Response {
+        self.hijack = Some(HijackHandler::new(handler));
+        self
+    }
+
+    /// Whether [hijack](Self::hijack) was called for this response.
+    pub fn is_hijacked(&self) -> bool {
+        self.hijack.is_some()
+    }
+
+    pub(crate) fn take_hijack(&mut self) -> Option<HijackHandler> {
+        self.hijack.take()
+    }
+
     /// Sets the StatusCode of the response and Returns a Muteable Reference to the Response
     /// ```rust,ignore
     /// res.status(200).send_text("hello");
     /// ```
+    ///
+    /// Any code from 100 to 999 is accepted, others become `500`. Codes without a canonical reason,
+    /// like `499`, are sent without one unless given with [set_status_with_reason](Self::set_status_with_reason).
     pub fn set_status(&mut self, status: u16) -> &mut Response {
         self.status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        self.reason = None;
         self
     }
 
+    /// Sets the StatusCode of the response and the reason phrase sent with it, like [set_status](Self::set_status).
+    ///
+    /// Fails if the reason has line breaks or other control characters, the status is left unchanged then.
+    /// ```rust,ignore
+    /// res.set_status_with_reason(499, "Client Closed Request")?;
+    /// ```
+    pub fn set_status_with_reason(&mut self, status: u16, reason: impl Into<Cow<'static, str>>) -> Result<&mut Response, InvalidReasonPhrase> {
+        let reason = reason.into();
+        // reason-phrase = 1*( HTAB / SP / VCHAR / obs-text )
+        if reason.bytes().any(|b| b != b'\t' && b.is_ascii_control()) {
+            return Err(InvalidReasonPhrase(reason.into_owned()));
+        }
+        self.set_status(status);
+        self.reason = Some(reason);
+        Ok(self)
+    }
+
+    /// The reason phrase sent with the status: the one given to [set_status_with_reason](Self::set_status_with_reason),
+    /// or the canonical one. Empty for codes without a canonical reason.
+    pub fn reason(&self) -> &str {
+        match &self.reason {
+            Some(reason) => reason,
+            None => self.status.canonical_reason().unwrap_or_default(),
+        }
+    }
+
     /// Adds a header to the response.
     /// The header is a key-value pair that provides additional information about the response.
     ///
@@ -51,13 +181,57 @@ impl Response {
         return Ok(());
     }
     /// Converts the `Response` into a raw HTTP response as Bytes.
+    ///
+    /// This copies the body behind the head, prefer [write_to](Self::write_to) to send a response.
     pub fn to_raw(&self) -> Bytes {
         let body_len = self.body.as_ref().map_or(0, |b| b.len());
         // Start buffer with a reasonable capacity to avoid reallocations.
         let mut buf = BytesMut::with_capacity(512 + body_len);
+        self.write_head(&mut buf);
+
+        // --- 6. Body ---
+        if let Some(ref body) = self.body {
+            buf.extend_from_slice(body);
+        }
+
+        // Convert mutable buffer to immutable Bytes type
+        buf.freeze()
+    }
+
+    /// Writes the response to `writer`, sending the head and the body as separate slices of one vectored write
+    /// so the body is never copied.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.write_with(writer, &mut BytesMut::with_capacity(512))
+    }
+
+    /// Like [write_to](Self::write_to), serializing the head into a reusable buffer.
+    pub(crate) fn write_with(&self, writer: &mut impl Write, head: &mut BytesMut) -> io::Result<()> {
+        head.clear();
+        self.write_head(head);
+        let body = self.body.as_deref().unwrap_or_default();
+
+        let mut slices = [IoSlice::new(head), IoSlice::new(body)];
+        let mut slices = &mut slices[..];
+        while !slices.is_empty() {
+            match writer.write_vectored(slices) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => IoSlice::advance_slices(&mut slices, n),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Serializes the status line and headers, including the blank line that ends them.
+    pub(crate) fn write_head(&self, buf: &mut BytesMut) {
+        let body_len = self.body.as_ref().map_or(0, |b| b.len());
 
         // --- 1. Status Line (HTTP/1.1 200 OK\r\n) ---
-        buf.extend_from_slice(b"HTTP/1.1 ");
+        buf.extend_from_slice(match self.version {
+            http::Version::HTTP_10 => b"HTTP/1.0 ",
+            _ => b"HTTP/1.1 ",
+        });
 
         // Use itoa::Buffer for stack-allocated status code formatting
         let mut status_buffer = itoa::Buffer::new();
@@ -66,8 +240,8 @@ impl Response {
         buf.extend_from_slice(status_code_str.as_bytes());
         buf.extend_from_slice(b" ");
 
-        // Canonical Reason (e.g., "OK", "Not Found")
-        buf.extend_from_slice(self.status.canonical_reason().unwrap_or("Unknown").as_bytes());
+        // Reason (e.g., "OK", "Not Found"), may be empty
+        buf.extend_from_slice(self.reason().as_bytes());
         buf.extend_from_slice(b"\r\n");
 
         // --- 2. Existing Headers ---
@@ -93,8 +267,9 @@ impl Response {
         }
 
         // --- 4. Content-Length Header Insertion ---
-        // Insert Content-Length if it's not set AND there is a body.
-        if !self.headers.contains_key("content-length") && body_len > 0 {
+        // Insert Content-Length if it's not set, even for empty bodies: without it a keep-alive client
+        // can only find the end of the body by the connection closing.
+        if !self.unframed && !self.headers.contains_key("content-length") && !self.headers.contains_key("transfer-encoding") && self.may_have_body() {
             buf.extend_from_slice(b"content-length: ");
 
             // Use itoa::Buffer for stack-allocated length formatting
@@ -107,14 +282,6 @@ impl Response {
 
         // --- 5. Header/Body Separator ---
         buf.extend_from_slice(b"\r\n");
-
-        // --- 6. Body ---
-        if let Some(ref body) = self.body {
-            buf.extend_from_slice(body);
-        }
-
-        // Convert mutable buffer to immutable Bytes type
-        buf.freeze()
     }
 
     /// Sends given String as given text
@@ -160,8 +327,58 @@ impl Response {
         }
     }
 
+    /// Sets the headers describing a file without reading it: `Content-Length`, `Accept-Ranges`, `Last-Modified`
+    /// and a weak `ETag` built from the size and modification time.
+    ///
+    /// This is all a `HEAD` request needs, the server never sends a body in response to `HEAD`.
+    pub fn set_file_headers(&mut self, metadata: &Metadata) {
+        let len = metadata.len();
+        let modified = metadata.modified().ok();
+        let mtime = modified.and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_secs());
+        self.headers.insert(HeaderName::from_static("content-length"), Self::len_to_header_value(len));
+        self.headers.insert(HeaderName::from_static("accept-ranges"), HeaderValue::from_static("bytes"));
+        if let Ok(etag) = HeaderValue::from_str(&format!("W/\"{len:x}-{mtime:x}\"")) {
+            self.headers.insert(HeaderName::from_static("etag"), etag);
+        }
+        if let Some(modified) = modified {
+            let date = chrono::DateTime::<chrono::Utc>::from(modified).format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+            if let Ok(date) = HeaderValue::from_str(&date) {
+                self.headers.insert(HeaderName::from_static("last-modified"), date);
+            }
+        }
+    }
+
+    /// Sends `len` bytes read from `reader` as the body, `chunk_size` bytes at a time while the response is
+    /// written, so a large file is never loaded whole. Content-Length is set to `len`.
+    ///
+    /// The reader must have `len` bytes, the connection is closed when it ends early. Setting
+    /// [body](Self::body) afterwards replaces the stream.
+    ///
+    /// ```rust,ignore
+    /// let file = File::open("video.mp4")?;
+    /// let len = file.metadata()?.len();
+    /// res.send_reader(file, len, 64 * 1024);
+    /// ```
+    pub fn send_reader(&mut self, reader: impl Read + Send + 'static, len: u64, chunk_size: usize) {
+        self.body = None;
+        self.headers.insert(HeaderName::from_static("content-length"), Self::len_to_header_value(len));
+        self.stream = Some(Box::new(BodyStream::new(reader, len, chunk_size)));
+    }
+
+    /// Whether the body is the stream of [send_reader](Self::send_reader), the one [take_stream](Self::take_stream) returns.
+    pub fn has_stream(&self) -> bool {
+        self.body.is_none() && self.stream.is_some()
+    }
+
+    /// Take the body set with [send_reader](Self::send_reader), unless a [body](Self::body) was set after it.
+    ///
+    /// [write_to](Self::write_to) only writes `body`, the stream is written after it with [`BodyStream::write_to`].
+    pub fn take_stream(&mut self) -> Option<BodyStream> {
+        self.stream.take().filter(|_| self.body.is_none()).map(|stream| *stream)
+    }
+
     /// Take a [File] Struct and sends it as a file.
-    /// File size is limited to 4MB. For larger files, chunked transfer\[WIP] is recommended.
+    /// File size is limited to 4MB. For larger files, see [send_reader](Self::send_reader).
     pub fn send_file(&mut self, mut file: File) {
         let metadata = match file.metadata() {
             Ok(m) => m,
@@ -175,7 +392,7 @@ impl Response {
         // ENFORCE LIMIT: 4MB
         if metadata.len() > Self::MAX_FILE_SIZE_BYTES {
             self.status = StatusCode::PAYLOAD_TOO_LARGE; // 413
-            self.body = Some(Bytes::from("File size exceeds 4MB limit. Use chunked encoding for larger files."));
+            self.body = Some(Bytes::from("File size exceeds 4MB limit. Use send_reader for larger files."));
             return;
         }
 
@@ -183,8 +400,7 @@ impl Response {
         match file.read_to_end(&mut buffer) {
             Ok(_) => {
                 self.body = Some(Bytes::from(buffer));
-                let len = self.body.as_ref().unwrap().len();
-                self.headers.insert(HeaderName::from_static("content-length"), Self::len_to_header_value(len));
+                self.set_file_headers(&metadata);
                 // ? NOTE: Consider adding feature : Content-Type based on file extension
             }
             Err(_) => {
@@ -208,7 +424,7 @@ impl Response {
     }
 
     /// A Utily Function for wrapping HeaderValue for Content-Lenght
-    fn len_to_header_value(len: usize) -> HeaderValue {
+    fn len_to_header_value(len: impl itoa::Integer) -> HeaderValue {
         let mut buffer = itoa::Buffer::new();
         let len_str = buffer.format(len);
 
@@ -216,3 +432,20 @@ impl Response {
         HeaderValue::from_bytes(len_str.as_bytes()).expect("itoa::Buffer output should be a valid HeaderValue")
     }
 }
+
+/// Decodes a body in chunked transfer coding, `None` if it is malformed. Trailers are dropped.
+fn decode_chunked(mut input: &[u8]) -> Option<Bytes> {
+    let mut body = BytesMut::with_capacity(input.len());
+    loop {
+        let line_end = input.windows(2).position(|w| w == b"\r\n")?;
+        let size_line = std::str::from_utf8(&input[..line_end]).ok()?;
+        // Chunk extensions follow a `;`
+        let size = usize::from_str_radix(size_line.split(';').next()?.trim(), 16).ok()?;
+        input = &input[line_end + 2..];
+        if size == 0 {
+            return Some(body.freeze());
+        }
+        body.extend_from_slice(input.get(..size)?);
+        input = input.get(size..)?.strip_prefix(b"\r\n")?;
+    }
+}
@@ -12,8 +12,51 @@
 //!
 //! - [`http`] - HTTP request and response types
 //! - [`runtime`] - Server runtime and coroutine support
+//! - `ws` - WebSocket connections, with the `ws` feature
+//! - `client` - An HTTP/1.1 client for upstream calls, with the `client` feature
+//!
+//! ## Features
+//!
+//! Protocol components are opt-in so embedded builds only compile what they use:
+//!
+//! | Feature | Default | Enables |
+//! |---------|---------|---------|
+//! | `json`  | yes (`full`) | JSON responses through `serde_json` |
+//! | `log`   | yes (`full`) | Logging through the `log` crate |
+//! | `tls`   | no | HTTPS with rustls, see `runtime::tls` |
+//! | `ws`    | no | WebSocket connections, see `ws` |
+//! | `client` | no | An HTTP/1.1 client, see `client`. HTTPS with `tls` as well |
+//!
+//! With `default-features = false` the runtime only speaks plain HTTP/1.1. HTTP/2 isn't implemented, so there is
+//! no feature for it. [`features`] tells crates
+//! built on top which components this build includes.
 
+#[cfg(feature = "client")]
+pub mod client;
 pub mod http;
 pub mod runtime;
+#[cfg(feature = "ws")]
+pub mod ws;
+
+/// The optional components included in this build, one constant per cargo feature.
+///
+/// Crates that forward features to the runtime can check them at compile time:
+///
+/// ```rust
+/// const _: () = assert!(!cfg!(feature = "ws") || feather_runtime::features::WS);
+/// ```
+pub mod features {
+    /// JSON responses, the `json` feature.
+    pub const JSON: bool = cfg!(feature = "json");
+    /// Logging, the `log` feature.
+    pub const LOG: bool = cfg!(feature = "log");
+    /// HTTPS, the `tls` feature.
+    pub const TLS: bool = cfg!(feature = "tls");
+    /// WebSocket connections, the `ws` feature.
+    pub const WS: bool = cfg!(feature = "ws");
+    /// The HTTP client, the `client` feature.
+    pub const CLIENT: bool = cfg!(feature = "client");
+}
 
 pub use ::http::{HeaderMap, HeaderName, HeaderValue, Method, Uri};
+pub use bumpalo::Bump;
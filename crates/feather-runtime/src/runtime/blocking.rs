@@ -0,0 +1,115 @@
+//! A pool of OS threads for work that would block the coroutine workers.
+//!
+//! Threads are started on demand, up to the `blocking_threads` of the [`ServerConfig`](super::server::ServerConfig),
+//! and stop after they've been idle for a while. Work beyond the limit waits for a free thread.
+
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Threads used before a server sets its own limit.
+pub(crate) const DEFAULT_THREADS: usize = 32;
+
+/// How long an idle thread waits for work before it stops.
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+static POOL: Pool = Pool {
+    state: Mutex::new(State {
+        queue: VecDeque::new(),
+        threads: 0,
+        idle: 0,
+        max_threads: DEFAULT_THREADS,
+    }),
+    available: Condvar::new(),
+};
+
+struct Pool {
+    state: Mutex<State>,
+    available: Condvar,
+}
+
+struct State {
+    queue: VecDeque<Task>,
+    threads: usize,
+    idle: usize,
+    max_threads: usize,
+}
+
+/// Run `work` on the blocking pool and wait for its result.
+///
+/// Called from a coroutine, only the coroutine waits: its worker thread goes on with other connections.
+/// Use it for CPU heavy work or blocking calls like FFI and synchronous database drivers, which would
+/// otherwise stall every coroutine scheduled on the same worker. A panic in `work` is resumed in the caller.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let hash = run_blocking(move || bcrypt::hash(password, 12))?;
+/// ```
+pub fn run_blocking<F, T>(work: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (sender, receiver) = may::sync::mpsc::channel();
+    POOL.submit(Box::new(move || {
+        let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(work)));
+    }));
+    match receiver.recv().expect("blocking tasks always send their result") {
+        Ok(value) => value,
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+/// Limit the pool to `threads` threads, threads above it stop once they finished their task.
+pub(crate) fn set_max_threads(threads: usize) {
+    POOL.lock().max_threads = threads.max(1);
+}
+
+impl Pool {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // Tasks run outside of the lock, so it can't be poisoned by them
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn submit(&'static self, task: Task) {
+        let mut state = self.lock();
+        state.queue.push_back(task);
+        if state.idle >= state.queue.len() {
+            self.available.notify_one();
+        } else if state.threads < state.max_threads {
+            state.threads += 1;
+            drop(state);
+            let spawned = std::thread::Builder::new().name("feather-blocking".to_string()).spawn(move || self.work());
+            if spawned.is_err() {
+                self.lock().threads -= 1;
+            }
+        }
+    }
+
+    fn work(&self) {
+        let mut state = self.lock();
+        loop {
+            if state.threads > state.max_threads {
+                break;
+            }
+            if let Some(task) = state.queue.pop_front() {
+                drop(state);
+                task();
+                state = self.lock();
+                continue;
+            }
+            state.idle += 1;
+            let (next, timeout) = self.available.wait_timeout(state, KEEP_ALIVE).unwrap_or_else(|poisoned| poisoned.into_inner());
+            state = next;
+            state.idle -= 1;
+            if timeout.timed_out() && state.queue.is_empty() {
+                break;
+            }
+        }
+        state.threads -= 1;
+    }
+}
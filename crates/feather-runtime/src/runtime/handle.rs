@@ -0,0 +1,182 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+type Hook = Box<dyn FnOnce() + Send>;
+
+/// State shared between a running [`Server`](super::Server) and its handles.
+#[derive(Default)]
+pub(crate) struct ServerState {
+    /// Set to `true` once a shutdown has been requested.
+    pub stopping: AtomicBool,
+    /// Number of connections currently being served.
+    pub connections: AtomicUsize,
+    /// The id given to the next accepted connection.
+    pub next_connection_id: AtomicU64,
+    /// The address the listener is bound to, once bound.
+    pub local_addr: Mutex<Option<SocketAddr>>,
+    /// The local IPC endpoint the server listens on, if any.
+    pub local_path: Mutex<Option<PathBuf>>,
+    /// Run once a shutdown has been requested, by the id of their [ShutdownHook].
+    pub hooks: Mutex<HashMap<u64, Hook>>,
+    /// The id given to the next [ShutdownHook].
+    pub next_hook_id: AtomicU64,
+}
+
+/// A cloneable handle to a running [`Server`](super::Server).
+///
+/// Use it to request a graceful shutdown from another thread or to inspect the server.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let server = Server::with_config(service, ServerConfig::default());
+/// let handle = server.handle();
+/// std::thread::spawn(move || server.run("127.0.0.1:5050"));
+/// // ...
+/// handle.shutdown();
+/// ```
+#[derive(Clone)]
+pub struct ServerHandle {
+    pub(crate) state: Arc<ServerState>,
+}
+
+impl ServerHandle {
+    /// Requests a graceful shutdown.
+    ///
+    /// The server stops accepting new connections, lets in-flight requests finish
+    /// (closing keep-alive connections after their current request) and `run` returns
+    /// once every connection is done or the shutdown timeout elapses.
+    pub fn shutdown(&self) {
+        if self.state.stopping.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // The accept loop is blocked waiting for a connection, knock on the door to wake it up.
+        if let Some(addr) = self.local_addr() {
+            let _ = TcpStream::connect_timeout(&Self::wake_addr(addr), Duration::from_secs(1));
+        }
+        if let Some(path) = self.local_path() {
+            super::server::local::wake(&path);
+        }
+        // Each on its own coroutine, a hook writing to a slow client must not hold up the others
+        let hooks = std::mem::take(&mut *self.state.hooks.lock());
+        for hook in hooks.into_values() {
+            super::spawn(hook);
+        }
+    }
+
+    /// Returns `true` until a shutdown has been requested.
+    pub fn is_running(&self) -> bool {
+        !self.state.stopping.load(Ordering::SeqCst)
+    }
+
+    /// The address the server is bound to, or `None` if it has not bound yet.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        *self.state.local_addr.lock()
+    }
+
+    /// The socket path or pipe name of a server started with [`Server::run_local`](super::Server::run_local).
+    pub fn local_path(&self) -> Option<PathBuf> {
+        self.state.local_path.lock().clone()
+    }
+
+    /// The number of connections currently being served.
+    pub fn active_connections(&self) -> usize {
+        self.state.connections.load(Ordering::SeqCst)
+    }
+
+    /// Unspecified addresses (`0.0.0.0`, `::`) can't be connected to, use loopback instead.
+    fn wake_addr(addr: SocketAddr) -> SocketAddr {
+        match addr.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), addr.port()),
+            IpAddr::V6(ip) if ip.is_unspecified() => SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), addr.port()),
+            _ => addr,
+        }
+    }
+}
+
+/// Tells connections taken over with [`Response::hijack`](crate::http::Response::hijack) that the server is shutting down.
+///
+/// The server waits for them like for any other connection, so long-lived ones should wrap up once it's triggered.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// response.hijack(|connection| {
+///     let stream = connection.stream.try_clone().unwrap();
+///     let _hook = connection.shutdown.on_shutdown(move || {
+///         let _ = stream.shutdown(std::net::Shutdown::Both);
+///     });
+///     // ...
+/// });
+/// ```
+#[derive(Clone)]
+pub struct ShutdownSignal(Arc<ServerState>);
+
+impl ShutdownSignal {
+    pub(crate) fn new(state: Arc<ServerState>) -> Self {
+        Self(state)
+    }
+
+    /// Returns `true` once a graceful shutdown has been requested.
+    pub fn is_triggered(&self) -> bool {
+        self.0.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Run `hook` on its own coroutine once a shutdown is requested, or right away on the calling one if it already was.
+    ///
+    /// Dropping the returned [ShutdownHook] cancels the hook if it didn't run yet.
+    pub fn on_shutdown(&self, hook: impl FnOnce() + Send + 'static) -> ShutdownHook {
+        let mut hooks = self.0.hooks.lock();
+        // Checked under the lock, `ServerHandle::shutdown` takes the hooks after setting the flag
+        if self.is_triggered() {
+            drop(hooks);
+            hook();
+            return ShutdownHook {
+                state: self.0.clone(),
+                id: None,
+            };
+        }
+        let id = self.0.next_hook_id.fetch_add(1, Ordering::Relaxed);
+        hooks.insert(id, Box::new(hook));
+        ShutdownHook {
+            state: self.0.clone(),
+            id: Some(id),
+        }
+    }
+}
+
+/// A hook registered with [`ShutdownSignal::on_shutdown`], cancelled when dropped.
+#[must_use = "the hook is cancelled when this is dropped"]
+pub struct ShutdownHook {
+    state: Arc<ServerState>,
+    id: Option<u64>,
+}
+
+impl Drop for ShutdownHook {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.state.hooks.lock().remove(&id);
+        }
+    }
+}
+
+/// Keeps [`ServerState::connections`] accurate even if the connection handler panics.
+pub(crate) struct ConnectionGuard(Arc<ServerState>);
+
+impl ConnectionGuard {
+    pub fn new(state: Arc<ServerState>) -> Self {
+        state.connections.fetch_add(1, Ordering::SeqCst);
+        Self(state)
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
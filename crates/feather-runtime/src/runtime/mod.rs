@@ -1,8 +1,21 @@
+mod blocking;
+pub mod handle;
+mod pool;
 pub mod service;
 
 pub mod server;
+mod task;
+#[cfg(feature = "tls")]
+pub mod tls;
 
+pub use blocking::run_blocking;
+pub use handle::{ServerHandle, ShutdownHook, ShutdownSignal};
 pub use server::Server;
 pub use service::Service;
+pub use task::{sleep, spawn};
+#[cfg(feature = "tls")]
+pub use tls::{TlsConfig, TlsError};
 
 pub use may::net::TcpStream as MayStream;
+/// Channels and locks that park a waiting coroutine instead of blocking its worker thread.
+pub use may::sync;
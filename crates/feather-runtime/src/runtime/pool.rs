@@ -0,0 +1,35 @@
+//! Per-worker pool of connection buffers.
+//!
+//! Every worker thread keeps its own free list, so taking and returning a buffer never contends with other
+//! workers. Coroutines never hold on to the pool across a yield, which makes the thread local safe to use
+//! even though coroutines can move between workers.
+
+use bytes::BytesMut;
+use std::cell::RefCell;
+
+use super::server::ServerConfig;
+
+thread_local! {
+    static POOL: RefCell<Vec<BytesMut>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Takes an empty buffer from the current worker's pool, or allocates a new one.
+pub(crate) fn take(config: &ServerConfig) -> BytesMut {
+    POOL.with_borrow_mut(|pool| pool.pop()).unwrap_or_else(|| BytesMut::with_capacity(config.buffer_size))
+}
+
+/// Returns a buffer to the current worker's pool.
+///
+/// The buffer is dropped instead if the pool is full, or if it grew past four times `buffer_size`
+/// while serving a large request, so a few big uploads don't pin memory forever.
+pub(crate) fn give(mut buffer: BytesMut, config: &ServerConfig) {
+    if buffer.capacity() > config.buffer_size * 4 {
+        return;
+    }
+    buffer.clear();
+    POOL.with_borrow_mut(|pool| {
+        if pool.len() < config.buffer_pool_size {
+            pool.push(buffer);
+        }
+    });
+}
@@ -1,37 +1,97 @@
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use http::StatusCode;
 #[cfg(feature = "log")]
 use log::{debug, info, warn};
 use may::net::{TcpListener, TcpStream};
 use num_cpus;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
 use std::io::{self, Read, Write};
 use std::net::{SocketAddr, ToSocketAddrs};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
 use std::{panic, sync::Arc};
 
-use crate::http::{Request, Response};
+use crate::http::{ArenaSlot, ConnectionInfo, HijackHandler, Hijacked, Request, Response, TooManyHeaders, find_header_end};
+use crate::runtime::handle::{ConnectionGuard, ServerHandle, ServerState, ShutdownSignal};
+use crate::runtime::pool;
 use crate::runtime::service::{ArcService, Service, ServiceResult};
+#[cfg(feature = "tls")]
+use crate::runtime::tls::TlsConfig;
+
+pub(super) mod local;
 
 /// Configuration for the HTTP server
 #[derive(Clone, Debug)]
 pub struct ServerConfig {
     /// Maximum request body size in bytes (default: 8192 = 8KB)
     pub max_body_size: usize,
-    /// Read timeout in seconds (default: 30)
+    /// Maximum size of the request line and headers in bytes (default: 16384 = 16KB)
+    pub max_header_size: usize,
+    /// Maximum number of request headers (default: 64)
+    pub max_headers: usize,
+    /// Upper bound for any single read from a client, in seconds (default: 30)
     pub read_timeout_secs: u64,
+    /// Time a client has to send the complete request headers, in seconds (default: 10)
+    pub header_timeout_secs: u64,
+    /// Maximum time between two reads of a request body, in seconds (default: 10)
+    pub body_timeout_secs: u64,
+    /// How long an idle keep-alive connection is kept open waiting for the next request, in seconds (default: 5)
+    pub keep_alive_timeout_secs: u64,
+    /// Maximum time a single write of a response may block on a client that doesn't read, in seconds (default: 30)
+    pub write_timeout_secs: u64,
     /// Number of worker threads (default: number of CPU cores)
     pub workers: usize,
     /// Stack size per coroutine in bytes (default: 65536 = 64KB)
     pub stack_size: usize,
+    /// Number of finished coroutines whose stacks are kept for reuse (default: 1000)
+    pub pool_capacity: usize,
+    /// Grow the coroutine pool with the number of concurrent connections, up to 8x `pool_capacity` (default: false)
+    pub auto_scale: bool,
+    /// Length of the queue of connections waiting to be accepted, when the server binds the socket itself (default: 1024)
+    pub backlog: i32,
+    /// Disable Nagle's algorithm (`TCP_NODELAY`) on accepted connections (default: false)
+    pub tcp_nodelay: bool,
+    /// Let several processes bind the same address with `SO_REUSEPORT`, when the server binds the socket itself.
+    /// The kernel balances connections between them. Only supported on Unix (default: false)
+    pub reuse_port: bool,
+    /// Kernel send buffer size of connections (`SO_SNDBUF`) in bytes, `None` keeps the OS default (default: None)
+    pub send_buffer_size: Option<usize>,
+    /// Kernel receive buffer size of connections (`SO_RCVBUF`) in bytes, `None` keeps the OS default (default: None)
+    pub recv_buffer_size: Option<usize>,
+    /// Initial capacity of the read and write buffers of a connection in bytes (default: 4096 = 4KB)
+    pub buffer_size: usize,
+    /// Number of idle buffers each worker thread keeps for reuse, 0 disables pooling (default: 256)
+    pub buffer_pool_size: usize,
+    /// How long a graceful shutdown waits for in-flight connections, in seconds (default: 30)
+    pub shutdown_timeout_secs: u64,
+    /// Maximum number of OS threads running work passed to [`run_blocking`](super::run_blocking) (default: 32)
+    pub blocking_threads: usize,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             max_body_size: 8192,
+            max_header_size: 16 * 1024,
+            max_headers: Request::DEFAULT_MAX_HEADERS,
             read_timeout_secs: 30,
+            header_timeout_secs: 10,
+            body_timeout_secs: 10,
+            keep_alive_timeout_secs: 5,
+            write_timeout_secs: 30,
             workers: num_cpus::get(),
             stack_size: 64 * 1024,
+            pool_capacity: 1000,
+            auto_scale: false,
+            backlog: 1024,
+            tcp_nodelay: false,
+            reuse_port: false,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            buffer_size: 4096,
+            buffer_pool_size: 256,
+            shutdown_timeout_secs: 30,
+            blocking_threads: super::blocking::DEFAULT_THREADS,
         }
     }
 }
@@ -40,8 +100,8 @@ impl Default for ServerConfig {
 pub struct Server {
     /// The user's application logic
     service: ArcService,
-    /// Flag to control server shutdown
-    running: Arc<AtomicBool>,
+    /// Shutdown flag, connection count and bound address, shared with [ServerHandle]s
+    state: Arc<ServerState>,
     /// Server configuration
     config: ServerConfig,
 }
@@ -49,66 +109,161 @@ pub struct Server {
 impl Server {
     /// Create a new Server instance with the given Service
     pub fn new(service: impl Service, max_body_size: usize) -> Self {
-        let mut config = ServerConfig::default();
-        config.max_body_size = max_body_size;
-        Self {
-            service: Arc::new(service),
-            running: Arc::new(AtomicBool::new(true)),
-            config,
-        }
+        let config = ServerConfig {
+            max_body_size,
+            ..ServerConfig::default()
+        };
+        Self::with_config(service, config)
     }
 
     /// Create a new Server instance with custom configuration
     pub fn with_config(service: impl Service, config: ServerConfig) -> Self {
         Self {
             service: Arc::new(service),
-            running: Arc::new(AtomicBool::new(true)),
+            state: Arc::new(ServerState::default()),
             config,
         }
     }
 
+    /// Returns a [ServerHandle] that can shut the server down from another thread.
+    pub fn handle(&self) -> ServerHandle {
+        ServerHandle {
+            state: self.state.clone(),
+        }
+    }
+
     /// Initiates a graceful shutdown of the server
     pub fn shutdown(&self) {
-        self.running.store(false, Ordering::SeqCst);
+        self.handle().shutdown();
     }
 
     /// Runs the server until shutdown is called
     pub fn run(&self, addr: impl ToSocketAddrs) -> io::Result<()> {
-        // Configure coroutine runtime
-        may::config().set_workers(self.config.workers);
-        may::config().set_stack_size(self.config.stack_size);
         #[cfg(feature = "log")]
         info!(
             "Feather Runtime Started on {}",
             addr.to_socket_addrs()?.next().unwrap_or(SocketAddr::from(([0, 0, 0, 0], 80)))
         );
 
-        let listener = TcpListener::bind(addr)?;
+        let listener = TcpListener::new(self.bind(addr)?)?;
+        self.serve(listener)
+    }
+
+    /// Bind a listener to `addr` with the configured `backlog` and socket options.
+    ///
+    /// Like [`std::net::TcpListener::bind`], every resolved address is tried until one succeeds.
+    pub fn bind(&self, addr: impl ToSocketAddrs) -> io::Result<std::net::TcpListener> {
+        let mut last_error = None;
+        for addr in addr.to_socket_addrs()? {
+            match bind_socket(addr, &self.config) {
+                Ok(listener) => return Ok(listener),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any addresses")))
+    }
+
+    /// Runs the server on an already bound std listener until shutdown is called.
+    ///
+    /// This is useful for socket activation (e.g. systemd) or tests that bind to port 0
+    /// and need to know the address before the server starts.
+    ///
+    /// The buffer sizes of the [ServerConfig] are applied to the listener, `backlog` and `reuse_port`
+    /// only take effect when binding and are up to whoever bound it.
+    pub fn run_on(&self, listener: std::net::TcpListener) -> io::Result<()> {
+        #[cfg(feature = "log")]
+        info!("Feather Runtime Started on {}", listener.local_addr()?);
+
+        set_buffer_sizes(&SockRef::from(&listener), &self.config)?;
+        let listener = TcpListener::new(listener)?;
+        self.serve(listener)
+    }
+
+    /// Runs the accept loop on an already bound listener until shutdown is called.
+    ///
+    /// After a shutdown is requested, in-flight connections are given up to
+    /// `shutdown_timeout_secs` to finish before this method returns.
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        self.accept_loop(listener, |mut stream, service, config, state| {
+            let remote_addr = stream.peer_addr()?;
+            Self::conn_handler(&mut stream, remote_addr, service, config, state)
+        })
+    }
+
+    /// Runs the server over HTTPS until shutdown is called.
+    ///
+    /// Requires the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub fn run_tls(&self, addr: impl ToSocketAddrs, tls: &TlsConfig) -> io::Result<()> {
+        #[cfg(feature = "log")]
+        info!(
+            "Feather Runtime Started on {} (TLS)",
+            addr.to_socket_addrs()?.next().unwrap_or(SocketAddr::from(([0, 0, 0, 0], 443)))
+        );
+
+        let listener = TcpListener::new(self.bind(addr)?)?;
+        self.serve_tls(listener, tls)
+    }
+
+    /// Like [serve](Self::serve), but terminates TLS on every accepted connection.
+    ///
+    /// Requires the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub fn serve_tls(&self, listener: TcpListener, tls: &TlsConfig) -> io::Result<()> {
+        let tls_config = tls.server_config().map_err(io::Error::other)?;
+        self.accept_loop(listener, move |stream, service, config, state| {
+            let remote_addr = stream.peer_addr()?;
+            let conn = rustls::ServerConnection::new(tls_config.clone()).map_err(io::Error::other)?;
+            let mut stream = rustls::StreamOwned::new(conn, stream);
+            let result = Self::conn_handler(&mut stream, remote_addr, service, config, state);
+            stream.conn.send_close_notify();
+            let _ = stream.flush();
+            result
+        })
+    }
+
+    /// Accepts connections and spawns a coroutine running `handler` for each of them.
+    fn accept_loop<H>(&self, listener: TcpListener, handler: H) -> io::Result<()>
+    where
+        H: Fn(TcpStream, ArcService, ServerConfig, Arc<ServerState>) -> io::Result<()> + Clone + Send + 'static,
+    {
+        // Configure coroutine runtime
+        may::config().set_workers(self.config.workers);
+        may::config().set_stack_size(self.config.stack_size);
+        may::config().set_pool_capacity(self.config.pool_capacity);
+        super::blocking::set_max_threads(self.config.blocking_threads);
+        *self.state.local_addr.lock() = Some(listener.local_addr()?);
+        if self.config.auto_scale {
+            self.spawn_pool_scaler();
+        }
+        self.service.on_start();
 
-        while self.running.load(Ordering::SeqCst) {
+        while !self.state.stopping.load(Ordering::SeqCst) {
             match listener.accept() {
                 Ok((stream, addr)) => {
+                    if self.state.stopping.load(Ordering::SeqCst) {
+                        // Most likely the wake-up connection from ServerHandle::shutdown
+                        break;
+                    }
                     #[cfg(feature = "log")]
                     debug!("New connection from {}", addr);
+                    if self.config.tcp_nodelay
+                        && let Err(e) = stream.set_nodelay(true)
+                    {
+                        #[cfg(feature = "log")]
+                        debug!("Failed to set TCP_NODELAY: {}", e);
+                    }
                     let service = self.service.clone();
                     let config = self.config.clone();
+                    let state = self.state.clone();
+                    let guard = ConnectionGuard::new(state.clone());
+                    let handler = handler.clone();
 
                     // Spawn a new coroutine for this connection with panic handling
                     may::go!(move || {
-                        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| Self::conn_handler(stream, service, config)));
-
-                        match result {
-                            Ok(Ok(())) => (), // Connection completed successfully
-                            Ok(Err(e)) => {
-                                #[cfg(feature = "log")]
-                                log::error!("Connection handler error: {}", e);
-                            }
-                            Err(e) => {
-                                let msg = e.downcast_ref::<String>().map(|s| s.as_str()).unwrap_or("Unknown panic");
-                                #[cfg(feature = "log")]
-                                log::error!("Connection handler panic: {}", msg);
-                            }
-                        }
+                        let _guard = guard;
+                        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| handler(stream, service, config, state)));
+                        Self::log_connection_result(result);
                     });
                 }
                 Err(e) => {
@@ -120,15 +275,79 @@ impl Server {
 
         #[cfg(feature = "log")]
         info!("Server shutting down");
+        self.drain();
+        self.service.on_shutdown();
         Ok(())
     }
 
+    /// Logs how a connection handler ended.
+    fn log_connection_result(result: std::thread::Result<io::Result<()>>) {
+        match result {
+            Ok(Ok(())) => (), // Connection completed successfully
+            Ok(Err(e)) => {
+                #[cfg(feature = "log")]
+                log::error!("Connection handler error: {}", e);
+            }
+            Err(e) => {
+                let msg = e.downcast_ref::<String>().map(|s| s.as_str()).unwrap_or("Unknown panic");
+                #[cfg(feature = "log")]
+                log::error!("Connection handler panic: {}", msg);
+            }
+        }
+    }
+
+    /// Resizes the coroutine pool with the number of open connections until shutdown.
+    ///
+    /// `may` fixes its worker threads when the runtime starts, so the pool is what can follow the load:
+    /// it doubles when more connections are open than stacks are kept, and halves back once it is mostly unused.
+    fn spawn_pool_scaler(&self) {
+        let state = self.state.clone();
+        let base = self.config.pool_capacity;
+        std::thread::spawn(move || {
+            let mut capacity = base;
+            while !state.stopping.load(Ordering::SeqCst) {
+                std::thread::sleep(Duration::from_millis(250));
+                let scaled = scaled_pool_capacity(capacity, state.connections.load(Ordering::Relaxed), base);
+                if scaled != capacity {
+                    #[cfg(feature = "log")]
+                    debug!("Coroutine pool capacity {} -> {}", capacity, scaled);
+                    capacity = scaled;
+                    may::config().set_pool_capacity(capacity);
+                }
+            }
+        });
+    }
+
+    /// Waits for in-flight connections to finish, up to the configured shutdown timeout.
+    fn drain(&self) {
+        let deadline = Instant::now() + Duration::from_secs(self.config.shutdown_timeout_secs);
+        while self.state.connections.load(Ordering::SeqCst) > 0 {
+            if Instant::now() >= deadline {
+                #[cfg(feature = "log")]
+                warn!("Shutdown timeout reached with {} connection(s) still open", self.state.connections.load(Ordering::SeqCst));
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
     /// Helper to send basic HTTP errors with proper headers
-    fn send_error(stream: &mut TcpStream, status: StatusCode, message: &str) -> io::Result<()> {
+    fn send_error(stream: &mut impl ConnStream, status: StatusCode, message: &str) -> io::Result<()> {
         let mut response = Response::default();
         response.set_status(status.as_u16());
         response.send_text(message);
+        Self::write_error(stream, response)
+    }
+
+    /// Like [send_error](Self::send_error) once the head of `request` is read, with the body formatted by the service.
+    fn reject(stream: &mut impl ConnStream, service: &ArcService, request: &Request, status: StatusCode, message: &str) -> io::Result<()> {
+        let mut response = Response::default();
+        response.set_status(status.as_u16());
+        service.format_error(request, &mut response, message);
+        Self::write_error(stream, response)
+    }
 
+    fn write_error(stream: &mut impl ConnStream, mut response: Response) -> io::Result<()> {
         // Add standard security headers
         response.add_header("X-Content-Type-Options", "nosniff").ok();
         response.add_header("X-Frame-Options", "DENY").ok();
@@ -136,137 +355,375 @@ impl Server {
         // Always close connection on error
         response.add_header("Connection", "close").ok();
 
-        stream.write_all(&response.to_raw())
+        stream.write_response(&response, &mut BytesMut::new())
+    }
+
+    /// Writes `response` and gives the connection to the handler of a hijacked response.
+    ///
+    /// The handler gets a duplicate of the socket, so the connection stays open when the server drops its own handle.
+    fn hand_over(stream: &mut impl ConnStream, response: &Response, handler: HijackHandler, buffer: &mut BytesMut, head: &mut BytesMut, state: &Arc<ServerState>) -> io::Result<()> {
+        let Some(owned) = stream.hijack() else {
+            Self::send_error(stream, StatusCode::NOT_IMPLEMENTED, "Connection hijacking is not supported on this listener")?;
+            return Ok(());
+        };
+        stream.write_response(response, head)?;
+        stream.flush()?;
+        handler.call(Hijacked {
+            stream: owned,
+            read_ahead: buffer.split().freeze(),
+            shutdown: ShutdownSignal::new(state.clone()),
+        });
+        Ok(())
     }
 
     /// The main coroutine function: reads, dispatches, and manages stream lifecycle.
-    fn conn_handler(mut stream: TcpStream, service: ArcService, config: ServerConfig) -> io::Result<()> {
-        let mut keep_alive = true;
-        let mut pipeline_buffer: Vec<u8> = Vec::new();
-        let remote_addr = stream.local_addr()?;
-        while keep_alive {
-            stream.set_read_timeout(Some(std::time::Duration::from_secs(config.read_timeout_secs)))?;
-
-            let body = pipeline_buffer;
-            pipeline_buffer = Vec::new();
-            // * 1. READ HEADERS
-            let mut buffer = body;
-            let mut temp = [0u8; 4096];
+    ///
+    /// Bytes read past the end of a request are kept for the next one, so a single read may carry a partial
+    /// request or several pipelined ones. Requests are answered in order until either side asks to close.
+    ///
+    /// Every phase has its own timeout so clients trickling bytes can't hold a coroutine forever: the headers must
+    /// arrive within `header_timeout_secs`, body reads may not stall longer than `body_timeout_secs`, and idle
+    /// keep-alive connections are closed after `keep_alive_timeout_secs`.
+    ///
+    /// The read and write buffers come from the worker's [pool] and go back to it when the connection is done.
+    fn conn_handler<S: ConnStream>(stream: &mut S, remote_addr: SocketAddr, service: ArcService, config: ServerConfig, state: Arc<ServerState>) -> io::Result<()> {
+        // Received bytes that don't belong to an answered request yet
+        let mut buffer = pool::take(&config);
+        // Serialized response heads
+        let mut head = pool::take(&config);
+        let result = Self::serve_connection(stream, remote_addr, service, &config, state, &mut buffer, &mut head);
+        pool::give(buffer, &config);
+        pool::give(head, &config);
+        result
+    }
 
-            loop {
-                let prev_len = buffer.len();
-                let n = stream.read(&mut temp)?;
-                if n == 0 {
-                    return Ok(()); // client closed connection, return Ok().
+    fn serve_connection<S: ConnStream>(stream: &mut S, remote_addr: SocketAddr, service: ArcService, config: &ServerConfig, state: Arc<ServerState>, buffer: &mut BytesMut, head: &mut BytesMut) -> io::Result<()> {
+        let read_cap = Duration::from_secs(config.read_timeout_secs);
+        let header_timeout = Duration::from_secs(config.header_timeout_secs);
+        // Clients that stop reading can't block the coroutine in a write forever
+        stream.set_write_timeout(Some(Duration::from_secs(config.write_timeout_secs)))?;
+        // The last response, handed back to the service to be reused
+        let mut spare: Option<Response> = None;
+        let arena = Arc::new(ArenaSlot::default());
+        let connection_id = state.next_connection_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut sequence = 0;
+        let mut bytes_read = 0;
+        let read_size = config.buffer_size.max(512);
+        let mut first = true;
+        loop {
+            // * 1. READ HEADERS
+            // A pipelined request may already be complete in the buffer, only read when it isn't.
+            // The header clock starts on accept, or with the first byte of a follow-up request.
+            let mut header_deadline = (first || !buffer.is_empty()).then(|| Instant::now() + header_timeout);
+            first = false;
+            // Move the leftover of a keep-alive read to the front, reclaiming the space of the requests before it
+            buffer.reserve(read_size);
+            let mut scanned = 0;
+            let header_end = loop {
+                if let Some(end) = find_header_end(&buffer[scanned..]) {
+                    break scanned + end;
                 }
-
-                buffer.extend_from_slice(&temp[..n]);
-
-                // Check for boundary, starting from up to 3 bytes before new data
-                // to catch boundaries split across reads
-                let check_from = prev_len.saturating_sub(3);
-                if buffer[check_from..].windows(4).any(|w| w == b"\r\n\r\n") {
-                    break;
+                // The buffer starts with the current head, so leftovers of the previous request never count
+                if buffer.len() > config.max_header_size {
+                    Self::send_error(stream, StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE, "Headers too large")?;
+                    return Ok(());
                 }
-
-                if buffer.len() > config.max_body_size {
-                    Self::send_error(&mut stream, StatusCode::PAYLOAD_TOO_LARGE, "Headers too large")?;
+                let timeout = match header_deadline {
+                    Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                    None => Duration::from_secs(config.keep_alive_timeout_secs),
+                };
+                if timeout.is_zero() {
+                    Self::send_error(stream, StatusCode::REQUEST_TIMEOUT, "Request header timeout")?;
                     return Ok(());
                 }
-            }
-
-            let header_end = buffer.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
-
-            let headers_raw = &buffer[..header_end];
-            let mut body = buffer[header_end..].to_vec();
+                stream.set_read_timeout(Some(timeout.min(read_cap)))?;
+                // Boundaries can be split across reads
+                scanned = buffer.len().saturating_sub(3);
+                let n = match read_into(stream, buffer, read_size) {
+                    Ok(n) => n,
+                    // Idle keep-alive connections are closed quietly
+                    Err(e) if is_timeout(&e) && header_deadline.is_none() => return Ok(()),
+                    Err(e) if is_timeout(&e) => {
+                        Self::send_error(stream, StatusCode::REQUEST_TIMEOUT, "Request header timeout")?;
+                        return Ok(());
+                    }
+                    Err(e) => return Err(e),
+                };
+                if n == 0 {
+                    return Ok(()); // client closed connection, return Ok().
+                }
+                header_deadline.get_or_insert_with(|| Instant::now() + header_timeout);
+                bytes_read += n as u64;
+            };
 
+            // A head can arrive whole in one read, along with a request before it
+            if header_end > config.max_header_size {
+                Self::send_error(stream, StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE, "Headers too large")?;
+                return Ok(());
+            }
+            let headers_raw = buffer.split_to(header_end);
 
             // * 2. PARSE HEADERS ONLY
-            let temp_request = match Request::parse(headers_raw, Bytes::new(), remote_addr) {
+            let temp_request = match Request::parse_with_max_headers(&headers_raw, Bytes::new(), remote_addr, config.max_headers) {
                 Ok(r) => r,
+                Err(e) if e.is::<TooManyHeaders>() => {
+                    Self::send_error(stream, StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE, "Too many headers")?;
+                    return Ok(());
+                }
                 Err(e) => {
-                    Self::send_error(&mut stream, StatusCode::BAD_REQUEST, &format!("Invalid request: {}", e))?;
+                    Self::send_error(stream, StatusCode::BAD_REQUEST, &format!("Invalid request: {}", e))?;
                     return Ok(());
                 }
             };
-            // * 3. REJECT CHUNKED ENCODING
-            if temp_request.headers.get(http::header::TRANSFER_ENCODING).map(|v| v.as_bytes().eq_ignore_ascii_case(b"chunked")).unwrap_or(false) {
-                Self::send_error(&mut stream, StatusCode::NOT_IMPLEMENTED, "Chunked transfer encoding not supported")?;
+            // * 3. REJECT TRANSFER ENCODINGS
+            // Bodies are only framed by Content-Length. Any coding list (`gzip, chunked`, `chunked `, ...) is refused
+            // instead of parsed, a proxy reading it differently would smuggle a request in the body.
+            if temp_request.headers.contains_key(http::header::TRANSFER_ENCODING) {
+                let (status, message) = match temp_request.headers.contains_key(http::header::CONTENT_LENGTH) {
+                    true => (StatusCode::BAD_REQUEST, "Transfer-Encoding and Content-Length are exclusive"),
+                    false => (StatusCode::NOT_IMPLEMENTED, "Transfer encodings are not supported"),
+                };
+                Self::reject(stream, &service, &temp_request, status, message)?;
                 return Ok(());
             }
 
-
             //* 4. HANDLE CONNECTION HEADER
-            keep_alive = match (temp_request.version, temp_request.headers.get(http::header::CONNECTION)) {
-                (http::Version::HTTP_11, Some(v)) if v.as_bytes().eq_ignore_ascii_case(b"close") => false,
+            // HTTP/1.1 connections persist unless closed, HTTP/1.0 ones only when asked to.
+            let connection = temp_request.headers.get(http::header::CONNECTION).map(|v| v.as_bytes());
+            let mut keep_alive = match (temp_request.version, connection) {
+                (_, Some(v)) if v.eq_ignore_ascii_case(b"close") => false,
                 (http::Version::HTTP_11, _) => true,
+                (http::Version::HTTP_10, Some(v)) => v.eq_ignore_ascii_case(b"keep-alive"),
                 _ => false,
             };
-
-  
-            //* 5. READ BODY (Content-Length) — FIXED
-            let content_length = temp_request.headers.get(http::header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<usize>().ok()).unwrap_or(0);
+            let version = temp_request.version;
+
+            //* 5. READ BODY (Content-Length)
+            let content_length = match temp_request.headers.get(http::header::CONTENT_LENGTH) {
+                None => 0,
+                Some(v) => match v.to_str().ok().and_then(|v| v.trim().parse::<usize>().ok()) {
+                    Some(len) => len,
+                    None => {
+                        // Guessing the length would desync every following request on this connection
+                        Self::reject(stream, &service, &temp_request, StatusCode::BAD_REQUEST, "Invalid Content-Length")?;
+                        return Ok(());
+                    }
+                },
+            };
 
             if content_length > config.max_body_size {
-                Self::send_error(&mut stream, StatusCode::PAYLOAD_TOO_LARGE, "Request body too large")?;
+                Self::reject(stream, &service, &temp_request, StatusCode::PAYLOAD_TOO_LARGE, "Request body too large")?;
                 return Ok(());
             }
 
-            // If we already read more than needed,  save excess for next request
-            if body.len() > content_length {
-                pipeline_buffer = body.split_off(content_length);
+            if buffer.len() < content_length {
+                stream.set_read_timeout(Some(Duration::from_secs(config.body_timeout_secs).min(read_cap)))?;
             }
-
-            while body.len() < content_length {
-                let n = stream.read(&mut temp)?;
+            // The buffer grows to the announced length at once, bounded by the `max_body_size` check above
+            while buffer.len() < content_length {
+                let n = match read_into(stream, buffer, content_length - buffer.len()) {
+                    Ok(n) => n,
+                    Err(e) if is_timeout(&e) => {
+                        Self::reject(stream, &service, &temp_request, StatusCode::REQUEST_TIMEOUT, "Request body timeout")?;
+                        return Ok(());
+                    }
+                    Err(e) => return Err(e),
+                };
                 if n == 0 {
-                    Self::send_error(&mut stream, StatusCode::BAD_REQUEST, "Unexpected EOF while reading request body")?;
+                    Self::reject(
+                        stream,
+                        &service,
+                        &temp_request,
+                        StatusCode::BAD_REQUEST,
+                        "Unexpected EOF while reading request body",
+                    )?;
                     return Ok(());
                 }
-
-                body.extend_from_slice(&temp[..n]);
-            }
-            if body.len() > content_length {
-                pipeline_buffer = body.split_off(content_length);
+                bytes_read += n as u64;
             }
+            // Anything past the body belongs to the next pipelined request
+            let body = buffer.split_to(content_length).freeze();
 
-         
             // * 6. BUILD FINAL REQUEST
-            let request = match Request::parse(headers_raw, Bytes::from(body), remote_addr) {
+            let mut request = match Request::parse_with_max_headers(&headers_raw, body, remote_addr, config.max_headers) {
                 Ok(r) => r,
                 Err(e) => {
-                    Self::send_error(&mut stream, StatusCode::BAD_REQUEST, &format!("Invalid request: {}", e))?;
+                    Self::send_error(stream, StatusCode::BAD_REQUEST, &format!("Invalid request: {}", e))?;
                     return Ok(());
                 }
             };
 
+            if let Some(response) = spare.take() {
+                request.recycle_response(response);
+            }
+            request.set_arena(arena.clone());
+            sequence += 1;
+            request.set_connection_info(ConnectionInfo {
+                id: connection_id,
+                sequence,
+                bytes_read,
+                keep_alive,
+            });
+
             //* 7. DISPATCH RESPONSE
-            let result = service.handle(request, None);
+            let is_head = request.method == http::Method::HEAD;
+            // A panicking service must not leave the client hanging, answer with a 500 and drop the connection.
+            let result = match panic::catch_unwind(panic::AssertUnwindSafe(|| service.handle(request, None))) {
+                Ok(result) => result,
+                Err(_) => {
+                    Self::send_error(stream, StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")?;
+                    return Ok(());
+                }
+            };
 
             match result {
-                Ok(ServiceResult::Response(response)) => {
-                    let raw = response.to_raw();
-                    stream.write_all(&raw)?;
+                Ok(ServiceResult::Response(mut response)) => {
+                    if let Some(handler) = response.take_hijack() {
+                        return Self::hand_over(stream, &response, handler, buffer, head, &state);
+                    }
+                    let body = response.take_stream();
+                    // A body HTTP/1.0 can't frame ends with the connection
+                    if version == http::Version::HTTP_10 && response.frame_for_http10() {
+                        keep_alive = false;
+                    }
+                    // Don't keep connections alive while the server is shutting down
+                    if state.stopping.load(Ordering::SeqCst) {
+                        keep_alive = false;
+                        response.headers.insert(http::header::CONNECTION, http::HeaderValue::from_static("close"));
+                    }
+                    // The service can close the connection too
+                    if response.headers.get(http::header::CONNECTION).is_some_and(|conn| conn.as_bytes().eq_ignore_ascii_case(b"close")) {
+                        keep_alive = false;
+                    } else if !keep_alive {
+                        response.headers.insert(http::header::CONNECTION, http::HeaderValue::from_static("close"));
+                    } else if version == http::Version::HTTP_10 {
+                        response.headers.insert(http::header::CONNECTION, http::HeaderValue::from_static("keep-alive"));
+                    }
+                    // HEAD gets the headers of the GET response but never a body
+                    if is_head {
+                        response.frame_for_head();
+                    }
+                    stream.write_response(&response, head)?;
+                    if let Some(body) = body
+                        && !is_head
+                    {
+                        body.write_to(stream)?;
+                    }
                     stream.flush()?;
                     if !keep_alive {
                         return Ok(());
                     }
-                    if let Some(conn) = response.headers.get(http::header::CONNECTION) {
-                        if conn.as_bytes().eq_ignore_ascii_case(b"close") {
-                            return Ok(());
-                        }
-                    }
+                    spare = Some(response);
                 }
 
                 Ok(ServiceResult::Consumed) => return Ok(()),
 
                 Err(e) => {
-                    Self::send_error(&mut stream, StatusCode::INTERNAL_SERVER_ERROR, &format!("Internal error: {}", e))?;
+                    Self::send_error(stream, StatusCode::INTERNAL_SERVER_ERROR, &format!("Internal error: {}", e))?;
                     return Ok(());
                 }
             }
         }
+    }
+}
 
-        Ok(())
+/// Streams the connection handler can read from with a timeout, plain TCP or TLS over TCP.
+trait ConnStream: Read + Write + Sized {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// An owned handle to the connection for [`Response::hijack`], `None` if it can't be handed over.
+    fn hijack(&self) -> Option<TcpStream> {
+        None
+    }
+
+    /// Writes a whole response, by default as a vectored write without copying the body.
+    fn write_response(&mut self, response: &Response, scratch: &mut BytesMut) -> io::Result<()> {
+        response.write_with(self, scratch)
+    }
+}
+
+impl ConnStream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+
+    fn hijack(&self) -> Option<TcpStream> {
+        self.try_clone().ok()
+    }
+}
+
+#[cfg(feature = "tls")]
+impl ConnStream for rustls::StreamOwned<rustls::ServerConnection, TcpStream> {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.sock.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.sock.set_write_timeout(timeout)
+    }
+
+    /// TLS copies the plaintext into its records anyway, a single write keeps the response in one record.
+    fn write_response(&mut self, response: &Response, scratch: &mut BytesMut) -> io::Result<()> {
+        scratch.clear();
+        response.write_head(scratch);
+        scratch.extend_from_slice(response.body.as_deref().unwrap_or_default());
+        self.write_all(scratch)
+    }
+}
+
+/// Reads at most `max` bytes from `stream` straight into the end of `buffer`.
+fn read_into(stream: &mut impl Read, buffer: &mut BytesMut, max: usize) -> io::Result<usize> {
+    let start = buffer.len();
+    buffer.resize(start + max, 0);
+    let result = stream.read(&mut buffer[start..]);
+    buffer.truncate(start + result.as_ref().map_or(0, |n| *n));
+    result
+}
+
+/// Timed out reads surface as `WouldBlock` on some platforms and `TimedOut` on others.
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+/// Binds a listening socket, `std` offers no way to pick the backlog.
+fn bind_socket(addr: SocketAddr, config: &ServerConfig) -> io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // Same as std: restarting right after a shutdown must not fail on sockets lingering in TIME_WAIT
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))))]
+    socket.set_reuse_port(config.reuse_port)?;
+    // Set before listen() so the TCP window scale of accepted connections accounts for them
+    set_buffer_sizes(&socket, config)?;
+    socket.bind(&addr.into())?;
+    socket.listen(config.backlog)?;
+    Ok(socket.into())
+}
+
+/// Applies the configured kernel buffer sizes to a listener, accepted connections inherit them.
+fn set_buffer_sizes(socket: &Socket, config: &ServerConfig) -> io::Result<()> {
+    if let Some(size) = config.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+    }
+    if let Some(size) = config.recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+    }
+    Ok(())
+}
+
+/// The pool capacity to use with `connections` open, never below `base` or above 8x `base`.
+fn scaled_pool_capacity(capacity: usize, connections: usize, base: usize) -> usize {
+    let max = base.saturating_mul(8);
+    if connections > capacity {
+        capacity.saturating_mul(2).max(connections).min(max)
+    } else if connections < capacity / 4 {
+        (capacity / 2).max(base)
+    } else {
+        capacity
     }
 }
@@ -0,0 +1,224 @@
+//! Local IPC listeners: Unix domain sockets on Unix, named pipes on Windows.
+//!
+//! `may` can't drive these endpoints, so every connection gets its own thread. They are meant for admin
+//! APIs and sidecars, not for public traffic.
+
+#[cfg(feature = "log")]
+use log::{info, warn};
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::{panic, thread};
+
+use super::Server;
+use crate::runtime::handle::ConnectionGuard;
+
+use sys::LocalListener;
+pub(crate) use sys::wake;
+
+impl Server {
+    /// Runs the server on a local IPC endpoint until shutdown is called.
+    ///
+    /// On Unix `path` is the path of a Unix domain socket, which is removed again on shutdown. A socket left
+    /// behind by a crashed server is replaced, one another server still listens on is an `AddrInUse` error.
+    /// On Windows it is the name of a named pipe, like `\\.\pipe\my-app`, that only accepts local clients.
+    /// Either way, requests go through the same service as TCP connections.
+    ///
+    /// Local connections are served by one thread each and report `127.0.0.1:0` as their remote address.
+    /// Named pipes have no read or write timeouts.
+    pub fn run_local(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut listener = LocalListener::bind(path)?;
+        *self.state.local_path.lock() = Some(path.to_path_buf());
+        #[cfg(feature = "log")]
+        info!("Feather Runtime Started on {}", path.display());
+        self.service.on_start();
+
+        while !self.state.stopping.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok(mut stream) => {
+                    if self.state.stopping.load(Ordering::SeqCst) {
+                        // Most likely the wake-up connection from ServerHandle::shutdown
+                        break;
+                    }
+                    let service = self.service.clone();
+                    let config = self.config.clone();
+                    let state = self.state.clone();
+                    let guard = ConnectionGuard::new(state.clone());
+                    thread::spawn(move || {
+                        let _guard = guard;
+                        let remote_addr = SocketAddr::from(([127, 0, 0, 1], 0));
+                        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| Self::conn_handler(&mut stream, remote_addr, service, config, state)));
+                        Self::log_connection_result(result);
+                    });
+                }
+                Err(e) => {
+                    #[cfg(feature = "log")]
+                    warn!("Failed to accept connection: {}", e);
+                }
+            }
+        }
+
+        #[cfg(feature = "log")]
+        info!("Server shutting down");
+        drop(listener);
+        self.drain();
+        self.service.on_shutdown();
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use super::super::ConnStream;
+
+    pub(super) struct LocalListener {
+        listener: UnixListener,
+        path: PathBuf,
+    }
+
+    impl LocalListener {
+        /// Bind to `path`, replacing the socket a crashed server left behind.
+        pub fn bind(path: &Path) -> io::Result<Self> {
+            if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+                // Nobody answering means nobody is listening, any other outcome leaves the file alone
+                match UnixStream::connect(path) {
+                    Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path)?,
+                    Ok(_) => return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("{} is in use by another server", path.display()))),
+                    Err(_) => {}
+                }
+            }
+            Ok(Self {
+                listener: UnixListener::bind(path)?,
+                path: path.to_path_buf(),
+            })
+        }
+
+        pub fn accept(&mut self) -> io::Result<UnixStream> {
+            self.listener.accept().map(|(stream, _)| stream)
+        }
+    }
+
+    impl Drop for LocalListener {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    /// Unblocks a listener waiting in `accept`.
+    pub(crate) fn wake(path: &Path) {
+        let _ = UnixStream::connect(path);
+    }
+
+    impl ConnStream for UnixStream {
+        fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+            UnixStream::set_read_timeout(self, timeout)
+        }
+
+        fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+            UnixStream::set_write_timeout(self, timeout)
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::ffi::OsStr;
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+    use std::path::Path;
+    use std::time::Duration;
+    use std::{iter, mem, ptr};
+
+    use windows_sys::Win32::Foundation::{ERROR_PIPE_CONNECTED, HANDLE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX};
+    use windows_sys::Win32::System::Pipes::{ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT};
+
+    use super::super::ConnStream;
+
+    /// Size of the in and out buffers of a pipe instance, only a hint for the OS.
+    const PIPE_BUFFER_SIZE: u32 = 64 * 1024;
+
+    pub(super) struct LocalListener {
+        name: Vec<u16>,
+        /// The instance clients connect to next
+        next: OwnedHandle,
+    }
+
+    impl LocalListener {
+        pub fn bind(path: &Path) -> io::Result<Self> {
+            let name: Vec<u16> = OsStr::new(path).encode_wide().chain(iter::once(0)).collect();
+            // The first instance fails if another process already owns the name
+            let next = create_instance(&name, true)?;
+            Ok(Self {
+                name,
+                next,
+            })
+        }
+
+        pub fn accept(&mut self) -> io::Result<File> {
+            // SAFETY: `next` is a valid pipe handle owned by the listener, no overlapped I/O is used.
+            if unsafe { ConnectNamedPipe(self.next.as_raw_handle() as HANDLE, ptr::null_mut()) } == 0 {
+                let e = io::Error::last_os_error();
+                // The client connected between creating the instance and this call
+                if e.raw_os_error() != Some(ERROR_PIPE_CONNECTED as i32) {
+                    return Err(e);
+                }
+            }
+            let next = create_instance(&self.name, false)?;
+            Ok(File::from(mem::replace(&mut self.next, next)))
+        }
+    }
+
+    fn create_instance(name: &[u16], first: bool) -> io::Result<OwnedHandle> {
+        let open_mode = if first {
+            PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE
+        } else {
+            PIPE_ACCESS_DUPLEX
+        };
+        // SAFETY: `name` is NUL terminated and outlives the call, null security attributes select the defaults.
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                PIPE_BUFFER_SIZE,
+                PIPE_BUFFER_SIZE,
+                0,
+                ptr::null(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the handle was just created and is owned by nobody else.
+        Ok(unsafe { OwnedHandle::from_raw_handle(handle as _) })
+    }
+
+    /// Unblocks a listener waiting in `accept`.
+    pub(crate) fn wake(path: &Path) {
+        let _ = OpenOptions::new().read(true).write(true).open(path);
+    }
+
+    /// Synchronous pipe handles don't support timeouts.
+    impl ConnStream for File {
+        fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn set_write_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
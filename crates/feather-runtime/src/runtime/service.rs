@@ -16,6 +16,20 @@ pub trait Service: Send + Sync + 'static {
     /// Handles an incoming request, receiving the Request and the underlying stream.
     /// The stream is passed as an `Option` to allow the service to consume it for upgrades.
     fn handle(&self, req: Request, stream: Option<TcpStream>) -> io::Result<ServiceResult>;
+
+    /// Writes the body of an error the server answers itself, after reading the head of `req` but before
+    /// calling [handle](Self::handle), like `413 Payload Too Large` for a body over the size limit.
+    /// The status is already set, `message` says what went wrong. Sent as text by default.
+    fn format_error(&self, req: &Request, res: &mut Response, message: &str) {
+        let _ = req;
+        res.send_text(message);
+    }
+
+    /// Called by the server once it is listening, before the first connection is accepted.
+    fn on_start(&self) {}
+
+    /// Called by the server after a shutdown, once in-flight connections are drained.
+    fn on_shutdown(&self) {}
 }
 
 pub type ArcService = Arc<dyn Service>;
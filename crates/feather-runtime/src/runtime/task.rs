@@ -0,0 +1,35 @@
+//! Background work on the coroutine runtime.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+
+/// Run `task` on a new coroutine without waiting for it.
+///
+/// Once a server started, the coroutine gets the `stack_size` of its [`ServerConfig`](super::server::ServerConfig).
+/// A panicking task is logged and doesn't affect the server. Graceful shutdown doesn't wait for tasks.
+///
+/// Tasks share the workers with the connections, so they should use the `may` aware I/O of the runtime
+/// or finish quickly instead of blocking on long operations.
+pub fn spawn<F>(task: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    may::go!(move || {
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(task)) {
+            #[cfg(feature = "log")]
+            {
+                let msg = payload.downcast_ref::<&str>().copied().or_else(|| payload.downcast_ref::<String>().map(String::as_str)).unwrap_or("Unknown panic");
+                log::error!("Background task panic: {}", msg);
+            }
+            #[cfg(not(feature = "log"))]
+            drop(payload);
+        }
+    });
+}
+
+/// Pause for `duration` without blocking the worker thread when called from a coroutine.
+///
+/// Outside of a coroutine the calling thread sleeps.
+pub fn sleep(duration: Duration) {
+    may::coroutine::sleep(duration);
+}
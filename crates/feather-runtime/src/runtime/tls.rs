@@ -0,0 +1,128 @@
+//! TLS termination backed by [rustls].
+//!
+//! Requires the `tls` feature.
+
+use rustls::InconsistentKeys;
+use rustls::crypto::ring as provider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Errors that can occur while loading certificates or building the TLS configuration.
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("Failed to read TLS file: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid PEM data: {0}")]
+    Pem(#[from] rustls::pki_types::pem::Error),
+    #[error("No certificates found in PEM data")]
+    NoCertificates,
+    #[error("Private key does not match the certificate")]
+    KeyMismatch,
+    #[error("TLS error: {0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+/// Certificates and protocol settings for serving HTTPS.
+///
+/// A `TlsConfig` always has a default certificate. Additional certificates can be registered
+/// per server name, they are selected through SNI (Server Name Indication) during the handshake.
+/// Wildcard names like `*.example.com` are supported.
+///
+/// ALPN advertises `http/1.1` by default.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let tls = TlsConfig::from_pem_files("certs/example.com.pem", "certs/example.com.key")?
+///     .with_sni_cert("api.example.com", api_cert_pem, api_key_pem)?;
+/// server.run_tls("0.0.0.0:443", &tls)?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    default: Arc<CertifiedKey>,
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+    alpn: Vec<Vec<u8>>,
+}
+
+impl TlsConfig {
+    /// Create a config from a PEM encoded certificate chain and private key.
+    pub fn from_pem(cert: impl AsRef<[u8]>, key: impl AsRef<[u8]>) -> Result<Self, TlsError> {
+        Ok(Self {
+            default: certified_key(cert.as_ref(), key.as_ref())?,
+            by_name: HashMap::new(),
+            alpn: vec![b"http/1.1".to_vec()],
+        })
+    }
+
+    /// Create a config from PEM files on disk.
+    pub fn from_pem_files(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> Result<Self, TlsError> {
+        Self::from_pem(std::fs::read(cert)?, std::fs::read(key)?)
+    }
+
+    /// Serve a different certificate to clients that ask for `server_name` through SNI.
+    pub fn with_sni_cert(mut self, server_name: &str, cert: impl AsRef<[u8]>, key: impl AsRef<[u8]>) -> Result<Self, TlsError> {
+        let key = certified_key(cert.as_ref(), key.as_ref())?;
+        self.by_name.insert(server_name.to_ascii_lowercase(), key);
+        Ok(self)
+    }
+
+    /// Set the protocols advertised through ALPN, in order of preference.
+    ///
+    /// Feather only speaks HTTP/1.1, so only change this if a proxy or client needs something specific.
+    pub fn with_alpn(mut self, protocols: &[&str]) -> Self {
+        self.alpn = protocols.iter().map(|p| p.as_bytes().to_vec()).collect();
+        self
+    }
+
+    /// Build the rustls server configuration.
+    pub(crate) fn server_config(&self) -> Result<Arc<rustls::ServerConfig>, TlsError> {
+        let resolver = SniResolver {
+            default: self.default.clone(),
+            by_name: self.by_name.clone(),
+        };
+        let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(provider::default_provider())).with_safe_default_protocol_versions()?.with_no_client_auth().with_cert_resolver(Arc::new(resolver));
+        config.alpn_protocols = self.alpn.clone();
+        Ok(Arc::new(config))
+    }
+}
+
+fn certified_key(cert: &[u8], key: &[u8]) -> Result<Arc<CertifiedKey>, TlsError> {
+    let certs = CertificateDer::pem_slice_iter(cert).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates);
+    }
+    let key = PrivateKeyDer::from_pem_slice(key)?;
+    let signing_key = provider::sign::any_supported_type(&key)?;
+    let key = CertifiedKey::new(certs, signing_key);
+    match key.keys_match() {
+        // Keys that can't tell their public key are accepted, the handshake will fail instead
+        Ok(()) | Err(rustls::Error::InconsistentKeys(InconsistentKeys::Unknown)) => Ok(Arc::new(key)),
+        Err(rustls::Error::InconsistentKeys(InconsistentKeys::KeyMismatch)) => Err(TlsError::KeyMismatch),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Picks the certificate matching the SNI name, falling back to the default one.
+#[derive(Debug)]
+struct SniResolver {
+    default: Arc<CertifiedKey>,
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let found = client_hello.server_name().and_then(|name| {
+            let name = name.to_ascii_lowercase();
+            let wildcard = name.split_once('.').map(|(_, parent)| format!("*.{parent}"));
+            self.by_name.get(&name).or_else(|| wildcard.and_then(|w| self.by_name.get(&w)))
+        });
+        Some(found.unwrap_or(&self.default).clone())
+    }
+}
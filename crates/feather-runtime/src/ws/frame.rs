@@ -0,0 +1,126 @@
+//! The WebSocket frame format, [RFC 6455 section 5](https://www.rfc-editor.org/rfc/rfc6455#section-5).
+
+use bytes::{Buf, BufMut, BytesMut};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum OpCode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl OpCode {
+    fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0x0 => Some(Self::Continuation),
+            0x1 => Some(Self::Text),
+            0x2 => Some(Self::Binary),
+            0x8 => Some(Self::Close),
+            0x9 => Some(Self::Ping),
+            0xA => Some(Self::Pong),
+            _ => None,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            Self::Continuation => 0x0,
+            Self::Text => 0x1,
+            Self::Binary => 0x2,
+            Self::Close => 0x8,
+            Self::Ping => 0x9,
+            Self::Pong => 0xA,
+        }
+    }
+
+    pub(crate) fn is_control(self) -> bool {
+        matches!(self, Self::Close | Self::Ping | Self::Pong)
+    }
+}
+
+/// A frame read from the client, its payload already unmasked.
+pub(crate) struct Frame {
+    pub(crate) fin: bool,
+    pub(crate) opcode: OpCode,
+    pub(crate) payload: BytesMut,
+}
+
+/// A violation of the protocol, answered with a close frame carrying the code.
+#[derive(Debug)]
+pub(crate) struct ProtocolError(pub(crate) u16, pub(crate) &'static str);
+
+impl ProtocolError {
+    pub(crate) const PROTOCOL: u16 = 1002;
+    pub(crate) const INVALID_DATA: u16 = 1007;
+    pub(crate) const TOO_BIG: u16 = 1009;
+}
+
+/// Takes the first frame off `buffer`, `None` if it isn't complete yet.
+///
+/// Client frames must be masked, and payloads longer than `max_payload` are refused before they're read.
+pub(crate) fn parse(buffer: &mut BytesMut, max_payload: usize) -> Result<Option<Frame>, ProtocolError> {
+    if buffer.len() < 2 {
+        return Ok(None);
+    }
+    let (first, second) = (buffer[0], buffer[1]);
+    if first & 0x70 != 0 {
+        return Err(ProtocolError(ProtocolError::PROTOCOL, "reserved bits are set"));
+    }
+    let Some(opcode) = OpCode::from_bits(first & 0x0F) else {
+        return Err(ProtocolError(ProtocolError::PROTOCOL, "unknown opcode"));
+    };
+    let fin = first & 0x80 != 0;
+    if second & 0x80 == 0 {
+        return Err(ProtocolError(ProtocolError::PROTOCOL, "client frames must be masked"));
+    }
+    let (len, offset) = match second & 0x7F {
+        126 if buffer.len() >= 4 => (u16::from_be_bytes([buffer[2], buffer[3]]) as u64, 4),
+        127 if buffer.len() >= 10 => (u64::from_be_bytes(buffer[2..10].try_into().unwrap()), 10),
+        126 | 127 => return Ok(None),
+        len => (len as u64, 2),
+    };
+    if opcode.is_control() && (!fin || len > 125) {
+        return Err(ProtocolError(
+            ProtocolError::PROTOCOL,
+            "control frames can't be fragmented or longer than 125 bytes",
+        ));
+    }
+    let len = match usize::try_from(len) {
+        Ok(len) if len <= max_payload => len,
+        _ => return Err(ProtocolError(ProtocolError::TOO_BIG, "message too big")),
+    };
+    if buffer.len() < offset + 4 + len {
+        return Ok(None);
+    }
+
+    let mask = [buffer[offset], buffer[offset + 1], buffer[offset + 2], buffer[offset + 3]];
+    buffer.advance(offset + 4);
+    let mut payload = buffer.split_to(len);
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok(Some(Frame {
+        fin,
+        opcode,
+        payload,
+    }))
+}
+
+/// Writes the head of an unmasked, unfragmented server frame carrying `len` bytes.
+pub(crate) fn write_head(buffer: &mut BytesMut, opcode: OpCode, len: usize) {
+    buffer.put_u8(0x80 | opcode.bits());
+    match len {
+        0..=125 => buffer.put_u8(len as u8),
+        126..=0xFFFF => {
+            buffer.put_u8(126);
+            buffer.put_u16(len as u16);
+        }
+        _ => {
+            buffer.put_u8(127);
+            buffer.put_u64(len as u64);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! The opening handshake, [RFC 6455 section 4.2](https://www.rfc-editor.org/rfc/rfc6455#section-4.2).
+
+use crate::http::{Request, Response};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use http::{HeaderValue, Method, Version, header};
+use sha1::{Digest, Sha1};
+
+/// Appended to the client's key before hashing it into `Sec-WebSocket-Accept`.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Why a request can't be upgraded, with the status to answer it with.
+pub(crate) enum Refusal {
+    /// Not a WebSocket request at all
+    NotUpgrade,
+    /// Another protocol version than 13
+    Version,
+    BadRequest(&'static str),
+}
+
+/// Checks the upgrade request and fills in the `101 Switching Protocols` response for it.
+pub(crate) fn respond(request: &Request, response: &mut Response) -> Result<(), Refusal> {
+    let has_token = |name, token: &str| request.headers.get_all(name).iter().filter_map(|value| value.to_str().ok()).flat_map(|value| value.split(',')).any(|value| value.trim().eq_ignore_ascii_case(token));
+    if !has_token(header::UPGRADE, "websocket") {
+        return Err(Refusal::NotUpgrade);
+    }
+    if request.method != Method::GET || request.version != Version::HTTP_11 {
+        return Err(Refusal::BadRequest("WebSocket handshakes must be HTTP/1.1 GET requests"));
+    }
+    if !has_token(header::CONNECTION, "upgrade") {
+        return Err(Refusal::BadRequest("Missing `Connection: Upgrade` header"));
+    }
+    if request.headers.get(header::SEC_WEBSOCKET_VERSION).is_none_or(|version| version != "13") {
+        return Err(Refusal::Version);
+    }
+    // The key is 16 random bytes in base64
+    let Some(key) = request.headers.get(header::SEC_WEBSOCKET_KEY).filter(|key| key.len() == 24) else {
+        return Err(Refusal::BadRequest("Missing or invalid `Sec-WebSocket-Key` header"));
+    };
+
+    response.set_status(101);
+    response.headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+    response.headers.insert(header::CONNECTION, HeaderValue::from_static("Upgrade"));
+    // Base64 is always a valid header value
+    response.headers.insert(header::SEC_WEBSOCKET_ACCEPT, HeaderValue::from_str(&accept_key(key.as_bytes())).unwrap());
+    Ok(())
+}
+
+/// `Sec-WebSocket-Accept` for a `Sec-WebSocket-Key`: the base64 SHA-1 of the key and the [GUID].
+///
+/// SHA-1 is broken for signatures, the handshake only uses it to prove the server speaks WebSocket.
+pub(crate) fn accept_key(key: &[u8]) -> String {
+    let digest = Sha1::new().chain_update(key).chain_update(GUID).finalize();
+    STANDARD.encode(digest)
+}
@@ -0,0 +1,62 @@
+//! WebSocket connections, [RFC 6455](https://www.rfc-editor.org/rfc/rfc6455). Requires the `ws` feature.
+//!
+//! [accept] answers the upgrade request and takes the connection over with [`Response::hijack`], the
+//! handler then talks to the client through a [WebSocket] on the connection's coroutine.
+//!
+//! ```rust,ignore
+//! if ws::accept(&request, &mut response, |mut socket| {
+//!     while let Ok(Some(Message::Text(text))) = socket.recv() {
+//!         let _ = socket.send_text(&text);
+//!     }
+//! }) {
+//!     return ServiceResult::Response(response);
+//! }
+//! ```
+
+mod frame;
+mod handshake;
+mod sender;
+mod socket;
+
+pub use sender::{Overflow, TrySendError, WsSender};
+pub use socket::{CloseFrame, Heartbeat, Message, WebSocket};
+
+use crate::http::{Request, Response};
+use http::{HeaderValue, header};
+
+/// Upgrades `request` to a WebSocket, calling `handler` with the socket once `response` is sent.
+///
+/// Returns `false` if `request` isn't a valid WebSocket handshake, `response` is then set to the error to answer with:
+/// `426 Upgrade Required` for plain HTTP requests or other protocol versions, `400 Bad Request` for malformed handshakes.
+pub fn accept(request: &Request, response: &mut Response, handler: impl FnOnce(WebSocket) + Send + 'static) -> bool {
+    match handshake::respond(request, response) {
+        Ok(()) => {
+            // A connection the socket can't be set up on is just closed
+            response.hijack(|connection| {
+                if let Ok(socket) = WebSocket::new(connection) {
+                    handler(socket);
+                }
+            });
+            true
+        }
+        Err(handshake::Refusal::NotUpgrade) => {
+            response.set_status(426).send_text("This endpoint only speaks WebSocket");
+            response.headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+            false
+        }
+        Err(handshake::Refusal::Version) => {
+            response.set_status(426).send_text("Unsupported WebSocket version");
+            response.headers.insert(header::SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+            false
+        }
+        Err(handshake::Refusal::BadRequest(reason)) => {
+            response.set_status(400).send_text(reason);
+            false
+        }
+    }
+}
+
+/// The `Sec-WebSocket-Accept` value the server answers `key` with, for clients and tests.
+pub fn accept_key(key: &str) -> String {
+    handshake::accept_key(key.as_bytes())
+}
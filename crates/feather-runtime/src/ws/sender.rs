@@ -0,0 +1,288 @@
+use super::frame::{self, OpCode};
+use super::socket::{CloseFrame, Message};
+use bytes::{Bytes, BytesMut};
+use may::net::TcpStream;
+use may::sync::{Mutex, mpsc};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::net::Shutdown;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use thiserror::Error;
+
+/// What [`WsSender::try_send`] does when the queue of a client that can't keep up is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Drop the connection, the client can reconnect and catch up.
+    #[default]
+    Close,
+    /// Drop the oldest queued message to make room, for feeds where only the latest state matters.
+    DropOldest,
+}
+
+/// Why [`WsSender::try_send`] didn't queue a message.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TrySendError {
+    #[error("the WebSocket is closing")]
+    Closed,
+    /// The queue was full and the [`Overflow::Close`] policy dropped the connection.
+    #[error("the client couldn't keep up and was disconnected")]
+    Overflowed,
+    /// A ping or pong payload was longer than the 125 bytes of a control frame.
+    #[error("control frames can't be longer than 125 bytes")]
+    TooLarge,
+}
+
+/// The longest payload of a ping, pong or close frame.
+const MAX_CONTROL_PAYLOAD: usize = 125;
+
+/// Sends on a [WebSocket](super::WebSocket) from other coroutines, get one with [`WebSocket::sender`](super::WebSocket::sender).
+///
+/// Clones share the connection, frames from different senders are never interleaved.
+///
+/// [send](Self::send) writes the message before returning, so a client that doesn't read holds it up until
+/// the server's write timeout. [try_send](Self::try_send) queues the message for a writer coroutine instead
+/// and never waits. Queued messages are written in order, direct sends may go out between them.
+#[derive(Clone)]
+pub struct WsSender(Arc<Shared>);
+
+struct Shared {
+    /// Written through while holding `writer`, shut down without it
+    stream: TcpStream,
+    writer: Mutex<Writer>,
+    /// A close frame was sent or queued, later sends fail
+    closing: AtomicBool,
+    /// Sent when the server shuts down
+    shutdown_close: parking_lot::Mutex<CloseFrame>,
+    outbox: parking_lot::Mutex<Outbox>,
+}
+
+struct Writer {
+    /// The frame being sent
+    out: BytesMut,
+    /// No frame may follow a close frame
+    close_sent: bool,
+}
+
+/// The messages of [`WsSender::try_send`] waiting for the writer coroutine.
+struct Outbox {
+    frames: VecDeque<(OpCode, Bytes)>,
+    capacity: usize,
+    overflow: Overflow,
+    /// Wakes the writer coroutine, started by the first `try_send`
+    wake: Option<mpsc::Sender<()>>,
+}
+
+impl WsSender {
+    /// How many messages [try_send](Self::try_send) queues by default before the [Overflow] policy applies.
+    pub const DEFAULT_QUEUE_CAPACITY: usize = 256;
+
+    pub(crate) fn new(stream: TcpStream) -> Self {
+        Self(Arc::new(Shared {
+            stream,
+            writer: Mutex::new(Writer {
+                out: BytesMut::new(),
+                close_sent: false,
+            }),
+            closing: AtomicBool::new(false),
+            shutdown_close: parking_lot::Mutex::new(CloseFrame {
+                code: 1001,
+                reason: "Server shutting down".to_string(),
+            }),
+            outbox: parking_lot::Mutex::new(Outbox {
+                frames: VecDeque::new(),
+                capacity: Self::DEFAULT_QUEUE_CAPACITY,
+                overflow: Overflow::default(),
+                wake: None,
+            }),
+        }))
+    }
+
+    /// Send a message, see [`WebSocket::send`](super::WebSocket::send).
+    ///
+    /// Pings and pongs with more than 125 bytes fail with [`io::ErrorKind::InvalidInput`].
+    pub fn send(&self, message: Message) -> io::Result<()> {
+        match message {
+            Message::Text(text) => self.send_frame(OpCode::Text, text.as_bytes()),
+            Message::Binary(data) => self.send_frame(OpCode::Binary, &data),
+            Message::Ping(data) | Message::Pong(data) if data.len() > MAX_CONTROL_PAYLOAD => Err(io::Error::new(io::ErrorKind::InvalidInput, TrySendError::TooLarge)),
+            Message::Ping(data) => self.send_frame(OpCode::Ping, &data),
+            Message::Pong(data) => self.send_frame(OpCode::Pong, &data),
+            Message::Close(None) => self.close(1000, ""),
+            Message::Close(Some(frame)) => self.close(frame.code, &frame.reason),
+        }
+    }
+
+    /// Send a text message.
+    pub fn send_text(&self, text: &str) -> io::Result<()> {
+        self.send_frame(OpCode::Text, text.as_bytes())
+    }
+
+    /// Send a binary message.
+    pub fn send_binary(&self, data: &[u8]) -> io::Result<()> {
+        self.send_frame(OpCode::Binary, data)
+    }
+
+    /// Queue a message without waiting for the client.
+    ///
+    /// Pings and pongs with more than 125 bytes are refused with [`TrySendError::TooLarge`].
+    /// When the client has [`set_send_queue`](super::WebSocket::set_send_queue) messages waiting already,
+    /// the socket's [Overflow] policy decides between dropping the oldest one and the connection.
+    ///
+    /// ```rust,ignore
+    /// for client in &clients {
+    ///     // A stalled client doesn't hold up the others
+    ///     let _ = client.try_send(Message::Text(price.clone()));
+    /// }
+    /// ```
+    pub fn try_send(&self, message: Message) -> Result<(), TrySendError> {
+        let (opcode, payload): (_, &[u8]) = match &message {
+            Message::Text(text) => (OpCode::Text, text.as_bytes()),
+            Message::Binary(data) => (OpCode::Binary, data),
+            Message::Ping(data) => (OpCode::Ping, data),
+            Message::Pong(data) => (OpCode::Pong, data),
+            Message::Close(frame) => {
+                let frame = frame.as_ref().map_or((1000, ""), |frame| (frame.code, frame.reason.as_str()));
+                return self.queue(OpCode::Close, &close_payload(frame.0, frame.1));
+            }
+        };
+        if opcode.is_control() && payload.len() > MAX_CONTROL_PAYLOAD {
+            return Err(TrySendError::TooLarge);
+        }
+        self.queue(opcode, payload)
+    }
+
+    /// Start the closing handshake, the socket's [recv](super::WebSocket::recv) sees the client's answer.
+    ///
+    /// The reason is cut to the 123 bytes that fit in a close frame, on a character boundary.
+    pub fn close(&self, code: u16, reason: &str) -> io::Result<()> {
+        if self.0.closing.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.lock().write(&self.0.stream, OpCode::Close, &close_payload(code, reason))
+    }
+
+    /// Whether a close frame was sent or queued, later sends fail.
+    pub fn is_closing(&self) -> bool {
+        self.0.closing.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn set_queue(&self, capacity: usize, overflow: Overflow) {
+        let mut outbox = self.0.outbox.lock();
+        outbox.capacity = capacity.max(1);
+        outbox.overflow = overflow;
+    }
+
+    pub(crate) fn set_shutdown_close(&self, frame: CloseFrame) {
+        *self.0.shutdown_close.lock() = frame;
+    }
+
+    /// Sends the close frame for a server shutdown.
+    pub(crate) fn shutdown(&self) {
+        let frame = self.0.shutdown_close.lock().clone();
+        let _ = self.close(frame.code, &frame.reason);
+    }
+
+    pub(crate) fn send_frame(&self, opcode: OpCode, payload: &[u8]) -> io::Result<()> {
+        if self.is_closing() {
+            return Err(closing());
+        }
+        self.lock().write(&self.0.stream, opcode, payload)
+    }
+
+    fn queue(&self, opcode: OpCode, payload: &[u8]) -> Result<(), TrySendError> {
+        let closing = opcode == OpCode::Close;
+        if self.0.closing.fetch_or(closing, Ordering::SeqCst) {
+            return Err(TrySendError::Closed);
+        }
+        let mut frame = BytesMut::with_capacity(payload.len() + 10);
+        frame::write_head(&mut frame, opcode, payload.len());
+        frame.extend_from_slice(payload);
+
+        let mut outbox = self.0.outbox.lock();
+        if outbox.frames.len() >= outbox.capacity {
+            match outbox.overflow {
+                Overflow::DropOldest => drop(outbox.frames.pop_front()),
+                Overflow::Close => {
+                    // A close frame would wait behind the queue, the client gets the connection reset instead
+                    outbox.frames.clear();
+                    self.0.closing.store(true, Ordering::SeqCst);
+                    let _ = self.0.stream.shutdown(Shutdown::Both);
+                    return Err(TrySendError::Overflowed);
+                }
+            }
+        }
+        outbox.frames.push_back((opcode, frame.freeze()));
+        let wake = outbox.wake.get_or_insert_with(|| {
+            let (wake, woken) = mpsc::channel();
+            let shared = Arc::downgrade(&self.0);
+            crate::runtime::spawn(move || drain(shared, woken));
+            wake
+        });
+        let _ = wake.send(());
+        Ok(())
+    }
+
+    /// A coroutine mutex, a sender waiting on a slow client blocks the other senders but not the worker thread.
+    fn lock(&self) -> may::sync::MutexGuard<'_, Writer> {
+        // A sender that panicked mid-frame leaves the stream unusable, which the next write reports
+        self.0.writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Writer {
+    fn write(&mut self, mut stream: &TcpStream, opcode: OpCode, payload: &[u8]) -> io::Result<()> {
+        if self.close_sent {
+            return Err(closing());
+        }
+        self.close_sent = opcode == OpCode::Close;
+        // One write per frame, a head sent on its own could wait for the peer's delayed ACK
+        self.out.clear();
+        frame::write_head(&mut self.out, opcode, payload.len());
+        self.out.extend_from_slice(payload);
+        stream.write_all(&self.out)?;
+        stream.flush()
+    }
+
+    fn write_encoded(&mut self, mut stream: &TcpStream, opcode: OpCode, frame: &[u8]) -> io::Result<()> {
+        if self.close_sent {
+            return Err(closing());
+        }
+        self.close_sent = opcode == OpCode::Close;
+        stream.write_all(frame)?;
+        stream.flush()
+    }
+}
+
+/// The writer coroutine of [`WsSender::try_send`], it ends with the last sender.
+fn drain(shared: Weak<Shared>, woken: mpsc::Receiver<()>) {
+    while woken.recv().is_ok() {
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        loop {
+            let next = shared.outbox.lock().frames.pop_front();
+            let Some((opcode, frame)) = next else {
+                break;
+            };
+            let mut writer = shared.writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if writer.write_encoded(&shared.stream, opcode, &frame).is_err() {
+                // Nothing queued gets through a broken or closed connection
+                shared.outbox.lock().frames.clear();
+                break;
+            }
+        }
+    }
+}
+
+fn close_payload(code: u16, reason: &str) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(2 + reason.len());
+    payload.extend_from_slice(&code.to_be_bytes());
+    // Control frames are limited to 125 bytes, the reason must stay valid UTF-8
+    payload.extend_from_slice(&reason.as_bytes()[..reason.floor_char_boundary(MAX_CONTROL_PAYLOAD - 2)]);
+    payload
+}
+
+fn closing() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "the WebSocket is closing")
+}
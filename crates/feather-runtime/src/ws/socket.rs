@@ -0,0 +1,336 @@
+use super::frame::{self, OpCode, ProtocolError};
+use super::sender::{Overflow, TrySendError, WsSender};
+use crate::http::Hijacked;
+use crate::runtime::{ShutdownHook, ShutdownSignal};
+use bytes::{Bytes, BytesMut};
+use may::net::TcpStream;
+use std::io::{self, Read};
+use std::net::Shutdown;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Hands out [`WebSocket::id`]s.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A message received from or sent to a [WebSocket].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Bytes),
+    /// Answered with a pong automatically.
+    Ping(Bytes),
+    Pong(Bytes),
+    /// The peer closed the connection, with its close code and reason if it sent one.
+    Close(Option<CloseFrame>),
+}
+
+/// The status a connection was closed with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CloseFrame {
+    /// `1000` for a normal closure, see [RFC 6455 section 7.4](https://www.rfc-editor.org/rfc/rfc6455#section-7.4) for the others.
+    pub code: u16,
+    pub reason: String,
+}
+
+/// Pings sent to a quiet peer to find out whether it's still there, see [`WebSocket::set_heartbeat`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Heartbeat {
+    /// How long the connection may be quiet before a ping is sent.
+    pub interval: Duration,
+    /// How many pings in a row may go unanswered before the connection is dropped.
+    pub max_missed: u32,
+}
+
+impl Default for Heartbeat {
+    /// A ping after 30 seconds of silence, dropping the peer after 2 unanswered pings.
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            max_missed: 2,
+        }
+    }
+}
+
+/// A WebSocket connection, accepted with [`accept`](super::accept).
+///
+/// Fragmented messages are reassembled, pings are answered and the closing handshake is handled:
+/// [recv](Self::recv) returns the peer's [`Message::Close`] once, then `None`.
+///
+/// Other coroutines can send on the connection through a [sender](Self::sender) while this one waits in [recv](Self::recv).
+/// Dropping the socket ends the connection, its senders fail from then on.
+///
+/// Peers that go away without closing are found with the [default heartbeat](Heartbeat::default),
+/// [recv](Self::recv) then fails with [`io::ErrorKind::TimedOut`].
+///
+/// When the server shuts down the client gets a close frame, `1001 Going Away` unless
+/// [changed](Self::set_shutdown_close), and [recv](Self::recv) returns its answer then `None`.
+pub struct WebSocket {
+    id: u64,
+    stream: TcpStream,
+    /// Received bytes that aren't a complete frame yet
+    buffer: BytesMut,
+    /// The first frames of a fragmented message
+    partial: Option<(OpCode, BytesMut)>,
+    max_message_size: usize,
+    writer: WsSender,
+    heartbeat: Option<Heartbeat>,
+    /// Pings sent since the peer was last heard from
+    missed: u32,
+    shutdown: ShutdownSignal,
+    /// Sends the close frame on shutdown
+    _on_shutdown: ShutdownHook,
+    /// The closing handshake is done or the connection broke
+    closed: bool,
+}
+
+impl WebSocket {
+    /// Messages larger than this close the connection with `1009 Message Too Big` by default.
+    pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+    pub(crate) fn new(connection: Hijacked) -> io::Result<Self> {
+        // The read timeout paces the heartbeat, the write timeout stays
+        let heartbeat = Heartbeat::default();
+        connection.stream.set_read_timeout(Some(heartbeat.interval))?;
+        let writer = WsSender::new(connection.stream.try_clone()?);
+        let sender = writer.clone();
+        let on_shutdown = connection.shutdown.on_shutdown(move || sender.shutdown());
+        Ok(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            stream: connection.stream,
+            buffer: BytesMut::from(&connection.read_ahead[..]),
+            partial: None,
+            max_message_size: Self::DEFAULT_MAX_MESSAGE_SIZE,
+            writer,
+            heartbeat: Some(heartbeat),
+            missed: 0,
+            shutdown: connection.shutdown,
+            _on_shutdown: on_shutdown,
+            closed: false,
+        })
+    }
+
+    /// Change how quiet peers are pinged, `None` waits for them forever.
+    ///
+    /// ```rust,ignore
+    /// socket.set_heartbeat(Some(Heartbeat { interval: Duration::from_secs(5), max_missed: 3 }))?;
+    /// ```
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) -> io::Result<()> {
+        self.stream.set_read_timeout(heartbeat.map(|heartbeat| heartbeat.interval))?;
+        self.heartbeat = heartbeat;
+        Ok(())
+    }
+
+    /// Change the close frame the client gets when the server shuts down.
+    ///
+    /// ```rust,ignore
+    /// // Service Restart, the client may reconnect
+    /// socket.set_shutdown_close(1012, "Deploying, reconnect in a few seconds");
+    /// ```
+    pub fn set_shutdown_close(&mut self, code: u16, reason: &str) {
+        self.writer.set_shutdown_close(CloseFrame {
+            code,
+            reason: reason.to_string(),
+        });
+    }
+
+    /// Triggered when the server shuts down, for handlers that don't spend their time in [recv](Self::recv).
+    ///
+    /// ```rust,ignore
+    /// while !socket.shutdown_signal().is_triggered() {
+    ///     socket.send_text(&next_tick())?;
+    ///     may::coroutine::sleep(Duration::from_secs(1));
+    /// }
+    /// ```
+    pub fn shutdown_signal(&self) -> &ShutdownSignal {
+        &self.shutdown
+    }
+
+    /// A number no other socket of this process has, to key registries of connections by.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// A handle that sends on this connection from anywhere, like another client's coroutine.
+    pub fn sender(&self) -> WsSender {
+        self.writer.clone()
+    }
+
+    /// Change how many messages [try_send](Self::try_send) queues for a client that doesn't keep up,
+    /// and what happens when it's full. By default [`WsSender::DEFAULT_QUEUE_CAPACITY`] and [`Overflow::Close`].
+    pub fn set_send_queue(&mut self, capacity: usize, overflow: Overflow) {
+        self.writer.set_queue(capacity, overflow);
+    }
+
+    /// Change the largest message [recv](Self::recv) accepts.
+    pub fn set_max_message_size(&mut self, size: usize) {
+        self.max_message_size = size;
+    }
+
+    /// Wait for the next message. `None` once the connection is closed.
+    ///
+    /// A client that breaks the protocol, like sending unmasked frames or invalid UTF-8 text, gets a close
+    /// frame with the matching code and the call fails with [`io::ErrorKind::InvalidData`].
+    ///
+    /// ```rust,ignore
+    /// while let Some(message) = socket.recv()? {
+    ///     if let Message::Text(text) = message {
+    ///         socket.send_text(&text)?;
+    ///     }
+    /// }
+    /// ```
+    pub fn recv(&mut self) -> io::Result<Option<Message>> {
+        while !self.closed {
+            let frame = match frame::parse(&mut self.buffer, self.max_message_size) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    self.fill()?;
+                    continue;
+                }
+                Err(error) => return Err(self.fail(error)),
+            };
+            match self.on_frame(frame) {
+                Ok(Some(message)) => return Ok(Some(message)),
+                Ok(None) => {}
+                Err(error) => return Err(self.fail(error)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Send a message. Sending [`Message::Close`] starts the closing handshake like [close](Self::close).
+    pub fn send(&mut self, message: Message) -> io::Result<()> {
+        self.writer.send(message)
+    }
+
+    /// Send a text message.
+    pub fn send_text(&mut self, text: &str) -> io::Result<()> {
+        self.writer.send_text(text)
+    }
+
+    /// Send a binary message.
+    pub fn send_binary(&mut self, data: &[u8]) -> io::Result<()> {
+        self.writer.send_binary(data)
+    }
+
+    /// Queue a message without waiting for the client, see [`WsSender::try_send`].
+    pub fn try_send(&self, message: Message) -> Result<(), TrySendError> {
+        self.writer.try_send(message)
+    }
+
+    /// Start the closing handshake. Keep calling [recv](Self::recv) until it returns `None` to let the client answer.
+    pub fn close(&mut self, code: u16, reason: &str) -> io::Result<()> {
+        self.writer.close(code, reason)
+    }
+
+    /// Whether the closing handshake is done or the connection broke.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    fn on_frame(&mut self, frame: frame::Frame) -> Result<Option<Message>, ProtocolError> {
+        let (opcode, payload) = match (frame.opcode, self.partial.take()) {
+            (OpCode::Ping, partial) => {
+                self.partial = partial;
+                let _ = self.writer.send_frame(OpCode::Pong, &frame.payload);
+                return Ok(Some(Message::Ping(frame.payload.freeze())));
+            }
+            (OpCode::Pong, partial) => {
+                self.partial = partial;
+                return Ok(Some(Message::Pong(frame.payload.freeze())));
+            }
+            (OpCode::Close, _) => return self.on_close(frame.payload).map(|frame| Some(Message::Close(frame))),
+            (OpCode::Text | OpCode::Binary, Some(_)) => return Err(ProtocolError(ProtocolError::PROTOCOL, "expected a continuation frame")),
+            (OpCode::Continuation, None) => return Err(ProtocolError(ProtocolError::PROTOCOL, "unexpected continuation frame")),
+            (OpCode::Continuation, Some((opcode, mut payload))) => {
+                if payload.len() + frame.payload.len() > self.max_message_size {
+                    return Err(ProtocolError(ProtocolError::TOO_BIG, "message too big"));
+                }
+                payload.unsplit(frame.payload);
+                (opcode, payload)
+            }
+            (opcode, None) => (opcode, frame.payload),
+        };
+        if !frame.fin {
+            self.partial = Some((opcode, payload));
+            return Ok(None);
+        }
+        match opcode {
+            OpCode::Text => match String::from_utf8(payload.to_vec()) {
+                Ok(text) => Ok(Some(Message::Text(text))),
+                Err(_) => Err(ProtocolError(ProtocolError::INVALID_DATA, "text messages must be UTF-8")),
+            },
+            _ => Ok(Some(Message::Binary(payload.freeze()))),
+        }
+    }
+
+    fn on_close(&mut self, payload: BytesMut) -> Result<Option<CloseFrame>, ProtocolError> {
+        let frame = match payload.len() {
+            0 => None,
+            1 => return Err(ProtocolError(ProtocolError::PROTOCOL, "invalid close frame")),
+            _ => match String::from_utf8(payload[2..].to_vec()) {
+                Ok(reason) => Some(CloseFrame {
+                    code: u16::from_be_bytes([payload[0], payload[1]]),
+                    reason,
+                }),
+                Err(_) => return Err(ProtocolError(ProtocolError::INVALID_DATA, "close reasons must be UTF-8")),
+            },
+        };
+        // Answer the peer's close frame, which does nothing if it answers ours
+        let code = frame.as_ref().map_or(1000, |frame| frame.code);
+        let _ = self.writer.close(code, "");
+        self.closed = true;
+        Ok(frame)
+    }
+
+    /// Closes the connection with the code of a protocol violation.
+    fn fail(&mut self, error: ProtocolError) -> io::Error {
+        let _ = self.writer.close(error.0, error.1);
+        self.closed = true;
+        io::Error::new(io::ErrorKind::InvalidData, error.1)
+    }
+
+    /// Reads more bytes into the buffer, a connection closed without a close frame counts as closed.
+    fn fill(&mut self) -> io::Result<()> {
+        let start = self.buffer.len();
+        self.buffer.resize(start + 4096, 0);
+        let result = self.stream.read(&mut self.buffer[start..]);
+        self.buffer.truncate(start + result.as_ref().map_or(0, |n| *n));
+        match result {
+            Ok(0) => {
+                self.closed = true;
+                Ok(())
+            }
+            Ok(_) => {
+                self.missed = 0;
+                Ok(())
+            }
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) && self.heartbeat.is_some() => self.beat(),
+            Err(e) => {
+                self.closed = true;
+                Err(e)
+            }
+        }
+    }
+
+    /// Pings the quiet peer, or gives up on it after too many unanswered pings.
+    fn beat(&mut self) -> io::Result<()> {
+        let max_missed = self.heartbeat.map_or(0, |heartbeat| heartbeat.max_missed);
+        if self.missed >= max_missed {
+            // No close frame, a peer that's gone wouldn't read it and the write could block until the write timeout
+            self.closed = true;
+            let _ = self.stream.shutdown(Shutdown::Both);
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "the peer stopped answering pings"));
+        }
+        self.missed += 1;
+        // Fails while closing, the missed pings then bound the wait for the peer's close frame
+        let _ = self.writer.send_frame(OpCode::Ping, b"");
+        Ok(())
+    }
+}
+
+impl Drop for WebSocket {
+    fn drop(&mut self) {
+        // The senders hold their own handle on the stream, which would keep the connection open
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
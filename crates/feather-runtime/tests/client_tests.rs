@@ -0,0 +1,183 @@
+#![cfg(feature = "client")]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use feather_runtime::client::{Cancel, Client, ClientError, ClientRequest};
+use http::header::AUTHORIZATION;
+use http::{HeaderValue, Method, StatusCode};
+
+/// Answers one connection per canned response, passing on the requests it read.
+fn serve(responses: Vec<Vec<u8>>) -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for response in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+            let mut stream = reader.into_inner();
+            stream.write_all(&response).unwrap();
+            let _ = sender.send(request);
+        }
+    });
+    (url, receiver)
+}
+
+fn canned(response: &str) -> Vec<u8> {
+    response.as_bytes().to_vec()
+}
+
+#[test]
+fn test_bodies_are_read_by_their_framing() {
+    let (url, requests) = serve(vec![
+        canned("HTTP/1.1 200 OK\r\nContent-Length: 5\r\nX-Trace: 1\r\n\r\nhello"),
+        canned("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3;ext=1\r\nhel\r\n2\r\nlo\r\n0\r\nTrailer: x\r\n\r\n"),
+        canned("HTTP/1.0 404 Not Found\r\n\r\nuntil the end"),
+    ]);
+    let client = Client::new();
+
+    let response = client.send(&ClientRequest::get(format!("{url}/first?page=2"))).unwrap();
+    assert_eq!((response.status, &response.body[..]), (StatusCode::OK, &b"hello"[..]));
+    assert_eq!(response.headers["x-trace"], "1");
+    let request = requests.recv().unwrap();
+    assert!(request.starts_with("GET /first?page=2 HTTP/1.1\r\n"), "{request}");
+    assert!(request.contains(&format!("host: {}\r\n", url.trim_start_matches("http://"))), "{request}");
+
+    let response = client.send(&ClientRequest::post(format!("{url}/second"), "name=ada")).unwrap();
+    assert_eq!(&response.body[..], b"hello");
+    let request = requests.recv().unwrap();
+    assert!(
+        request.starts_with("POST /second HTTP/1.1\r\n") && request.ends_with("content-length: 8\r\n\r\nname=ada"),
+        "{request}"
+    );
+
+    let response = client.send(&ClientRequest::get(&url)).unwrap();
+    assert_eq!((response.status, &response.body[..]), (StatusCode::NOT_FOUND, &b"until the end"[..]));
+}
+
+#[test]
+fn test_bodies_over_the_limit_are_an_error() {
+    let (url, _requests) = serve(vec![
+        canned("HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\nhello world"),
+        canned("HTTP/1.1 200 OK\r\n\r\nhello world"),
+        canned("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nhello \r\n5\r\nworld\r\n0\r\n\r\n"),
+        canned("HTTP/1.1 200 OK\r\n\r\nhello"),
+    ]);
+    let client = Client::new().max_body(5);
+    for _ in 0..3 {
+        assert!(matches!(
+            client.send(&ClientRequest::get(&url)),
+            Err(ClientError::TooLarge {
+                limit: 5
+            })
+        ));
+    }
+    // Exactly at the limit is fine
+    assert_eq!(&client.send(&ClientRequest::get(&url)).unwrap().body[..], b"hello");
+}
+
+#[test]
+fn test_invalid_responses_are_an_error() {
+    let (url, _requests) = serve(vec![canned("HTTP/1.1 OK\r\n\r\n"), canned("HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort")]);
+    let client = Client::new();
+    assert!(matches!(client.send(&ClientRequest::get(&url)), Err(ClientError::InvalidResponse(_))));
+    assert!(matches!(client.send(&ClientRequest::get(&url)), Err(ClientError::InvalidResponse(_))));
+}
+
+#[test]
+fn test_redirects_are_followed_without_credentials_for_other_hosts() {
+    let (other, other_requests) = serve(vec![canned("HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\ndone")]);
+    let (url, requests) = serve(vec![
+        canned("HTTP/1.1 303 See Other\r\nLocation: /created\r\nContent-Length: 0\r\n\r\n"),
+        canned(&format!("HTTP/1.1 307 Temporary Redirect\r\nLocation: {other}/moved\r\nContent-Length: 0\r\n\r\n")),
+    ]);
+    let request = ClientRequest::post(format!("{url}/users"), "name=ada").header(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+    let response = Client::new().send(&request).unwrap();
+    assert_eq!(&response.body[..], b"done");
+
+    assert!(requests.recv().unwrap().starts_with("POST /users "));
+    // 303 turns the request into a GET on the same host, which keeps the credentials
+    let request = requests.recv().unwrap();
+    assert!(
+        request.starts_with("GET /created ") && request.contains("authorization: Bearer secret"),
+        "{request}"
+    );
+    let request = other_requests.recv().unwrap();
+    assert!(request.starts_with("GET /moved ") && !request.contains("authorization"), "{request}");
+}
+
+#[test]
+fn test_redirects_are_limited() {
+    let redirect = "HTTP/1.1 302 Found\r\nLocation: /again\r\nContent-Length: 0\r\n\r\n";
+    let (url, _requests) = serve(vec![canned(redirect), canned(redirect), canned(redirect)]);
+    assert!(matches!(
+        Client::new().max_redirects(1).send(&ClientRequest::get(&url)),
+        Err(ClientError::Redirect(_))
+    ));
+    let response = Client::new().max_redirects(0).send(&ClientRequest::get(&url)).unwrap();
+    assert_eq!(response.status, StatusCode::FOUND);
+}
+
+#[test]
+fn test_requests_time_out_and_cancel() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (accepted, connections) = mpsc::channel();
+    // Holds the connections open without answering
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let _ = accepted.send(stream.unwrap());
+        }
+    });
+
+    let request = ClientRequest::get(&url).timeout(Duration::from_millis(100));
+    assert!(matches!(Client::new().send(&request), Err(ClientError::TimedOut)));
+    drop(connections.recv().unwrap());
+
+    let cancel = Cancel::new();
+    let (done, result) = mpsc::channel();
+    let waiting = cancel.clone();
+    let client = thread::spawn(move || {
+        let request = ClientRequest::new(Method::GET, url);
+        let _ = done.send(Client::new().send_cancellable(&request, &waiting));
+    });
+    let _connection = connections.recv().unwrap();
+    assert!(result.try_recv().is_err());
+    cancel.cancel();
+    assert!(matches!(result.recv().unwrap(), Err(ClientError::Cancelled)));
+    client.join().unwrap();
+    assert!(matches!(
+        Client::new().send_cancellable(&ClientRequest::get("http://127.0.0.1:1/"), &cancel),
+        Err(ClientError::Cancelled)
+    ));
+}
+
+#[test]
+fn test_https_needs_the_tls_feature() {
+    let result = Client::new().send(&ClientRequest::get("https://127.0.0.1:1/"));
+    if cfg!(feature = "tls") {
+        assert!(matches!(result, Err(ClientError::Io(_))));
+    } else {
+        assert!(matches!(result, Err(ClientError::TlsUnavailable)));
+    }
+}
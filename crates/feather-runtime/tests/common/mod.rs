@@ -1,12 +1,11 @@
-
-
 use bytes::Bytes;
-use feather_runtime::http::{Request, Response};
+use feather_runtime::http::{Request, Response, find_header_end};
 use feather_runtime::runtime::service::{Service, ServiceResult};
 use may::net::TcpStream;
 use std::io;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
+#[allow(dead_code)]
 pub const ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0000);
 /// A simple echo service for testing
 #[allow(dead_code)]
@@ -37,7 +36,7 @@ pub fn create_test_request(method: &str, path: &str, body: &[u8]) -> Vec<u8> {
 
 #[allow(dead_code)]
 pub fn split_request(buf: &[u8]) -> (&[u8], Bytes) {
-    let header_end = buf.windows(4).position(|w| w == b"\r\n\r\n").map(|p| p + 4).unwrap_or(buf.len());
+    let header_end = find_header_end(buf).unwrap_or(buf.len());
 
     let headers = &buf[..header_end];
     let body = Bytes::copy_from_slice(&buf[header_end..]);
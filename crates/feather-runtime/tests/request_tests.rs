@@ -1,10 +1,11 @@
 use bytes::Bytes;
-use feather_runtime::http::Request;
+use feather_runtime::Method;
+use feather_runtime::http::{Request, TooManyHeaders, find_header_end};
 mod common;
 use common::ADDR;
 
 pub fn split_request(buf: &[u8]) -> (&[u8], Bytes) {
-    let header_end = buf.windows(4).position(|w| w == b"\r\n\r\n").map(|p| p + 4).unwrap_or(buf.len());
+    let header_end = find_header_end(buf).unwrap_or(buf.len());
 
     let headers = &buf[..header_end];
     let body = Bytes::copy_from_slice(&buf[header_end..]);
@@ -79,3 +80,41 @@ fn test_valid_http_methods() {
         assert_eq!(request.method.as_str(), *method);
     }
 }
+
+#[test]
+fn test_parse_with_max_headers() {
+    let headers: String = (0..100).map(|i| format!("X-Header-{i}: {i}\r\n")).collect();
+    let raw = format!("GET / HTTP/1.1\r\n{headers}\r\n");
+    let error = Request::parse(raw.as_bytes(), Bytes::new(), ADDR).unwrap_err();
+    assert!(error.is::<TooManyHeaders>());
+    let request = Request::parse_with_max_headers(raw.as_bytes(), Bytes::new(), ADDR, 100).unwrap();
+    assert_eq!(request.headers.len(), 100);
+}
+
+#[test]
+fn test_builder_sets_every_part() {
+    let request = Request::builder().method(Method::POST).uri("/users/7?notify=true").header("Authorization", "Bearer token").body("name=Ada").param("id", "7").extension(42u32).remote_addr(ADDR).build().unwrap();
+    assert_eq!(request.method, Method::POST);
+    assert_eq!(request.path().as_ref(), "/users/7");
+    assert_eq!(request.query().unwrap().get("notify").unwrap(), "true");
+    assert_eq!(request.headers.get("authorization").unwrap(), "Bearer token");
+    assert_eq!(request.headers.get("content-length").unwrap(), "8");
+    assert_eq!(*request.body, *b"name=Ada");
+    assert_eq!(request.param("id"), Some("7"));
+    assert_eq!(request.extensions.get::<u32>(), Some(&42));
+
+    let default = Request::builder().build().unwrap();
+    assert_eq!(default.method, Method::GET);
+    assert_eq!(default.path().as_ref(), "/");
+
+    assert!(Request::builder().uri("not a uri").header("X-Ok", "1").build().is_err());
+    assert!(Request::builder().header("Bad Name", "value").build().is_err());
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_builder_json_body() {
+    let request = Request::builder().method(Method::POST).json(&serde_json::json!({ "user": "ada" })).build().unwrap();
+    assert_eq!(request.headers.get("content-type").unwrap(), "application/json");
+    assert_eq!(request.json().unwrap()["user"], "ada");
+}
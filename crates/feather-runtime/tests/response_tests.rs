@@ -80,3 +80,97 @@ fn test_response_headers_case_insensitivity() {
     assert!(raw_lower.contains("content-type: text/plain"));
     assert!(raw_lower.contains("content-length: 5"));
 }
+
+#[test]
+fn test_send_file_advertises_ranges_and_etag() {
+    let path = std::env::temp_dir().join(format!("feather-send-file-{}.txt", std::process::id()));
+    std::fs::write(&path, b"file contents").unwrap();
+
+    let mut response = Response::default();
+    response.send_file(std::fs::File::open(&path).unwrap());
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(response.headers.get("content-length").unwrap(), "13");
+    assert_eq!(response.headers.get("accept-ranges").unwrap(), "bytes");
+    assert!(response.headers.get("etag").unwrap().to_str().unwrap().starts_with("W/\"d-"));
+    assert!(response.headers.get("last-modified").unwrap().to_str().unwrap().ends_with(" GMT"));
+}
+
+#[test]
+fn test_reset_keeps_header_capacity() {
+    let mut response = Response::default();
+    response.set_status(404);
+    response.add_header("X-Custom", "value").unwrap();
+    response.send_text("Not Found");
+    let capacity = response.headers.capacity();
+
+    response.reset();
+    assert_eq!(response.status.as_u16(), 200);
+    assert!(response.headers.is_empty());
+    assert!(response.body.is_none());
+    assert_eq!(response.headers.capacity(), capacity);
+}
+
+/// A writer that accepts at most 7 bytes per call, to exercise partial vectored writes.
+struct Trickle(Vec<u8>);
+
+impl std::io::Write for Trickle {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(7);
+        self.0.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_to_matches_to_raw() {
+    let mut response = Response::default();
+    response.add_header("Date", "Thu, 01 Jan 2026 00:00:00 GMT").unwrap();
+    response.send_text("a body that spans several partial writes");
+
+    let mut out = Trickle(Vec::new());
+    response.write_to(&mut out).unwrap();
+    assert_eq!(out.0, response.to_raw());
+}
+
+#[test]
+fn test_status_without_canonical_reason() {
+    let mut response = Response::default();
+    response.set_status(499);
+    assert!(response.to_raw().starts_with(b"HTTP/1.1 499 \r\n"));
+
+    response.set_status_with_reason(799, "Upstream Retry").unwrap();
+    assert_eq!(response.reason(), "Upstream Retry");
+    assert!(response.to_raw().starts_with(b"HTTP/1.1 799 Upstream Retry\r\n"));
+
+    // A reason can't smuggle a header, the status stays as it was
+    assert!(response.set_status_with_reason(200, "OK\r\nX-Injected: 1").is_err());
+    assert_eq!(response.status.as_u16(), 799);
+
+    // Setting a status again drops the custom reason
+    response.set_status(404);
+    assert!(response.to_raw().starts_with(b"HTTP/1.1 404 Not Found\r\n"));
+}
+
+#[test]
+fn test_send_reader_streams_until_a_body_replaces_it() {
+    let mut response = Response::default();
+    response.send_reader(std::io::Cursor::new(b"hello world".to_vec()), 5, 2);
+    assert_eq!(response.headers.get("content-length").unwrap(), "5");
+    assert_eq!(response.body, None);
+    assert_eq!(response.take_stream().unwrap().into_bytes().unwrap(), "hello");
+    assert!(response.take_stream().is_none());
+
+    response.send_reader(std::io::Cursor::new(b"hello".to_vec()), 5, 2);
+    response.send_text("error");
+    assert!(response.take_stream().is_none());
+    assert!(Response::default().take_stream().is_none());
+
+    // Lengths past 4 GiB are sent whole on 32-bit targets too
+    response.send_reader(std::io::empty(), 5_000_000_000, 64 * 1024);
+    assert_eq!(response.headers.get("content-length").unwrap(), "5000000000");
+}
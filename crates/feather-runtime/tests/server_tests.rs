@@ -0,0 +1,709 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+mod common;
+
+use common::{EchoService, create_test_request};
+use feather_runtime::http::{Request, Response};
+use feather_runtime::runtime::server::ServerConfig;
+use feather_runtime::runtime::service::ServiceResult;
+use feather_runtime::runtime::{Server, Service};
+
+fn start_server() -> (feather_runtime::runtime::ServerHandle, thread::JoinHandle<std::io::Result<()>>) {
+    start_server_with(ServerConfig {
+        shutdown_timeout_secs: 2,
+        ..ServerConfig::default()
+    })
+}
+
+fn start_server_with(config: ServerConfig) -> (feather_runtime::runtime::ServerHandle, thread::JoinHandle<std::io::Result<()>>) {
+    let server = Server::with_config(EchoService, config);
+    let handle = server.handle();
+    let thread = thread::spawn(move || server.run("127.0.0.1:0"));
+    while handle.local_addr().is_none() {
+        thread::sleep(Duration::from_millis(5));
+    }
+    (handle, thread)
+}
+
+#[test]
+fn test_graceful_shutdown_stops_server() {
+    let (handle, thread) = start_server();
+    let addr = handle.local_addr().unwrap();
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&create_test_request("POST", "/", b"before shutdown")).unwrap();
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("Echo: before shutdown"));
+    drop(stream);
+
+    handle.shutdown();
+    assert!(!handle.is_running());
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_run_on_prebound_listener() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::with_config(EchoService, ServerConfig::default());
+    let handle = server.handle();
+    let thread = thread::spawn(move || server.run_on(listener));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(&create_test_request("POST", "/", b"prebound")).unwrap();
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("Echo: prebound"));
+    drop(stream);
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_head_response_has_no_body() {
+    let (handle, thread) = start_server();
+    let addr = handle.local_addr().unwrap();
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"HEAD / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let mut raw = String::new();
+    stream.read_to_string(&mut raw).unwrap();
+    let lower = raw.to_lowercase();
+    assert!(lower.contains("content-length: 6"));
+    assert!(raw.ends_with("\r\n\r\n"));
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_pipelined_requests_in_one_write() {
+    let (handle, thread) = start_server();
+    let addr = handle.local_addr().unwrap();
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut pipelined = create_test_request("POST", "/", b"first");
+    pipelined.extend_from_slice(&create_test_request("POST", "/", b"second"));
+    pipelined.extend_from_slice(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    stream.write_all(&pipelined).unwrap();
+
+    let mut raw = String::new();
+    stream.read_to_string(&mut raw).unwrap();
+    assert_eq!(raw.matches("HTTP/1.1 200 OK").count(), 3);
+    let first = raw.find("Echo: first").unwrap();
+    let second = raw.find("Echo: second").unwrap();
+    assert!(first < second);
+    assert!(raw.to_lowercase().contains("connection: close"));
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_transfer_encodings_are_rejected_and_close_the_connection() {
+    let (handle, thread) = start_server();
+    let addr = handle.local_addr().unwrap();
+    let smuggled = "0\r\n\r\nGET /admin HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+    // Without a length the encoding isn't implemented, with one the framing is ambiguous
+    let encodings = ["chunked", "Chunked ", "gzip, chunked", "identity, chunked", "identity"];
+    let cases = encodings.map(|encoding| (encoding, None, "501")).into_iter().chain([("chunked", Some(smuggled.len()), "400"), ("gzip, chunked", Some(5), "400")]);
+    for (encoding, length, status) in cases {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let length = length.map(|len| format!("Content-Length: {len}\r\n")).unwrap_or_default();
+        let request = format!("POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: {encoding}\r\n{length}\r\n{smuggled}");
+        stream.write_all(request.as_bytes()).unwrap();
+
+        // The server answers once and closes, nothing after the head is read as another request
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).unwrap();
+        assert!(raw.starts_with(&format!("HTTP/1.1 {status}")), "{encoding:?}: {raw}");
+        assert_eq!(raw.matches("HTTP/1.1").count(), 1, "{encoding:?}: {raw}");
+        assert!(raw.to_lowercase().contains("connection: close"));
+    }
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_request_split_across_writes() {
+    let (handle, thread) = start_server();
+    let addr = handle.local_addr().unwrap();
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let request = create_test_request("POST", "/", b"split body");
+    let (head, tail) = request.split_at(20);
+    stream.write_all(head).unwrap();
+    stream.flush().unwrap();
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(tail).unwrap();
+
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("Echo: split body"));
+    drop(stream);
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_keep_alive_head_split_across_reads() {
+    let (handle, thread) = start_server();
+    let addr = handle.local_addr().unwrap();
+
+    let first = create_test_request("POST", "/", b"first");
+    let mut second = b"POST / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 6\r\n\r\n".to_vec();
+    let head_len = second.len();
+    second.extend_from_slice(b"second");
+    // The second head ends in the first read at every offset, including inside its blank line
+    for split in [1, 10, head_len - 4, head_len - 3, head_len - 2, head_len - 1, head_len, head_len + 3] {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut start = first.clone();
+        start.extend_from_slice(&second[..split]);
+        stream.write_all(&start).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        stream.write_all(&second[split..]).unwrap();
+
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).unwrap();
+        assert_eq!(raw.matches("HTTP/1.1 200 OK").count(), 2, "split at {split}: {raw}");
+        assert!(raw.ends_with("Echo: second"), "split at {split}: {raw}");
+    }
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_slow_headers_time_out() {
+    let (handle, thread) = start_server_with(ServerConfig {
+        header_timeout_secs: 1,
+        shutdown_timeout_secs: 2,
+        ..ServerConfig::default()
+    });
+    let addr = handle.local_addr().unwrap();
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: local").unwrap();
+    let mut raw = String::new();
+    stream.read_to_string(&mut raw).unwrap();
+    assert!(raw.starts_with("HTTP/1.1 408"));
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_idle_keep_alive_connection_is_closed() {
+    let (handle, thread) = start_server_with(ServerConfig {
+        keep_alive_timeout_secs: 1,
+        shutdown_timeout_secs: 2,
+        ..ServerConfig::default()
+    });
+    let addr = handle.local_addr().unwrap();
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    stream.write_all(&create_test_request("POST", "/", b"once")).unwrap();
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("Echo: once"));
+    // The server hangs up once the keep-alive timeout passes
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+/// Reports how much memory the request arena already held when the request arrived.
+struct ArenaService;
+
+impl Service for ArenaService {
+    fn handle(&self, req: Request, _stream: Option<may::net::TcpStream>) -> std::io::Result<ServiceResult> {
+        let held = req.arena().allocated_bytes();
+        req.arena().alloc_slice_fill_copy(1024, 0u8);
+        let mut response = Response::default();
+        response.send_text(format!("held {}", held > 0));
+        Ok(ServiceResult::Response(response))
+    }
+}
+
+#[test]
+fn test_arena_is_reused_across_keep_alive_requests() {
+    let server = Server::with_config(ArenaService, ServerConfig::default());
+    let handle = server.handle();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let thread = thread::spawn(move || server.run_on(listener));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut buf = [0u8; 1024];
+    stream.write_all(&create_test_request("GET", "/", b"")).unwrap();
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("held false"));
+    stream.write_all(&create_test_request("GET", "/", b"")).unwrap();
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("held true"));
+    drop(stream);
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+/// Answers with the connection statistics of the request.
+struct ConnectionInfoService;
+
+impl Service for ConnectionInfoService {
+    fn handle(&self, req: Request, _stream: Option<may::net::TcpStream>) -> std::io::Result<ServiceResult> {
+        let info = req.connection_info().unwrap();
+        let mut response = Response::default();
+        response.send_text(format!(
+            "id={} seq={} read={} keep_alive={}",
+            info.id, info.sequence, info.bytes_read, info.keep_alive
+        ));
+        Ok(ServiceResult::Response(response))
+    }
+}
+
+#[test]
+fn test_connection_info_counts_requests() {
+    let server = Server::with_config(ConnectionInfoService, ServerConfig::default());
+    let handle = server.handle();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let thread = thread::spawn(move || server.run_on(listener));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let first = create_test_request("GET", "/", b"");
+    let mut buf = [0u8; 1024];
+    stream.write_all(&first).unwrap();
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains(&format!("id=1 seq=1 read={} keep_alive=true", first.len())));
+    stream.write_all(&first).unwrap();
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains(&format!("id=1 seq=2 read={} keep_alive=true", first.len() * 2)));
+    drop(stream);
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_small_backlog_with_auto_scale_serves_concurrent_clients() {
+    let (handle, thread) = start_server_with(ServerConfig {
+        backlog: 4,
+        pool_capacity: 2,
+        auto_scale: true,
+        shutdown_timeout_secs: 2,
+        ..ServerConfig::default()
+    });
+    let addr = handle.local_addr().unwrap();
+
+    let clients: Vec<_> = (0..8)
+        .map(|i| {
+            thread::spawn(move || {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                stream.write_all(&create_test_request("POST", "/", format!("client {i}").as_bytes())).unwrap();
+                let mut buf = [0u8; 1024];
+                let n = stream.read(&mut buf).unwrap();
+                assert!(String::from_utf8_lossy(&buf[..n]).contains(&format!("Echo: client {i}")));
+            })
+        })
+        .collect();
+    for client in clients {
+        client.join().unwrap();
+    }
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_local_serves_unix_socket() {
+    use std::os::unix::net::UnixStream;
+
+    let path = std::env::temp_dir().join(format!("feather-local-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let server = Server::with_config(
+        EchoService,
+        ServerConfig {
+            shutdown_timeout_secs: 2,
+            ..ServerConfig::default()
+        },
+    );
+    let handle = server.handle();
+    let socket = path.clone();
+    let thread = thread::spawn(move || server.run_local(socket));
+    while handle.local_path().is_none() {
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    let mut stream = UnixStream::connect(&path).unwrap();
+    stream.write_all(&create_test_request("POST", "/", b"over a socket")).unwrap();
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("Echo: over a socket"));
+    drop(stream);
+
+    // A second server doesn't take the socket over
+    let second = Server::with_config(EchoService, ServerConfig::default()).run_local(&path).unwrap_err();
+    assert_eq!(second.kind(), std::io::ErrorKind::AddrInUse);
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+    assert!(!path.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_local_replaces_a_stale_socket() {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let path = std::env::temp_dir().join(format!("feather-stale-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    // Dropping a listener leaves its file behind, like a crashed server
+    drop(UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    let server = Server::with_config(EchoService, ServerConfig::default());
+    let handle = server.handle();
+    let socket = path.clone();
+    let thread = thread::spawn(move || server.run_local(socket));
+    while handle.local_path().is_none() {
+        thread::sleep(Duration::from_millis(5));
+    }
+    let mut stream = UnixStream::connect(&path).unwrap();
+    stream.write_all(&create_test_request("POST", "/", b"stale")).unwrap();
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("Echo: stale"));
+    drop(stream);
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_large_headers_and_body_grow_the_buffer() {
+    let (handle, thread) = start_server_with(ServerConfig {
+        max_body_size: 64 * 1024,
+        shutdown_timeout_secs: 2,
+        ..ServerConfig::default()
+    });
+    let addr = handle.local_addr().unwrap();
+    let body = "b".repeat(40 * 1024);
+    let cookie = "c".repeat(12 * 1024);
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let request = format!(
+        "POST / HTTP/1.1\r\nHost: localhost\r\nCookie: {cookie}\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = Vec::new();
+    let mut buf = [0u8; 16 * 1024];
+    while !String::from_utf8_lossy(&response).ends_with(&body) {
+        let n = stream.read(&mut buf).unwrap();
+        assert_ne!(n, 0, "connection closed early");
+        response.extend_from_slice(&buf[..n]);
+    }
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 200"));
+    drop(stream);
+
+    // Headers past `max_header_size` are still refused
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let cookie = "c".repeat(20 * 1024);
+    stream.write_all(format!("GET / HTTP/1.1\r\nHost: localhost\r\nCookie: {cookie}\r\n\r\n").as_bytes()).unwrap();
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 431"));
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_header_limit_holds_for_pipelined_heads() {
+    let (handle, thread) = start_server_with(ServerConfig {
+        buffer_size: 64 * 1024,
+        max_header_size: 1024,
+        shutdown_timeout_secs: 2,
+        ..ServerConfig::default()
+    });
+    let addr = handle.local_addr().unwrap();
+
+    // The oversized head arrives complete in the same read as the request before it
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut pipelined = create_test_request("POST", "/", b"first");
+    let cookie = "c".repeat(2 * 1024);
+    pipelined.extend_from_slice(format!("GET / HTTP/1.1\r\nHost: localhost\r\nCookie: {cookie}\r\n\r\n").as_bytes());
+    stream.write_all(&pipelined).unwrap();
+
+    let mut raw = String::new();
+    stream.read_to_string(&mut raw).unwrap();
+    assert!(raw.starts_with("HTTP/1.1 200 OK"), "{raw}");
+    assert!(raw.contains("HTTP/1.1 431"), "{raw}");
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+/// Answers with a body the handler already encoded in chunks.
+struct ChunkedService;
+
+impl Service for ChunkedService {
+    fn handle(&self, _req: Request, _stream: Option<may::net::TcpStream>) -> std::io::Result<ServiceResult> {
+        let mut response = Response::default();
+        response.add_header("Transfer-Encoding", "chunked").unwrap();
+        response.body = Some("5\r\nhello\r\n0\r\n\r\n".into());
+        Ok(ServiceResult::Response(response))
+    }
+}
+
+#[test]
+fn test_http10_responses_are_not_chunked() {
+    let server = Server::with_config(ChunkedService, ServerConfig::default());
+    let handle = server.handle();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let thread = thread::spawn(move || server.run_on(listener));
+
+    // Keep-alive is opt-in, the body is decoded and framed by its length
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut buf = [0u8; 1024];
+    for _ in 0..2 {
+        stream.write_all(b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n").unwrap();
+        let n = stream.read(&mut buf).unwrap();
+        let text = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+        assert!(text.starts_with("http/1.0 200"));
+        assert!(text.contains("content-length: 5"));
+        assert!(!text.contains("transfer-encoding"));
+        assert!(text.ends_with("\r\n\r\nhello"));
+    }
+
+    // Without it the connection is closed after the response
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.to_lowercase().contains("connection: close"));
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+/// Answers with a body and no `Content-Length`, in the transfer coding named by the path if there is one.
+struct UnframedService;
+
+impl Service for UnframedService {
+    fn handle(&self, req: Request, _stream: Option<may::net::TcpStream>) -> std::io::Result<ServiceResult> {
+        let mut response = Response::default();
+        response.body = Some("hello".into());
+        match req.uri.path() {
+            "/gzip" => response.add_header("Transfer-Encoding", "gzip").unwrap(),
+            "/gzip-chunked" => {
+                response.body = Some("5\r\nhello\r\n0\r\n\r\n".into());
+                response.add_header("Transfer-Encoding", "gzip, chunked").unwrap()
+            }
+            _ => {}
+        }
+        Ok(ServiceResult::Response(response))
+    }
+}
+
+#[test]
+fn test_head_keeps_the_length_unset_and_http10_closes_after_other_codings() {
+    let server = Server::with_config(UnframedService, ServerConfig::default());
+    let handle = server.handle();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let thread = thread::spawn(move || server.run_on(listener));
+    let exchange = |request: &[u8]| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response.to_lowercase()
+    };
+
+    let response = exchange(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    assert!(response.contains("content-length: 5"), "{response}");
+    // The body is gone, a length of 0 would be a lie
+    let response = exchange(b"HEAD / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    assert!(!response.contains("content-length"), "{response}");
+    assert!(response.ends_with("\r\n\r\n"));
+
+    // HTTP/1.0 can't carry the coding, the body ends when the connection does
+    for path in ["/gzip", "/gzip-chunked"] {
+        let response = exchange(format!("GET {path} HTTP/1.0\r\nConnection: keep-alive\r\n\r\n").as_bytes());
+        assert!(response.starts_with("http/1.0 200"), "{response}");
+        assert!(!response.contains("transfer-encoding"), "{response}");
+        assert!(!response.contains("content-length"), "{response}");
+        assert!(response.contains("connection: close"), "{response}");
+        assert!(response.ends_with("\r\n\r\nhello"), "{response}");
+    }
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+/// Streams a body larger than its chunks, or a reader that ends early for `/short`.
+struct StreamService;
+
+impl Service for StreamService {
+    fn handle(&self, req: Request, _stream: Option<may::net::TcpStream>) -> std::io::Result<ServiceResult> {
+        let mut response = Response::default();
+        let body = vec![b'x'; 100_000];
+        let short = req.uri.path() == "/short";
+        // `/short` announces more than the reader has
+        let len = body.len() as u64 * (1 + short as u64);
+        response.send_reader(std::io::Cursor::new(body), len, 4096);
+        Ok(ServiceResult::Response(response))
+    }
+}
+
+#[test]
+fn test_streamed_bodies_are_written_after_the_head() {
+    let server = Server::with_config(StreamService, ServerConfig::default());
+    let handle = server.handle();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let thread = thread::spawn(move || server.run_on(listener));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    let split = response.windows(4).position(|window| window == b"\r\n\r\n").unwrap() + 4;
+    assert!(String::from_utf8_lossy(&response[..split]).to_lowercase().contains("content-length: 100000"));
+    assert_eq!(&response[split..], &vec![b'x'; 100_000][..]);
+
+    // HEAD gets the length but no body
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"HEAD / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.ends_with("\r\n\r\n"));
+
+    // A reader shorter than the announced length drops the connection
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /short HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    let split = response.windows(4).position(|window| window == b"\r\n\r\n").unwrap() + 4;
+    assert_eq!(response.len() - split, 100_000);
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_bind_applies_socket_options() {
+    let config = ServerConfig {
+        reuse_port: true,
+        recv_buffer_size: Some(64 * 1024),
+        ..ServerConfig::default()
+    };
+    let server = Server::with_config(EchoService, config);
+    let first = server.bind("127.0.0.1:0").unwrap();
+    let addr = first.local_addr().unwrap();
+    // A second listener on the same port only binds with SO_REUSEPORT
+    let second = server.bind(addr).unwrap();
+    assert!(Server::with_config(EchoService, ServerConfig::default()).bind(addr).is_err());
+    // Linux doubles the requested size for bookkeeping
+    assert!(socket2::SockRef::from(&second).recv_buffer_size().unwrap() >= 64 * 1024);
+}
+
+#[test]
+fn test_too_many_headers_get_431() {
+    let (handle, thread) = start_server_with(ServerConfig {
+        max_headers: 4,
+        ..ServerConfig::default()
+    });
+    let addr = handle.local_addr().unwrap();
+    let send = |headers: usize| {
+        let mut request = String::from("GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n");
+        for i in 2..headers {
+            request.push_str(&format!("X-Header-{i}: {i}\r\n"));
+        }
+        request.push_str("\r\n");
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+    assert!(send(4).starts_with("HTTP/1.1 200"));
+    assert!(send(5).starts_with("HTTP/1.1 431"));
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+/// Switches to a line protocol that echoes what the client sends, upper-cased.
+struct UpgradeService;
+
+impl Service for UpgradeService {
+    fn handle(&self, _req: Request, _stream: Option<may::net::TcpStream>) -> std::io::Result<ServiceResult> {
+        let mut response = Response::default();
+        response.set_status(101);
+        response.add_header("Upgrade", "shout").unwrap();
+        response.add_header("Connection", "Upgrade").unwrap();
+        response.hijack(|mut conn| {
+            let mut received = conn.read_ahead.to_vec();
+            let mut buf = [0u8; 64];
+            while !received.ends_with(b"\n") {
+                match conn.stream.read(&mut buf) {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => received.extend_from_slice(&buf[..n]),
+                }
+            }
+            conn.stream.write_all(&received.to_ascii_uppercase()).ok();
+        });
+        Ok(ServiceResult::Response(response))
+    }
+}
+
+#[test]
+fn test_hijacked_connection_switches_protocol() {
+    let server = Server::with_config(UpgradeService, ServerConfig::default());
+    let handle = server.handle();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let thread = thread::spawn(move || server.run_on(listener));
+
+    // Bytes sent right behind the request reach the handler too
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: shout\r\n\r\nhello ").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(b"world\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 101"));
+    assert!(response.ends_with("\r\n\r\nHELLO WORLD\n"));
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_run_blocking_returns_results_and_resumes_panics() {
+    use feather_runtime::runtime::run_blocking;
+
+    let handles: Vec<_> = (0..8u64).map(|n| thread::spawn(move || run_blocking(move || n * n))).collect();
+    let squares: Vec<u64> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    assert_eq!(squares, [0, 1, 4, 9, 16, 25, 36, 49]);
+
+    let panicked = std::panic::catch_unwind(|| run_blocking(|| panic!("native code failed")));
+    assert_eq!(*panicked.unwrap_err().downcast::<&str>().unwrap(), "native code failed");
+}
@@ -0,0 +1,86 @@
+#![cfg(feature = "tls")]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+mod common;
+
+use common::{EchoService, create_test_request};
+use feather_runtime::runtime::server::ServerConfig;
+use feather_runtime::runtime::{Server, ServerHandle, TlsConfig, TlsError};
+use rustls::pki_types::{CertificateDer, ServerName};
+
+fn self_signed(name: &str) -> (String, String, CertificateDer<'static>) {
+    let cert = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+    (cert.cert.pem(), cert.signing_key.serialize_pem(), cert.cert.der().clone())
+}
+
+fn start_tls_server(tls: TlsConfig) -> (ServerHandle, SocketAddr) {
+    let server = Server::with_config(EchoService, ServerConfig::default());
+    let handle = server.handle();
+    thread::spawn(move || server.run_tls("127.0.0.1:0", &tls));
+    while handle.local_addr().is_none() {
+        thread::sleep(Duration::from_millis(5));
+    }
+    let addr = handle.local_addr().unwrap();
+    (handle, addr)
+}
+
+/// Sends a request over TLS, trusting only `trusted`, and returns the response and negotiated ALPN protocol.
+fn request(addr: SocketAddr, server_name: &str, trusted: CertificateDer<'static>) -> (String, Option<Vec<u8>>) {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(trusted).unwrap();
+    let mut config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider())).with_safe_default_protocol_versions().unwrap().with_root_certificates(roots).with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    let conn = rustls::ClientConnection::new(Arc::new(config), ServerName::try_from(server_name.to_string()).unwrap()).unwrap();
+    let mut stream = rustls::StreamOwned::new(conn, TcpStream::connect(addr).unwrap());
+    stream.write_all(&create_test_request("POST", "/", b"over tls")).unwrap();
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).unwrap();
+    let alpn = stream.conn.alpn_protocol().map(|p| p.to_vec());
+    (String::from_utf8_lossy(&buf[..n]).to_string(), alpn)
+}
+
+#[test]
+fn test_tls_roundtrip_negotiates_http11() {
+    let (cert, key, der) = self_signed("localhost");
+    let (handle, addr) = start_tls_server(TlsConfig::from_pem(cert, key).unwrap());
+
+    let (response, alpn) = request(addr, "localhost", der);
+    assert!(response.contains("Echo: over tls"));
+    assert_eq!(alpn.as_deref(), Some(&b"http/1.1"[..]));
+    handle.shutdown();
+}
+
+#[test]
+fn test_tls_selects_certificate_by_sni() {
+    let (cert, key, _) = self_signed("localhost");
+    let (api_cert, api_key, api_der) = self_signed("api.example.test");
+    let tls = TlsConfig::from_pem(cert, key).unwrap().with_sni_cert("api.example.test", api_cert, api_key).unwrap();
+    let (handle, addr) = start_tls_server(tls);
+
+    let (response, _) = request(addr, "api.example.test", api_der);
+    assert!(response.contains("Echo: over tls"));
+    handle.shutdown();
+}
+
+#[test]
+fn test_tls_config_rejects_missing_certificate() {
+    let (_, key, _) = self_signed("localhost");
+    assert!(TlsConfig::from_pem("", key).is_err());
+}
+
+#[test]
+fn test_tls_config_rejects_mismatched_key() {
+    let (cert, _, _) = self_signed("localhost");
+    let (_, other_key, _) = self_signed("localhost");
+    assert!(matches!(TlsConfig::from_pem(&cert, &other_key), Err(TlsError::KeyMismatch)));
+    let (api_cert, _, _) = self_signed("api.example.test");
+    let (key_cert, key, _) = self_signed("localhost");
+    let tls = TlsConfig::from_pem(key_cert, &key).unwrap();
+    assert!(matches!(tls.with_sni_cert("api.example.test", api_cert, key), Err(TlsError::KeyMismatch)));
+}
@@ -0,0 +1,313 @@
+#![cfg(feature = "ws")]
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use feather_runtime::http::{Request, Response};
+use feather_runtime::runtime::server::ServerConfig;
+use feather_runtime::runtime::service::ServiceResult;
+use feather_runtime::runtime::{Server, ServerHandle, Service};
+use feather_runtime::ws::{self, Heartbeat, Message, Overflow, TrySendError};
+
+/// Echoes text and binary messages until the client closes, pinging quickly on `/heartbeat`.
+struct EchoSocket;
+
+impl Service for EchoSocket {
+    fn handle(&self, request: Request, _stream: Option<may::net::TcpStream>) -> std::io::Result<ServiceResult> {
+        let mut response = Response::default();
+        let heartbeat = request.uri.path() == "/heartbeat";
+        ws::accept(&request, &mut response, move |mut socket| {
+            socket.set_max_message_size(1024);
+            if heartbeat {
+                let heartbeat = Heartbeat {
+                    interval: Duration::from_millis(100),
+                    max_missed: 2,
+                };
+                socket.set_heartbeat(Some(heartbeat)).unwrap();
+            }
+            while let Ok(Some(message)) = socket.recv() {
+                let _ = match message {
+                    Message::Text(text) => socket.send_text(&text),
+                    Message::Binary(data) => socket.send_binary(&data),
+                    _ => Ok(()),
+                };
+            }
+        });
+        Ok(ServiceResult::Response(response))
+    }
+}
+
+/// Queues 2000 messages of 32KB with `try_send` and reports how that went, then says `done`.
+struct Flood(Overflow, mpsc::Sender<Result<(), TrySendError>>);
+
+impl Service for Flood {
+    fn handle(&self, request: Request, _stream: Option<may::net::TcpStream>) -> std::io::Result<ServiceResult> {
+        let mut response = Response::default();
+        let (overflow, report) = (self.0, self.1.clone());
+        ws::accept(&request, &mut response, move |mut socket| {
+            socket.set_send_queue(4, overflow);
+            let chunk = bytes::Bytes::from(vec![b'x'; 32 * 1024]);
+            let result = (0..2000).try_for_each(|_| socket.try_send(Message::Binary(chunk.clone())));
+            let _ = report.send(result);
+            let _ = socket.try_send(Message::Text("done".to_string()));
+            while let Ok(Some(_)) = socket.recv() {}
+        });
+        Ok(ServiceResult::Response(response))
+    }
+}
+
+/// Tries to ping with too much data, then closes with a reason too long for a close frame.
+struct LongControlFrames(mpsc::Sender<(std::io::ErrorKind, Result<(), TrySendError>)>);
+
+impl Service for LongControlFrames {
+    fn handle(&self, request: Request, _stream: Option<may::net::TcpStream>) -> std::io::Result<ServiceResult> {
+        let mut response = Response::default();
+        let report = self.0.clone();
+        ws::accept(&request, &mut response, move |mut socket| {
+            let ping = bytes::Bytes::from(vec![b'x'; 126]);
+            let sent = socket.send(Message::Ping(ping.clone())).unwrap_err().kind();
+            let _ = report.send((sent, socket.try_send(Message::Pong(ping))));
+            let _ = socket.close(1001, &"é".repeat(100));
+            while let Ok(Some(_)) = socket.recv() {}
+        });
+        Ok(ServiceResult::Response(response))
+    }
+}
+
+fn start() -> (ServerHandle, thread::JoinHandle<std::io::Result<()>>) {
+    serve(EchoSocket)
+}
+
+fn serve(service: impl Service) -> (ServerHandle, thread::JoinHandle<std::io::Result<()>>) {
+    let server = Server::with_config(
+        service,
+        ServerConfig {
+            shutdown_timeout_secs: 2,
+            ..ServerConfig::default()
+        },
+    );
+    let handle = server.handle();
+    let thread = thread::spawn(move || server.run("127.0.0.1:0"));
+    while handle.local_addr().is_none() {
+        thread::sleep(Duration::from_millis(5));
+    }
+    (handle, thread)
+}
+
+/// Opens a WebSocket with the sample key of RFC 6455 and returns the stream after the `101` head.
+fn connect(handle: &ServerHandle, path: &str) -> TcpStream {
+    let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n");
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut head = Vec::new();
+    let mut byte = [0u8];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).unwrap();
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8(head).unwrap().to_lowercase();
+    assert!(head.starts_with("http/1.1 101 switching protocols"), "{head}");
+    assert!(head.contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo="), "{head}");
+    stream
+}
+
+/// Sends a masked client frame.
+fn send_frame(stream: &mut TcpStream, first: u8, payload: &[u8]) {
+    let mask = [0x12, 0x34, 0x56, 0x78];
+    let mut frame = vec![first, 0x80 | payload.len() as u8];
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    stream.write_all(&frame).unwrap();
+}
+
+/// Reads a server frame of up to 64KB, returning its first byte and payload.
+fn read_frame(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    let mut head = [0u8; 2];
+    stream.read_exact(&mut head).unwrap();
+    assert_eq!(head[1] & 0x80, 0, "server frames are not masked");
+    let len = match head[1] {
+        126 => {
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len).unwrap();
+            u16::from_be_bytes(len) as usize
+        }
+        len => len as usize,
+    };
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).unwrap();
+    (head[0], payload)
+}
+
+#[test]
+fn test_accept_key_matches_rfc_sample() {
+    assert_eq!(ws::accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+}
+
+#[test]
+fn test_websocket_echo_and_closing_handshake() {
+    let (handle, thread) = start();
+    let mut stream = connect(&handle, "/chat");
+
+    send_frame(&mut stream, 0x81, b"hello");
+    assert_eq!(read_frame(&mut stream), (0x81, b"hello".to_vec()));
+
+    // A fragmented message with a ping in the middle
+    send_frame(&mut stream, 0x02, b"ab");
+    send_frame(&mut stream, 0x89, b"are you there");
+    send_frame(&mut stream, 0x80, b"cd");
+    assert_eq!(read_frame(&mut stream), (0x8A, b"are you there".to_vec()));
+    assert_eq!(read_frame(&mut stream), (0x82, b"abcd".to_vec()));
+
+    send_frame(&mut stream, 0x88, &[0x03, 0xE8]);
+    assert_eq!(read_frame(&mut stream), (0x88, vec![0x03, 0xE8]));
+    assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0);
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_websocket_protocol_errors_close_the_connection() {
+    let (handle, thread) = start();
+
+    // Invalid UTF-8 in a text message
+    let mut stream = connect(&handle, "/chat");
+    send_frame(&mut stream, 0x81, &[0xFF, 0xFE]);
+    let (first, payload) = read_frame(&mut stream);
+    assert_eq!((first, &payload[..2]), (0x88, &1007u16.to_be_bytes()[..]));
+
+    // Unmasked client frame
+    let mut stream = connect(&handle, "/chat");
+    stream.write_all(&[0x81, 0x02, b'h', b'i']).unwrap();
+    let (first, payload) = read_frame(&mut stream);
+    assert_eq!((first, &payload[..2]), (0x88, &1002u16.to_be_bytes()[..]));
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_plain_requests_are_refused() {
+    let (handle, thread) = start();
+    let send = |request: &str| {
+        let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut buf = [0u8; 1024];
+        let n = stream.read(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..n]).to_lowercase()
+    };
+
+    let response = send("GET /chat HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert!(response.starts_with("http/1.1 426") && response.contains("upgrade: websocket"), "{response}");
+    let response = send("GET /chat HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 8\r\n\r\n");
+    assert!(response.starts_with("http/1.1 426") && response.contains("sec-websocket-version: 13"), "{response}");
+    let response = send("GET /chat HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Version: 13\r\n\r\n");
+    assert!(response.starts_with("http/1.1 400"), "{response}");
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_heartbeat_drops_peers_that_stop_answering() {
+    let (handle, thread) = start();
+    let mut stream = connect(&handle, "/heartbeat");
+
+    // Answered pings keep the connection open past the missed ping limit
+    for _ in 0..3 {
+        let (first, payload) = read_frame(&mut stream);
+        assert_eq!(first, 0x89);
+        send_frame(&mut stream, 0x8A, &payload);
+    }
+    send_frame(&mut stream, 0x81, b"still here");
+    assert_eq!(read_frame(&mut stream), (0x81, b"still here".to_vec()));
+
+    // Going quiet gets two pings, then the connection is dropped
+    assert_eq!(read_frame(&mut stream).0, 0x89);
+    assert_eq!(read_frame(&mut stream).0, 0x89);
+    assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0);
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_shutdown_closes_open_sockets() {
+    let (handle, thread) = start();
+    let mut stream = connect(&handle, "/chat");
+    send_frame(&mut stream, 0x81, b"hello");
+    assert_eq!(read_frame(&mut stream), (0x81, b"hello".to_vec()));
+
+    handle.shutdown();
+    let (first, payload) = read_frame(&mut stream);
+    assert_eq!((first, &payload[..2]), (0x88, &1001u16.to_be_bytes()[..]));
+    assert_eq!(&payload[2..], b"Server shutting down");
+    send_frame(&mut stream, 0x88, &payload[..2]);
+    assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0);
+
+    thread.join().unwrap().unwrap();
+    assert_eq!(handle.active_connections(), 0);
+}
+
+#[test]
+fn test_try_send_drops_clients_that_dont_keep_up() {
+    let (report, reported) = mpsc::channel();
+    let (handle, thread) = serve(Flood(Overflow::Close, report));
+    let _stream = connect(&handle, "/flood");
+
+    let result = reported.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(result, Err(TrySendError::Overflowed));
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_try_send_drops_oldest_messages_when_asked_to() {
+    let (report, reported) = mpsc::channel();
+    let (handle, thread) = serve(Flood(Overflow::DropOldest, report));
+    let mut stream = connect(&handle, "/flood");
+
+    // Queueing never waits for the client
+    assert_eq!(reported.recv_timeout(Duration::from_secs(5)).unwrap(), Ok(()));
+    let mut received = 0;
+    loop {
+        match read_frame(&mut stream) {
+            (0x82, _) => received += 1,
+            (first, payload) => {
+                assert_eq!((first, &payload[..]), (0x81, &b"done"[..]));
+                break;
+            }
+        }
+    }
+    assert!(received < 2000, "{received}");
+
+    send_frame(&mut stream, 0x88, &[0x03, 0xE8]);
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_control_frames_stay_within_125_bytes() {
+    let (report, reported) = mpsc::channel();
+    let (handle, thread) = serve(LongControlFrames(report));
+    let mut stream = connect(&handle, "/control");
+
+    let (sent, queued) = reported.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(sent, std::io::ErrorKind::InvalidInput);
+    assert_eq!(queued, Err(TrySendError::TooLarge));
+
+    // 61 two-byte characters fit, the 62nd would be cut in half
+    let (first, payload) = read_frame(&mut stream);
+    assert_eq!(first, 0x88);
+    assert_eq!(payload[..2], 1001u16.to_be_bytes());
+    assert_eq!(std::str::from_utf8(&payload[2..]).unwrap(), "é".repeat(61));
+    send_frame(&mut stream, 0x88, &payload[..2]);
+
+    handle.shutdown();
+    thread.join().unwrap().unwrap();
+}
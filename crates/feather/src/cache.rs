@@ -0,0 +1,231 @@
+//! A bounded cache of values that expire, shared by the clones of the context. See [`AppContext::cache`].
+//!
+//! Use it to memoize lookups that are expensive but don't have to be fresh on every request, like geo-IP
+//! resolution or the permissions of a user. Entries expire after their time to live, and once the cache
+//! holds its [capacity](crate::App::cache_capacity) the least recently used entry makes room for a new one.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! app.get("/dashboard", middleware!(|req, res, ctx| {
+//!     let user = req.param("user").unwrap_or_default();
+//!     let permissions = ctx.cache().try_get_or_insert_with(&format!("permissions:{user}"), Duration::from_secs(60), || {
+//!         ctx.get_state::<Directory>().permissions(&user)
+//!     })?;
+//!     res.finish_json(&permissions)
+//! }));
+//! ```
+//!
+//! [`AppContext::cache`]: crate::AppContext::cache
+
+use parking_lot::Mutex;
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Entries kept before a new app sets its own limit.
+const DEFAULT_CAPACITY: usize = 10_000;
+
+/// A key-value cache with a time to live per entry and a bounded number of entries.
+///
+/// Values are computed outside the lock, so a slow computation doesn't hold up other keys. When the same
+/// missing key is requested concurrently the value may be computed more than once, the last one is kept.
+/// A key holds a single value: asking for it as another type computes and replaces it.
+#[derive(Clone)]
+pub struct Cache(Arc<Mutex<Inner>>);
+
+struct Inner {
+    entries: HashMap<String, Entry>,
+    /// Keys by the time they were last used, the first one is evicted
+    recent: BTreeMap<u64, String>,
+    clock: u64,
+    capacity: usize,
+}
+
+struct Entry {
+    value: Arc<dyn Any + Send + Sync>,
+    expires: Instant,
+    used: u64,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(Inner {
+            entries: HashMap::new(),
+            recent: BTreeMap::new(),
+            clock: 0,
+            capacity: DEFAULT_CAPACITY,
+        })))
+    }
+}
+
+impl Cache {
+    /// The value cached under `key`, when there is one that hasn't expired.
+    pub fn get<V: Clone + Send + Sync + 'static>(&self, key: &str) -> Option<V> {
+        self.0.lock().get(key)
+    }
+
+    /// Cache `value` under `key` for `ttl`, replacing what was cached there.
+    pub fn insert<V: Send + Sync + 'static>(&self, key: impl Into<String>, ttl: Duration, value: V) {
+        self.0.lock().insert(key.into(), ttl, Arc::new(value));
+    }
+
+    /// The value cached under `key`, or the one `compute` returns, cached for `ttl`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let country = ctx.cache().get_or_insert_with(&ip, Duration::from_secs(3600), || geoip.lookup(&ip));
+    /// ```
+    pub fn get_or_insert_with<V: Clone + Send + Sync + 'static>(&self, key: &str, ttl: Duration, compute: impl FnOnce() -> V) -> V {
+        if let Some(value) = self.get(key) {
+            return value;
+        }
+        let value = compute();
+        self.insert(key, ttl, value.clone());
+        value
+    }
+
+    /// Like [get_or_insert_with](Self::get_or_insert_with) for lookups that can fail. Errors aren't cached,
+    /// the next call computes the value again.
+    pub fn try_get_or_insert_with<V: Clone + Send + Sync + 'static, E>(&self, key: &str, ttl: Duration, compute: impl FnOnce() -> Result<V, E>) -> Result<V, E> {
+        if let Some(value) = self.get(key) {
+            return Ok(value);
+        }
+        let value = compute()?;
+        self.insert(key, ttl, value.clone());
+        Ok(value)
+    }
+
+    /// Drop the entry of `key`, `true` when there was one.
+    pub fn remove(&self, key: &str) -> bool {
+        self.0.lock().remove(key).is_some()
+    }
+
+    /// Drop every entry.
+    pub fn clear(&self) {
+        let mut inner = self.0.lock();
+        inner.entries.clear();
+        inner.recent.clear();
+    }
+
+    /// The number of entries, including expired ones that weren't dropped yet.
+    pub fn len(&self) -> usize {
+        self.0.lock().entries.len()
+    }
+
+    /// Whether the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Keep at most `capacity` entries, evicting the least recently used ones above it.
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.0.lock();
+        inner.capacity = capacity.max(1);
+        while inner.entries.len() > inner.capacity {
+            inner.evict();
+        }
+    }
+}
+
+impl Inner {
+    fn get<V: Clone + 'static>(&mut self, key: &str) -> Option<V> {
+        let entry = self.entries.get(key)?;
+        if entry.expires <= Instant::now() {
+            self.remove(key);
+            return None;
+        }
+        let value = entry.value.downcast_ref::<V>()?.clone();
+        let used = self.tick();
+        let entry = self.entries.get_mut(key).expect("the entry was just read");
+        let key = self.recent.remove(&entry.used).expect("every entry is in the recent list");
+        entry.used = used;
+        self.recent.insert(used, key);
+        Some(value)
+    }
+
+    fn insert(&mut self, key: String, ttl: Duration, value: Arc<dyn Any + Send + Sync>) {
+        self.remove(&key);
+        if self.entries.len() >= self.capacity {
+            self.entries.retain(|_, entry| entry.expires > Instant::now());
+            self.recent.retain(|_, key| self.entries.contains_key(key));
+        }
+        while self.entries.len() >= self.capacity {
+            self.evict();
+        }
+        let used = self.tick();
+        // A ttl too long to represent never expires in practice
+        let expires = Instant::now().checked_add(ttl).unwrap_or_else(|| Instant::now() + Duration::from_secs(100 * 365 * 24 * 3600));
+        self.recent.insert(used, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                expires,
+                used,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.entries.remove(key)?;
+        self.recent.remove(&entry.used);
+        Some(entry)
+    }
+
+    fn evict(&mut self) {
+        if let Some((_, key)) = self.recent.pop_first() {
+            self.entries.remove(&key);
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_entries_expire_and_the_least_recently_used_is_evicted() {
+        let cache = Cache::default();
+        cache.set_capacity(2);
+        let computed = Cell::new(0);
+        let lookup = |key: &str| {
+            cache.get_or_insert_with(key, Duration::from_secs(60), || {
+                computed.set(computed.get() + 1);
+                key.to_uppercase()
+            })
+        };
+
+        assert_eq!(lookup("a"), "A");
+        assert_eq!(lookup("a"), "A");
+        assert_eq!(computed.get(), 1);
+        lookup("b");
+        // `a` was used after `b`, so `b` makes room for `c`
+        lookup("a");
+        lookup("c");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get::<String>("a").as_deref(), Some("A"));
+        assert_eq!(cache.get::<String>("b"), None);
+
+        cache.insert("short", Duration::ZERO, 1u32);
+        assert_eq!(cache.get::<u32>("short"), None);
+        // Another type under the same key is a miss
+        assert_eq!(cache.get::<u32>("a"), None);
+
+        let failed: Result<u32, &str> = cache.try_get_or_insert_with("flaky", Duration::from_secs(60), || Err("unavailable"));
+        assert!(failed.is_err());
+        let retried: Result<u32, &str> = cache.try_get_or_insert_with("flaky", Duration::from_secs(60), || Ok(7));
+        assert_eq!(retried, Ok(7));
+        assert!(cache.remove("flaky"));
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,390 @@
+//! Settings of an app read from layered sources: its defaults, config files and environment variables.
+//!
+//! Each source overrides the keys it sets in the ones before it, so a file only needs the settings that
+//! differ from the defaults and the environment only the ones that differ per deployment. The result is
+//! deserialized with serde. Requires the `json` feature, and the `toml` feature for TOML files.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! #[derive(Default, Serialize, Deserialize)]
+//! #[serde(default)]
+//! struct Settings {
+//!     address: String,
+//!     database: Database,
+//! }
+//!
+//! #[derive(Default, Serialize, Deserialize)]
+//! struct Database {
+//!     url: String,
+//!     pool_size: u32,
+//! }
+//!
+//! let mut app = App::new();
+//! // `config.toml` if there is one, then `APP_ADDRESS`, `APP_DATABASE__URL`, ..
+//! let settings = app.load_config::<Settings>();
+//!
+//! app.get("/", middleware!(|_req, res, ctx| {
+//!     let settings = ctx.config::<Settings>();
+//!     res.finish_text(format!("pool of {}", settings.database.pool_size))
+//! }));
+//! app.listen(&settings.address);
+//! ```
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where [App::load_config_with](crate::App::load_config_with) reads settings from, in order.
+///
+/// Defaults come from the `Default` impl of the settings, [files](Self::file) are TOML or JSON by their
+/// extension, and environment variables are read last. TOML files take the `toml` feature, their dates
+/// and times are read as strings.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let loader = ConfigLoader::new()
+///     .file("config/base.toml")
+///     .optional_file("config/local.toml")
+///     .env_prefix("MYAPP");
+/// let settings: Settings = loader.load()?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ConfigLoader {
+    files: Vec<(PathBuf, bool)>,
+    env_prefix: Option<String>,
+}
+
+impl ConfigLoader {
+    /// A loader of the defaults only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merge the `.toml` or `.json` file at `path`, failing when it doesn't exist.
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.files.push((path.into(), true));
+        self
+    }
+
+    /// Merge the file at `path` when it exists, like a local override that isn't checked in.
+    pub fn optional_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.files.push((path.into(), false));
+        self
+    }
+
+    /// Merge the environment variables starting with `prefix` and an underscore. The rest of the name is
+    /// the key in lowercase, with a double underscore between nested keys: `APP_DATABASE__URL` sets `database.url`.
+    ///
+    /// Values are read as the type of the default they replace. For keys without a default, like `None`
+    /// options, a value that is valid JSON is read as such, so a string like `"123"` has to be quoted.
+    pub fn env_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.env_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Read and merge the sources into `T`.
+    ///
+    /// # Errors
+    ///
+    /// When a file can't be read or parsed, an environment variable doesn't parse as the type of its
+    /// default, or the merged settings don't deserialize into `T`.
+    pub fn load<T: DeserializeOwned + Serialize + Default>(&self) -> io::Result<T> {
+        self.load_from_vars(std::env::vars())
+    }
+
+    pub(crate) fn load_from_vars<T: DeserializeOwned + Serialize + Default>(&self, vars: impl IntoIterator<Item = (String, String)>) -> io::Result<T> {
+        let mut merged = serde_json::to_value(T::default()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("defaults don't serialize: {e}")))?;
+        for (path, required) in &self.files {
+            match read_file(path) {
+                Ok(value) => merge(&mut merged, value),
+                Err(e) if e.kind() == io::ErrorKind::NotFound && !required => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if let Some(prefix) = &self.env_prefix {
+            let prefix = format!("{}_", prefix.trim_end_matches('_'));
+            let mut vars: Vec<_> = vars.into_iter().filter(|(name, _)| name.starts_with(&prefix)).collect();
+            // Nested keys after their parents, so `APP_DB__URL` lands in the object of `APP_DB='{..}'`
+            vars.sort();
+            for (name, value) in vars {
+                let path: Vec<String> = name[prefix.len()..].split("__").map(str::to_lowercase).collect();
+                set_var(&mut merged, &path, &name, value)?;
+            }
+        }
+        T::deserialize(merged).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("invalid config: {e}")))
+    }
+}
+
+/// The settings of a TOML or JSON file.
+fn read_file(path: &Path) -> io::Result<Value> {
+    let text = std::fs::read_to_string(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    let parsed = match path.extension().and_then(|extension| extension.to_str()) {
+        #[cfg(feature = "toml")]
+        Some("toml") => parse_toml(&text),
+        #[cfg(not(feature = "toml"))]
+        Some("toml") => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{}: reading .toml config files requires the `toml` feature", path.display()),
+            ));
+        }
+        Some("json") => serde_json::from_str(&text).map_err(|e| e.to_string()),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: config files have to be .toml or .json", path.display()),
+            ));
+        }
+    };
+    parsed.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {e}", path.display())))
+}
+
+/// The settings of a TOML file, as the same [Value] tree as the other sources.
+#[cfg(feature = "toml")]
+fn parse_toml(text: &str) -> Result<Value, String> {
+    fn convert(value: toml::Value) -> Result<Value, String> {
+        Ok(match value {
+            toml::Value::String(string) => Value::String(string),
+            toml::Value::Integer(integer) => Value::from(integer),
+            toml::Value::Float(float) => Value::Number(serde_json::Number::from_f64(float).ok_or_else(|| format!("{float} can't be a setting"))?),
+            toml::Value::Boolean(boolean) => Value::Bool(boolean),
+            toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+            toml::Value::Array(array) => Value::Array(array.into_iter().map(convert).collect::<Result<_, _>>()?),
+            toml::Value::Table(table) => Value::Object(table.into_iter().map(|(key, value)| Ok((key, convert(value)?))).collect::<Result<_, String>>()?),
+        })
+    }
+    let table: toml::Table = toml::from_str(text).map_err(|e| e.to_string())?;
+    convert(toml::Value::Table(table))
+}
+
+/// Merge `value` into `into`, recursing into objects both have.
+fn merge(into: &mut Value, value: Value) {
+    match (into, value) {
+        (Value::Object(into), Value::Object(value)) => {
+            for (key, value) in value {
+                match into.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        into.insert(key, value);
+                    }
+                }
+            }
+        }
+        (into, value) => *into = value,
+    }
+}
+
+/// Set the variable `name` at `path`, parsed as the type of the value it replaces.
+fn set_var(merged: &mut Value, path: &[String], name: &str, value: String) -> io::Result<()> {
+    let mut node = merged;
+    for key in path {
+        if !node.is_object() {
+            *node = Value::Object(Map::new());
+        }
+        node = node.as_object_mut().expect("just made an object").entry(key.clone()).or_insert(Value::Null);
+    }
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("{name} has an invalid value `{value}`"));
+    let parsed = match node {
+        Value::String(_) => Value::String(value.clone()),
+        Value::Bool(_) => Value::Bool(value.trim().parse().map_err(|_| invalid())?),
+        Value::Number(_) => match serde_json::from_str(value.trim()) {
+            Ok(Value::Number(number)) => Value::Number(number),
+            _ => return Err(invalid()),
+        },
+        Value::Array(_) | Value::Object(_) => serde_json::from_str(&value).map_err(|_| invalid())?,
+        Value::Null => serde_json::from_str(&value).unwrap_or_else(|_| Value::String(value.clone())),
+    };
+    *node = parsed;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    struct Settings {
+        address: String,
+        debug: bool,
+        name: Option<String>,
+        database: Database,
+    }
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Database {
+        url: String,
+        pool_size: u32,
+    }
+
+    impl Default for Settings {
+        fn default() -> Self {
+            Self {
+                address: "127.0.0.1:5050".to_string(),
+                debug: false,
+                name: None,
+                database: Database {
+                    url: "postgres://localhost/app".to_string(),
+                    pool_size: 4,
+                },
+            }
+        }
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("feather-config-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_sources_override_each_other_in_order() {
+        let dir = temp_dir("layers");
+        std::fs::write(dir.join("base.json"), r#"{ "address": "0.0.0.0:80", "database": { "pool_size": 16 } }"#).unwrap();
+        std::fs::write(dir.join("local.json"), r#"{ "debug": true }"#).unwrap();
+
+        let loader = ConfigLoader::new().file(dir.join("base.json")).file(dir.join("local.json")).optional_file(dir.join("missing.toml")).env_prefix("APP");
+        let settings: Settings = loader.load_from_vars(vars(&[("APP_DATABASE__URL", "postgres://db/prod"), ("APP_NAME", "api"), ("OTHER_DEBUG", "false")])).unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                address: "0.0.0.0:80".to_string(),
+                debug: true,
+                name: Some("api".to_string()),
+                database: Database {
+                    url: "postgres://db/prod".to_string(),
+                    pool_size: 16,
+                },
+            }
+        );
+
+        let error = loader.load_from_vars::<Settings>(vars(&[("APP_DATABASE__POOL_SIZE", "many")])).unwrap_err();
+        assert_eq!(error.to_string(), "APP_DATABASE__POOL_SIZE has an invalid value `many`");
+        let error = ConfigLoader::new().file(dir.join("missing.toml")).load_from_vars::<Settings>(Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_reads_toml_config_files() {
+        #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+        #[serde(default)]
+        struct Service {
+            name: String,
+            started: String,
+            hosts: Vec<String>,
+            limits: Limits,
+            database: Database,
+            workers: Vec<Worker>,
+        }
+
+        #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+        struct Limits {
+            burst: u32,
+            per: Per,
+        }
+
+        #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+        struct Per {
+            second: f64,
+        }
+
+        #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+        struct Worker {
+            queue: String,
+            concurrency: Option<u32>,
+        }
+
+        let dir = temp_dir("shapes");
+        let text = r#"
+# Server settings
+name = "feather"   # inline comment
+started = 1979-05-27T07:32:00Z
+hosts = [
+    "a",
+    "b", # trailing comma
+]
+limits = { burst = 10, per.second = 0.5 }
+
+[database]
+url = 'postgres://localhost/app'
+pool_size = 16
+
+[[workers]]
+queue = "mail"
+
+[[workers]]
+queue = "jobs"
+concurrency = 4
+"#;
+        std::fs::write(dir.join("app.toml"), text).unwrap();
+        let service: Service = ConfigLoader::new().file(dir.join("app.toml")).load_from_vars(Vec::new()).unwrap();
+        assert_eq!(
+            service,
+            Service {
+                name: "feather".to_string(),
+                started: "1979-05-27T07:32:00Z".to_string(),
+                hosts: vec!["a".to_string(), "b".to_string()],
+                limits: Limits {
+                    burst: 10,
+                    per: Per {
+                        second: 0.5,
+                    },
+                },
+                database: Database {
+                    url: "postgres://localhost/app".to_string(),
+                    pool_size: 16,
+                },
+                workers: vec![
+                    Worker {
+                        queue: "mail".to_string(),
+                        concurrency: None,
+                    },
+                    Worker {
+                        queue: "jobs".to_string(),
+                        concurrency: Some(4),
+                    },
+                ],
+            }
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_malformed_toml_reports_file_and_position() {
+        let dir = temp_dir("malformed");
+        for (text, message) in [("a = 1\na = 2\n", "line 2, column 1"), ("[server]\nport = 80 80\n", "line 2, column 8"), ("name = \"unterminated\n", "line 1, column")] {
+            std::fs::write(dir.join("app.toml"), text).unwrap();
+            let error = ConfigLoader::new().file(dir.join("app.toml")).load_from_vars::<Settings>(Vec::new()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            let (error, expected) = (error.to_string(), format!("{}: TOML parse error at {message}", dir.join("app.toml").display()));
+            assert!(error.starts_with(&expected), "{error}");
+        }
+        // The message quotes the line with the error
+        let error = ConfigLoader::new().file(dir.join("app.toml")).load_from_vars::<Settings>(Vec::new()).unwrap_err();
+        assert!(error.to_string().contains("1 | name = \"unterminated"), "{error}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(not(feature = "toml"))]
+    #[test]
+    fn test_toml_files_need_the_feature() {
+        let dir = temp_dir("no-toml");
+        std::fs::write(dir.join("app.toml"), "address = \"0.0.0.0:80\"\n").unwrap();
+        let error = ConfigLoader::new().file(dir.join("app.toml")).load_from_vars::<Settings>(Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        assert!(error.to_string().ends_with("reading .toml config files requires the `toml` feature"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+//! Reading and writing cookies.
+//!
+//! Cookies pick their security attributes from the application [`Profile`]: unless set explicitly,
+//! cookies are sent with `Secure` and `SameSite=Lax` everywhere but in [`Profile::Dev`], where plain
+//! `http://localhost` has to keep working.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use feather::cookie::{Cookie, RequestCookies, ResponseCookies};
+//!
+//! app.get("/", middleware!(|req, res, ctx| {
+//!     let visits: u32 = req.cookie("visits").and_then(|v| v.parse().ok()).unwrap_or(0);
+//!     res.set_cookie(ctx, Cookie::new("visits", (visits + 1).to_string()).max_age(3600))?;
+//!     res.finish_text(format!("Visits: {visits}"))
+//! }));
+//! ```
+
+use crate::{AppContext, Error, Profile, Request, Response};
+use feather_runtime::{HeaderName, HeaderValue};
+use std::error::Error as StdError;
+use std::fmt::{self, Write};
+
+/// The `SameSite` attribute of a cookie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    fn as_str(self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// A cookie to be sent with [`ResponseCookies::set_cookie`].
+///
+/// `Secure` and `SameSite` default to the values of the current [`Profile`], call
+/// [`secure`](Self::secure) or [`same_site`](Self::same_site) to override them.
+#[derive(Clone, Debug)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<u64>,
+    http_only: bool,
+    secure: Option<bool>,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// Create a `HttpOnly` cookie valid for the whole site (`Path=/`).
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            path: Some("/".to_string()),
+            domain: None,
+            max_age: None,
+            http_only: true,
+            secure: None,
+            same_site: None,
+        }
+    }
+
+    /// Set the `Path` attribute.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Set the `Domain` attribute.
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Expire the cookie after `seconds`. Without it the cookie lasts until the browser is closed.
+    pub fn max_age(mut self, seconds: u64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    /// Set whether the cookie is hidden from JavaScript (default: `true`).
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Override the profile default of the `Secure` attribute.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = Some(secure);
+        self
+    }
+
+    /// Override the profile default of the `SameSite` attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// The name of the cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value of the cookie.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Render the `Set-Cookie` header value, filling unset attributes with the defaults of `profile`.
+    ///
+    /// # Errors
+    ///
+    /// When the name isn't a token, the value has characters outside of the cookie-octets of RFC 6265
+    /// (like spaces, `;`, `,` or `"`), or the path or domain contain a `;` or control characters. They would
+    /// end the cookie early or add attributes to it, encode such values first, with base64 for example.
+    pub fn to_header_value(&self, profile: Profile) -> Result<String, CookieError> {
+        if self.name.is_empty() || !self.name.bytes().all(is_token) {
+            return Err(CookieError::new("name", &self.name));
+        }
+        let value = self.value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(&self.value);
+        if !value.bytes().all(is_cookie_octet) {
+            return Err(CookieError::new("value", &self.value));
+        }
+        for (part, attribute) in [("path", &self.path), ("domain", &self.domain)] {
+            if let Some(attribute) = attribute
+                && !attribute.bytes().all(|byte| !byte.is_ascii_control() && byte != b';')
+            {
+                return Err(CookieError::new(part, attribute));
+            }
+        }
+
+        let mut out = format!("{}={}", self.name, self.value);
+        if let Some(path) = &self.path {
+            let _ = write!(out, "; Path={path}");
+        }
+        if let Some(domain) = &self.domain {
+            let _ = write!(out, "; Domain={domain}");
+        }
+        if let Some(max_age) = self.max_age {
+            let _ = write!(out, "; Max-Age={max_age}");
+        }
+        if self.http_only {
+            out.push_str("; HttpOnly");
+        }
+        let strict_defaults = !profile.is_dev();
+        let same_site = self.same_site.or(strict_defaults.then_some(SameSite::Lax));
+        // Browsers reject `SameSite=None` without `Secure`
+        if self.secure.unwrap_or(strict_defaults || same_site == Some(SameSite::None)) {
+            out.push_str("; Secure");
+        }
+        if let Some(same_site) = same_site {
+            let _ = write!(out, "; SameSite={}", same_site.as_str());
+        }
+        Ok(out)
+    }
+}
+
+/// A [`Cookie`] that can't be sent, see [`Cookie::to_header_value`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CookieError {
+    part: &'static str,
+    value: String,
+}
+
+impl CookieError {
+    fn new(part: &'static str, value: &str) -> Self {
+        Self {
+            part,
+            value: value.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for CookieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid cookie {}: {:?}", self.part, self.value)
+    }
+}
+
+impl StdError for CookieError {}
+
+/// The characters of a token (RFC 9110), which cookie names are.
+fn is_token(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+/// The characters allowed in a cookie value by RFC 6265: printable ASCII but spaces, `"`, `,`, `;` and `\`.
+fn is_cookie_octet(byte: u8) -> bool {
+    matches!(byte, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
+}
+
+/// Cookie access for [`Request`].
+pub trait RequestCookies {
+    /// Get the value of the cookie called `name`.
+    fn cookie(&self, name: &str) -> Option<&str>;
+    /// Get every cookie sent with the request as `(name, value)` pairs.
+    fn cookies(&self) -> Vec<(&str, &str)>;
+}
+
+impl RequestCookies for Request {
+    fn cookie(&self, name: &str) -> Option<&str> {
+        self.cookies().into_iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    fn cookies(&self) -> Vec<(&str, &str)> {
+        self.headers.get_all("cookie").iter().filter_map(|header| header.to_str().ok()).flat_map(|header| header.split(';')).filter_map(|pair| pair.trim().split_once('=')).map(|(name, value)| (name.trim(), value.trim().trim_matches('"'))).collect()
+    }
+}
+
+/// Cookie writing for [`Response`].
+pub trait ResponseCookies {
+    /// Add a `Set-Cookie` header, using the profile of `ctx` for the attributes left unset.
+    fn set_cookie(&mut self, ctx: &AppContext, cookie: Cookie) -> Result<(), Error>;
+    /// Tell the browser to delete the cookie called `name` (set on `Path=/`).
+    fn remove_cookie(&mut self, ctx: &AppContext, name: &str) -> Result<(), Error>;
+}
+
+impl ResponseCookies for Response {
+    fn set_cookie(&mut self, ctx: &AppContext, cookie: Cookie) -> Result<(), Error> {
+        let value = HeaderValue::from_str(&cookie.to_header_value(ctx.profile())?)?;
+        self.headers.append(HeaderName::from_static("set-cookie"), value);
+        Ok(())
+    }
+
+    fn remove_cookie(&mut self, ctx: &AppContext, name: &str) -> Result<(), Error> {
+        self.set_cookie(ctx, Cookie::new(name, "").max_age(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_defaults() {
+        let cookie = Cookie::new("id", "42");
+        assert_eq!(cookie.to_header_value(Profile::Dev).unwrap(), "id=42; Path=/; HttpOnly");
+        assert_eq!(cookie.to_header_value(Profile::Prod).unwrap(), "id=42; Path=/; HttpOnly; Secure; SameSite=Lax");
+    }
+
+    #[test]
+    fn test_explicit_attributes_win() {
+        let cookie = Cookie::new("id", "42").secure(false).same_site(SameSite::Strict).max_age(60).http_only(false);
+        assert_eq!(cookie.to_header_value(Profile::Prod).unwrap(), "id=42; Path=/; Max-Age=60; SameSite=Strict");
+        let cross_site = Cookie::new("id", "42").same_site(SameSite::None);
+        assert_eq!(cross_site.to_header_value(Profile::Dev).unwrap(), "id=42; Path=/; HttpOnly; Secure; SameSite=None");
+    }
+
+    #[test]
+    fn test_invalid_cookies_are_rejected() {
+        for name in ["", "user id", "id;", "a=b", "naïve"] {
+            let error = Cookie::new(name, "1").to_header_value(Profile::Prod).unwrap_err();
+            assert_eq!(error.to_string(), format!("invalid cookie name: {name:?}"));
+        }
+        for value in ["a b", "a;Domain=evil.com", "a,b", "say \"hi\"", "back\\slash", "line\r\nX-Injected: 1", "é"] {
+            assert!(Cookie::new("id", value).to_header_value(Profile::Prod).is_err(), "{value:?}");
+        }
+        assert!(Cookie::new("id", "1").path("/; Domain=evil.com").to_header_value(Profile::Prod).is_err());
+        assert!(Cookie::new("id", "1").domain("example.com\r\n").to_header_value(Profile::Prod).is_err());
+
+        let quoted = Cookie::new("id", "\"a=b\"").http_only(false);
+        assert_eq!(quoted.to_header_value(Profile::Dev).unwrap(), "id=\"a=b\"; Path=/");
+        assert!(Cookie::new("session", "eyJhbGciOi.eyJzdWIi-_=").to_header_value(Profile::Prod).is_ok());
+    }
+
+    #[test]
+    fn test_set_cookie_fails_on_invalid_cookies() {
+        let ctx = AppContext::new();
+        let mut response = Response::default();
+        let error = response.set_cookie(&ctx, Cookie::new("id", "a; Max-Age=0")).unwrap_err();
+        assert!(error.is::<CookieError>());
+        assert!(response.headers.get("set-cookie").is_none());
+    }
+}
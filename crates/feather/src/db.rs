@@ -0,0 +1,106 @@
+//! Database connection pools kept in the context, built on [r2d2](https://docs.rs/r2d2).
+//!
+//! [App::with_pool](crate::App::with_pool) opens a pool of connections for any r2d2 manager, like the ones
+//! of `r2d2_sqlite` or `r2d2_postgres`, and [`AppContext::db`](crate::AppContext::db) checks a connection out
+//! of it. Connections go back to the pool when they are dropped. Requires the `db` feature.
+//!
+//! Checking out never blocks a worker thread: when every connection is in use, the handler's coroutine waits
+//! on the [blocking pool](crate::AppContext::run_blocking) until one is returned. Checkouts are counted in the
+//! [metrics](crate::AppContext::metrics):
+//!
+//! - `db_checkouts`: connections checked out
+//! - `db_checkout_waits`: checkouts that had to wait for a connection
+//! - `db_checkout_wait_ms`: the time spent waiting, in milliseconds
+//! - `db_checkout_errors`: checkouts that timed out or failed to connect
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use r2d2_sqlite::SqliteConnectionManager;
+//!
+//! let mut app = App::new();
+//! app.with_pool(SqliteConnectionManager::file("app.db"), 8);
+//!
+//! app.get("/users", middleware!(|_req, res, ctx| {
+//!     let conn = ctx.db::<SqliteConnectionManager>()?;
+//!     let count: i64 = conn.query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))?;
+//!     res.finish_text(format!("{count} users"))
+//! }));
+//! ```
+
+use crate::internals::Metrics;
+use std::time::Instant;
+
+pub use r2d2::{Error, ManageConnection, Pool, PooledConnection};
+
+const CHECKOUTS: &str = "db_checkouts";
+const WAITS: &str = "db_checkout_waits";
+const WAIT_MS: &str = "db_checkout_wait_ms";
+const ERRORS: &str = "db_checkout_errors";
+
+/// Check a connection out of `pool`, waiting on the blocking pool when none is idle.
+pub(crate) fn checkout<M: ManageConnection>(pool: &Pool<M>, metrics: &Metrics) -> Result<PooledConnection<M>, Error> {
+    metrics.counter(CHECKOUTS).increment();
+    if let Some(conn) = pool.try_get() {
+        return Ok(conn);
+    }
+    metrics.counter(WAITS).increment();
+    let started = Instant::now();
+    let waiting = pool.clone();
+    let result = feather_runtime::runtime::run_blocking(move || waiting.get());
+    metrics.counter(WAIT_MS).add(started.elapsed().as_millis() as u64);
+    if result.is_err() {
+        metrics.counter(ERRORS).increment();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::App;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    struct Numbers(AtomicUsize);
+
+    impl r2d2::ManageConnection for Numbers {
+        type Connection = usize;
+        type Error = std::io::Error;
+
+        fn connect(&self) -> Result<usize, Self::Error> {
+            Ok(self.0.fetch_add(1, Ordering::SeqCst))
+        }
+
+        fn is_valid(&self, _conn: &mut usize) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _conn: &mut usize) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_checkouts_wait_for_returned_connections() {
+        let mut app = App::without_logger();
+        app.with_pool(Numbers(AtomicUsize::new(0)), 2);
+        let ctx = app.context().clone();
+
+        let first = ctx.db::<Numbers>().unwrap();
+        let second = ctx.db::<Numbers>().unwrap();
+        assert_ne!(*first, *second);
+        let returned = *first;
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            drop(first);
+        });
+        // Both are in use, the third checkout waits for the first to come back
+        assert_eq!(*ctx.db::<Numbers>().unwrap(), returned);
+
+        let metrics = ctx.metrics();
+        assert_eq!(metrics.counter("db_checkouts").get(), 3);
+        assert_eq!(metrics.counter("db_checkout_waits").get(), 1);
+        assert_eq!(metrics.counter("db_checkout_errors").get(), 0);
+        drop(second);
+    }
+}
@@ -0,0 +1,185 @@
+//! Typed handler parameters, read from the request before the handler runs.
+//!
+//! A function marked with [`#[handler]`](crate::handler) takes its inputs as parameters instead of parsing
+//! them from the request by hand. Every parameter type implements [FromRequest], except for `&mut Response`,
+//! `&mut Request` and `&AppContext`, which are passed through. When a parameter can't be extracted, the
+//! request is answered with the status of the [Rejection], `400 Bad Request` for a malformed body, and the
+//! handler doesn't run.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use feather::extract::{Json, Path};
+//! use feather::handler;
+//!
+//! #[derive(Deserialize)]
+//! struct NewPost {
+//!     title: String,
+//! }
+//!
+//! #[handler]
+//! fn create_post(Path(user): Path<u64>, Json(post): Json<NewPost>, res: &mut Response, ctx: &AppContext) -> Outcome {
+//!     let id = ctx.get_state::<Posts>().insert(user, post.title);
+//!     res.set_status(201).finish_text(id.to_string())
+//! }
+//!
+//! app.post("/users/:id/posts", create_post);
+//! ```
+//!
+//! Own types implement [FromRequest] to be extracted the same way, like the user of a session.
+
+use crate::internals::AppContext;
+use crate::internals::send_error;
+use crate::{Outcome, end};
+use feather_runtime::http::{Request, Response};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A value read from the request for a [`#[handler]`](crate::handler) parameter.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// struct ApiKey(String);
+///
+/// impl FromRequest for ApiKey {
+///     fn from_request(req: &mut Request, _ctx: &AppContext) -> Result<Self, Rejection> {
+///         match req.headers.get("x-api-key").and_then(|value| value.to_str().ok()) {
+///             Some(key) => Ok(ApiKey(key.to_string())),
+///             None => Err(Rejection::new(401, "missing API key")),
+///         }
+///     }
+/// }
+/// ```
+pub trait FromRequest: Sized {
+    /// Read the value, or the rejection the request is answered with.
+    fn from_request(req: &mut Request, ctx: &AppContext) -> Result<Self, Rejection>;
+}
+
+/// Why a [FromRequest] parameter couldn't be extracted, answered with its status and message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    status: u16,
+    message: String,
+}
+
+impl Rejection {
+    /// A rejection answered with `status` and `message` as plain text.
+    pub fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// A `400 Bad Request` rejection.
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(400, message)
+    }
+
+    /// The status the request is answered with.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The message sent as the body.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Answer the request with the rejection and stop processing it, as JSON for clients that accept it
+    /// like the other errors of the framework.
+    pub fn respond(self, req: &Request, res: &mut Response) -> Outcome {
+        send_error(req, res, self.status, &self.message);
+        end!()
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+impl Error for Rejection {}
+
+/// The single route parameter, parsed into `T`, like the `42` of `/users/42` for a `/users/:id` route.
+///
+/// Routes with several parameters read them with [`Request::param`] instead. A value that doesn't parse
+/// is rejected with `400 Bad Request`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Path<T>(pub T);
+
+impl<T: FromStr> FromRequest for Path<T>
+where
+    T::Err: fmt::Display,
+{
+    fn from_request(req: &mut Request, _ctx: &AppContext) -> Result<Self, Rejection> {
+        let params = req.params();
+        let mut values = params.iter();
+        let (Some((name, value)), None) = (values.next(), values.next()) else {
+            return Err(Rejection::new(500, format!("Path needs a route with a single parameter, found {}", params.len())));
+        };
+        match value.parse() {
+            Ok(value) => Ok(Path(value)),
+            Err(e) => Err(Rejection::bad_request(format!("invalid `{name}` parameter: {e}"))),
+        }
+    }
+}
+
+/// The body of the request, deserialized from JSON. Requires the `json` feature.
+///
+/// A body that isn't valid JSON for `T` is rejected with `400 Bad Request`.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "json")]
+impl<T: serde::de::DeserializeOwned> FromRequest for Json<T> {
+    fn from_request(req: &mut Request, _ctx: &AppContext) -> Result<Self, Rejection> {
+        match serde_json::from_slice(&req.body) {
+            Ok(value) => Ok(Json(value)),
+            Err(e) => Err(Rejection::bad_request(format!("invalid JSON body: {e}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestClient;
+    use crate::{App, Finalizer, handler};
+
+    #[handler]
+    fn show_user(Path(id): Path<u64>, res: &mut Response, ctx: &AppContext) -> Outcome {
+        res.finish_text(format!("{} {id}", ctx.get_state::<String>()))
+    }
+
+    #[cfg(feature = "json")]
+    #[handler]
+    fn rename(Json(name): Json<String>, req: &mut Request, res: &mut Response) -> Outcome {
+        res.finish_text(format!("{} is now {name}", req.param("id").unwrap_or_default()))
+    }
+
+    #[test]
+    fn test_handlers_get_their_parameters() {
+        let mut app = App::without_logger();
+        app.context().set_state(String::from("user"));
+        app.get("/users/:id", show_user);
+        #[cfg(feature = "json")]
+        app.put("/users/:id", rename);
+        let client = TestClient::new(app);
+
+        assert_eq!(client.get("/users/7").send().body.as_deref(), Some(&b"user 7"[..]));
+        let rejected = client.get("/users/seven").send();
+        assert_eq!(rejected.status.as_u16(), 400);
+        assert_eq!(rejected.body.as_deref(), Some(&b"invalid `id` parameter: invalid digit found in string"[..]));
+
+        #[cfg(feature = "json")]
+        {
+            assert_eq!(client.put("/users/7").body(r#""Ada""#).send().body.as_deref(), Some(&b"7 is now Ada"[..]));
+            assert_eq!(client.put("/users/7").body("Ada").send().status.as_u16(), 400);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use feather_runtime::Method;
+use feather_runtime::http::Request;
+
+use super::app::Route;
+use super::discovery::RouteMeta;
+
+/// Typed values attached to a route with [`RouteHandle::with_meta`], one per type.
+///
+/// They are shared by every request the route handles, read them from a handler or middleware with
+/// [`RequestAnnotations::route_meta`] and before serving with [`App::routes`](crate::App::routes).
+#[derive(Clone, Default)]
+pub struct Annotations(Arc<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>);
+
+impl Annotations {
+    /// The value of type `T`, if the route has one.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.0.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    /// Whether the route has a value of type `T`.
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.0.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Whether the route has no values.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        Arc::make_mut(&mut self.0).insert(TypeId::of::<T>(), Arc::new(value));
+    }
+}
+
+/// The route just added to an [App](crate::App) or a [Router](crate::Router), to annotate it.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// struct Tag(&'static str);
+/// struct Deprecated;
+///
+/// app.get("/users", list_users).with_meta(Tag("users"));
+/// app.get("/v1/users", list_users).with_meta(Tag("users")).with_meta(Deprecated);
+/// ```
+pub struct RouteHandle<'a> {
+    route: &'a mut Route,
+}
+
+impl<'a> RouteHandle<'a> {
+    pub(crate) fn new(route: &'a mut Route) -> Self {
+        Self {
+            route,
+        }
+    }
+
+    /// Attach `meta` to the route, replacing the value of the same type attached before.
+    ///
+    /// Any type works, like a tag for metrics labels, a rate limit policy or an entry of the API docs.
+    pub fn with_meta<T: Send + Sync + 'static>(self, meta: T) -> Self {
+        self.route.annotations.insert(meta);
+        self
+    }
+
+    /// Describe the route in the automatic `OPTIONS` responses, see [`App::describe`](crate::App::describe).
+    pub fn describe(self, meta: RouteMeta) -> Self {
+        self.route.meta = meta;
+        self
+    }
+}
+
+/// A registered route, listed by [`App::routes`](crate::App::routes) and [`Router::routes`](crate::Router::routes).
+#[derive(Clone, Copy)]
+pub struct RouteInfo<'a> {
+    route: &'a Route,
+}
+
+impl<'a> RouteInfo<'a> {
+    pub(crate) fn new(route: &'a Route) -> Self {
+        Self {
+            route,
+        }
+    }
+
+    /// The method the route answers.
+    pub fn method(&self) -> &'a Method {
+        &self.route.method
+    }
+
+    /// The path pattern of the route, like `/users/:id`, including the mount prefix once mounted.
+    pub fn path(&self) -> &'a str {
+        &self.route.path
+    }
+
+    /// The value of type `T` attached with [`RouteHandle::with_meta`], if any.
+    pub fn meta<T: Send + Sync + 'static>(&self) -> Option<&'a T> {
+        self.route.annotations.get()
+    }
+
+    /// Every value attached to the route.
+    pub fn annotations(&self) -> &'a Annotations {
+        &self.route.annotations
+    }
+}
+
+/// Reading the [Annotations] of the route handling a request.
+pub trait RequestAnnotations {
+    /// The value of type `T` attached to the route that matched the request, if any.
+    ///
+    /// Global middleware run before routing, they see the route in their [after](crate::Middleware::after) hook.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// struct DeprecationHeader;
+    ///
+    /// impl Middleware for DeprecationHeader {
+    ///     fn handle(&self, _req: &mut Request, _res: &mut Response, _ctx: &AppContext) -> Outcome {
+    ///         next!()
+    ///     }
+    ///
+    ///     fn after(&self, req: &Request, res: &mut Response, _ctx: &AppContext) {
+    ///         if req.route_meta::<Deprecated>().is_some() {
+    ///             let _ = res.add_header("Deprecation", "true");
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// app.use_middleware(DeprecationHeader);
+    /// ```
+    fn route_meta<T: Send + Sync + 'static>(&self) -> Option<&T>;
+}
+
+impl RequestAnnotations for Request {
+    fn route_meta<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.extensions.get::<Annotations>()?.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestClient;
+    use crate::{App, Finalizer, Router, middleware, next_route};
+
+    struct Tag(&'static str);
+    struct Deprecated;
+
+    #[test]
+    fn test_annotations_are_listed_and_read_at_dispatch() {
+        let tagged = middleware!(|req, res| res.finish_text(req.route_meta::<Tag>().map_or("untagged", |tag| tag.0)));
+        let mut app = App::without_logger();
+        app.get("/users", tagged).with_meta(Tag("users")).with_meta(Deprecated);
+        app.get("/health", tagged);
+        app.get("/orders/:id", middleware!(|_req, _res, _ctx| next_route!())).with_meta(Tag("skipped"));
+        app.get("/orders/:id", tagged);
+        let mut api = Router::new();
+        api.post("/items", tagged).with_meta(Tag("items"));
+        app.mount("/api", api);
+
+        let routes: Vec<_> = app.routes().map(|route| (route.method().as_str(), route.path(), route.meta::<Tag>().map(|tag| tag.0))).collect();
+        assert_eq!(routes[0], ("GET", "/users", Some("users")));
+        assert_eq!(routes[4], ("POST", "/api/items", Some("items")));
+        assert!(app.routes().next().unwrap().annotations().contains::<Deprecated>());
+        assert!(app.routes().nth(1).unwrap().annotations().is_empty());
+
+        let client = TestClient::new(app);
+        assert_eq!(client.get("/users").send().body.as_deref(), Some(&b"users"[..]));
+        assert_eq!(client.get("/health").send().body.as_deref(), Some(&b"untagged"[..]));
+        assert_eq!(client.post("/api/items").send().body.as_deref(), Some(&b"items"[..]));
+        // The route that passed the request on doesn't tag it
+        assert_eq!(client.get("/orders/1").send().body.as_deref(), Some(&b"untagged"[..]));
+    }
+}
@@ -1,15 +1,30 @@
 use super::AppContext;
-use super::error_stack::ErrorHandler;
+use super::annotations::{Annotations, RouteHandle, RouteInfo};
+use super::controller::{Controller, controller_router};
+use super::diagnostic::{Diagnostic, route_conflicts};
+use super::discovery::{RouteMeta, describe_routes};
+use super::error_stack::{ErrorHandler, ErrorHandlers, PanicError, ServeError};
+use super::resource::{Resource, resource_routes};
 use super::route_methods;
+use super::schedule::{Cron, Timing};
+use super::shutdown::ShutdownHandle;
 use crate::internals::Router;
-use crate::internals::service::AppService;
-use crate::middlewares::Middleware;
+use crate::internals::service::{AppService, LifecycleHook};
+use crate::middlewares::{BodyTransform, Middleware};
 pub use feather_runtime::Method;
 use feather_runtime::runtime::server::Server;
 pub use feather_runtime::runtime::server::ServerConfig;
+#[cfg(feature = "tls")]
+use feather_runtime::runtime::tls::TlsConfig;
 use std::borrow::Cow;
 
+use std::io;
+use std::panic::{AssertUnwindSafe, Location};
+use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
 use std::{fmt::Display, net::ToSocketAddrs};
 
 /// A route in the application.
@@ -20,6 +35,29 @@ pub struct Route {
     pub method: Method,
     pub path: Cow<'static, str>,
     pub middleware: Arc<dyn Middleware>,
+    /// Where the route was registered, for diagnostics
+    pub location: Option<&'static Location<'static>>,
+    /// Description for the automatic `OPTIONS` responses
+    pub meta: RouteMeta,
+    /// Typed values attached with `RouteHandle::with_meta`
+    pub annotations: Annotations,
+}
+
+/// A global middleware registered on the [App].
+///
+/// Named middlewares carry an `enabled` flag that can be flipped at runtime
+/// through [`MiddlewareToggles`](super::MiddlewareToggles).
+pub(crate) struct GlobalMiddleware {
+    pub enabled: Option<Arc<AtomicBool>>,
+    pub middleware: Arc<dyn Middleware>,
+}
+
+impl GlobalMiddleware {
+    /// Unnamed middlewares are always enabled.
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.as_ref().is_none_or(|enabled| enabled.load(Ordering::Acquire))
+    }
 }
 
 /// A Feather application.
@@ -43,12 +81,19 @@ pub struct Route {
 /// ```
 pub struct App {
     routes: Vec<Route>,
-    middleware: Vec<Arc<dyn Middleware>>,
+    middleware: Vec<GlobalMiddleware>,
+    transforms: Vec<Arc<dyn BodyTransform>>,
     context: AppContext,
-    error_handler: Option<ErrorHandler>,
+    error_handlers: ErrorHandlers,
     server_config: ServerConfig,
+    start_hooks: Vec<LifecycleHook>,
+    shutdown_hooks: Vec<LifecycleHook>,
+    providers: Vec<Provider>,
 }
 
+/// Builds a piece of state at startup, see [`App::provide`].
+type Provider = Box<dyn FnOnce(&AppContext) -> Result<(), String> + Send>;
+
 impl App {
     /// Create a new instance of the application
     /// Also initializes the Logger if the `log` feature is enabled.
@@ -92,9 +137,13 @@ impl App {
         Self {
             routes: Vec::new(),
             middleware: Vec::new(),
+            transforms: Vec::new(),
             context: AppContext::new(),
-            error_handler: None,
+            error_handlers: ErrorHandlers::default(),
             server_config: ServerConfig::default(),
+            start_hooks: Vec::new(),
+            shutdown_hooks: Vec::new(),
+            providers: Vec::new(),
         }
     }
     /// Create a new instance of the application without initializing the logger.
@@ -103,9 +152,13 @@ impl App {
         Self {
             routes: Vec::new(),
             middleware: Vec::new(),
+            transforms: Vec::new(),
             context: AppContext::new(),
-            error_handler: None,
+            error_handlers: ErrorHandlers::default(),
             server_config: ServerConfig::default(),
+            start_hooks: Vec::new(),
+            shutdown_hooks: Vec::new(),
+            providers: Vec::new(),
         }
     }
 
@@ -119,6 +172,7 @@ impl App {
     ///     read_timeout_secs: 60,             // 60 seconds
     ///     workers: 4,                        // 4 worker threads
     ///     stack_size: 128 * 1024,            // 128KB
+    ///     ..ServerConfig::default()
     /// };
     ///
     /// let mut app = App::with_config(config);
@@ -154,9 +208,13 @@ impl App {
         Self {
             routes: Vec::new(),
             middleware: Vec::new(),
+            transforms: Vec::new(),
             context: AppContext::new(),
-            error_handler: None,
+            error_handlers: ErrorHandlers::default(),
             server_config: config,
+            start_hooks: Vec::new(),
+            shutdown_hooks: Vec::new(),
+            providers: Vec::new(),
         }
     }
     /// Returns a mutable reference to the [AppContext].
@@ -181,6 +239,278 @@ impl App {
     pub fn context(&mut self) -> &mut AppContext {
         &mut self.context
     }
+    /// Open a pool of up to `size` connections from `manager` for [`AppContext::db`], see [`db`](crate::db).
+    /// Requires the `db` feature.
+    ///
+    /// The pool connects right away, its [`Pool`](crate::db::Pool) is also stored as state.
+    ///
+    /// # Panics
+    ///
+    /// When the connections can't be established, so a broken deployment doesn't start.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.with_pool(SqliteConnectionManager::file("app.db"), 8);
+    /// ```
+    #[cfg(feature = "db")]
+    #[track_caller]
+    pub fn with_pool<M: crate::db::ManageConnection>(&mut self, manager: M, size: u32) {
+        if size == 0 {
+            panic!("{}", Diagnostic::error("database pool without connections").help("pass a pool size of at least 1"));
+        }
+        match crate::db::Pool::builder().max_size(size).build(manager) {
+            Ok(pool) => self.context.set_state(pool),
+            Err(e) => panic!(
+                "{}",
+                Diagnostic::error("failed to open the database pool").with("manager", std::any::type_name::<M>()).cause(e).help("check that the database is reachable")
+            ),
+        }
+    }
+
+    /// Load the settings `T` and store them for [`AppContext::config`], see [`config`](crate::config).
+    /// Requires the `json` feature.
+    ///
+    /// The defaults of `T` are overridden by `config.toml` when there is one, read with the `toml` feature,
+    /// and then by the environment variables starting with `APP_`. Use [load_config_with](Self::load_config_with) for other sources.
+    ///
+    /// # Panics
+    ///
+    /// When a source is invalid or the settings don't deserialize, so a broken deployment doesn't start.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let settings = app.load_config::<Settings>();
+    /// app.listen(&settings.address);
+    /// ```
+    #[cfg(feature = "json")]
+    #[track_caller]
+    pub fn load_config<T>(&mut self) -> Arc<T>
+    where
+        T: serde::de::DeserializeOwned + serde::Serialize + Default + Send + Sync + 'static,
+    {
+        self.load_config_with(&crate::config::ConfigLoader::new().optional_file("config.toml").env_prefix("APP"))
+    }
+
+    /// Load the settings `T` from the sources of `loader` and store them for [`AppContext::config`].
+    /// Requires the `json` feature.
+    ///
+    /// # Panics
+    ///
+    /// When a source is invalid or the settings don't deserialize.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let settings = app.load_config_with::<Settings>(&ConfigLoader::new().file("settings.json").env_prefix("SHOP"));
+    /// ```
+    #[cfg(feature = "json")]
+    #[track_caller]
+    pub fn load_config_with<T>(&mut self, loader: &crate::config::ConfigLoader) -> Arc<T>
+    where
+        T: serde::de::DeserializeOwned + serde::Serialize + Default + Send + Sync + 'static,
+    {
+        match loader.load::<T>() {
+            Ok(config) => {
+                self.context.set_state(config);
+                self.context.get_state::<T>()
+            }
+            Err(e) => panic!("{}", Diagnostic::error("invalid configuration").with("type", std::any::type_name::<T>()).cause(e)),
+        }
+    }
+
+    /// Set up the context's [JwtManager](crate::jwt::JwtManager) from `config`, see
+    /// [`JwtManager::from_config`](crate::jwt::JwtManager::from_config). Requires the `jwt` feature.
+    ///
+    /// # Panics
+    ///
+    /// When the config is invalid or its key files can't be read, so a broken deployment doesn't start.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let mut app = App::new();
+    /// app.enable_jwt(&JwtConfig::from_env()?);
+    /// ```
+    #[cfg(feature = "jwt")]
+    #[track_caller]
+    pub fn enable_jwt(&mut self, config: &crate::jwt::JwtConfig) {
+        match crate::jwt::JwtManager::from_config(config) {
+            Ok(jwt) => self.context.set_jwt(jwt),
+            Err(e) => panic!(
+                "{}",
+                Diagnostic::error("invalid JWT configuration").cause(e).help("set `JWT_SECRET`, or the key files of `JwtConfig`")
+            ),
+        }
+    }
+    /// Build a piece of state with `provider` when the server starts, and store it for [`AppContext::get_state`].
+    ///
+    /// Providers run in the order they were added, before the server binds and before the [on_start](Self::on_start)
+    /// hooks, so a provider can take what earlier ones stored from `ctx`. If one fails, or panics on missing state,
+    /// the server doesn't start: [start](Self::start) and [try_listen](Self::try_listen) return the error and the
+    /// `listen` methods panic with it.
+    /// # Example
+    /// ```rust,ignore
+    /// app.provide(|_ctx| Settings::load("settings.toml"));
+    /// app.provide(|ctx| Database::connect(&ctx.get_state::<Settings>().database_url));
+    /// app.provide(|ctx| Ok::<_, Infallible>(UserRepo::new(ctx.get_state::<Database>().clone())));
+    /// ```
+    pub fn provide<T, E>(&mut self, provider: impl FnOnce(&AppContext) -> Result<T, E> + Send + 'static)
+    where
+        T: Send + Sync + 'static,
+        E: Display,
+    {
+        self.providers.push(Box::new(move |ctx| {
+            let name = std::any::type_name::<T>();
+            match std::panic::catch_unwind(AssertUnwindSafe(|| provider(ctx))) {
+                Ok(Ok(value)) => {
+                    ctx.set_state(value);
+                    Ok(())
+                }
+                Ok(Err(e)) => Err(format!("failed to provide `{name}`: {e}")),
+                Err(payload) => Err(format!("failed to provide `{name}`: {}", PanicError::from_payload(payload).message())),
+            }
+        }));
+    }
+
+    /// Run `hook` when the server starts, after it is listening and before the first request is handled.
+    ///
+    /// Use it to warm caches or open connection pools and store them with [`AppContext::set_state`].
+    /// Hooks run in the order they were added.
+    /// # Example
+    /// ```rust,ignore
+    /// app.on_start(|ctx| {
+    ///     ctx.set_state(Pool::connect("postgres://localhost/app").expect("database unreachable"));
+    /// });
+    /// ```
+    pub fn on_start(&mut self, hook: impl Fn(&AppContext) + Send + Sync + 'static) {
+        self.start_hooks.push(Box::new(hook));
+    }
+
+    /// Run `task` in the background once the server is listening, after the [on_start](Self::on_start) hooks.
+    ///
+    /// Warmup tasks run concurrently, each on its own coroutine. Until all of them succeeded the health report
+    /// fails, so a [`Readiness`](crate::middlewares::builtins::Readiness) endpoint answers `503` and load
+    /// balancers only send traffic once caches, templates or keys are primed. A failed task keeps the app unready.
+    /// # Example
+    /// ```rust,ignore
+    /// app.warmup(|ctx| {
+    ///     ctx.set_state(Templates::load("templates/")?);
+    ///     Ok(())
+    /// });
+    /// app.use_middleware(Readiness::default());
+    /// ```
+    pub fn warmup(&mut self, task: impl FnOnce(&AppContext) -> Result<(), Box<dyn std::error::Error>> + Send + 'static) {
+        self.context.warmup().add(Box::new(task));
+    }
+
+    /// Run `task` every `interval` while the server runs, for periodic maintenance like pruning sessions.
+    ///
+    /// The first run is one `interval` after the server started, and each following one an `interval` after
+    /// the previous run finished, so runs never overlap. A panicking task is reported and runs again on schedule.
+    /// Shutting down stops the schedule and waits for a run in progress.
+    /// # Example
+    /// ```rust,ignore
+    /// app.every(Duration::from_secs(60), |ctx| {
+    ///     ctx.get_state::<Sessions>().prune_expired();
+    /// });
+    /// ```
+    #[track_caller]
+    pub fn every(&mut self, interval: Duration, task: impl Fn(&AppContext) + Send + Sync + 'static) {
+        if interval.is_zero() {
+            panic!(
+                "{}",
+                Diagnostic::error("scheduled task without an interval").help("pass a positive `Duration` to `app.every`")
+            );
+        }
+        self.context.scheduler().add(Timing::Every(interval), format!("every {interval:?}"), Box::new(task));
+    }
+
+    /// Run `task` at the times matching the cron expression `cron`, in UTC, while the server runs.
+    ///
+    /// The five fields are minute, hour, day of month, month and day of week, like `*/5 * * * *` for every
+    /// five minutes or `30 3 * * mon-fri` for 3:30 on weekdays. `@hourly`, `@daily`, `@weekly`, `@monthly` and
+    /// `@yearly` work as well. Runs behave like the ones of [every](Self::every): a run that takes longer than
+    /// the schedule skips the times it missed.
+    ///
+    /// # Panics
+    ///
+    /// When `cron` isn't a valid expression.
+    /// # Example
+    /// ```rust,ignore
+    /// app.schedule("0 4 * * *", |ctx| {
+    ///     ctx.get_state::<Cache>().refresh();
+    /// });
+    /// ```
+    #[track_caller]
+    pub fn schedule(&mut self, cron: &str, task: impl Fn(&AppContext) + Send + Sync + 'static) {
+        match Cron::parse(cron) {
+            Ok(parsed) => self.context.scheduler().add(Timing::Cron(parsed), cron.to_string(), Box::new(task)),
+            Err(e) => panic!(
+                "{}",
+                Diagnostic::error("invalid cron expression").with("schedule", format_args!("{cron:?}")).cause(e).help("use five fields: minute hour day month weekday, like `*/5 * * * *`")
+            ),
+        }
+    }
+
+    /// Set how many entries [`AppContext::cache`] keeps before evicting the least recently used ones.
+    /// Default is 10000.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.cache_capacity(100_000);
+    /// ```
+    pub fn cache_capacity(&mut self, entries: usize) {
+        self.context.cache().set_capacity(entries);
+    }
+
+    /// Subscribe `handler` to the events of type `E` [emitted](AppContext::emit) anywhere in the app.
+    ///
+    /// Lets a module like a mailer or an audit log react to what happens elsewhere without being called
+    /// directly. Every subscriber of an event runs on its own coroutine, in no particular order.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.on_event::<UserRegistered>(|ctx, event| {
+    ///     ctx.get_state::<Mailer>().send_welcome(&event.email);
+    /// });
+    /// app.on_event::<UserRegistered>(|ctx, event| {
+    ///     ctx.get_state::<AuditLog>().record(format!("user {} registered", event.id));
+    /// });
+    /// ```
+    pub fn on_event<E: Send + Sync + 'static>(&mut self, handler: impl Fn(&AppContext, &E) + Send + Sync + 'static) {
+        self.context.events().subscribe(handler);
+    }
+
+    /// Set the number of workers running [enqueued](AppContext::enqueue) jobs and how long a shutdown waits for them.
+    /// # Example
+    /// ```rust,ignore
+    /// app.configure_jobs(JobConfig {
+    ///     workers: 16,
+    ///     ..Default::default()
+    /// });
+    /// ```
+    pub fn configure_jobs(&mut self, config: crate::jobs::JobConfig) {
+        self.context.jobs().configure(config);
+    }
+
+    /// Run `hook` when the server stops, after the in-flight requests finished.
+    ///
+    /// Only servers that shut down gracefully run these hooks, like the ones started with [start](Self::start)
+    /// or [listen_with_graceful_shutdown](Self::listen_with_graceful_shutdown).
+    /// # Example
+    /// ```rust,ignore
+    /// app.on_shutdown(|ctx| {
+    ///     ctx.get_state::<Pool>().close();
+    /// });
+    /// ```
+    pub fn on_shutdown(&mut self, hook: impl Fn(&AppContext) + Send + Sync + 'static) {
+        self.shutdown_hooks.push(Box::new(hook));
+    }
+
     /// Set up custom error handling for the application.
     ///
     /// By default, Feather catches errors and returns a 500 response. Use this to
@@ -196,9 +526,57 @@ impl App {
     ///
     /// app.set_error_handler(Box::new(error_handler));
     /// ```
+    ///
+    /// Handlers registered with [on_error](Self::on_error) for the type of the error run instead.
     #[inline]
     pub fn set_error_handler(&mut self, handler: ErrorHandler) {
-        self.error_handler = Some(handler)
+        self.error_handlers.set_catch_all(handler)
+    }
+
+    /// Handle the errors of type `E` returned by middleware and route handlers.
+    ///
+    /// The handler gets the error itself, taken out of the [Error](crate::Error) it was returned in. When
+    /// several handlers are registered for a type the first one runs, errors of other types go to the
+    /// [catch-all handler](Self::set_error_handler) or get the default response.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// use std::io;
+    ///
+    /// app.on_error::<io::Error>(|err, _req, res| {
+    ///     if err.kind() == io::ErrorKind::NotFound {
+    ///         res.set_status(404).send_text("Not Found");
+    ///     } else {
+    ///         res.set_status(503).send_text("Storage unavailable");
+    ///     }
+    /// });
+    /// app.on_error::<PanicError>(|err, req, res| {
+    ///     eprintln!("{} {} panicked: {}", req.method, req.uri, err.message());
+    ///     res.set_status(500).send_text("Something went wrong");
+    /// });
+    /// ```
+    pub fn on_error<E: std::error::Error + 'static>(&mut self, handler: impl Fn(&E, &feather_runtime::http::Request, &mut feather_runtime::http::Response) + Send + Sync + 'static) {
+        self.error_handlers.on(handler);
+    }
+
+    /// Build the JSON body of the errors answered by the framework, for clients that accept JSON.
+    /// Requires the `json` feature.
+    ///
+    /// That covers the 404 of paths without a route, the 500 of unhandled errors and panics, the 401 of
+    /// JWT and session guards, rejected handler parameters, [HttpError](crate::HttpError)s and the requests
+    /// the server turns away itself, like `413 Payload Too Large`. By default the body is
+    /// `{"error": {"code": 404, "message": "Not Found"}}`, clients that don't accept JSON get the message
+    /// as text.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.error_body(|status, message| json!({ "status": status, "title": message, "type": "about:blank" }));
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn error_body(&mut self, body: impl Fn(u16, &str) -> serde_json::Value + Send + Sync + 'static) {
+        self.error_handlers.set_body(Arc::new(body));
     }
 
     /// Set the maximum request body size in bytes.
@@ -213,7 +591,31 @@ impl App {
         self
     }
 
-    /// Set the read timeout in seconds for client connections.
+    /// Set the maximum size of the request line and headers in bytes.
+    /// Default is 16384 bytes (16KB), larger requests get a `431 Request Header Fields Too Large`.
+    /// # Example
+    /// ```rust,ignore
+    /// app.max_header_size(64 * 1024); // Large cookies or tokens
+    /// ```
+    #[inline]
+    pub fn max_header_size(&mut self, size: usize) -> &mut Self {
+        self.server_config.max_header_size = size;
+        self
+    }
+
+    /// Set the maximum number of request headers.
+    /// Default is 64, requests with more get a `431 Request Header Fields Too Large`.
+    /// # Example
+    /// ```rust,ignore
+    /// app.max_headers(128);
+    /// ```
+    #[inline]
+    pub fn max_headers(&mut self, count: usize) -> &mut Self {
+        self.server_config.max_headers = count;
+        self
+    }
+
+    /// Set the upper bound in seconds for any single read from a client.
     /// Default is 30 seconds.
     /// # Example
     /// ```rust,ignore
@@ -225,6 +627,54 @@ impl App {
         self
     }
 
+    /// Set the time in seconds a client has to send the complete request headers.
+    /// Default is 10 seconds.
+    /// # Example
+    /// ```rust,ignore
+    /// app.header_timeout(5); // 5 seconds
+    /// ```
+    #[inline]
+    pub fn header_timeout(&mut self, seconds: u64) -> &mut Self {
+        self.server_config.header_timeout_secs = seconds;
+        self
+    }
+
+    /// Set the maximum time in seconds between two reads of a request body.
+    /// Default is 10 seconds.
+    /// # Example
+    /// ```rust,ignore
+    /// app.body_timeout(30); // 30 seconds
+    /// ```
+    #[inline]
+    pub fn body_timeout(&mut self, seconds: u64) -> &mut Self {
+        self.server_config.body_timeout_secs = seconds;
+        self
+    }
+
+    /// Set the maximum time in seconds a response write may block on a client that doesn't read.
+    /// Default is 30 seconds.
+    /// # Example
+    /// ```rust,ignore
+    /// app.write_timeout(10); // 10 seconds
+    /// ```
+    #[inline]
+    pub fn write_timeout(&mut self, seconds: u64) -> &mut Self {
+        self.server_config.write_timeout_secs = seconds;
+        self
+    }
+
+    /// Set how long in seconds an idle keep-alive connection waits for the next request.
+    /// Default is 5 seconds.
+    /// # Example
+    /// ```rust,ignore
+    /// app.keep_alive_timeout(15); // 15 seconds
+    /// ```
+    #[inline]
+    pub fn keep_alive_timeout(&mut self, seconds: u64) -> &mut Self {
+        self.server_config.keep_alive_timeout_secs = seconds;
+        self
+    }
+
     /// Set the number of worker threads for handling connections.
     /// Default is the number of CPU cores.
     /// # Example
@@ -250,6 +700,91 @@ impl App {
         self
     }
 
+    /// Set the maximum number of OS threads running [`AppContext::run_blocking`] work.
+    /// Default is 32.
+    /// # Example
+    /// ```rust,ignore
+    /// app.blocking_threads(8); // At most 8 image conversions at once
+    /// ```
+    #[inline]
+    pub fn blocking_threads(&mut self, count: usize) -> &mut Self {
+        self.server_config.blocking_threads = count;
+        self
+    }
+
+    /// Set how many finished coroutines keep their stack for reuse.
+    /// Default is 1000.
+    /// # Example
+    /// ```rust,ignore
+    /// app.pool_capacity(5000); // Many concurrent connections
+    /// ```
+    #[inline]
+    pub fn pool_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.server_config.pool_capacity = capacity;
+        self
+    }
+
+    /// Grow and shrink the coroutine pool with the number of open connections.
+    /// Default is off, the pool keeps the size set by [pool_capacity](Self::pool_capacity).
+    /// # Example
+    /// ```rust,ignore
+    /// app.auto_scale(true);
+    /// ```
+    #[inline]
+    pub fn auto_scale(&mut self, enabled: bool) -> &mut Self {
+        self.server_config.auto_scale = enabled;
+        self
+    }
+
+    /// Set the length of the queue of connections waiting to be accepted.
+    /// Default is 1024, the OS may cap it (e.g. `net.core.somaxconn` on Linux).
+    /// # Example
+    /// ```rust,ignore
+    /// app.backlog(4096);
+    /// ```
+    #[inline]
+    pub fn backlog(&mut self, backlog: i32) -> &mut Self {
+        self.server_config.backlog = backlog;
+        self
+    }
+
+    /// Disable Nagle's algorithm (`TCP_NODELAY`) on accepted connections.
+    /// Default is `false`. Lowers latency for small responses at the cost of more packets.
+    /// # Example
+    /// ```rust,ignore
+    /// app.tcp_nodelay(true);
+    /// ```
+    #[inline]
+    pub fn tcp_nodelay(&mut self, enabled: bool) -> &mut Self {
+        self.server_config.tcp_nodelay = enabled;
+        self
+    }
+
+    /// Bind with `SO_REUSEPORT` so several processes can listen on the same address.
+    /// Default is `false`. Only supported on Unix.
+    /// # Example
+    /// ```rust,ignore
+    /// app.reuse_port(true);
+    /// ```
+    #[inline]
+    pub fn reuse_port(&mut self, enabled: bool) -> &mut Self {
+        self.server_config.reuse_port = enabled;
+        self
+    }
+
+    /// Set the kernel send and receive buffer sizes (`SO_SNDBUF` / `SO_RCVBUF`) of connections in bytes.
+    /// By default the OS picks them.
+    /// # Example
+    /// ```rust,ignore
+    /// app.socket_buffers(256 * 1024, 256 * 1024);
+    /// ```
+    #[inline]
+    pub fn socket_buffers(&mut self, send: usize, recv: usize) -> &mut Self {
+        self.server_config.send_buffer_size = Some(send);
+        self.server_config.recv_buffer_size = Some(recv);
+        self
+    }
+
     /// Add a route to the application.
     ///
     /// This is the generic method for adding routes. For convenience, use the
@@ -273,12 +808,123 @@ impl App {
     /// }));
     /// ```
     #[inline]
-    pub fn route<M: Middleware + 'static>(&mut self, method: Method, path: impl Into<Cow<'static, str>>, middleware: M) {
+    #[track_caller]
+    pub fn route<M: Middleware + 'static>(&mut self, method: Method, path: impl Into<Cow<'static, str>>, middleware: M) -> RouteHandle<'_> {
         self.routes.push(Route {
             method,
             path: path.into(),
             middleware: Arc::new(middleware),
+            location: Some(Location::caller()),
+            meta: RouteMeta::default(),
+            annotations: Annotations::default(),
         });
+        RouteHandle::new(self.routes.last_mut().expect("the route was just added"))
+    }
+
+    /// Add a route for a custom HTTP method, like `PURGE` or the WebDAV `PROPFIND`.
+    ///
+    /// Method names are case-sensitive, `purge` and `PURGE` are different methods.
+    /// # Panics
+    /// If `method` is not a valid HTTP method token.
+    /// # Example
+    /// ```rust,ignore
+    /// app.method("PURGE", "/cache/:key", |req: &mut Request, res: &mut Response, ctx: &AppContext| {
+    ///     ctx.get_state::<Cache>().invalidate(req.param("key").unwrap());
+    ///     res.set_status(204);
+    ///     next!()
+    /// });
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn method<M: Middleware + 'static>(&mut self, method: &str, path: impl Into<Cow<'static, str>>, middleware: M) -> RouteHandle<'_> {
+        self.route(super::extension_method(method), path, middleware)
+    }
+
+    /// Describe the route registered for `method` and `path` in the automatic `OPTIONS` responses.
+    ///
+    /// `OPTIONS` requests that no route handles are answered with the methods registered for the path
+    /// in the `Allow` header, so clients can discover the API without an OpenAPI document.
+    /// [RouteMeta] adds the accepted content types and the required authentication.
+    /// # Example
+    /// ```rust,ignore
+    /// app.post("/users", create_user);
+    /// app.describe(Method::POST, "/users", RouteMeta::new().accepts("application/json").auth("Bearer"));
+    /// // OPTIONS /users -> Allow: POST, OPTIONS
+    /// //                   Accept-Post: application/json
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn describe(&mut self, method: Method, path: &str, meta: RouteMeta) {
+        describe_routes(&mut self.routes, method, path, meta);
+    }
+
+    /// The routes registered so far, in the order they are matched, with the values attached to them.
+    ///
+    /// Use it to generate API docs or check route policies before serving.
+    /// # Example
+    /// ```rust,ignore
+    /// app.get("/users", list_users).with_meta(Tag("users"));
+    ///
+    /// for route in app.routes() {
+    ///     let tag = route.meta::<Tag>().map_or("untagged", |tag| tag.0);
+    ///     println!("{} {} [{tag}]", route.method(), route.path());
+    /// }
+    /// ```
+    pub fn routes(&self) -> impl Iterator<Item = RouteInfo<'_>> {
+        self.routes.iter().map(RouteInfo::new)
+    }
+
+    /// Add a WebSocket route at `path`.
+    ///
+    /// The handler runs once the handshake is answered, with the socket, the upgrade request (headers, query and
+    /// route parameters) and the [AppContext]. Requests that aren't valid handshakes get `426 Upgrade Required`
+    /// or `400 Bad Request`. Errors returned by the handler are logged and close the connection.
+    /// # Example
+    /// ```rust,ignore
+    /// app.ws("/echo", |mut socket, req, ctx| {
+    ///     ctx.get_state::<State<Stats>>().lock().connected(req.remote_addr());
+    ///     while let Some(Message::Text(text)) = socket.recv()? {
+    ///         socket.send_text(&text)?;
+    ///     }
+    ///     Ok(())
+    /// });
+    /// ```
+    #[cfg(feature = "ws")]
+    #[inline]
+    #[track_caller]
+    pub fn ws<F>(&mut self, path: impl Into<Cow<'static, str>>, handler: F)
+    where
+        F: Fn(crate::ws::WebSocket, &feather_runtime::http::Request, &AppContext) -> Result<(), Box<dyn std::error::Error>> + Send + Sync + 'static,
+    {
+        let path = path.into();
+        let label = crate::ws::RouteLabel::new(&path);
+        self.route(Method::GET, path, crate::ws::WsRoute(Arc::new(handler), label));
+    }
+
+    /// Add a WebSocket route at `path` that only accepts clients with a valid JWT. Requires the `ws` and `jwt` features.
+    ///
+    /// The token is read from the `Authorization: Bearer` header or, for browsers that can't set headers
+    /// on WebSockets, from the `token` query parameter, and decoded with the context's [JwtManager](crate::jwt::JwtManager).
+    /// Clients without a valid token get `401 Unauthorized` and the connection is never upgraded.
+    /// The handler receives the decoded claims after the context.
+    /// # Example
+    /// ```rust,ignore
+    /// app.ws_protected("/feed", |mut socket, _req, _ctx, claims: SimpleClaims| {
+    ///     socket.send_text(&format!("Welcome, {}", claims.sub))?;
+    ///     Ok(())
+    /// });
+    /// ```
+    #[cfg(all(feature = "ws", feature = "jwt"))]
+    #[inline]
+    #[track_caller]
+    pub fn ws_protected<C, F>(&mut self, path: impl Into<Cow<'static, str>>, handler: F)
+    where
+        C: crate::jwt::Claim + Send + 'static,
+        F: Fn(crate::ws::WebSocket, &feather_runtime::http::Request, &AppContext, C) -> Result<(), Box<dyn std::error::Error>> + Send + Sync + 'static,
+    {
+        let path = path.into();
+        let label = crate::ws::RouteLabel::new(&path);
+        self.route(Method::GET, path, crate::ws::ProtectedWsRoute(Arc::new(handler), label, std::marker::PhantomData));
     }
 
     /// Mount a [Router] to a specific path prefix.
@@ -294,14 +940,13 @@ impl App {
         let prefix = prefix.into();
         let prefix_trimmed = prefix.trim_matches('/');
 
+        #[cfg(feature = "ws")]
+        for (index, label) in &router.ws_labels {
+            label.set(&join_paths(prefix_trimmed, &router.routes[*index].path));
+        }
+
         for mut route in router.routes {
-            // Join Paths here
-            let path_trimmed = route.path.trim_matches('/');
-            let new_path = if path_trimmed.is_empty() {
-                format!("/{}", prefix_trimmed)
-            } else {
-                format!("/{}/{}", prefix_trimmed, path_trimmed)
-            };
+            let new_path = join_paths(prefix_trimmed, &route.path);
             route.path = Cow::Owned(new_path); // Moooooooo
 
             if !router.middleware.is_empty() {
@@ -315,6 +960,33 @@ impl App {
         }
     }
 
+    /// Register a [Controller], mounting its routes and middleware under its prefix.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.register_controller(UserController::new(pool.clone()));
+    /// ```
+    #[track_caller]
+    pub fn register_controller<C: Controller>(&mut self, controller: C) {
+        let prefix = controller.prefix().to_string();
+        self.mount(prefix, controller_router(controller));
+    }
+
+    /// Register the RESTful routes of a [Resource] under `path`.
+    ///
+    /// See [Resource] for the generated routes.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.resource("/users", UserController);
+    /// ```
+    #[track_caller]
+    pub fn resource<R: Resource>(&mut self, path: &str, resource: R) {
+        self.routes.extend(resource_routes(path, resource));
+    }
+
     /// Add a global middleware to the application that will be applied to all routes.
     ///
     /// Global middleware runs on every request before any route-specific middleware.
@@ -329,7 +1001,70 @@ impl App {
     /// ```
     #[inline]
     pub fn use_middleware(&mut self, middleware: impl Middleware + 'static) {
-        self.middleware.push(Arc::new(middleware));
+        self.middleware.push(GlobalMiddleware {
+            enabled: None,
+            middleware: Arc::new(middleware),
+        });
+    }
+
+    /// Add a global middleware under a name so it can be switched on and off at runtime.
+    ///
+    /// The middleware starts enabled. Use [`AppContext::middleware_toggles`] to flip it
+    /// while the server is running (e.g. from an admin route), or
+    /// [`ShutdownHandle::middleware_toggles`] from outside of it. A disabled middleware is
+    /// skipped as if it returned `next!()`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.use_named_middleware("maintenance", middleware!(|_req, res, _ctx| {
+    ///     res.set_status(503);
+    ///     res.finish_text("Down for maintenance")
+    /// }));
+    /// app.context().middleware_toggles().disable("maintenance");
+    /// ```
+    pub fn use_named_middleware(&mut self, name: &str, middleware: impl Middleware + 'static) {
+        let enabled = self.context.middleware_toggles().register(name);
+        self.middleware.push(GlobalMiddleware {
+            enabled: Some(enabled),
+            middleware: Arc::new(middleware),
+        });
+    }
+
+    /// How long a graceful shutdown may take.
+    #[cfg(all(windows, feature = "windows-service"))]
+    pub(crate) fn shutdown_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.server_config.shutdown_timeout_secs)
+    }
+
+    /// Run `middleware` before every other global middleware, but only while `enabled` is set.
+    #[cfg(all(windows, feature = "windows-service"))]
+    pub(crate) fn prepend_gate(&mut self, enabled: Arc<AtomicBool>, middleware: impl Middleware + 'static) {
+        self.middleware.insert(
+            0,
+            GlobalMiddleware {
+                enabled: Some(enabled),
+                middleware: Arc::new(middleware),
+            },
+        );
+    }
+
+    /// Add a [BodyTransform] that post-processes response bodies.
+    ///
+    /// Transforms run after the middleware chain and route handler, in the order they were added.
+    /// Each one sees the body produced by the previous one.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.use_transform(MyCompressor::default());
+    /// app.use_transform(|body: Bytes, _res: &mut Response| -> Result<Bytes, Box<dyn Error>> {
+    ///     Ok(body)
+    /// });
+    /// ```
+    #[inline]
+    pub fn use_transform(&mut self, transform: impl BodyTransform + 'static) {
+        self.transforms.push(Arc::new(transform));
     }
 
     route_methods!(
@@ -353,7 +1088,8 @@ impl App {
     ///
     /// # Panics
     ///
-    /// Panics if the server fails to bind to the specified address.
+    /// Panics if the server fails to bind to the specified address or a configured secret is unusable.
+    /// Use [try_listen](Self::try_listen) to handle these errors instead.
     ///
     /// # Example
     ///
@@ -361,13 +1097,247 @@ impl App {
     /// app.listen("127.0.0.1:5050");
     /// ```
     pub fn listen(self, address: impl ToSocketAddrs + Display) {
+        let endpoint = address.to_string();
+        if let Err(error) = self.try_listen(address) {
+            panic!("{}", error.diagnostic(endpoint));
+        }
+    }
+
+    /// Like [listen](Self::listen), but returns an error instead of panicking when the server can't start.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// use feather::ServeError;
+    ///
+    /// match app.try_listen("0.0.0.0:80") {
+    ///     Err(ServeError::PermissionDenied(_)) => eprintln!("Run with privileges or use a port above 1024"),
+    ///     Err(e) => eprintln!("Failed to start: {e}"),
+    ///     Ok(()) => {}
+    /// }
+    /// ```
+    pub fn try_listen(self, address: impl ToSocketAddrs + Display) -> Result<(), ServeError> {
+        let server = self.into_server().map_err(|e| ServeError::Config(e.to_string()))?;
+        let listener = server.bind(&address).map_err(|e| ServeError::bind(&address, e))?;
+        // Print the resolved address so binding to port 0 shows the port that was picked
+        match listener.local_addr() {
+            Ok(bound) => println!("Feather listening on : http://{bound}",),
+            Err(_) => println!("Feather listening on : http://{address}",),
+        }
+        server.run_on(listener).map_err(ServeError::Io)
+    }
+
+    /// Like [listen](Self::listen), but shuts the server down gracefully once `shutdown` receives a message.
+    ///
+    /// This lets an application that embeds Feather control when it stops. Dropping every sender of the
+    /// channel also triggers the shutdown. With crossbeam or async channels, forward the message to a
+    /// `std::sync::mpsc` channel or use [start](Self::start) and its [ShutdownHandle] instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server fails to bind to the specified address.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let (stop, stopped) = std::sync::mpsc::channel();
+    /// std::thread::spawn(move || {
+    ///     supervisor.wait_for_exit();
+    ///     stop.send(()).ok();
+    /// });
+    /// app.listen_until("127.0.0.1:5050", stopped);
+    /// ```
+    pub fn listen_until<T: Send + 'static>(self, address: impl ToSocketAddrs + Display, shutdown: Receiver<T>) {
+        let server = self.into_server().unwrap_or_else(|e| start_failed(&address, e));
+        let listener = server.bind(&address).unwrap_or_else(|e| start_failed(&address, e));
+        match listener.local_addr() {
+            Ok(bound) => println!("Feather listening on : http://{bound}",),
+            Err(_) => println!("Feather listening on : http://{address}",),
+        }
+        let handle = server.handle();
+        std::thread::spawn(move || {
+            // A disconnected channel can never deliver the message, so it stops the server as well
+            let _ = shutdown.recv();
+            handle.shutdown();
+        });
+        server.run_on(listener).unwrap_or_else(|e| start_failed(&address, e));
+    }
+
+    /// Bind to `address` and start the application on a background thread.
+    ///
+    /// Unlike [listen_with_graceful_shutdown](Self::listen_with_graceful_shutdown) the socket is bound
+    /// before this returns, so binding errors are reported here and [`ShutdownHandle::local_addr`]
+    /// always holds the resolved address. Bind to port `0` to let the OS pick a free port.
+    /// Unusable secrets, like a JWT secret that is too short, fail with [`io::ErrorKind::InvalidInput`]
+    /// before the socket is bound.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let handle = app.start("127.0.0.1:0")?;
+    /// let addr = handle.local_addr().unwrap();
+    /// // ... send requests to `addr`
+    /// handle.shutdown_and_wait()?;
+    /// ```
+    pub fn start(self, address: impl ToSocketAddrs) -> io::Result<ShutdownHandle> {
+        let toggles = self.context.middleware_toggles().clone();
+        let server = self.into_server()?;
+        let listener = server.bind(address)?;
+        let local_addr = listener.local_addr()?;
+        println!("Feather listening on : http://{local_addr}",);
+        let handle = server.handle();
+        let thread = std::thread::spawn(move || server.run_on(listener));
+        Ok(ShutdownHandle::new(handle, thread, Some(local_addr), toggles))
+    }
+
+    /// Start the application on a local IPC endpoint: a Unix domain socket on Unix, a named pipe on Windows.
+    ///
+    /// Only processes on the same machine can connect, which suits admin APIs and sidecars.
+    /// Routing and middleware work exactly like with [listen](Self::listen).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the socket or pipe can't be created, e.g. because another server still listens on the path.
+    /// A socket file left behind by a crashed server is replaced.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// // Unix: curl --unix-socket /run/app/admin.sock http://localhost/health
+    /// app.listen_local("/run/app/admin.sock");
+    ///
+    /// // Windows
+    /// app.listen_local(r"\\.\pipe\app-admin");
+    /// ```
+    pub fn listen_local(mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        // Start hooks only run once the endpoint is bound, and this one goes first
+        let endpoint = path.display().to_string();
+        self.start_hooks.insert(0, Box::new(move |_: &AppContext| println!("Feather listening on : {endpoint}")));
+        self.into_server().and_then(|server| server.run_local(path)).unwrap_or_else(|e| start_failed(path.display(), e));
+    }
+
+    /// Start the application on an already bound listener.
+    ///
+    /// Use this when the socket is created by someone else, like systemd socket activation
+    /// or a test harness that binds to port 0 to get a free port.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server fails to start on the listener.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    /// let port = listener.local_addr().unwrap().port();
+    /// app.listen_on(listener);
+    ///
+    /// // systemd socket activation (fd 3 is the first passed socket)
+    /// use std::os::fd::FromRawFd;
+    /// let listener = unsafe { std::net::TcpListener::from_raw_fd(3) };
+    /// app.listen_on(listener);
+    /// ```
+    pub fn listen_on(self, listener: std::net::TcpListener) {
+        let endpoint = match listener.local_addr() {
+            Ok(address) => address.to_string(),
+            Err(_) => "a pre-bound socket".to_string(),
+        };
+        println!("Feather listening on : http://{endpoint}",);
+        self.into_server().and_then(|server| server.run_on(listener)).unwrap_or_else(|e| start_failed(&endpoint, e));
+    }
+
+    /// Start the application over HTTPS and listen for incoming requests.
+    ///
+    /// Works like [listen](Self::listen), but terminates TLS itself so no reverse proxy is needed.
+    /// Requires the `tls` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the TLS configuration is invalid or the server fails to bind to the specified address.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// use feather::TlsConfig;
+    ///
+    /// let tls = TlsConfig::from_pem_files("cert.pem", "key.pem").expect("Failed to load certificate");
+    /// app.listen_tls("0.0.0.0:443", tls);
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn listen_tls(self, address: impl ToSocketAddrs + Display, tls: TlsConfig) {
+        println!("Feather listening on : https://{address}",);
+        let endpoint = address.to_string();
+        self.into_server().and_then(|server| server.run_tls(address, &tls)).unwrap_or_else(|e| start_failed(&endpoint, e));
+    }
+
+    /// Start the application on a background thread and return a [ShutdownHandle] to stop it gracefully.
+    ///
+    /// On shutdown the server stops accepting connections, finishes the in-flight requests
+    /// (waiting at most `shutdown_timeout_secs` from the [ServerConfig]) and then returns.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let handle = app.listen_with_graceful_shutdown("127.0.0.1:5050");
+    ///
+    /// // With the `signals` feature, stop on Ctrl+C / SIGTERM:
+    /// handle.shutdown_on_signal().expect("Failed to install signal handler");
+    ///
+    /// handle.wait().expect("Server error");
+    /// ```
+    pub fn listen_with_graceful_shutdown(self, address: impl ToSocketAddrs + Display + Send + 'static) -> ShutdownHandle {
+        let toggles = self.context.middleware_toggles().clone();
+        let server = self.into_server().unwrap_or_else(|e| start_failed(&address, e));
+        println!("Feather listening on : http://{address}",);
+        let handle = server.handle();
+        let thread = std::thread::spawn(move || server.run(address));
+        ShutdownHandle::new(handle, thread, None, toggles)
+    }
+
+    /// Consume the App and build the runtime server for it.
+    ///
+    /// Fails if the configured secrets are unusable or a [provider](Self::provide) fails.
+    fn into_server(mut self) -> io::Result<Server> {
+        let config = std::mem::take(&mut self.server_config);
+        Ok(Server::with_config(self.into_service()?, config))
+    }
+
+    /// Consume the App and build the service answering its requests, see [into_server](Self::into_server).
+    pub(crate) fn into_service(self) -> io::Result<AppService> {
+        self.context.check_secrets().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        for provider in self.providers {
+            provider(&self.context).map_err(io::Error::other)?;
+        }
+        for conflict in route_conflicts(&self.routes) {
+            conflict.emit();
+        }
         let svc = AppService {
             routes: self.routes,
             middleware: self.middleware,
+            transforms: self.transforms,
             context: self.context,
-            error_handler: self.error_handler,
+            error_handlers: self.error_handlers,
+            start_hooks: self.start_hooks,
+            shutdown_hooks: self.shutdown_hooks,
         };
-        println!("Feather listening on : http://{address}",);
-        Server::with_config(svc, self.server_config).run(address).expect("Failed to start server");
+        Ok(svc)
+    }
+}
+
+/// Panics with a diagnostic when the server can't start on `endpoint`.
+fn start_failed(endpoint: impl Display, error: io::Error) -> ! {
+    panic!("{}", Diagnostic::error("failed to start server").with("address", endpoint).cause(error))
+}
+
+/// Joins a mount prefix, trimmed of slashes, with a route path.
+fn join_paths(prefix_trimmed: &str, path: &str) -> String {
+    let path_trimmed = path.trim_matches('/');
+    if path_trimmed.is_empty() {
+        format!("/{}", prefix_trimmed)
+    } else if prefix_trimmed.is_empty() {
+        format!("/{}", path_trimmed)
+    } else {
+        format!("/{}/{}", prefix_trimmed, path_trimmed)
     }
 }
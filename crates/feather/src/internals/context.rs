@@ -1,13 +1,32 @@
-use parking_lot::{Mutex, MutexGuard, RwLock};
+use parking_lot::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::any::{Any, TypeId};
 use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
 
 use std::sync::Arc;
-
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::events::EventBus;
+#[cfg(feature = "client")]
+use super::fan_out::{self, FanOutPolicy};
+use super::health::{HealthCheck, HealthReport};
+use super::schedule::Scheduler;
+use super::warmup::Warmup;
+use super::{Diagnostic, Metrics, MiddlewareToggles, Profile};
+use crate::cache::Cache;
+use crate::jobs::{Job, JobQueue};
 #[cfg(feature = "jwt")]
 use crate::jwt::JwtManager;
+#[cfg(feature = "ws")]
+use crate::ws::WsHub;
+#[cfg(feature = "client")]
+use feather_runtime::client::{Client, ClientError, ClientRequest, ClientResponse};
 
 type Erased = dyn Any + Send + Sync;
+type Probe = Box<dyn Fn(&Erased) -> Result<(), String> + Send + Sync>;
+type Probes = Vec<(TypeId, &'static str, Probe)>;
+type Teardown = Box<dyn FnOnce(&Erased) + Send>;
+type Teardowns = Vec<(TypeId, &'static str, Teardown)>;
 
 /// A thread-safe wrapper for mutable application state.
 ///
@@ -25,6 +44,7 @@ type Erased = dyn Any + Send + Sync;
 ///
 /// - Read-only configuration (store directly)
 /// - Data that should be immutable (just implement Clone)
+/// - Data read on every request and rarely written, see [`ReadState`] and [`WatchState`]
 ///
 /// # Example
 ///
@@ -156,6 +176,133 @@ impl<S: Clone> State<S> {
     }
 }
 
+/// Like [`State`], but readers don't wait for each other.
+///
+/// Backed by a [`parking_lot::RwLock`]: any number of requests can read at the same time, a writer waits
+/// for them to finish and blocks new readers meanwhile. Use it for state that is read on every request and
+/// changed in place now and then, like a routing table or a feature flag map.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::ReadState;
+///
+/// app.context().set_state(ReadState::new(HashMap::<String, bool>::new()));
+///
+/// app.get("/", middleware!(|_req, res, ctx| {
+///     let flags = ctx.get_state::<ReadState<HashMap<String, bool>>>();
+///     let beta = flags.with_scope(|flags| flags.get("beta").copied().unwrap_or(false));
+///     res.finish_text(if beta { "beta" } else { "stable" })
+/// }));
+/// ```
+pub struct ReadState<S> {
+    inner: RwLock<S>,
+}
+
+impl<S> ReadState<S> {
+    pub fn new(state: S) -> Self {
+        Self {
+            inner: RwLock::new(state),
+        }
+    }
+
+    /// Execute a closure with shared access to the inner state, alongside other readers.
+    ///
+    /// # Panics
+    ///
+    /// Writing to the same `ReadState<T>` within the scope deadlocks.
+    pub fn with_scope<R>(&self, f: impl FnOnce(&S) -> R) -> R {
+        f(&self.inner.read())
+    }
+
+    /// Execute a closure with exclusive access to the inner state, readers wait until it returns.
+    pub fn with_mut_scope<R>(&self, f: impl FnOnce(&mut S) -> R) -> R {
+        f(&mut self.inner.write())
+    }
+
+    /// Get a shared guard to read the inner state directly.
+    pub fn read(&self) -> RwLockReadGuard<'_, S> {
+        self.inner.read()
+    }
+
+    /// Get an exclusive guard to change the inner state directly.
+    pub fn write(&self) -> RwLockWriteGuard<'_, S> {
+        self.inner.write()
+    }
+}
+
+impl<S: Clone> ReadState<S> {
+    /// Get a clone of the inner state.
+    pub fn get_clone(&self) -> S {
+        self.inner.read().clone()
+    }
+}
+
+/// State replaced as a whole, for configuration that is reloaded rather than edited.
+///
+/// Readers [load](Self::load) the current value as an `Arc` and keep a consistent snapshot for as long
+/// as they hold it, while [store](Self::store) swaps in the next one. The lock is only held to clone or swap
+/// the `Arc`, so neither side waits on the other's work. [version](Self::version) tells whether the value
+/// changed since it was last looked at.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::WatchState;
+///
+/// app.context().set_state(WatchState::new(Settings::load()?));
+///
+/// app.post("/admin/reload", middleware!(|_req, res, ctx| {
+///     ctx.get_state::<WatchState<Settings>>().store(Settings::load()?);
+///     res.finish_text("reloaded")
+/// }));
+///
+/// app.get("/", middleware!(|_req, res, ctx| {
+///     let settings = ctx.get_state::<WatchState<Settings>>().load();
+///     res.finish_text(settings.greeting.clone())
+/// }));
+/// ```
+pub struct WatchState<S> {
+    current: RwLock<Arc<S>>,
+    version: AtomicU64,
+}
+
+impl<S> WatchState<S> {
+    pub fn new(state: S) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(state)),
+            version: AtomicU64::new(0),
+        }
+    }
+
+    /// The current value. Later [stores](Self::store) don't affect it.
+    pub fn load(&self) -> Arc<S> {
+        self.current.read().clone()
+    }
+
+    /// Replace the value, requests that already [loaded](Self::load) the old one keep using it.
+    pub fn store(&self, state: S) {
+        // The old value is dropped after the lock is released, readers never wait on its destructor
+        let old = std::mem::replace(&mut *self.current.write(), Arc::new(state));
+        self.version.fetch_add(1, Ordering::Release);
+        drop(old);
+    }
+
+    /// Replace the value with one derived from the current one, like a copy with a field changed.
+    ///
+    /// Concurrent updates are applied one after the other, none of them is lost.
+    pub fn update(&self, f: impl FnOnce(&S) -> S) {
+        let mut current = self.current.write();
+        *current = Arc::new(f(&current));
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    /// How many times the value was replaced.
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+}
+
 #[derive(Clone)]
 /// Application-wide context for state management and request handling.
 ///
@@ -165,6 +312,8 @@ impl<S: Clone> State<S> {
 /// - Share resources between requests
 ///
 /// `AppContext` is thread-safe and can be accessed from multiple threads simultaneously.
+/// Clones share the state, metrics and toggles, which is how work that outlives a request, like a
+/// WebSocket handler, keeps access to them.
 ///
 /// # Example
 ///
@@ -186,6 +335,20 @@ impl<S: Clone> State<S> {
 /// ```
 pub struct AppContext {
     pub inner: Arc<RwLock<HashMap<TypeId, Arc<Erased>>>>,
+    names: Arc<RwLock<HashMap<TypeId, &'static str>>>,
+    toggles: MiddlewareToggles,
+    metrics: Metrics,
+    /// `None` until set with `FEATHER_ENV` or [`set_profile`](Self::set_profile)
+    profile: Option<Profile>,
+    probes: Arc<RwLock<Probes>>,
+    teardowns: Arc<Mutex<Teardowns>>,
+    warmup: Warmup,
+    scheduler: Scheduler,
+    jobs: JobQueue,
+    events: EventBus,
+    cache: Cache,
+    #[cfg(feature = "ws")]
+    ws_hub: WsHub,
     #[cfg(feature = "jwt")]
     jwt: Option<JwtManager>,
 }
@@ -201,13 +364,213 @@ impl AppContext {
     /// let ctx = AppContext::new();
     /// ```
     pub fn new() -> Self {
+        let metrics = Metrics::default();
         Self {
             inner: Arc::new(RwLock::new(HashMap::new())),
+            names: Arc::new(RwLock::new(HashMap::new())),
+            toggles: MiddlewareToggles::default(),
+            #[cfg(feature = "ws")]
+            ws_hub: WsHub::with_metrics(metrics.clone()),
+            metrics,
+            profile: Profile::from_env_var(),
+            probes: Arc::new(RwLock::new(Vec::new())),
+            teardowns: Arc::new(Mutex::new(Vec::new())),
+            warmup: Warmup::default(),
+            scheduler: Scheduler::default(),
+            jobs: JobQueue::default(),
+            events: EventBus::default(),
+            cache: Cache::default(),
             #[cfg(feature = "jwt")]
             jwt: None,
         }
     }
 
+    pub(crate) fn warmup(&self) -> &Warmup {
+        &self.warmup
+    }
+
+    pub(crate) fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    pub(crate) fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Access the runtime switches of named global middlewares.
+    ///
+    /// See [`App::use_named_middleware`](crate::App::use_named_middleware).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// ctx.middleware_toggles().disable("compression");
+    /// ```
+    pub fn middleware_toggles(&self) -> &MiddlewareToggles {
+        &self.toggles
+    }
+
+    /// Access the application-wide [`Metrics`] registry.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// ctx.metrics().counter("signups").increment();
+    /// ```
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Access the [`WsHub`] tracking the sockets of [`App::ws`](crate::App::ws) routes. Requires the `ws` feature.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// ctx.ws_hub().to_room("lobby").send(Message::Text("Server restarting".into()));
+    /// ```
+    #[cfg(feature = "ws")]
+    pub fn ws_hub(&self) -> &WsHub {
+        &self.ws_hub
+    }
+
+    /// Run `task` in the background, without delaying the response.
+    ///
+    /// The task runs on its own coroutine with the server's [`stack_size`](crate::App::stack_size).
+    /// Panics in the task are logged and the server isn't waiting for it on shutdown.
+    /// Clone what the task needs from the state before moving it in.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let mailer = ctx.get_state::<Mailer>();
+    /// ctx.spawn(move || {
+    ///     mailer.send_welcome(&email);
+    /// });
+    /// res.finish_text("Signed up")
+    /// ```
+    pub fn spawn(&self, task: impl FnOnce() + Send + 'static) {
+        feather_runtime::runtime::spawn(task);
+    }
+
+    /// Run `work` on a pool of OS threads and wait for its result, without holding up the other coroutines.
+    ///
+    /// Handlers share their worker threads with many connections, so CPU heavy work or blocking calls like
+    /// FFI and synchronous drivers belong here. The pool has up to [`blocking_threads`](crate::App::blocking_threads)
+    /// threads. A panic in `work` is resumed in the handler.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let thumbnail = ctx.run_blocking(move || image::load_from_memory(&upload).map(|image| image.thumbnail(128, 128)))?;
+    /// ```
+    pub fn run_blocking<T: Send + 'static>(&self, work: impl FnOnce() -> T + Send + 'static) -> T {
+        feather_runtime::runtime::run_blocking(work)
+    }
+
+    /// Send every request with the runtime's HTTP client, each on its own coroutine, and wait for all of them.
+    ///
+    /// Made for aggregation endpoints that ask several upstream services at once. Each request is limited by its
+    /// own [timeout](feather_runtime::client::ClientRequest::timeout), 30 seconds if it has none, and the results
+    /// are in the order of the requests. The `policy` decides whether a failed request cancels the others. No request
+    /// keeps running once this returns. Requires the `client` feature.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let requests = [
+    ///     ClientRequest::get(profile_url).timeout(Duration::from_millis(300)),
+    ///     ClientRequest::get(orders_url).timeout(Duration::from_secs(2)),
+    /// ];
+    /// let [profile, orders] = ctx.parallel_fetch(requests, FanOutPolicy::Collect).try_into().unwrap();
+    /// let profile = profile?;
+    /// let orders = orders.ok().filter(|orders| orders.status.is_success());
+    /// ```
+    #[cfg(feature = "client")]
+    pub fn parallel_fetch(&self, requests: impl IntoIterator<Item = ClientRequest>, policy: FanOutPolicy) -> Vec<Result<ClientResponse, ClientError>> {
+        fan_out::fan_out(&Client::new(), requests, policy)
+    }
+
+    /// Like [parallel_fetch](Self::parallel_fetch) with a client of your own, e.g. one trusting an internal CA.
+    #[cfg(feature = "client")]
+    pub fn parallel_fetch_with(&self, client: &Client, requests: impl IntoIterator<Item = ClientRequest>, policy: FanOutPolicy) -> Vec<Result<ClientResponse, ClientError>> {
+        fan_out::fan_out(client, requests, policy)
+    }
+
+    /// Queue `job` for the background workers, see [`jobs`](crate::jobs).
+    ///
+    /// Unlike [spawn](Self::spawn), failed jobs are retried, at most [`JobConfig::workers`](crate::jobs::JobConfig::workers)
+    /// run at once, and a graceful shutdown waits for the queued ones.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// ctx.enqueue(SendWelcome { email });
+    /// ctx.enqueue(Job::new(move |ctx: &AppContext| ctx.get_state::<Search>().reindex(id)).retry(RetryPolicy::never()));
+    /// ```
+    pub fn enqueue(&self, job: impl Into<Job>) {
+        self.jobs.enqueue(job);
+    }
+
+    /// The queue of [enqueued](Self::enqueue) jobs.
+    pub fn jobs(&self) -> &JobQueue {
+        &self.jobs
+    }
+
+    /// Publish `event` to the subscribers of its type, added with [`App::on_event`](crate::App::on_event).
+    ///
+    /// Each subscriber runs on its own coroutine, so the emitter doesn't wait for them and doesn't need to
+    /// know who is listening. Panics in a subscriber are logged. Returns the number of subscribers notified.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// ctx.emit(UserRegistered { id: user.id, email: user.email.clone() });
+    /// res.finish_json(&user)
+    /// ```
+    pub fn emit<E: Send + Sync + 'static>(&self, event: E) -> usize {
+        self.events.emit(self, event)
+    }
+
+    /// The app-wide [`Cache`] for memoizing expensive lookups, see [`cache`](crate::cache).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let country = ctx.cache().get_or_insert_with(&ip, Duration::from_secs(3600), || geoip.lookup(&ip));
+    /// ```
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+
+    /// The [`Profile`] the application runs in, read from `FEATHER_ENV` by default.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// if ctx.profile().is_dev() {
+    ///     res.add_header("X-Debug", "1")?;
+    /// }
+    /// ```
+    pub fn profile(&self) -> Profile {
+        self.profile.unwrap_or_default()
+    }
+
+    /// The profile if it was set with `FEATHER_ENV` or [`set_profile`](Self::set_profile), rather than defaulted.
+    pub(crate) fn explicit_profile(&self) -> Option<Profile> {
+        self.profile
+    }
+
+    /// Override the [`Profile`] detected from `FEATHER_ENV`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.context().set_profile(Profile::Staging);
+    /// ```
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.profile = Some(profile);
+    }
+
     /// Sets the JWT manager for this context.
     ///
     /// This should be called before any middleware tries to access the JWT manager.
@@ -245,8 +608,110 @@ impl AppContext {
     ///
     /// [`set_jwt`]: Self::set_jwt
     #[cfg(feature = "jwt")]
+    #[track_caller]
     pub fn jwt(&self) -> &JwtManager {
-        self.jwt.as_ref().expect("JwtManager has not been set!")
+        match &self.jwt {
+            Some(jwt) => jwt,
+            None => panic!(
+                "{}",
+                Diagnostic::error("no JwtManager configured").help("call `ctx.set_jwt(JwtManager::new(secret))` before the server starts")
+            ),
+        }
+    }
+
+    /// Check a connection out of the pool opened with [`App::with_pool`](crate::App::with_pool) for the manager `M`,
+    /// see [`db`](crate::db). Requires the `db` feature.
+    ///
+    /// # Errors
+    ///
+    /// When no connection could be had within the connection timeout of the pool.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no pool for `M`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let conn = ctx.db::<SqliteConnectionManager>()?;
+    /// conn.execute("DELETE FROM sessions WHERE expires < ?1", [now])?;
+    /// ```
+    #[cfg(feature = "db")]
+    #[track_caller]
+    pub fn db<M: crate::db::ManageConnection>(&self) -> Result<crate::db::PooledConnection<M>, crate::db::Error> {
+        match self.try_get_state::<crate::db::Pool<M>>() {
+            Some(pool) => crate::db::checkout(&pool, &self.metrics),
+            None => panic!(
+                "{}",
+                Diagnostic::error("no database pool for the requested manager").with("manager", std::any::type_name::<M>()).help("open one with `app.with_pool(manager, size)` before the server starts")
+            ),
+        }
+    }
+
+    /// Get the settings loaded with [`App::load_config`](crate::App::load_config). Requires the `json` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no settings of type `T` were loaded.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let settings = ctx.config::<Settings>();
+    /// let pool = Pool::connect(&settings.database.url)?;
+    /// ```
+    #[cfg(feature = "json")]
+    #[track_caller]
+    pub fn config<T: Send + Sync + 'static>(&self) -> Arc<T> {
+        match self.try_get_state::<T>() {
+            Some(config) => config,
+            None => panic!(
+                "{}",
+                Diagnostic::error("config not loaded for requested type").with("type", std::any::type_name::<T>()).help("load it with `app.load_config::<T>()` before the server starts")
+            ),
+        }
+    }
+
+    /// Register the engine [`res.render`](crate::templates::ResponseTemplates::render) renders pages with,
+    /// replacing the one registered before. Requires the `json` feature.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// use feather::templates::MiniJinja;
+    ///
+    /// app.context().set_templates(MiniJinja::new("templates"));
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn set_templates(&self, engine: impl crate::templates::TemplateEngine) {
+        self.set_state(crate::templates::Templates(Arc::new(engine)));
+    }
+
+    /// Get the engine registered with [`set_templates`](Self::set_templates). Requires the `json` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no engine was registered.
+    #[cfg(feature = "json")]
+    #[track_caller]
+    pub fn templates(&self) -> Arc<dyn crate::templates::TemplateEngine> {
+        match self.try_get_state::<crate::templates::Templates>() {
+            Some(templates) => templates.0.clone(),
+            None => panic!(
+                "{}",
+                Diagnostic::error("no template engine registered").help("register one with `ctx.set_templates(engine)` before the server starts")
+            ),
+        }
+    }
+
+    /// Checks the configured secrets, so a weak one stops the server at startup instead of
+    /// failing the first request that uses it.
+    pub(crate) fn check_secrets(&self) -> Result<(), String> {
+        #[cfg(feature = "jwt")]
+        if let Some(jwt) = &self.jwt {
+            jwt.check_secret(self.profile())?;
+        }
+        Ok(())
     }
 
     /// Insert or replace a state value keyed by its concrete type.
@@ -268,6 +733,105 @@ impl AppContext {
     pub fn set_state<T: Send + Sync + 'static>(&self, value: T) {
         let mut map = self.inner.write();
         map.insert(TypeId::of::<T>(), Arc::new(value));
+        self.names.write().insert(TypeId::of::<T>(), std::any::type_name::<T>());
+    }
+
+    /// Insert or replace a state value along with a probe that reports whether it is healthy.
+    ///
+    /// Probes run on every [`health`](Self::health) check, like the ones of the
+    /// [`Readiness`](crate::middlewares::builtins::Readiness) endpoint, so registering a dependency
+    /// this way makes it observable without extra wiring.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// ctx.set_state_with_health(pool, |pool: &Pool| pool.ping());
+    /// ```
+    pub fn set_state_with_health<T, E>(&self, value: T, probe: impl Fn(&T) -> Result<(), E> + Send + Sync + 'static)
+    where
+        T: Send + Sync + 'static,
+        E: std::fmt::Display,
+    {
+        let probe: Probe = Box::new(move |value: &Erased| match value.downcast_ref::<T>() {
+            Some(value) => probe(value).map_err(|e| e.to_string()),
+            None => Ok(()),
+        });
+        let mut probes = self.probes.write();
+        probes.retain(|(id, _, _)| *id != TypeId::of::<T>());
+        probes.push((TypeId::of::<T>(), std::any::type_name::<T>(), probe));
+        drop(probes);
+        self.set_state(value);
+    }
+
+    /// Insert or replace a state value along with the `teardown` closing it when the server shuts down.
+    ///
+    /// Teardowns run once, after the [`on_shutdown`](crate::App::on_shutdown) hooks and in the reverse order
+    /// of registration, so state set up later, which may use earlier state, is closed first. A value replaced
+    /// with another call is dropped without its teardown, the new one's is run instead, and removed state
+    /// isn't torn down.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// ctx.set_state_with_teardown(pool, |pool: &Pool| pool.close());
+    /// ```
+    pub fn set_state_with_teardown<T>(&self, value: T, teardown: impl FnOnce(&T) + Send + 'static)
+    where
+        T: Send + Sync + 'static,
+    {
+        let teardown: Teardown = Box::new(move |value: &Erased| {
+            if let Some(value) = value.downcast_ref::<T>() {
+                teardown(value);
+            }
+        });
+        let mut teardowns = self.teardowns.lock();
+        teardowns.retain(|(id, _, _)| *id != TypeId::of::<T>());
+        teardowns.push((TypeId::of::<T>(), std::any::type_name::<T>(), teardown));
+        drop(teardowns);
+        self.set_state(value);
+    }
+
+    /// Runs the teardowns of [`set_state_with_teardown`](Self::set_state_with_teardown), last registered first.
+    /// A panicking teardown is reported and doesn't keep the others from running.
+    pub(crate) fn teardown(&self) {
+        let teardowns = std::mem::take(&mut *self.teardowns.lock());
+        for (id, name, teardown) in teardowns.into_iter().rev() {
+            let Some(value) = self.inner.read().get(&id).cloned() else {
+                continue;
+            };
+            if panic::catch_unwind(AssertUnwindSafe(|| teardown(&*value))).is_err() {
+                Diagnostic::error("state teardown panicked").with("type", name).note("the remaining state is still torn down").emit();
+            }
+        }
+    }
+
+    /// Run the probes registered with [`set_state_with_health`](Self::set_state_with_health).
+    ///
+    /// While tasks added with [`App::warmup`](crate::App::warmup) are running or if one of them failed,
+    /// the report starts with a failing `warmup` check.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let report = ctx.health();
+    /// if !report.is_healthy() {
+    ///     eprintln!("{report}");
+    /// }
+    /// ```
+    pub fn health(&self) -> HealthReport {
+        let probes = self.probes.read();
+        let checks = self
+            .warmup
+            .check()
+            .into_iter()
+            .chain(probes.iter().map(|(id, name, probe)| {
+                // Clone the value out so slow probes don't hold the state lock
+                let value = self.inner.read().get(id).cloned();
+                let result = value.map_or(Ok(()), |value| probe(&*value));
+                HealthCheck::new(name, result.err())
+            }))
+            .collect();
+        HealthReport::new(checks)
     }
 
     /// Try to fetch state by type, returning `Some(Arc<T>)` if present.
@@ -307,8 +871,41 @@ impl AppContext {
     /// ```
     ///
     /// [`try_get_state`]: Self::try_get_state
+    #[track_caller]
     pub fn get_state<T: Send + Sync + 'static>(&self) -> Arc<T> {
-        self.try_get_state::<T>().expect("state not found for requested type")
+        match self.try_get_state::<T>() {
+            Some(state) => state,
+            None => {
+                let stored = match self.debug_entries() {
+                    entries if entries.is_empty() => "nothing".to_string(),
+                    entries => entries.join(", "),
+                };
+                panic!(
+                    "{}",
+                    Diagnostic::error("state not found for requested type").with("type", std::any::type_name::<T>()).with("stored", stored).help("register it with `ctx.set_state(...)` before the server starts, or use `try_get_state`")
+                )
+            }
+        }
+    }
+
+    /// The type names of the stored state, sorted, to find out why a [`get_state`](Self::get_state) misses.
+    ///
+    /// Wrappers are part of the name, so a value stored as `State<Config>` doesn't show up as `Config`.
+    /// Values inserted directly into [`inner`](Self::inner) are listed as `<unnamed>`.
+    /// The [`StateInspector`](crate::middlewares::builtins::StateInspector) middleware serves this list in development.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// for name in ctx.debug_entries() {
+    ///     println!("{name}");
+    /// }
+    /// ```
+    pub fn debug_entries(&self) -> Vec<&'static str> {
+        let names = self.names.read();
+        let mut entries: Vec<_> = self.inner.read().keys().map(|id| names.get(id).copied().unwrap_or("<unnamed>")).collect();
+        entries.sort_unstable();
+        entries
     }
 
     /// Remove a state value of the given type.
@@ -323,6 +920,8 @@ impl AppContext {
     /// }
     /// ```
     pub fn remove_state<T: Send + Sync + 'static>(&self) -> bool {
+        self.probes.write().retain(|(id, _, _)| *id != TypeId::of::<T>());
+        self.names.write().remove(&TypeId::of::<T>());
         let mut map = self.inner.write();
         map.remove(&TypeId::of::<T>()).is_some()
     }
@@ -355,6 +954,68 @@ mod tests {
         host: String,
     }
 
+    #[test]
+    fn test_read_state_allows_concurrent_readers() {
+        let state = ReadState::new(Counter {
+            count: 1,
+        });
+        let (first, second) = (state.read(), state.read());
+        assert_eq!(first.count + second.count, 2);
+        drop((first, second));
+        state.with_mut_scope(|counter| counter.count += 1);
+        assert_eq!(state.with_scope(|counter| counter.count), 2);
+        assert_eq!(state.get_clone().count, 2);
+    }
+
+    #[test]
+    fn test_watch_state_swaps_whole_values() {
+        let state = WatchState::new(Config {
+            port: 80,
+            host: "localhost".to_string(),
+        });
+        let before = state.load();
+        state.update(|config| Config {
+            port: 8080,
+            ..config.clone()
+        });
+        state.store(Config {
+            port: state.load().port + 1,
+            host: "example.com".to_string(),
+        });
+
+        // Snapshots taken before stay consistent
+        assert_eq!((before.port, before.host.as_str()), (80, "localhost"));
+        let after = state.load();
+        assert_eq!((after.port, after.host.as_str()), (8081, "example.com"));
+        assert_eq!(state.version(), 2);
+    }
+
+    #[test]
+    fn test_teardowns_run_once_in_reverse_order() {
+        let ctx = AppContext::new();
+        let closed = Arc::new(Mutex::new(Vec::new()));
+        let log = closed.clone();
+        ctx.set_state_with_teardown(String::from("pool"), move |pool| log.lock().push(pool.clone()));
+        let log = closed.clone();
+        ctx.set_state_with_teardown(
+            Counter {
+                count: 1,
+            },
+            move |_| panic!("teardown failed"),
+        );
+        ctx.set_state_with_teardown(
+            Config {
+                port: 80,
+                host: "cache".to_string(),
+            },
+            move |config| log.lock().push(config.host.clone()),
+        );
+
+        ctx.teardown();
+        ctx.teardown();
+        assert_eq!(*closed.lock(), ["cache", "pool"]);
+    }
+
     #[test]
     fn test_set_and_get_state() {
         let ctx = AppContext::new();
@@ -676,4 +1337,16 @@ mod tests {
         let final_value = ctx.get_state::<String>();
         assert_eq!(*final_value, "value-99");
     }
+
+    #[test]
+    fn test_spawn_runs_in_background() {
+        use std::sync::mpsc;
+        use std::time::Duration;
+
+        let ctx = AppContext::new();
+        let (done, finished) = mpsc::channel();
+        ctx.spawn(|| panic!("a failing task doesn't take others down"));
+        ctx.spawn(move || done.send(42).unwrap());
+        assert_eq!(finished.recv_timeout(Duration::from_secs(5)), Ok(42));
+    }
 }
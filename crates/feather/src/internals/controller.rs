@@ -0,0 +1,177 @@
+use std::borrow::Cow;
+use std::panic::Location;
+use std::sync::Arc;
+
+use feather_runtime::Method;
+use feather_runtime::http::{Request, Response};
+
+use super::Router;
+use super::app::Route;
+use crate::middlewares::Middleware;
+use crate::{AppContext, Outcome};
+
+type Handler<C> = Arc<dyn Fn(&C, &mut Request, &mut Response, &AppContext) -> Outcome + Send + Sync>;
+
+/// A route declared by a [Controller].
+///
+/// The handler receives the controller itself, so it can use the dependencies the controller was built with.
+pub struct RouteDef<C> {
+    method: Method,
+    path: Cow<'static, str>,
+    handler: Handler<C>,
+}
+
+macro_rules! route_def_methods {
+    ($($method:ident $name:ident)+) => {
+        $(
+            /// Declare a route for the HTTP method.
+            #[inline]
+            pub fn $name(path: impl Into<Cow<'static, str>>, handler: impl Fn(&C, &mut Request, &mut Response, &AppContext) -> Outcome + Send + Sync + 'static) -> Self {
+                Self::new(Method::$method, path, handler)
+            }
+        )+
+    }
+}
+
+impl<C> RouteDef<C> {
+    /// Declare a route with any HTTP method.
+    pub fn new(method: Method, path: impl Into<Cow<'static, str>>, handler: impl Fn(&C, &mut Request, &mut Response, &AppContext) -> Outcome + Send + Sync + 'static) -> Self {
+        Self {
+            method,
+            path: path.into(),
+            handler: Arc::new(handler),
+        }
+    }
+
+    route_def_methods!(
+        GET get
+        POST post
+        PUT put
+        DELETE delete
+        PATCH patch
+        HEAD head
+        OPTIONS options
+    );
+}
+
+/// A group of handlers implemented as a struct, registered with [`App::register_controller`](crate::App::register_controller).
+///
+/// Controllers get their dependencies (database pools, clients, configuration...) injected when they
+/// are constructed instead of fetching them from the [`AppContext`] in every handler.
+/// All routes are mounted under [`prefix`](Self::prefix) and the controller [`middleware`](Self::middleware)
+/// only runs for them, like a mounted [`Router`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::{Controller, RouteDef};
+///
+/// struct UserController {
+///     db: Pool,
+/// }
+///
+/// impl UserController {
+///     fn list(&self, _req: &mut Request, res: &mut Response, _ctx: &AppContext) -> Outcome {
+///         let users = self.db.all_users()?;
+///         res.finish_json(&users)
+///     }
+/// }
+///
+/// impl Controller for UserController {
+///     fn prefix(&self) -> &str {
+///         "/users"
+///     }
+///
+///     fn routes(&self) -> Vec<RouteDef<Self>> {
+///         vec![RouteDef::get("/", Self::list)]
+///     }
+/// }
+///
+/// app.register_controller(UserController { db: pool });
+/// ```
+pub trait Controller: Sized + Send + Sync + 'static {
+    /// Path prefix for all routes of the controller. Defaults to the root.
+    fn prefix(&self) -> &str {
+        "/"
+    }
+
+    /// The routes handled by this controller, relative to [`prefix`](Self::prefix).
+    fn routes(&self) -> Vec<RouteDef<Self>>;
+
+    /// Middleware that runs before every route of this controller.
+    fn middleware(&self) -> Vec<Arc<dyn Middleware>> {
+        Vec::new()
+    }
+}
+
+/// Turns a [Controller] into a [Router] that can be mounted at its prefix.
+#[track_caller]
+pub(crate) fn controller_router<C: Controller>(controller: C) -> Router {
+    let location = Location::caller();
+    let defs = controller.routes();
+    let middleware = controller.middleware();
+    let controller = Arc::new(controller);
+    let routes = defs
+        .into_iter()
+        .map(|def| {
+            let controller = controller.clone();
+            let handler = def.handler;
+            Route {
+                method: def.method,
+                path: def.path,
+                middleware: Arc::new(move |req: &mut Request, res: &mut Response, ctx: &AppContext| handler(&controller, req, res, ctx)),
+                location: Some(location),
+                meta: Default::default(),
+                annotations: Default::default(),
+            }
+        })
+        .collect();
+    Router {
+        routes,
+        middleware,
+        #[cfg(feature = "ws")]
+        ws_labels: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internals::Finalizer;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    struct Greeter {
+        greeting: String,
+    }
+
+    impl Greeter {
+        fn greet(&self, _req: &mut Request, res: &mut Response, _ctx: &AppContext) -> Outcome {
+            res.finish_text(self.greeting.clone())
+        }
+    }
+
+    impl Controller for Greeter {
+        fn prefix(&self) -> &str {
+            "/greet"
+        }
+
+        fn routes(&self) -> Vec<RouteDef<Self>> {
+            vec![RouteDef::get("/", Self::greet), RouteDef::get("/again", Self::greet)]
+        }
+    }
+
+    #[test]
+    fn test_controller_routes_share_state() {
+        let router = controller_router(Greeter {
+            greeting: "hello".to_string(),
+        });
+        let paths: Vec<_> = router.routes.iter().map(|route| route.path.as_ref()).collect();
+        assert_eq!(paths, ["/", "/again"]);
+
+        let raw = b"GET /greet/again HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let mut request = Request::parse(raw, Default::default(), SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap();
+        let mut response = Response::default();
+        router.routes[1].middleware.handle(&mut request, &mut response, &AppContext::new()).unwrap();
+        assert_eq!(response.body.as_deref(), Some(&b"hello"[..]));
+    }
+}
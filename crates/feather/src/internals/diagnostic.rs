@@ -0,0 +1,281 @@
+use super::app::Route;
+use feather_runtime::Method;
+use std::error::Error;
+use std::fmt::{self, Display, Write};
+use std::io::IsTerminal;
+use std::panic::Location;
+
+/// How serious a [Diagnostic] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    /// ANSI color of the label: bold red or bold yellow.
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Error => "\x1b[1;31m",
+            Severity::Warning => "\x1b[1;33m",
+        }
+    }
+}
+
+/// A problem found by the framework, with the context needed to track it down.
+///
+/// Feather reports route conflicts, unhandled errors and panics as diagnostics: a one-line message followed by
+/// aligned fields like the route and the source location, in the spirit of compiler errors.
+///
+/// ```text
+/// warning: conflicting routes
+///    route: GET /users/:id
+///       at: src/main.rs:14:9
+///    first: GET /users/:user at src/main.rs:10:9
+///     help: requests only reach the later route when the first one returns `next_route!()`
+/// ```
+///
+/// [`emit`](Self::emit) sends it through the logger when one is installed and to stderr otherwise,
+/// colored when stderr is a terminal and `NO_COLOR` is not set.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::Diagnostic;
+///
+/// app.set_error_handler(Box::new(|err, req, res| {
+///     Diagnostic::error("request failed").route(&req.method, &req.path()).cause(&err).emit();
+///     res.set_status(500).send_text("Internal Server Error");
+/// }));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    severity: Severity,
+    message: String,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl Diagnostic {
+    /// A new error diagnostic.
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message.into())
+    }
+
+    /// A new warning diagnostic.
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message.into())
+    }
+
+    fn new(severity: Severity, message: String) -> Self {
+        Self {
+            severity,
+            message,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Add a labeled line of context.
+    pub fn with(mut self, label: &'static str, value: impl Display) -> Self {
+        self.fields.push((label, value.to_string()));
+        self
+    }
+
+    /// The route the diagnostic is about.
+    pub fn route(self, method: &Method, path: &str) -> Self {
+        self.with("route", format_args!("{method} {path}"))
+    }
+
+    /// The place in the source the diagnostic points to.
+    pub fn location(self, location: &Location<'_>) -> Self {
+        self.with("at", location)
+    }
+
+    /// The error that caused the diagnostic.
+    pub fn cause(self, error: impl Display) -> Self {
+        self.with("cause", error)
+    }
+
+    /// The error that caused the diagnostic and every error it wraps, following [`Error::source`]. Shows the
+    /// context added with crates like `anyhow` or `eyre`, one `caused by` line each.
+    pub fn cause_chain(self, error: &(dyn Error + 'static)) -> Self {
+        let mut diagnostic = self.cause(error);
+        let mut source = error.source();
+        while let Some(error) = source {
+            diagnostic = diagnostic.with("caused by", error);
+            source = error.source();
+        }
+        diagnostic
+    }
+
+    /// Additional information.
+    pub fn note(self, note: impl Display) -> Self {
+        self.with("note", note)
+    }
+
+    /// A suggestion on how to fix the problem.
+    pub fn help(self, help: impl Display) -> Self {
+        self.with("help", help)
+    }
+
+    /// How serious the problem is.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// The one-line summary.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The value of the first field with `label`.
+    pub fn field(&self, label: &str) -> Option<&str> {
+        self.fields.iter().find(|(l, _)| *l == label).map(|(_, value)| value.as_str())
+    }
+
+    /// Render the diagnostic, with ANSI colors if `color` is set.
+    pub fn render(&self, color: bool) -> String {
+        let paint = |code: &'static str| {
+            if color {
+                code
+            } else {
+                ""
+            }
+        };
+        let reset = paint("\x1b[0m");
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "{}{}{reset}{}: {}{reset}",
+            paint(self.severity.color()),
+            self.severity.label(),
+            paint("\x1b[1m"),
+            self.message
+        );
+
+        // Labels are right-aligned so the values line up
+        let width = self.fields.iter().map(|(label, _)| label.len()).max().unwrap_or(0) + 2;
+        for (label, value) in &self.fields {
+            let mut lines = value.lines();
+            let _ = write!(out, "\n{}{label:>width$}:{reset} {}", paint("\x1b[1;34m"), lines.next().unwrap_or_default());
+            for line in lines {
+                let _ = write!(out, "\n{:width$}  {line}", "");
+            }
+        }
+        out
+    }
+
+    /// Report the diagnostic through the logger, or on stderr if no logger takes it.
+    pub fn emit(&self) {
+        let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        #[cfg(feature = "log")]
+        {
+            let level = match self.severity {
+                Severity::Error => log::Level::Error,
+                Severity::Warning => log::Level::Warn,
+            };
+            if log::log_enabled!(level) {
+                log::log!(level, "{}", self.render(color));
+                return;
+            }
+        }
+        eprintln!("{}", self.render(color));
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(false))
+    }
+}
+
+/// Warns about routes that can never be reached because an earlier route has the same method and pattern.
+///
+/// Parameter names don't matter, `/users/:id` and `/users/:name` conflict.
+pub(crate) fn route_conflicts(routes: &[Route]) -> Vec<Diagnostic> {
+    fn shape(path: &str) -> Vec<&str> {
+        path.trim_matches('/')
+            .split('/')
+            .map(|segment| {
+                if segment.starts_with(':') {
+                    ":"
+                } else {
+                    segment
+                }
+            })
+            .collect()
+    }
+    let mut conflicts = Vec::new();
+    for (i, route) in routes.iter().enumerate() {
+        let Some(first) = routes[..i].iter().find(|earlier| earlier.method == route.method && shape(&earlier.path) == shape(&route.path)) else {
+            continue;
+        };
+        let mut diagnostic = Diagnostic::warning("conflicting routes").route(&route.method, &route.path);
+        if let Some(location) = route.location {
+            diagnostic = diagnostic.location(location);
+        }
+        diagnostic = match first.location {
+            Some(location) => diagnostic.with("first", format_args!("{} {} at {location}", first.method, first.path)),
+            None => diagnostic.with("first", format_args!("{} {}", first.method, first.path)),
+        };
+        conflicts.push(diagnostic.help("requests only reach the later route when the first one returns `next_route!()`"));
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AppContext, Outcome, Request, Response, next};
+    use std::borrow::Cow;
+    use std::sync::Arc;
+
+    fn route(method: Method, path: &'static str) -> Route {
+        Route {
+            method,
+            path: Cow::Borrowed(path),
+            middleware: Arc::new(|_: &mut Request, _: &mut Response, _: &AppContext| -> Outcome { next!() }),
+            location: Some(Location::caller()),
+            meta: Default::default(),
+            annotations: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_route_conflicts() {
+        let routes = [route(Method::GET, "/users/:id"), route(Method::POST, "/users/:id"), route(Method::GET, "/users/:name/"), route(Method::GET, "/users/me")];
+        let conflicts = route_conflicts(&routes);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].field("route"), Some("GET /users/:name/"));
+        assert!(conflicts[0].field("first").unwrap().starts_with("GET /users/:id at "));
+    }
+
+    #[test]
+    fn test_render_aligns_fields() {
+        let diagnostic = Diagnostic::warning("conflicting routes").route(&Method::GET, "/users/:id").with("first", "src/main.rs:10:9").help("first line\nsecond line");
+        assert_eq!(
+            diagnostic.to_string(),
+            "warning: conflicting routes\n  route: GET /users/:id\n  first: src/main.rs:10:9\n   help: first line\n         second line"
+        );
+        assert_eq!(diagnostic.field("first"), Some("src/main.rs:10:9"));
+        assert!(diagnostic.render(true).starts_with("\x1b[1;33mwarning\x1b[0m"));
+    }
+
+    #[cfg(feature = "anyhow")]
+    #[test]
+    fn test_cause_chain_lists_the_sources() {
+        let error = anyhow::Error::new(std::io::Error::other("disk full")).context("writing users.json").context("saving user 7");
+        let error = crate::Error::from_anyhow(error);
+        let diagnostic = Diagnostic::error("unhandled error in route handler").cause_chain(error.source());
+        assert_eq!(
+            diagnostic.to_string(),
+            "error: unhandled error in route handler\n      cause: saving user 7\n  caused by: writing users.json\n  caused by: disk full"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::panic::Location;
+
+use feather_runtime::http::Response;
+use feather_runtime::{HeaderName, HeaderValue, Method};
+
+use super::Diagnostic;
+use super::app::Route;
+use super::service::AppService;
+
+/// What a route expects from clients, advertised in the automatic `OPTIONS` responses.
+///
+/// Attach it with [`App::describe`](crate::App::describe) or [`Router::describe`](crate::Router::describe).
+///
+/// # Example
+///
+/// ```rust,ignore
+/// app.post("/users", create_user);
+/// app.describe(Method::POST, "/users", RouteMeta::new().accepts("application/json").auth("Bearer"));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RouteMeta {
+    accepts: Vec<Cow<'static, str>>,
+    auth: Option<Cow<'static, str>>,
+}
+
+impl RouteMeta {
+    /// An empty description.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a content type the route accepts as request body.
+    pub fn accepts(mut self, content_type: impl Into<Cow<'static, str>>) -> Self {
+        self.accepts.push(content_type.into());
+        self
+    }
+
+    /// Mark the route as requiring authentication with `scheme`, like `Bearer` or `Basic`.
+    pub fn auth(mut self, scheme: impl Into<Cow<'static, str>>) -> Self {
+        self.auth = Some(scheme.into());
+        self
+    }
+
+    /// The content types accepted as request body.
+    pub fn accepted(&self) -> &[Cow<'static, str>] {
+        &self.accepts
+    }
+
+    /// The authentication scheme the route requires, if any.
+    pub fn auth_scheme(&self) -> Option<&str> {
+        self.auth.as_deref()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.accepts.is_empty() && self.auth.is_none()
+    }
+}
+
+/// Sets `meta` on the routes registered for `method` and `path`, warning if there are none.
+#[track_caller]
+pub(crate) fn describe_routes(routes: &mut [Route], method: Method, path: &str, meta: RouteMeta) {
+    let mut found = false;
+    for route in routes.iter_mut().filter(|route| route.method == method && route.path.trim_matches('/') == path.trim_matches('/')) {
+        route.meta = meta.clone();
+        found = true;
+    }
+    if !found {
+        Diagnostic::warning("described route does not exist").route(&method, path).location(Location::caller()).help("register the route before describing it").emit();
+    }
+}
+
+/// Answers an `OPTIONS` request no route handled with the methods registered for `path`.
+///
+/// The `Allow` header lists the methods, `Accept-Post` and `Accept-Patch` the accepted content types,
+/// and the body has a line per method with its [RouteMeta]:
+///
+/// ```text
+/// GET
+/// POST accepts=application/json auth=Bearer
+/// OPTIONS
+/// ```
+///
+/// Returns `false` if no route matches `path`.
+pub(crate) fn answer_options(routes: &[Route], path: &str, response: &mut Response) -> bool {
+    let mut params = HashMap::new();
+    let mut methods: Vec<(&Method, &RouteMeta)> = Vec::new();
+    for route in routes.iter().filter(|route| AppService::match_route(&route.path, path, &mut params)) {
+        match methods.iter_mut().find(|(method, _)| **method == route.method) {
+            // Routes chained with `NextRoute` share a method, the first description wins
+            Some((_, meta)) if meta.is_empty() => *meta = &route.meta,
+            Some(_) => {}
+            None => methods.push((&route.method, &route.meta)),
+        }
+    }
+    if methods.is_empty() {
+        return false;
+    }
+
+    let mut allow: Vec<&str> = methods.iter().map(|(method, _)| method.as_str()).collect();
+    let mut body = String::new();
+    for (method, meta) in &methods {
+        body.push_str(method.as_str());
+        if !meta.accepts.is_empty() {
+            let _ = write!(body, " accepts={}", meta.accepts.join(","));
+        }
+        if let Some(scheme) = &meta.auth {
+            let _ = write!(body, " auth={scheme}");
+        }
+        body.push('\n');
+        let accept_header = match **method {
+            Method::POST => "accept-post",
+            Method::PATCH => "accept-patch",
+            _ => continue,
+        };
+        if !meta.accepts.is_empty()
+            && let Ok(value) = HeaderValue::from_str(&meta.accepts.join(", "))
+        {
+            response.headers.insert(HeaderName::from_static(accept_header), value);
+        }
+    }
+    if !allow.contains(&"OPTIONS") {
+        allow.push("OPTIONS");
+        body.push_str("OPTIONS\n");
+    }
+    if let Ok(value) = HeaderValue::from_str(&allow.join(", ")) {
+        response.headers.insert(HeaderName::from_static("allow"), value);
+    }
+    response.set_status(200).send_text(body);
+    true
+}
@@ -1,9 +1,606 @@
 // I get it its kinda pointess to open a new module for just a 2 types but maybe I'll add more features to the errors ;)
 
+use super::Diagnostic;
 use feather_runtime::http::{Request, Response};
-use std::error::Error;
+use std::any::Any;
+use std::error::Error as StdError;
+#[cfg(feature = "json")]
+use std::sync::Arc;
+use std::{fmt, io};
 
-type BoxError = Box<dyn Error>;
+/// Type Alias for the Error Handling Function: `Box<dyn Fn(Error,&Request,&mut Response)>`
+pub type ErrorHandler = Box<dyn Fn(Error, &Request, &mut Response) + Send + Sync>;
 
-/// Type Alias for the Error Handling Function: `Box<dyn Fn(BoxError,&Request,&mut Response)>`
-pub type ErrorHandler = Box<dyn Fn(BoxError, &Request, &mut Response) + Send + Sync>;
+/// A handler of [`App::on_error`](crate::App::on_error), `false` when the error isn't of its type.
+type TypedErrorHandler = Box<dyn Fn(&Error, &Request, &mut Response) -> bool + Send + Sync>;
+
+/// Builds the JSON body of an error from its status and message, see [`App::error_body`](crate::App::error_body).
+#[cfg(feature = "json")]
+pub type ErrorBody = Arc<dyn Fn(u16, &str) -> serde_json::Value + Send + Sync>;
+
+/// The [ErrorBody] of the app, carried by the requests so the errors answered by middleware use it too.
+#[cfg(feature = "json")]
+#[derive(Clone)]
+pub(crate) struct ErrorFormat(pub(crate) ErrorBody);
+
+/// The handlers of [`App::on_error`](crate::App::on_error) and the catch-all of
+/// [`App::set_error_handler`](crate::App::set_error_handler).
+#[derive(Default)]
+pub(crate) struct ErrorHandlers {
+    typed: Vec<TypedErrorHandler>,
+    catch_all: Option<ErrorHandler>,
+    #[cfg(feature = "json")]
+    body: Option<ErrorFormat>,
+}
+
+impl ErrorHandlers {
+    pub(crate) fn on<E: StdError + 'static>(&mut self, handler: impl Fn(&E, &Request, &mut Response) + Send + Sync + 'static) {
+        self.typed.push(Box::new(move |error, request, response| match error.downcast_ref::<E>() {
+            Some(error) => {
+                handler(error, request, response);
+                true
+            }
+            None => false,
+        }));
+    }
+
+    pub(crate) fn set_catch_all(&mut self, handler: ErrorHandler) {
+        self.catch_all = Some(handler);
+    }
+
+    #[cfg(feature = "json")]
+    pub(crate) fn set_body(&mut self, body: ErrorBody) {
+        self.body = Some(ErrorFormat(body));
+    }
+
+    /// Hand the [ErrorBody] of the app to `request`, for [send_error].
+    pub(crate) fn attach(&self, request: &mut Request) {
+        #[cfg(feature = "json")]
+        if let Some(format) = &self.body {
+            request.extensions.insert(format.clone());
+        }
+        #[cfg(not(feature = "json"))]
+        let _ = request;
+    }
+
+    /// Hands `error` to the first handler registered for its type, or to the catch-all. The error is given
+    /// back when no handler takes it, for the default response.
+    pub(crate) fn handle(&self, error: Error, request: &Request, response: &mut Response) -> Result<(), Error> {
+        if self.typed.iter().any(|handler| handler(&error, request, response)) {
+            return Ok(());
+        }
+        match &self.catch_all {
+            Some(handler) => {
+                handler(error, request, response);
+                Ok(())
+            }
+            None => Err(error),
+        }
+    }
+}
+
+/// The error of an [`Outcome`](crate::Outcome), what a middleware or route handler failed with.
+///
+/// Any error converts into it with `?`, so handlers keep propagating the errors of the libraries they use.
+/// Reports of `anyhow` and `eyre` convert with [from_anyhow](Self::from_anyhow) and [from_eyre](Self::from_eyre)
+/// behind their features. Their context is kept as the chain of [sources](std::error::Error::source) and printed
+/// when the error is reported, along with the [backtrace](Self::backtrace) `anyhow` captured.
+/// Besides the error itself it carries the status the client should get and, optionally, a message that is
+/// safe to show it. Without an [error handler](crate::App::set_error_handler) the response uses both,
+/// falling back to `500 Internal Server Error` so internal details don't leak.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::Error;
+///
+/// app.get("/users/:id", middleware!(|req, res, ctx| {
+///     let id: u64 = req.param("id").unwrap_or_default().parse().map_err(|e| Error::new(e).with_status(400).with_public_message("invalid user id"))?;
+///     let user = ctx.get_state::<Users>().find(id)?; // Any other error is a 500
+///     res.finish_json(&user)
+/// }));
+/// ```
+pub struct Error {
+    source: Box<dyn StdError>,
+    status: Option<u16>,
+    public_message: Option<String>,
+    backtrace: Option<String>,
+}
+
+impl Error {
+    /// Wrap `source`, answered with a 500 unless a status is set.
+    pub fn new(source: impl StdError + 'static) -> Self {
+        Self::from_boxed(Box::new(source))
+    }
+
+    /// An error that is only a message, for failures that don't come from another error.
+    pub fn msg(message: impl fmt::Display) -> Self {
+        Self::new(Message(message.to_string()))
+    }
+
+    /// Wrap an already boxed error.
+    pub fn from_boxed(source: Box<dyn StdError>) -> Self {
+        // An HttpError says how to answer itself
+        let (status, public_message) = match source.downcast_ref::<HttpError>() {
+            Some(error) => (Some(error.status), Some(error.message.clone())),
+            None => (None, None),
+        };
+        Self {
+            source,
+            status,
+            public_message,
+            backtrace: None,
+        }
+    }
+
+    /// Wrap an `anyhow` report, keeping its context as the chain of sources and the backtrace it captured.
+    ///
+    /// Reports aren't errors themselves and can't convert with `?` next to every error that does, so
+    /// handlers convert them with `.map_err(Error::from_anyhow)?`.
+    #[cfg(feature = "anyhow")]
+    pub fn from_anyhow(report: anyhow::Error) -> Self {
+        let backtrace = Some(report.backtrace()).filter(|backtrace| backtrace.status() == std::backtrace::BacktraceStatus::Captured).map(ToString::to_string);
+        let mut error = Self::from_boxed(report.into());
+        error.backtrace = backtrace;
+        error
+    }
+
+    /// Wrap an `eyre` report, keeping its context as the chain of sources, see [from_anyhow](Self::from_anyhow).
+    #[cfg(feature = "eyre")]
+    pub fn from_eyre(report: eyre::Report) -> Self {
+        Self::from_boxed(report.into())
+    }
+
+    /// Answer the request with `status` instead of 500.
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Send `message` as the body instead of the generic reason of the status. The error itself is never
+    /// sent to the client, it may contain internal details.
+    pub fn with_public_message(mut self, message: impl Into<String>) -> Self {
+        self.public_message = Some(message.into());
+        self
+    }
+
+    /// The status set with [with_status](Self::with_status), if any.
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    /// The message set with [with_public_message](Self::with_public_message), if any.
+    pub fn public_message(&self) -> Option<&str> {
+        self.public_message.as_deref()
+    }
+
+    /// The backtrace of an `anyhow::Error` this error was converted from, when `anyhow` captured one.
+    /// That takes `RUST_BACKTRACE=1` or `RUST_LIB_BACKTRACE=1`.
+    pub fn backtrace(&self) -> Option<&str> {
+        self.backtrace.as_deref()
+    }
+
+    /// The wrapped error.
+    pub fn source(&self) -> &(dyn StdError + 'static) {
+        &*self.source
+    }
+
+    /// Whether the wrapped error is a `T`.
+    pub fn is<T: StdError + 'static>(&self) -> bool {
+        self.source.is::<T>()
+    }
+
+    /// The wrapped error as a `T`, if it is one.
+    pub fn downcast_ref<T: StdError + 'static>(&self) -> Option<&T> {
+        self.source.downcast_ref::<T>()
+    }
+
+    /// Unwrap the error, dropping the status and public message.
+    pub fn into_inner(self) -> Box<dyn StdError> {
+        self.source
+    }
+
+    /// Add the chain of sources and the backtrace of the error to `diagnostic`.
+    pub(crate) fn report(&self, diagnostic: Diagnostic) -> Diagnostic {
+        let diagnostic = diagnostic.cause_chain(self.source());
+        match &self.backtrace {
+            Some(backtrace) => diagnostic.with("backtrace", backtrace),
+            None => diagnostic,
+        }
+    }
+
+    /// The status the default error pipeline answers with.
+    pub(crate) fn response_status(&self) -> u16 {
+        self.status.unwrap_or(500)
+    }
+
+    /// Answer with the status and public message, or the generic reason of the status, see [send_error].
+    pub(crate) fn respond(&self, request: &Request, response: &mut Response) {
+        response.set_status(self.response_status());
+        let reason = response.status.canonical_reason().unwrap_or("Internal Server Error");
+        send_error(request, response, self.response_status(), self.public_message.as_deref().unwrap_or(reason));
+    }
+}
+
+/// Answer with an error the framework generated, like a 404 for a path without a route or a 401 for a
+/// missing token. Clients that accept JSON get `{"error": {"code": 404, "message": "Not Found"}}`, or the
+/// body of [`App::error_body`](crate::App::error_body), with the `json` feature. Others get `message` as text.
+pub(crate) fn send_error(request: &Request, response: &mut Response, status: u16, message: &str) {
+    response.set_status(status);
+    #[cfg(feature = "json")]
+    if accepts_json(request) {
+        let body = match request.extensions.get::<ErrorFormat>() {
+            Some(ErrorFormat(body)) => body(status, message),
+            None => serde_json::json!({ "error": { "code": status, "message": message } }),
+        };
+        response.send_json(&body);
+        return;
+    }
+    #[cfg(not(feature = "json"))]
+    let _ = request;
+    response.send_text(message);
+}
+
+impl<E: StdError + 'static> From<E> for Error {
+    fn from(source: E) -> Self {
+        Self::new(source)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Error").field("source", &self.source).field("status", &self.status).field("public_message", &self.public_message).finish()
+    }
+}
+
+/// Whether the `Accept` header of `request` lists `application/json` as a media range, without `q=0`.
+#[cfg(feature = "json")]
+fn accepts_json(request: &Request) -> bool {
+    let mut ranges = request.headers.get_all("accept").iter().filter_map(|value| value.to_str().ok()).flat_map(|value| value.split(','));
+    ranges.any(|range| {
+        let mut parts = range.split(';');
+        let media_type = parts.next().unwrap_or_default().trim();
+        let refused = parts.any(|param| param.trim().strip_prefix("q=").and_then(|q| q.trim().parse::<f32>().ok()) == Some(0.0));
+        media_type.eq_ignore_ascii_case("application/json") && !refused
+    })
+}
+
+/// An error meant for the client: a status and a message, sent as the response when returned from a handler.
+///
+/// Return it with `?` or through [`bail_404!`](crate::bail_404) and [`ensure_authorized!`](crate::ensure_authorized).
+/// The [Error] it converts into takes its status and message, so the client gets them instead of a 500,
+/// as JSON when it accepts JSON and the `json` feature is enabled.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::HttpError;
+///
+/// app.delete("/users/:id", middleware!(|req, res, ctx| {
+///     let users = ctx.get_state::<Users>();
+///     let id = req.param("id").unwrap_or_default();
+///     if !users.remove(&id) {
+///         return Err(HttpError::not_found(format!("no user {id}")).into());
+///     }
+///     res.set_status(204);
+///     next!()
+/// }));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl HttpError {
+    /// An error answered with `status` and `message`.
+    pub fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// A `400 Bad Request`.
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(400, message)
+    }
+
+    /// A `401 Unauthorized`, for requests without valid credentials.
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(401, message)
+    }
+
+    /// A `403 Forbidden`, for clients that aren't allowed to do what they asked.
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(403, message)
+    }
+
+    /// A `404 Not Found`.
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(404, message)
+    }
+
+    /// The status the client gets.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The message the client gets.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+impl StdError for HttpError {}
+
+/// The source of [Error::msg].
+#[derive(Debug)]
+struct Message(String);
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl StdError for Message {}
+
+/// The error passed to the error handler when a middleware or route handler panics.
+///
+/// Feather catches the panic, sets the status to 500 and hands this error to the
+/// error handler so the client always gets a response.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// app.set_error_handler(Box::new(|err, _req, res| {
+///     if let Some(panic) = err.downcast_ref::<PanicError>() {
+///         eprintln!("Handler panicked: {}", panic.message());
+///     }
+///     res.send_text("Something went wrong");
+/// }));
+/// ```
+#[derive(Debug)]
+pub struct PanicError {
+    message: String,
+}
+
+impl PanicError {
+    /// Builds the error from the payload returned by [`std::panic::catch_unwind`].
+    pub(crate) fn from_payload(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload.downcast_ref::<&str>().map(|s| s.to_string()).unwrap_or_else(|| "Unknown panic".to_string()),
+        };
+        Self {
+            message,
+        }
+    }
+
+    /// The message the handler panicked with.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for PanicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "handler panicked: {}", self.message)
+    }
+}
+
+impl StdError for PanicError {}
+
+/// Why [`App::try_listen`](crate::App::try_listen) couldn't serve.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// match app.try_listen("0.0.0.0:80") {
+///     Err(ServeError::AddrInUse(_)) => eprintln!("Port 80 is taken, is another instance running?"),
+///     Err(e) => eprintln!("{e}"),
+///     Ok(()) => {}
+/// }
+/// ```
+#[derive(Debug)]
+pub enum ServeError {
+    /// Another socket is already bound to the address.
+    AddrInUse(String),
+    /// Binding the address isn't allowed, like ports below 1024 without privileges.
+    PermissionDenied(String),
+    /// The address doesn't belong to any interface of this machine.
+    AddrNotAvailable(String),
+    /// The application is misconfigured, like a JWT secret that is too short.
+    Config(String),
+    /// Any other I/O error while binding or serving.
+    Io(io::Error),
+}
+
+impl ServeError {
+    /// Classifies an error returned while binding `address`.
+    pub(crate) fn bind(address: impl fmt::Display, error: io::Error) -> Self {
+        let address = address.to_string();
+        match error.kind() {
+            io::ErrorKind::AddrInUse => Self::AddrInUse(address),
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied(address),
+            io::ErrorKind::AddrNotAvailable => Self::AddrNotAvailable(address),
+            _ => Self::Io(error),
+        }
+    }
+
+    /// The diagnostic [`App::listen`](crate::App::listen) panics with.
+    pub(crate) fn diagnostic(&self, address: impl fmt::Display) -> Diagnostic {
+        let diagnostic = Diagnostic::error("failed to start server").with("address", address).cause(self);
+        match self {
+            Self::AddrInUse(_) => diagnostic.help("stop the process using the port or pick another one, port `0` lets the OS choose"),
+            Self::PermissionDenied(_) => diagnostic.help("ports below 1024 need elevated privileges, use a higher port behind a reverse proxy"),
+            Self::AddrNotAvailable(_) => diagnostic.help("bind to `0.0.0.0` or `127.0.0.1` instead of an address this machine doesn't have"),
+            Self::Config(_) | Self::Io(_) => diagnostic,
+        }
+    }
+}
+
+impl fmt::Display for ServeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddrInUse(address) => write!(f, "address {address} is already in use"),
+            Self::PermissionDenied(address) => write!(f, "permission denied to bind {address}"),
+            Self::AddrNotAvailable(address) => write!(f, "address {address} is not available on this machine"),
+            Self::Config(message) => write!(f, "invalid configuration: {message}"),
+            Self::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl StdError for ServeError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestClient;
+    use crate::{App, Finalizer, middleware};
+
+    #[test]
+    fn test_http_errors_are_answered_with_their_status() {
+        let mut app = App::without_logger();
+        app.get(
+            "/posts/:id",
+            middleware!(|req, res| {
+                let id = req.param("id").unwrap_or_default();
+                if id != "1" {
+                    crate::bail_404!("no post {id}");
+                }
+                crate::ensure_authorized!(req.headers.contains_key("x-admin"), "only admins can read post {id}");
+                res.finish_text("post 1")
+            }),
+        );
+        let client = TestClient::new(app);
+
+        let response = client.get("/posts/2").send();
+        assert_eq!(response.status.as_u16(), 404);
+        assert_eq!(response.body.as_deref(), Some(&b"no post 2"[..]));
+        let response = client.get("/posts/1").send();
+        assert_eq!(response.status.as_u16(), 403);
+        assert_eq!(response.body.as_deref(), Some(&b"only admins can read post 1"[..]));
+        assert_eq!(client.get("/posts/1").header("X-Admin", "1").send().body.as_deref(), Some(&b"post 1"[..]));
+
+        #[cfg(feature = "json")]
+        {
+            let response = client.get("/posts/2").header("Accept", "application/json").send();
+            assert_eq!(response.status.as_u16(), 404);
+            assert_eq!(response.body.as_deref(), Some(&br#"{"error":{"code":404,"message":"no post 2"}}"#[..]));
+        }
+    }
+
+    #[test]
+    fn test_errors_keep_the_http_error() {
+        let error = Error::from(HttpError::bad_request("missing name"));
+        assert_eq!(error.status(), Some(400));
+        assert_eq!(error.public_message(), Some("missing name"));
+        assert_eq!(error.downcast_ref::<HttpError>().map(HttpError::status), Some(400));
+        assert_eq!(Error::from(std::io::Error::other("disk")).status(), None);
+    }
+
+    #[cfg(feature = "anyhow")]
+    #[test]
+    fn test_anyhow_errors_keep_their_context_and_backtrace() {
+        use anyhow::Context;
+
+        let report = Err::<(), _>(std::io::Error::other("disk full")).context("saving user 7").unwrap_err();
+        let captured = report.backtrace();
+        let expected = (captured.status() == std::backtrace::BacktraceStatus::Captured).then(|| captured.to_string());
+        let error = Error::from_anyhow(report);
+        assert_eq!(error.to_string(), "saving user 7");
+        assert_eq!(error.backtrace().map(str::to_string), expected);
+
+        let mut error = Error::from_anyhow(anyhow::anyhow!("lost connection"));
+        error.backtrace = Some("0: feather::handler".to_string());
+        let diagnostic = error.report(Diagnostic::error("unhandled error in route handler"));
+        let expected = "error: unhandled error in route handler\n      cause: lost connection\n  backtrace: 0: feather::handler";
+        assert_eq!(diagnostic.to_string(), expected);
+    }
+
+    #[cfg(feature = "eyre")]
+    #[test]
+    fn test_eyre_reports_convert_with_their_context() {
+        use eyre::WrapErr;
+
+        let mut app = App::without_logger();
+        app.get(
+            "/config",
+            middleware!(|res| {
+                let port: u16 = "http".parse().wrap_err("reading the port").map_err(Error::from_eyre)?;
+                res.finish_text(port.to_string())
+            }),
+        );
+        assert_eq!(TestClient::new(app).get("/config").send().status.as_u16(), 500);
+
+        let error = Error::from_eyre(eyre::eyre!("upstream timed out").wrap_err("loading the feed"));
+        let diagnostic = error.report(Diagnostic::error("unhandled error in route handler"));
+        let expected = "error: unhandled error in route handler\n      cause: loading the feed\n  caused by: upstream timed out";
+        assert_eq!(diagnostic.to_string(), expected);
+        assert_eq!(error.backtrace(), None);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_framework_errors_use_the_error_body() {
+        use feather_runtime::runtime::Service;
+
+        let mut app = App::without_logger();
+        app.error_body(|status, message| serde_json::json!({ "status": status, "title": message }));
+        let service = app.into_service().unwrap();
+        let mut request = Request::builder().uri("/upload").header("Accept", "application/json").build().unwrap();
+        let mut response = Response::default();
+        response.set_status(413);
+        service.format_error(&request, &mut response, "Request body too large");
+        assert_eq!(response.body.as_deref(), Some(&br#"{"status":413,"title":"Request body too large"}"#[..]));
+        request.headers.remove("accept");
+        service.format_error(&request, &mut response, "Request body too large");
+        assert_eq!(response.body.as_deref(), Some(&b"Request body too large"[..]));
+
+        let mut app = App::without_logger();
+        app.get("/panic", middleware!(|_req, _res, _ctx| panic!("boom")));
+        let client = TestClient::new(app);
+        let response = client.get("/missing").header("Accept", "application/json").send();
+        assert_eq!(response.status.as_u16(), 404);
+        assert_eq!(response.body.as_deref(), Some(&br#"{"error":{"code":404,"message":"Not Found"}}"#[..]));
+        let response = client.get("/panic").header("Accept", "application/json").send();
+        assert_eq!(response.body.as_deref(), Some(&br#"{"error":{"code":500,"message":"Internal Server Error"}}"#[..]));
+        assert_eq!(client.get("/missing").send().body.as_deref(), Some(&b"Not Found"[..]));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_accept_is_read_as_media_ranges() {
+        let accepts = |accept: &str| accepts_json(&Request::builder().uri("/").header("Accept", accept).build().unwrap());
+        assert!(accepts("application/json"));
+        assert!(accepts("text/html, Application/JSON;q=0.5"));
+        assert!(accepts("text/html;q=0.9, application/json ; q=1"));
+        assert!(!accepts("application/json;q=0"));
+        assert!(!accepts("text/html, application/json; q=0.000"));
+        assert!(!accepts("application/jsonp"));
+        assert!(!accepts("text/plain; note=application/json"));
+        assert!(!accepts("*/*"));
+    }
+}
@@ -0,0 +1,79 @@
+use super::{AppContext, Diagnostic, PanicError};
+use parking_lot::RwLock;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+type Subscriber = Arc<dyn Fn(&AppContext, &(dyn Any + Send + Sync)) + Send + Sync>;
+type Subscribers = HashMap<TypeId, Vec<(&'static str, Subscriber)>>;
+
+/// The subscribers of [`App::on_event`](crate::App::on_event), by event type.
+#[derive(Clone, Default)]
+pub(crate) struct EventBus(Arc<RwLock<Subscribers>>);
+
+impl EventBus {
+    pub(crate) fn subscribe<E: Send + Sync + 'static>(&self, handler: impl Fn(&AppContext, &E) + Send + Sync + 'static) {
+        let subscriber: Subscriber = Arc::new(move |ctx, event| {
+            if let Some(event) = event.downcast_ref::<E>() {
+                handler(ctx, event);
+            }
+        });
+        self.0.write().entry(TypeId::of::<E>()).or_default().push((std::any::type_name::<E>(), subscriber));
+    }
+
+    /// Runs every subscriber of `E` on its own coroutine, returns how many there are.
+    pub(crate) fn emit<E: Send + Sync + 'static>(&self, ctx: &AppContext, event: E) -> usize {
+        let subscribers = match self.0.read().get(&TypeId::of::<E>()) {
+            Some(subscribers) => subscribers.clone(),
+            None => return 0,
+        };
+        let event: Arc<dyn Any + Send + Sync> = Arc::new(event);
+        for (name, subscriber) in &subscribers {
+            let (name, subscriber, event, ctx) = (*name, subscriber.clone(), event.clone(), ctx.clone());
+            feather_runtime::runtime::spawn(move || {
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| subscriber(&ctx, &*event))) {
+                    let panic = PanicError::from_payload(payload);
+                    Diagnostic::error("event subscriber panicked").with("event", name).cause(panic.message()).note("the other subscribers of the event still run").emit();
+                }
+            });
+        }
+        subscribers.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::App;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    struct UserRegistered {
+        email: String,
+    }
+
+    struct OrderPlaced;
+
+    #[test]
+    fn test_subscribers_receive_their_events() {
+        let mut app = App::without_logger();
+        let (sender, received) = mpsc::channel();
+        let audit = sender.clone();
+        app.on_event::<UserRegistered>(move |_ctx, event| sender.send(format!("welcome {}", event.email)).unwrap());
+        app.on_event::<UserRegistered>(move |_ctx, event| audit.send(format!("audit {}", event.email)).unwrap());
+        app.on_event::<UserRegistered>(|_ctx, _event| panic!("a failing subscriber doesn't stop the others"));
+        let ctx = app.context().clone();
+
+        assert_eq!(
+            ctx.emit(UserRegistered {
+                email: "ada@example.com".to_string(),
+            }),
+            3
+        );
+        assert_eq!(ctx.emit(OrderPlaced), 0);
+
+        let mut messages: Vec<String> = (0..2).map(|_| received.recv_timeout(Duration::from_secs(1)).unwrap()).collect();
+        messages.sort();
+        assert_eq!(messages, ["audit ada@example.com", "welcome ada@example.com"]);
+    }
+}
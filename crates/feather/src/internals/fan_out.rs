@@ -0,0 +1,141 @@
+use feather_runtime::client::{Cancel, Client, ClientError, ClientRequest, ClientResponse};
+use feather_runtime::runtime::sync::mpsc;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+
+/// What [`AppContext::parallel_fetch`](crate::AppContext::parallel_fetch) does when one of its requests fails.
+///
+/// A request fails when it has no response: it timed out, couldn't connect or got an invalid answer. Error
+/// statuses like `502` are responses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FanOutPolicy {
+    /// Let the other requests finish and return every result, for pages that render what they got.
+    #[default]
+    Collect,
+    /// Cancel the other requests, which fail with [`ClientError::Cancelled`], for endpoints that need all of them.
+    FailFast,
+}
+
+/// Sends every request on its own coroutine and waits for all of them, results in request order.
+///
+/// No request outlives the call: each one ends at its own deadline, or when a [`FanOutPolicy::FailFast`]
+/// cancels it, and every coroutine has sent its result before this returns.
+pub(crate) fn fan_out(client: &Client, requests: impl IntoIterator<Item = ClientRequest>, policy: FanOutPolicy) -> Vec<Result<ClientResponse, ClientError>> {
+    let cancel = Cancel::new();
+    let (sender, receiver) = mpsc::channel();
+    let mut results = Vec::new();
+    for (index, request) in requests.into_iter().enumerate() {
+        let (client, cancel, sender) = (client.clone(), cancel.clone(), sender.clone());
+        feather_runtime::runtime::spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| client.send_cancellable(&request, &cancel))).unwrap_or_else(|_| Err(ClientError::Io(io::Error::other("the request panicked"))));
+            let _ = sender.send((index, result));
+        });
+        results.push(None);
+    }
+    drop(sender);
+
+    for _ in 0..results.len() {
+        // Every coroutine sends exactly once, so this waits for all of them
+        let Ok((index, result)) = receiver.recv() else {
+            break;
+        };
+        if result.is_err() && policy == FanOutPolicy::FailFast {
+            cancel.cancel();
+        }
+        results[index] = Some(result);
+    }
+    results.into_iter().map(|result| result.unwrap_or(Err(ClientError::Cancelled))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FanOutPolicy;
+    use crate::AppContext;
+    use feather_runtime::client::{ClientError, ClientRequest};
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// A server passing on every connection it accepts, answering none by itself.
+    fn upstream() -> (String, mpsc::Receiver<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let _ = sender.send(stream.unwrap());
+            }
+        });
+        (url, receiver)
+    }
+
+    fn answer(mut stream: TcpStream, body: &str) {
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request).unwrap();
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+    }
+
+    #[test]
+    fn test_requests_run_concurrently_and_keep_their_order() {
+        let (url, connections) = upstream();
+        let server = thread::spawn(move || {
+            // Only answers once all three are connected, which sequential requests never would be
+            let streams: Vec<TcpStream> = connections.iter().take(3).collect();
+            for (stream, body) in streams.into_iter().zip(["a", "b", "c"]) {
+                answer(stream, body);
+            }
+        });
+        let requests = ["/users", "/orders", "/stock"].map(|path| ClientRequest::get(format!("{url}{path}")).timeout(Duration::from_secs(30)));
+
+        let results = AppContext::new().parallel_fetch(requests, FanOutPolicy::Collect);
+        server.join().unwrap();
+        let mut bodies: Vec<_> = results.into_iter().map(|result| String::from_utf8(result.unwrap().body.to_vec()).unwrap()).collect();
+        // The server answered in the order it accepted, which is up to the scheduler
+        bodies.sort();
+        assert_eq!(bodies, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_collect_keeps_the_results_of_the_others() {
+        let (url, connections) = upstream();
+        let server = thread::spawn(move || {
+            let streams: Vec<TcpStream> = connections.iter().take(2).collect();
+            let (mut answered, mut held) = (None, Vec::new());
+            for mut stream in streams {
+                let mut request = [0; 1024];
+                let n = stream.read(&mut request).unwrap();
+                if String::from_utf8_lossy(&request[..n]).starts_with("GET /fast ") {
+                    write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nfast").unwrap();
+                    answered = Some(stream);
+                } else {
+                    held.push(stream);
+                }
+            }
+            (answered, held)
+        });
+        let requests = [ClientRequest::get(format!("{url}/fast")).timeout(Duration::from_secs(30)), ClientRequest::get(format!("{url}/slow")).timeout(Duration::from_millis(100))];
+
+        let results = AppContext::new().parallel_fetch(requests, FanOutPolicy::Collect);
+        assert_eq!(&results[0].as_ref().unwrap().body[..], b"fast");
+        assert!(matches!(results[1], Err(ClientError::TimedOut)));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_fail_fast_cancels_the_others_before_returning() {
+        let (url, connections) = upstream();
+        let requests = [ClientRequest::get(format!("{url}/hangs")).timeout(Duration::from_secs(30)), ClientRequest::get("http://127.0.0.1:1/refused")];
+
+        let results = AppContext::new().parallel_fetch(requests, FanOutPolicy::FailFast);
+        assert!(matches!(results[0], Err(ClientError::Cancelled)), "{:?}", results[0]);
+        assert!(matches!(results[1], Err(ClientError::Io(_))), "{:?}", results[1]);
+        // Either it never connected or its connection is already closed
+        if let Ok(mut stream) = connections.try_recv() {
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut request = Vec::new();
+            stream.read_to_end(&mut request).unwrap();
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt::{self, Display};
+
+/// The result of one health probe, see [`AppContext::set_state_with_health`](crate::AppContext::set_state_with_health).
+#[derive(Clone, Debug)]
+pub struct HealthCheck {
+    name: &'static str,
+    error: Option<String>,
+}
+
+impl HealthCheck {
+    pub(crate) fn new(name: &'static str, error: Option<String>) -> Self {
+        Self {
+            name,
+            error,
+        }
+    }
+
+    /// The type name of the probed state.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Why the probe failed, `None` if it passed.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Whether the probe passed.
+    pub fn is_healthy(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// The results of every health probe registered in the [`AppContext`](crate::AppContext).
+///
+/// Displayed as one line per dependency:
+///
+/// ```text
+/// ok   myapp::Cache
+/// fail myapp::Pool: connection refused
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct HealthReport {
+    checks: Vec<HealthCheck>,
+}
+
+impl HealthReport {
+    pub(crate) fn new(checks: Vec<HealthCheck>) -> Self {
+        Self {
+            checks,
+        }
+    }
+
+    /// Whether every probe passed. A report without probes is healthy.
+    pub fn is_healthy(&self) -> bool {
+        self.checks.iter().all(HealthCheck::is_healthy)
+    }
+
+    /// The result of each probe, in registration order.
+    pub fn checks(&self) -> &[HealthCheck] {
+        &self.checks
+    }
+}
+
+impl Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            match &check.error {
+                None => writeln!(f, "ok   {}", check.name)?,
+                Some(error) => writeln!(f, "fail {}: {error}", check.name)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A monotonically increasing counter registered in [`Metrics`].
+///
+/// Cloning a `Counter` gives another handle to the same value.
+#[derive(Clone, Debug, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    /// Increase the counter by one.
+    #[inline]
+    pub fn increment(&self) {
+        self.add(1);
+    }
+
+    /// Increase the counter by `n`.
+    #[inline]
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Current value of the counter.
+    #[inline]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A value that goes up and down registered in [`Metrics`], like the number of open connections.
+///
+/// Cloning a `Gauge` gives another handle to the same value.
+#[derive(Clone, Debug, Default)]
+pub struct Gauge(Arc<AtomicU64>);
+
+impl Gauge {
+    /// Increase the gauge by one.
+    #[inline]
+    pub fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Decrease the gauge by one, it doesn't go below zero.
+    #[inline]
+    pub fn decrement(&self) {
+        let _ = self.0.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |value| value.checked_sub(1));
+    }
+
+    /// Replace the value of the gauge.
+    #[inline]
+    pub fn set(&self, value: u64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    /// Current value of the gauge.
+    #[inline]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Application-wide registry of named metrics.
+///
+/// Builtins register their counters and gauges here (e.g. `slow_requests`) and you can add your own.
+/// Use [`snapshot`](Self::snapshot) to export everything, for example from a `/metrics` route.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let hits = ctx.metrics().counter("cache_hits");
+/// hits.increment();
+///
+/// app.get("/metrics", middleware!(|_req, res, ctx| {
+///     let body: String = ctx.metrics().snapshot().iter().map(|(k, v)| format!("{k} {v}\n")).collect();
+///     res.finish_text(body)
+/// }));
+/// ```
+#[derive(Clone, Default)]
+pub struct Metrics {
+    counters: Arc<RwLock<HashMap<String, Counter>>>,
+    gauges: Arc<RwLock<HashMap<String, Gauge>>>,
+}
+
+impl Metrics {
+    /// Get the counter with the given name, creating it on first use.
+    pub fn counter(&self, name: &str) -> Counter {
+        if let Some(counter) = self.counters.read().get(name) {
+            return counter.clone();
+        }
+        self.counters.write().entry(name.to_string()).or_default().clone()
+    }
+
+    /// Get the gauge with the given name, creating it on first use.
+    pub fn gauge(&self, name: &str) -> Gauge {
+        if let Some(gauge) = self.gauges.read().get(name) {
+            return gauge.clone();
+        }
+        self.gauges.write().entry(name.to_string()).or_default().clone()
+    }
+
+    /// Remove a gauge from the snapshot, for ones named after something that's gone, like an emptied room.
+    pub fn remove_gauge(&self, name: &str) {
+        self.gauges.write().remove(name);
+    }
+
+    /// Returns the name and current value of every metric, sorted by name.
+    pub fn snapshot(&self) -> Vec<(String, u64)> {
+        let mut snapshot: Vec<_> = self.counters.read().iter().map(|(name, counter)| (name.clone(), counter.get())).collect();
+        snapshot.extend(self.gauges.read().iter().map(|(name, gauge)| (name.clone(), gauge.get())));
+        snapshot.sort_by(|a, b| a.0.cmp(&b.0));
+        snapshot
+    }
+}
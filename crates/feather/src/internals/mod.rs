@@ -3,19 +3,51 @@
 //! This module contains the core application logic, state management, and error handling.
 //! Most users will only interact with [`App`] and [`AppContext`].
 
+mod annotations;
 mod app;
 mod context;
+mod controller;
+mod diagnostic;
+mod discovery;
 mod error_stack;
+mod events;
+#[cfg(feature = "client")]
+mod fan_out;
+mod health;
+mod metrics;
+mod profile;
+mod resource;
 mod router;
 mod runtime_extensions;
+mod schedule;
 mod service;
+mod shutdown;
+mod toggles;
+mod warmup;
 
+pub use annotations::{Annotations, RequestAnnotations, RouteHandle, RouteInfo};
 pub use app::App;
 pub use context::AppContext;
-pub use context::State;
+pub use context::{ReadState, State, WatchState};
+pub use controller::{Controller, RouteDef};
+pub use diagnostic::{Diagnostic, Severity};
+pub use discovery::RouteMeta;
+pub use error_stack::{Error, ErrorHandler, HttpError, PanicError, ServeError};
+#[cfg(feature = "client")]
+pub use fan_out::FanOutPolicy;
+#[cfg(feature = "json")]
+pub use error_stack::ErrorBody;
+pub(crate) use error_stack::send_error;
+pub use health::{HealthCheck, HealthReport};
+pub use metrics::{Counter, Gauge, Metrics};
+pub use profile::Profile;
+pub use resource::Resource;
 pub use feather_runtime::{HeaderMap, HeaderName, HeaderValue, Method, Uri};
-pub use router::Router;
+pub use router::{AttributeRoute, Router};
+pub(crate) use service::{AppService, NoRouteMatched};
 pub use runtime_extensions::Finalizer;
+pub use shutdown::ShutdownHandle;
+pub use toggles::MiddlewareToggles;
 
 /// Used internally to generate the route methods for DRY(Don't Repeat Yourself).
 macro_rules! route_methods {
@@ -23,11 +55,21 @@ macro_rules! route_methods {
         $(
             /// Adds a route to the application for the HTTP method.
             #[inline]
-            pub fn $name<M: Middleware + 'static>(&mut self, path: impl Into<String>, middleware: M) {
-                self.route(Method::$method, path.into(), middleware);
+            #[track_caller]
+            pub fn $name<M: Middleware + 'static>(&mut self, path: impl Into<String>, middleware: M) -> crate::internals::RouteHandle<'_> {
+                self.route(Method::$method, path.into(), middleware)
             }
         )+
     }
 }
 
 use route_methods;
+
+/// Parses the name of a route method, accepting extension methods like `PURGE` or `PROPFIND`.
+#[track_caller]
+fn extension_method(name: &str) -> Method {
+    match Method::from_bytes(name.as_bytes()) {
+        Ok(method) => method,
+        Err(_) => panic!("{}", Diagnostic::error("invalid HTTP method").with("method", format_args!("{name:?}")).help("method names are case-sensitive tokens without spaces or separators, like `PURGE`")),
+    }
+}
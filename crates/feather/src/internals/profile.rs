@@ -0,0 +1,85 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// The environment the application runs in.
+///
+/// The profile is read from the `FEATHER_ENV` environment variable when the [`App`](crate::App)
+/// is created and defaults to [`Profile::Dev`]. Builtin middlewares use it to pick sensible
+/// defaults, e.g. [`CrawlerControl`](crate::middlewares::builtins::CrawlerControl) keeps
+/// everything but production out of search indexes once a profile is set.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// // FEATHER_ENV=prod ./my-app
+/// let mut app = App::new();
+/// assert_eq!(app.context().profile(), Profile::Prod);
+///
+/// // Or override it in code
+/// app.context().set_profile(Profile::Staging);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Profile {
+    /// Local development (`dev`, `development`)
+    #[default]
+    Dev,
+    /// Pre-production environments (`staging`, `stage`)
+    Staging,
+    /// Production (`prod`, `production`)
+    Prod,
+}
+
+impl Profile {
+    /// The environment variable the profile is read from.
+    pub const ENV_VAR: &'static str = "FEATHER_ENV";
+
+    /// Read the profile from `FEATHER_ENV`, falling back to [`Profile::Dev`] if it is unset or unknown.
+    pub fn from_env() -> Self {
+        Self::from_env_var().unwrap_or_default()
+    }
+
+    /// The profile named by `FEATHER_ENV`, or `None` if it is unset or unknown.
+    pub(crate) fn from_env_var() -> Option<Self> {
+        std::env::var(Self::ENV_VAR).ok().and_then(|value| value.parse().ok())
+    }
+
+    /// Returns `true` for [`Profile::Dev`].
+    #[inline]
+    pub fn is_dev(self) -> bool {
+        self == Profile::Dev
+    }
+
+    /// Returns `true` for [`Profile::Prod`].
+    #[inline]
+    pub fn is_prod(self) -> bool {
+        self == Profile::Prod
+    }
+
+    /// The canonical name of the profile.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Profile::Dev => "dev",
+            Profile::Staging => "staging",
+            Profile::Prod => "prod",
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "dev" | "development" => Ok(Profile::Dev),
+            "staging" | "stage" => Ok(Profile::Staging),
+            "prod" | "production" => Ok(Profile::Prod),
+            other => Err(format!("Unknown profile: {other}")),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
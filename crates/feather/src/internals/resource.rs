@@ -0,0 +1,196 @@
+use std::borrow::Cow;
+use std::panic::Location;
+use std::sync::Arc;
+
+use feather_runtime::Method;
+use feather_runtime::http::{Request, Response};
+
+use super::app::Route;
+use crate::{AppContext, Outcome, next_route};
+
+/// A RESTful resource whose handlers are mapped to routes by [`App::resource`](crate::App::resource).
+///
+/// Every handler is optional. The ones you don't implement skip to the next matching route,
+/// so they behave as if they were never registered.
+///
+/// | Handler  | Route                        |
+/// |----------|------------------------------|
+/// | `index`  | `GET /users`                 |
+/// | `create` | `POST /users`                |
+/// | `show`   | `GET /users/:id`             |
+/// | `update` | `PUT` and `PATCH /users/:id` |
+/// | `delete` | `DELETE /users/:id`          |
+///
+/// The name of the id parameter can be changed with [`Resource::PARAM`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::{App, Resource};
+///
+/// struct UserController;
+///
+/// impl Resource for UserController {
+///     fn index(&self, _req: &mut Request, res: &mut Response, _ctx: &AppContext) -> Outcome {
+///         res.finish_text("all users")
+///     }
+///
+///     fn show(&self, req: &mut Request, res: &mut Response, _ctx: &AppContext) -> Outcome {
+///         let id = req.param("id").unwrap_or_default();
+///         res.finish_text(format!("user {id}"))
+///     }
+/// }
+///
+/// let mut app = App::new();
+/// app.resource("/users", UserController);
+/// ```
+pub trait Resource: Send + Sync + 'static {
+    /// Name of the route parameter holding the id of a single item.
+    const PARAM: &'static str = "id";
+
+    /// List the collection.
+    fn index(&self, _request: &mut Request, _response: &mut Response, _ctx: &AppContext) -> Outcome {
+        next_route!()
+    }
+
+    /// Show a single item.
+    fn show(&self, _request: &mut Request, _response: &mut Response, _ctx: &AppContext) -> Outcome {
+        next_route!()
+    }
+
+    /// Create a new item.
+    fn create(&self, _request: &mut Request, _response: &mut Response, _ctx: &AppContext) -> Outcome {
+        next_route!()
+    }
+
+    /// Update (replace or patch) a single item.
+    fn update(&self, _request: &mut Request, _response: &mut Response, _ctx: &AppContext) -> Outcome {
+        next_route!()
+    }
+
+    /// Delete a single item.
+    fn delete(&self, _request: &mut Request, _response: &mut Response, _ctx: &AppContext) -> Outcome {
+        next_route!()
+    }
+}
+
+type Action<R> = fn(&R, &mut Request, &mut Response, &AppContext) -> Outcome;
+
+/// Builds the routes of a [Resource] mounted at `path`.
+#[track_caller]
+pub(crate) fn resource_routes<R: Resource>(path: &str, resource: R) -> Vec<Route> {
+    let location = Location::caller();
+    let resource = Arc::new(resource);
+    let base = format!("/{}", path.trim_matches('/'));
+    let item = if base == "/" {
+        format!("/:{}", R::PARAM)
+    } else {
+        format!("{base}/:{}", R::PARAM)
+    };
+
+    let actions: [(Method, &String, Action<R>); 6] =
+        [(Method::GET, &base, R::index), (Method::POST, &base, R::create), (Method::GET, &item, R::show), (Method::PUT, &item, R::update), (Method::PATCH, &item, R::update), (Method::DELETE, &item, R::delete)];
+    actions
+        .into_iter()
+        .map(|(method, path, action)| {
+            let resource = resource.clone();
+            Route {
+                method,
+                path: Cow::Owned(path.clone()),
+                middleware: Arc::new(move |req: &mut Request, res: &mut Response, ctx: &AppContext| action(&resource, req, res, ctx)),
+                location: Some(location),
+                meta: Default::default(),
+                annotations: Default::default(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::Path;
+    use crate::internals::service::AppService;
+    use crate::testing::TestClient;
+    use crate::{Finalizer, next};
+    use feather_runtime::runtime::Service;
+    use feather_runtime::runtime::service::ServiceResult;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    struct Users;
+
+    impl Resource for Users {
+        fn show(&self, req: &mut Request, res: &mut Response, _ctx: &AppContext) -> Outcome {
+            res.send_text(format!("user {}", req.param("id").unwrap_or_default()));
+            next!()
+        }
+    }
+
+    fn send(service: &AppService, method: &str, path: &str) -> Response {
+        let raw = format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let request = Request::parse(raw.as_bytes(), Default::default(), SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap();
+        match service.handle(request, None).unwrap() {
+            ServiceResult::Response(response) => response,
+            ServiceResult::Consumed => panic!("Expected Response variant"),
+        }
+    }
+
+    #[test]
+    fn test_resource_routes() {
+        let service = AppService {
+            routes: resource_routes("/users/", Users),
+            middleware: Vec::new(),
+            transforms: Vec::new(),
+            context: AppContext::new(),
+            error_handlers: Default::default(),
+            start_hooks: Vec::new(),
+            shutdown_hooks: Vec::new(),
+        };
+        assert_eq!(send(&service, "GET", "/users/7").body.as_deref(), Some(&b"user 7"[..]));
+        // Handlers that are not implemented behave like missing routes
+        assert_eq!(send(&service, "DELETE", "/users/7").status.as_u16(), 404);
+        assert_eq!(send(&service, "GET", "/users").status.as_u16(), 404);
+    }
+
+    struct Posts {
+        titles: Vec<&'static str>,
+    }
+
+    #[crate::resource]
+    impl Posts {
+        const PARAM: &'static str = "post";
+
+        fn index(&self, res: &mut Response) -> Outcome {
+            res.finish_text(self.titles.join(", "))
+        }
+
+        fn show(&self, Path(post): Path<usize>, res: &mut Response) -> Outcome {
+            match self.title(post) {
+                Some(title) => res.finish_text(title),
+                None => Err(crate::HttpError::not_found("no such post").into()),
+            }
+        }
+
+        fn title(&self, post: usize) -> Option<&'static str> {
+            self.titles.get(post).copied()
+        }
+    }
+
+    #[test]
+    fn test_resource_macro_actions_take_typed_parameters() {
+        let mut app = crate::App::without_logger();
+        app.resource(
+            "/posts",
+            Posts {
+                titles: vec!["Hello", "Routing"],
+            },
+        );
+        let client = TestClient::new(app);
+
+        assert_eq!(client.get("/posts").send().body.as_deref(), Some(&b"Hello, Routing"[..]));
+        assert_eq!(client.get("/posts/1").send().body.as_deref(), Some(&b"Routing"[..]));
+        assert_eq!(client.get("/posts/7").send().status.as_u16(), 404);
+        assert_eq!(client.get("/posts/first").send().status.as_u16(), 400);
+        assert_eq!(client.delete("/posts/1").send().status.as_u16(), 404);
+    }
+}
@@ -1,9 +1,13 @@
 use std::borrow::Cow;
+use std::panic::Location;
 use std::sync::Arc;
 
 use feather_runtime::Method;
 use feather_runtime::http::{Request, Response};
 
+use super::annotations::{Annotations, RouteHandle, RouteInfo};
+use super::discovery::{RouteMeta, describe_routes};
+use super::resource::{Resource, resource_routes};
 use super::route_methods;
 use crate::internals::app::Route;
 use crate::middlewares::Middleware;
@@ -24,6 +28,9 @@ use crate::{AppContext, MiddlewareResult, Outcome};
 pub struct Router {
     pub(crate) routes: Vec<Route>,
     pub(crate) middleware: Vec<Arc<dyn Middleware>>,
+    /// The WebSocket routes by index, their connection gauge is named after the mounted path
+    #[cfg(feature = "ws")]
+    pub(crate) ws_labels: Vec<(usize, crate::ws::RouteLabel)>,
 }
 
 impl Router {
@@ -31,6 +38,8 @@ impl Router {
         Self {
             routes: Vec::new(),
             middleware: Vec::new(),
+            #[cfg(feature = "ws")]
+            ws_labels: Vec::new(),
         }
     }
 
@@ -38,12 +47,71 @@ impl Router {
         self.middleware.push(Arc::new(mw));
     }
 
-    pub fn route<M: Middleware + 'static>(&mut self, method: Method, path: impl Into<Cow<'static, str>>, mw: M) {
+    #[track_caller]
+    pub fn route<M: Middleware + 'static>(&mut self, method: Method, path: impl Into<Cow<'static, str>>, mw: M) -> RouteHandle<'_> {
         self.routes.push(Route {
             method,
             path: path.into(),
             middleware: Arc::new(mw),
+            location: Some(Location::caller()),
+            meta: RouteMeta::default(),
+            annotations: Annotations::default(),
         });
+        RouteHandle::new(self.routes.last_mut().expect("the route was just added"))
+    }
+
+    /// Add a route for a custom HTTP method, see [`App::method`](crate::App::method).
+    #[track_caller]
+    pub fn method<M: Middleware + 'static>(&mut self, method: &str, path: impl Into<Cow<'static, str>>, mw: M) -> RouteHandle<'_> {
+        self.route(super::extension_method(method), path, mw)
+    }
+
+    /// The routes registered so far, relative to the mount prefix, see [`App::routes`](crate::App::routes).
+    pub fn routes(&self) -> impl Iterator<Item = RouteInfo<'_>> {
+        self.routes.iter().map(RouteInfo::new)
+    }
+
+    /// Describe a route in the automatic `OPTIONS` responses, see [`App::describe`](crate::App::describe).
+    #[track_caller]
+    pub fn describe(&mut self, method: Method, path: &str, meta: RouteMeta) {
+        describe_routes(&mut self.routes, method, path, meta);
+    }
+
+    /// Add a WebSocket route, see [`App::ws`](crate::App::ws).
+    ///
+    /// Parameters of the mount prefix and of `path` are both available from the upgrade request,
+    /// and the router's middleware runs before the handshake is answered.
+    #[cfg(feature = "ws")]
+    #[track_caller]
+    pub fn ws<F>(&mut self, path: impl Into<Cow<'static, str>>, handler: F)
+    where
+        F: Fn(crate::ws::WebSocket, &Request, &AppContext) -> Result<(), Box<dyn std::error::Error>> + Send + Sync + 'static,
+    {
+        let path = path.into();
+        let label = crate::ws::RouteLabel::new(&path);
+        self.route(Method::GET, path, crate::ws::WsRoute(Arc::new(handler), label.clone()));
+        self.ws_labels.push((self.routes.len() - 1, label));
+    }
+
+    /// Add a WebSocket route for clients with a valid JWT, see [`App::ws_protected`](crate::App::ws_protected).
+    #[cfg(all(feature = "ws", feature = "jwt"))]
+    #[track_caller]
+    pub fn ws_protected<C, F>(&mut self, path: impl Into<Cow<'static, str>>, handler: F)
+    where
+        C: crate::jwt::Claim + Send + 'static,
+        F: Fn(crate::ws::WebSocket, &Request, &AppContext, C) -> Result<(), Box<dyn std::error::Error>> + Send + Sync + 'static,
+    {
+        let path = path.into();
+        let label = crate::ws::RouteLabel::new(&path);
+        let route = crate::ws::ProtectedWsRoute(Arc::new(handler), label.clone(), std::marker::PhantomData);
+        self.route(Method::GET, path, route);
+        self.ws_labels.push((self.routes.len() - 1, label));
+    }
+
+    /// Register the RESTful routes of a [Resource] under `path`, relative to the mount prefix.
+    #[track_caller]
+    pub fn resource<R: Resource>(&mut self, path: &str, resource: R) {
+        self.routes.extend(resource_routes(path, resource));
     }
 
     route_methods!(
@@ -57,6 +125,17 @@ impl Router {
     );
 }
 
+/// The route of a handler declared with an attribute like `#[get("/users/:id")]`.
+///
+/// Implemented by the attribute macros on a hidden type named like the handler, [`register!`](crate::register)
+/// reads the route from it. There's no need to implement it by hand.
+pub trait AttributeRoute {
+    /// The method of the route.
+    const METHOD: Method;
+    /// The path of the route, with its parameters.
+    const PATH: &'static str;
+}
+
 /// This is a Light Wrapper Middleware that handles the scoping logic
 pub(crate) struct ScopedMiddleware {
     pub router_stack: Vec<Arc<dyn Middleware>>,
@@ -0,0 +1,278 @@
+use super::{AppContext, Diagnostic, PanicError};
+use chrono::{DateTime, Datelike, Days, NaiveDate, TimeDelta, Timelike, Utc};
+use parking_lot::Mutex;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+pub(crate) type ScheduledTask = Box<dyn Fn(&AppContext) + Send + Sync>;
+
+/// How long a stopping server waits for runs in progress.
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often a sleeping schedule checks whether the server stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// When a task added with [`App::every`](crate::App::every) or [`App::schedule`](crate::App::schedule) runs.
+pub(crate) enum Timing {
+    Every(Duration),
+    Cron(Cron),
+}
+
+impl Timing {
+    /// How long to wait from `now` until the next run.
+    fn until_next(&self, now: DateTime<Utc>) -> Option<Duration> {
+        match self {
+            Timing::Every(interval) => Some(*interval),
+            Timing::Cron(cron) => (cron.next_after(now)? - now).to_std().ok(),
+        }
+    }
+}
+
+/// The scheduled tasks of an app, started and stopped with the server.
+#[derive(Clone, Default)]
+pub(crate) struct Scheduler(Arc<Inner>);
+
+#[derive(Default)]
+struct Inner {
+    queued: Mutex<Vec<(Timing, String, ScheduledTask)>>,
+    stopping: AtomicBool,
+    running: AtomicUsize,
+}
+
+impl Scheduler {
+    pub(crate) fn add(&self, timing: Timing, label: String, task: ScheduledTask) {
+        self.0.queued.lock().push((timing, label, task));
+    }
+
+    /// Starts every task on its own coroutine. Runs of a task never overlap: the next one is planned
+    /// once the previous one finished.
+    pub(crate) fn start(&self, ctx: &AppContext) {
+        let tasks = std::mem::take(&mut *self.0.queued.lock());
+        for (timing, label, task) in tasks {
+            let (inner, ctx) = (self.0.clone(), ctx.clone());
+            feather_runtime::runtime::spawn(move || {
+                while let Some(wait) = timing.until_next(Utc::now())
+                    && inner.sleep(wait)
+                {
+                    inner.running.fetch_add(1, Ordering::SeqCst);
+                    if !inner.stopping.load(Ordering::SeqCst)
+                        && let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| task(&ctx)))
+                    {
+                        let panic = PanicError::from_payload(payload);
+                        Diagnostic::error("scheduled task panicked").with("schedule", &label).cause(panic.message()).note("the task runs again on schedule").emit();
+                    }
+                    inner.running.fetch_sub(1, Ordering::SeqCst);
+                }
+            });
+        }
+    }
+
+    /// Stops planning runs and waits for the ones in progress.
+    pub(crate) fn stop(&self) {
+        self.0.stopping.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        while self.0.running.load(Ordering::SeqCst) > 0 {
+            if Instant::now() >= deadline {
+                Diagnostic::warning("shutdown timeout reached with scheduled tasks running").with("running", self.0.running.load(Ordering::SeqCst)).emit();
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Inner {
+    /// Sleeps for `duration`, `false` when the server stopped in the meantime.
+    fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        loop {
+            if self.stopping.load(Ordering::SeqCst) {
+                return false;
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return true;
+            }
+            feather_runtime::runtime::sleep(left.min(POLL_INTERVAL));
+        }
+    }
+}
+
+/// A cron expression: minute, hour, day of month, month and day of week, matched in UTC.
+///
+/// Fields take `*`, values, ranges like `1-5`, steps like `*/15` or `10-50/20` and lists of those.
+/// Months and weekdays can be named (`jan`, `mon`), Sunday is `0` or `7`. The `@hourly`, `@daily`,
+/// `@weekly`, `@monthly` and `@yearly` shorthands are understood as well.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Like classic cron, a restricted day of month and day of week match when either does
+    any_day: bool,
+    any_weekday: bool,
+}
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl Cron {
+    pub(crate) fn parse(expression: &str) -> Result<Self, String> {
+        let expression = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("expected 5 fields (minute hour day month weekday), found {}", fields.len()));
+        };
+        let weekdays = field("weekday", weekday, 0, 7, &WEEKDAYS, 0)?;
+        let cron = Self {
+            minutes: field("minute", minute, 0, 59, &[], 0)?,
+            hours: field("hour", hour, 0, 23, &[], 0)?,
+            days: field("day", day, 1, 31, &[], 0)?,
+            months: field("month", month, 1, 12, &MONTHS, 1)?,
+            // 7 is Sunday too
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        };
+        match cron.next_after(Utc::now()) {
+            Some(_) => Ok(cron),
+            None => Err("the expression never matches a date".to_string()),
+        }
+    }
+
+    /// The first minute after `time` the expression matches, `None` when it never does.
+    pub(crate) fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut time = time.with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        // Leap days match at least once in 8 years
+        let until = time.year() + 8;
+        while time.year() <= until {
+            let date = time.date_naive();
+            if !has(self.months, date.month()) {
+                let (year, month) = if date.month() == 12 {
+                    (date.year() + 1, 1)
+                } else {
+                    (date.year(), date.month() + 1)
+                };
+                time = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?.and_utc();
+            } else if !self.matches_day(date) {
+                time = date.checked_add_days(Days::new(1))?.and_hms_opt(0, 0, 0)?.and_utc();
+            } else if !has(self.hours, time.hour()) {
+                time = time.with_minute(0)? + TimeDelta::hours(1);
+            } else if !has(self.minutes, time.minute()) {
+                time += TimeDelta::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = has(self.days, date.day());
+        let weekday = has(self.weekdays, date.weekday().num_days_from_sunday());
+        match self.any_day || self.any_weekday {
+            true => day && weekday,
+            false => day || weekday,
+        }
+    }
+}
+
+fn has(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+/// The values a field matches, as bits. `names` stand for the values from `first` on.
+fn field(name: &str, text: &str, min: u32, max: u32, names: &[&str], first: u32) -> Result<u64, String> {
+    let invalid = || format!("invalid {name} `{text}`");
+    let value = |value: &str| match names.iter().position(|known| known.eq_ignore_ascii_case(value)) {
+        Some(index) => Ok(index as u32 + first),
+        None => value.parse::<u32>().map_err(|_| invalid()),
+    };
+    let mut bits = 0;
+    for item in text.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<usize>().ok().filter(|step| *step > 0).ok_or_else(invalid)?)),
+            None => (item, None),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `5/15` runs from 5 to the end of the range
+            None if step.is_some() => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("{name} `{text}` is out of range {min}-{max}"));
+        }
+        for value in (start..=end).step_by(step.unwrap_or(1)) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::App;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+    }
+
+    fn next(expression: &str, after: &str) -> String {
+        Cron::parse(expression).unwrap().next_after(at(after)).unwrap().to_rfc3339()
+    }
+
+    #[test]
+    fn test_cron_finds_the_next_run() {
+        assert_eq!(next("*/5 * * * *", "2024-03-10T10:07:30Z"), "2024-03-10T10:10:00+00:00");
+        assert_eq!(next("0 9-17/4 * * mon-fri", "2024-03-09T12:00:00Z"), "2024-03-11T09:00:00+00:00");
+        assert_eq!(next("30 2 1 jan,jul *", "2024-03-10T00:00:00Z"), "2024-07-01T02:30:00+00:00");
+        assert_eq!(next("@daily", "2024-12-31T23:59:00Z"), "2025-01-01T00:00:00+00:00");
+        // Day of month or day of week when both are restricted
+        assert_eq!(next("0 0 13 * 5", "2024-03-10T00:00:00Z"), "2024-03-13T00:00:00+00:00");
+        assert_eq!(next("0 0 29 2 *", "2024-03-01T00:00:00Z"), "2028-02-29T00:00:00+00:00");
+        assert_eq!(next("0 12 * * 7", "2024-03-10T11:00:00Z"), "2024-03-10T12:00:00+00:00");
+
+        assert!(Cron::parse("* * *").unwrap_err().contains("expected 5 fields"));
+        assert_eq!(Cron::parse("60 * * * *").unwrap_err(), "minute `60` is out of range 0-59");
+        assert_eq!(Cron::parse("* * * foo *").unwrap_err(), "invalid month `foo`");
+        assert!(Cron::parse("0 0 30 2 *").unwrap_err().contains("never matches"));
+    }
+
+    #[test]
+    fn test_tasks_run_until_the_server_stops() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut app = App::without_logger();
+        let counter = runs.clone();
+        app.every(Duration::from_millis(10), move |_ctx| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        app.every(Duration::from_millis(10), |_ctx| panic!("keeps failing"));
+        let handle = app.start("127.0.0.1:0").unwrap();
+        for _ in 0..100 {
+            if runs.load(Ordering::SeqCst) >= 3 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        handle.shutdown_and_wait().unwrap();
+
+        let stopped_at = runs.load(Ordering::SeqCst);
+        assert!(stopped_at >= 3, "{stopped_at}");
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(runs.load(Ordering::SeqCst), stopped_at);
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
 
 use feather_runtime::http::Request;
@@ -8,44 +9,76 @@ use feather_runtime::runtime::Service;
 use feather_runtime::runtime::service::ServiceResult;
 
 use crate::AppContext;
-use crate::internals::app::Route;
-use crate::internals::error_stack::ErrorHandler;
-use crate::middlewares::Middleware;
+use crate::internals::Annotations;
+use crate::internals::Diagnostic;
+use crate::internals::Method;
+use crate::internals::app::{GlobalMiddleware, Route};
+use crate::internals::discovery::answer_options;
+use crate::internals::error_stack::{Error, ErrorHandlers, PanicError, send_error};
+use crate::middlewares::BodyTransform;
+use crate::middlewares::transform::apply_transforms;
+
+/// Hint attached to the diagnostics of errors that reach the default 500 response.
+const NO_ERROR_HANDLER: &str = "register a handler with `App::on_error` or `App::set_error_handler` to customize the response";
+
+/// Marks requests that no route handled, so `after` hooks can tell the router's 404 from a handler's.
+#[derive(Clone, Copy)]
+pub(crate) struct NoRouteMatched;
+
+/// A callback run with the [AppContext] when the server starts or stops.
+pub(crate) type LifecycleHook = Box<dyn Fn(&AppContext) + Send + Sync>;
 
 pub(crate) struct AppService {
     pub routes: Vec<Route>,
-    pub middleware: Vec<Arc<dyn Middleware>>,
+    pub middleware: Vec<GlobalMiddleware>,
+    pub transforms: Vec<Arc<dyn BodyTransform>>,
     pub context: AppContext,
-    pub error_handler: Option<ErrorHandler>,
+    pub error_handlers: ErrorHandlers,
+    pub start_hooks: Vec<LifecycleHook>,
+    pub shutdown_hooks: Vec<LifecycleHook>,
 }
 
 impl AppService {
-    fn run_middleware(mut request: &mut Request, routes: &[Route], global_middleware: &[Arc<dyn Middleware>], context: &AppContext, error_handler: &Option<ErrorHandler>) -> Response {
-        let mut response = Response::default();
+    /// Runs the middlewares and the matching route, pushing the index of every global middleware whose `handle` ran to `entered`.
+    fn run_middleware(mut request: &mut Request, routes: &[Route], global_middleware: &[GlobalMiddleware], context: &AppContext, error_handlers: &ErrorHandlers, entered: &mut Vec<usize>) -> Response {
+        let mut response = request.take_response();
         // Run global middleware
 
-        for middleware in global_middleware {
-            match middleware.handle(&mut request, &mut response, &context) {
+        for (index, global) in global_middleware.iter().enumerate() {
+            if !global.is_enabled() {
+                continue;
+            }
+            entered.push(index);
+            match global.middleware.handle(&mut request, &mut response, &context) {
                 Ok(crate::middlewares::MiddlewareResult::Next) => {}
                 Ok(crate::middlewares::MiddlewareResult::NextRoute) => break,
                 Ok(crate::middlewares::MiddlewareResult::End) => return response,
                 Err(e) => {
-                    if let Some(handler) = &error_handler {
-                        handler(e, &request, &mut response)
-                    } else {
-                        eprintln!("Unhandled Error caught in middlewares: {}", e);
-                        response.set_status(500).send_text("Internal Server Error!");
+                    if let Err(e) = error_handlers.handle(e, request, &mut response) {
+                        if e.response_status() >= 500 {
+                            e.report(Diagnostic::error("unhandled error in middleware").route(&request.method, &request.path())).help(NO_ERROR_HANDLER).emit();
+                        }
+                        e.respond(request, &mut response);
                         return response;
                     }
                 }
             }
         }
         let method = request.method.clone();
+        let path = request.path().into_owned();
+        // Reused between routes, only handed to the request once a route matches
+        let mut params = HashMap::new();
         // Run route-specific middleware
         let mut found = false;
         for route in routes.iter().filter(|r| r.method == method) {
-            if let Some(params) = Self::match_route(&route.path, &request.path()) {
-                request.set_params(params);
+            if Self::match_route(&route.path, &path, &mut params) {
+                request.set_params(std::mem::take(&mut params));
+                // A route skipped with `NextRoute` doesn't leave its annotations to the next one
+                if route.annotations.is_empty() {
+                    request.extensions.remove::<Annotations>();
+                } else {
+                    request.extensions.insert(route.annotations.clone());
+                }
                 match route.middleware.handle(request, &mut response, &context) {
                     Ok(crate::middlewares::MiddlewareResult::NextRoute) => {
                         // Skip this match and keep looking for the next matching route
@@ -56,47 +89,406 @@ impl AppService {
                         break;
                     }
                     Err(e) => {
-                        if let Some(handler) = &error_handler {
-                            handler(e, &request, &mut response)
-                        } else {
-                            eprintln!("Unhandled Error caught in Route Middlewares : {}", e);
-                            response.set_status(500).send_text("Internal Server Error");
-                            break;
+                        if let Err(e) = error_handlers.handle(e, request, &mut response) {
+                            // Errors with a client status are answered, not reported
+                            if e.response_status() >= 500 {
+                                let mut diagnostic = Diagnostic::error("unhandled error in route handler").route(&route.method, &route.path).with("request", &path);
+                                if let Some(location) = route.location {
+                                    diagnostic = diagnostic.location(location);
+                                }
+                                e.report(diagnostic).help(NO_ERROR_HANDLER).emit();
+                            }
+                            e.respond(request, &mut response);
                         }
+                        // The route failed, it still answered the request
+                        found = true;
+                        break;
                     }
                 }
             }
         }
-        if !found {
-            response.set_status(404).send_text("404 Not Found");
+        // Unhandled OPTIONS requests list the methods of the path instead
+        let answered = found || (method == Method::OPTIONS && answer_options(routes, &path, &mut response));
+        if !answered {
+            request.extensions.insert(NoRouteMatched);
+            send_error(request, &mut response, 404, "Not Found");
         }
 
         response
     }
-    fn match_route<'r>(pattern: &'r str, path: &'r str) -> Option<HashMap<String, String>> {
-        let mut params = HashMap::new();
-        let pattern_parts: Vec<&str> = pattern.trim_matches('/').split('/').collect();
-        let path_parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+    /// Matches `path` against a route pattern, filling `params` with the `:name` segments.
+    pub(crate) fn match_route(pattern: &str, path: &str, params: &mut HashMap<String, String>) -> bool {
+        params.clear();
+        let mut pattern_parts = pattern.trim_matches('/').split('/');
+        let mut path_parts = path.trim_matches('/').split('/');
 
-        if pattern_parts.len() != path_parts.len() {
-            return None;
-        }
-
-        for (pat, val) in pattern_parts.iter().zip(path_parts.iter()) {
-            if pat.starts_with(':') {
-                params.insert(pat[1..].to_string(), val.to_string());
-            } else if pat != val {
-                return None;
+        loop {
+            match (pattern_parts.next(), path_parts.next()) {
+                (None, None) => return true,
+                (Some(pat), Some(val)) => {
+                    if let Some(name) = pat.strip_prefix(':') {
+                        params.insert(name.to_string(), val.to_string());
+                    } else if pat != val {
+                        return false;
+                    }
+                }
+                // Different number of segments
+                _ => return false,
             }
         }
+    }
+}
 
-        Some(params)
+impl AppService {
+    /// Turns a panic caught during dispatch into a 500 response, routed through the error handler if there is one.
+    fn recover(&self, payload: Box<dyn std::any::Any + Send>, request: &Request) -> Response {
+        let error = PanicError::from_payload(payload);
+        let mut response = Response::default();
+        response.set_status(500);
+        if let Err(error) = self.error_handlers.handle(Error::new(error), request, &mut response) {
+            let message = error.downcast_ref::<PanicError>().map_or("", PanicError::message);
+            Diagnostic::error("handler panicked").route(&request.method, &request.path()).cause(message).help(NO_ERROR_HANDLER).emit();
+            send_error(request, &mut response, 500, "Internal Server Error");
+        }
+        response
     }
 }
 
 impl Service for AppService {
     fn handle(&self, mut req: feather_runtime::http::Request, _stream: Option<MayStream>) -> std::io::Result<ServiceResult> {
-        let response = Self::run_middleware(&mut req, &self.routes, &self.middleware, &self.context, &self.error_handler);
-        return Ok(ServiceResult::Response(response));
+        self.error_handlers.attach(&mut req);
+        let mut entered = Vec::with_capacity(self.middleware.len());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            Self::run_middleware(&mut req, &self.routes, &self.middleware, &self.context, &self.error_handlers, &mut entered)
+        }));
+        let mut response = match result {
+            Ok(response) => response,
+            Err(payload) => self.recover(payload, &req),
+        };
+        // Only the middlewares that saw the request get to see the response, even a 500 from a panic
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            for &index in entered.iter().rev() {
+                self.middleware[index].middleware.after(&req, &mut response, &self.context);
+            }
+        }));
+        if let Err(payload) = result {
+            response = self.recover(payload, &req);
+        }
+        if let Err(e) = apply_transforms(&self.transforms, &req, &mut response)
+            && let Err(e) = self.error_handlers.handle(Error::from_boxed(e), &req, &mut response)
+        {
+            e.report(Diagnostic::error("unhandled error in body transform").route(&req.method, &req.path())).help(NO_ERROR_HANDLER).emit();
+            send_error(&req, &mut response, 500, "Internal Server Error");
+        }
+        Ok(ServiceResult::Response(response))
+    }
+
+    fn format_error(&self, request: &Request, response: &mut Response, message: &str) {
+        // Only the head was read, the copy is cheap
+        let mut request = request.clone();
+        self.error_handlers.attach(&mut request);
+        let status = response.status.as_u16();
+        send_error(&request, response, status, message);
+    }
+
+    fn on_start(&self) {
+        self.context.jobs().start(&self.context);
+        for hook in &self.start_hooks {
+            hook(&self.context);
+        }
+        self.context.warmup().run(&self.context);
+        self.context.scheduler().start(&self.context);
+    }
+
+    fn on_shutdown(&self) {
+        self.context.scheduler().stop();
+        for hook in &self.shutdown_hooks {
+            hook(&self.context);
+        }
+        self.context.jobs().close();
+        self.context.teardown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internals::{Finalizer, Method};
+    use feather_runtime::http::Request;
+    use std::borrow::Cow;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    const ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+    fn service_with_route(path: &'static str, middleware: impl crate::middlewares::Middleware + 'static) -> AppService {
+        AppService {
+            routes: vec![Route {
+                method: Method::GET,
+                path: Cow::Borrowed(path),
+                middleware: Arc::new(middleware),
+                location: None,
+                meta: Default::default(),
+                annotations: Default::default(),
+            }],
+            middleware: Vec::new(),
+            transforms: Vec::new(),
+            context: AppContext::new(),
+            error_handlers: ErrorHandlers::default(),
+            start_hooks: Vec::new(),
+            shutdown_hooks: Vec::new(),
+        }
+    }
+
+    fn get(service: &AppService, path: &str) -> Response {
+        let raw = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        let request = Request::parse(raw.as_bytes(), Default::default(), ADDR).unwrap();
+        match service.handle(request, None).unwrap() {
+            ServiceResult::Response(mut response) => {
+                // Like the server, write a streamed body after the rest of the response
+                if let Some(stream) = response.take_stream() {
+                    response.body = Some(stream.into_bytes().unwrap());
+                }
+                response
+            }
+            ServiceResult::Consumed => panic!("Expected Response variant"),
+        }
+    }
+
+    #[test]
+    fn test_panicking_handler_returns_500() {
+        let service = service_with_route("/boom", |_: &mut Request, _: &mut Response, _: &AppContext| -> crate::Outcome { panic!("boom") });
+        let response = get(&service, "/boom");
+        assert_eq!(response.status.as_u16(), 500);
+    }
+
+    #[test]
+    fn test_panic_is_routed_to_error_handler() {
+        let mut service = service_with_route("/boom", |_: &mut Request, _: &mut Response, _: &AppContext| -> crate::Outcome { panic!("boom") });
+        service.error_handlers.set_catch_all(Box::new(|err, _req, res| {
+            let panic = err.downcast_ref::<PanicError>().expect("expected a PanicError");
+            res.send_text(panic.message().to_string());
+        }));
+        let response = get(&service, "/boom");
+        assert_eq!(response.status.as_u16(), 500);
+        assert_eq!(response.body.as_deref(), Some(&b"boom"[..]));
+    }
+
+    #[test]
+    fn test_errors_are_answered_with_their_status() {
+        let service = service_with_route("/users/:id", |req: &mut Request, _: &mut Response, _: &AppContext| -> crate::Outcome {
+            match req.param("id") {
+                Some("0") => Err(Error::msg("no user 0").with_status(404).with_public_message("user not found")),
+                Some("1") => Err(Error::msg("db password is hunter2").with_status(503)),
+                _ => Err(std::io::Error::other("disk on fire"))?,
+            }
+        });
+        let response = get(&service, "/users/0");
+        assert_eq!(response.status.as_u16(), 404);
+        assert_eq!(response.body.as_deref(), Some(&b"user not found"[..]));
+        // Without a public message the client only gets the reason of the status
+        let response = get(&service, "/users/1");
+        assert_eq!(response.status.as_u16(), 503);
+        assert_eq!(response.body.as_deref(), Some(&b"Service Unavailable"[..]));
+        let response = get(&service, "/users/2");
+        assert_eq!(response.status.as_u16(), 500);
+        assert_eq!(response.body.as_deref(), Some(&b"Internal Server Error"[..]));
+    }
+
+    #[test]
+    fn test_errors_are_dispatched_by_type() {
+        let mut service = service_with_route("/files/:name", |req: &mut Request, _: &mut Response, _: &AppContext| -> crate::Outcome {
+            match req.param("name") {
+                Some("missing") => Err(std::io::Error::new(std::io::ErrorKind::NotFound, "missing"))?,
+                Some("number") => Err("x".parse::<u8>().unwrap_err())?,
+                _ => panic!("boom"),
+            }
+        });
+        service.error_handlers.on::<std::io::Error>(|err, _req, res| {
+            res.set_status(404).send_text(format!("io: {}", err.kind()));
+        });
+        service.error_handlers.on::<std::io::Error>(|_err, _req, _res| unreachable!("the first handler of a type wins"));
+        service.error_handlers.set_catch_all(Box::new(|err, _req, res| {
+            res.set_status(400).send_text(format!("other: {err}"));
+        }));
+
+        let response = get(&service, "/files/missing");
+        assert_eq!(response.status.as_u16(), 404);
+        assert_eq!(response.body.as_deref(), Some(&b"io: entity not found"[..]));
+        assert_eq!(get(&service, "/files/number").body.as_deref(), Some(&b"other: invalid digit found in string"[..]));
+        // Panics are errors too, without a handler of their own they reach the catch-all
+        assert_eq!(get(&service, "/files/boom").body.as_deref(), Some(&b"other: handler panicked: boom"[..]));
+    }
+
+    /// Records its `after` call, then ends the request if `end` is set.
+    struct Recorder {
+        name: &'static str,
+        end: bool,
+        seen: Arc<std::sync::Mutex<Vec<&'static str>>>,
+    }
+
+    impl crate::middlewares::Middleware for Recorder {
+        fn handle(&self, _: &mut Request, _: &mut Response, _: &AppContext) -> crate::Outcome {
+            if self.end {
+                crate::end!()
+            } else {
+                crate::next!()
+            }
+        }
+
+        fn after(&self, _: &Request, _: &mut Response, _: &AppContext) {
+            self.seen.lock().unwrap().push(self.name);
+        }
+    }
+
+    #[test]
+    fn test_after_runs_in_reverse_for_entered_middlewares_only() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut service = service_with_route("/", |_: &mut Request, res: &mut Response, _: &AppContext| -> crate::Outcome {
+            res.finish_text("ok")
+        });
+        for (name, end, enabled) in [("first", false, true), ("off", false, false), ("last", true, true), ("never", false, true)] {
+            service.middleware.push(GlobalMiddleware {
+                enabled: Some(Arc::new(std::sync::atomic::AtomicBool::new(enabled))),
+                middleware: Arc::new(Recorder {
+                    name,
+                    end,
+                    seen: seen.clone(),
+                }),
+            });
+        }
+        get(&service, "/");
+        assert_eq!(*seen.lock().unwrap(), ["last", "first"]);
+
+        // A panicking handler still reaches the hooks of the middlewares before it
+        let mut service = service_with_route("/boom", |_: &mut Request, _: &mut Response, _: &AppContext| -> crate::Outcome { panic!("boom") });
+        service.middleware.push(GlobalMiddleware {
+            enabled: None,
+            middleware: Arc::new(Recorder {
+                name: "panic",
+                end: false,
+                seen: seen.clone(),
+            }),
+        });
+        assert_eq!(get(&service, "/boom").status.as_u16(), 500);
+        assert_eq!(seen.lock().unwrap().last(), Some(&"panic"));
+    }
+
+    #[test]
+    fn test_panicking_after_hook_returns_500() {
+        struct Broken;
+        impl crate::middlewares::Middleware for Broken {
+            fn handle(&self, _: &mut Request, _: &mut Response, _: &AppContext) -> crate::Outcome {
+                crate::next!()
+            }
+
+            fn after(&self, _: &Request, _: &mut Response, _: &AppContext) {
+                panic!("after");
+            }
+        }
+        let mut service = service_with_route("/", |_: &mut Request, res: &mut Response, _: &AppContext| -> crate::Outcome {
+            res.finish_text("ok")
+        });
+        service.middleware.push(GlobalMiddleware {
+            enabled: None,
+            middleware: Arc::new(Broken),
+        });
+        assert_eq!(get(&service, "/").status.as_u16(), 500);
+    }
+
+    #[test]
+    fn test_static_and_route_precedence() {
+        use crate::middlewares::builtins::ServeStatic;
+        let dir = std::env::temp_dir().join(format!("feather-static-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("page.txt"), "file").unwrap();
+        std::fs::write(dir.join("only.txt"), "only").unwrap();
+        let route = |_: &mut Request, res: &mut Response, _: &AppContext| -> crate::Outcome {
+            res.send_text("route");
+            crate::next!()
+        };
+
+        let mut service = service_with_route("/page.txt", route);
+        service.middleware.push(GlobalMiddleware {
+            enabled: None,
+            middleware: Arc::new(ServeStatic::new(&dir)),
+        });
+        assert_eq!(get(&service, "/page.txt").body.as_deref(), Some(&b"file"[..]));
+
+        let mut service = service_with_route("/page.txt", route);
+        service.middleware.push(GlobalMiddleware {
+            enabled: None,
+            middleware: Arc::new(ServeStatic::new(&dir).routes_first(true)),
+        });
+        assert_eq!(get(&service, "/page.txt").body.as_deref(), Some(&b"route"[..]));
+        let response = get(&service, "/only.txt");
+        assert_eq!(response.status.as_u16(), 200);
+        assert_eq!(response.body.as_deref(), Some(&b"only"[..]));
+        assert_eq!(get(&service, "/missing.txt").status.as_u16(), 404);
+
+        let mut service = service_with_route("/missing.txt", route);
+        service.middleware.push(GlobalMiddleware {
+            enabled: None,
+            middleware: Arc::new(ServeStatic::new(&dir).fallthrough(false)),
+        });
+        assert_eq!(get(&service, "/missing.txt").status.as_u16(), 404);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_extension_method_routes() {
+        let mut router = crate::Router::new();
+        router.method("PURGE", "/cache/:key", |_: &mut Request, res: &mut Response, _: &AppContext| -> crate::Outcome {
+            res.set_status(204);
+            crate::next!()
+        });
+        let mut service = service_with_route("/cache/page", |_: &mut Request, res: &mut Response, _: &AppContext| -> crate::Outcome {
+            res.finish_text("cached")
+        });
+        service.routes.extend(router.routes);
+
+        let request = Request::parse(b"PURGE /cache/page HTTP/1.1\r\nHost: localhost\r\n\r\n", Default::default(), ADDR).unwrap();
+        match service.handle(request, None).unwrap() {
+            ServiceResult::Response(response) => assert_eq!(response.status.as_u16(), 204),
+            ServiceResult::Consumed => panic!("Expected Response variant"),
+        }
+        assert_eq!(get(&service, "/cache/page").body.as_deref(), Some(&b"cached"[..]));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid HTTP method")]
+    fn test_invalid_extension_method_panics() {
+        crate::Router::new().method("NOT A METHOD", "/", |_: &mut Request, _: &mut Response, _: &AppContext| -> crate::Outcome {
+            crate::next!()
+        });
+    }
+
+    #[test]
+    fn test_options_describes_routes() {
+        let handler = |_: &mut Request, res: &mut Response, _: &AppContext| -> crate::Outcome { res.finish_text("ok") };
+        let mut router = crate::Router::new();
+        router.get("/users/:id", handler);
+        router.post("/users/:id", handler);
+        let meta = crate::RouteMeta::new().accepts("application/json").accepts("application/xml").auth("Bearer");
+        router.describe(Method::POST, "/users/:id", meta);
+        let mut service = service_with_route("/health", handler);
+        service.routes.extend(router.routes);
+
+        let options = |path: &str| {
+            let raw = format!("OPTIONS {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+            match service.handle(Request::parse(raw.as_bytes(), Default::default(), ADDR).unwrap(), None).unwrap() {
+                ServiceResult::Response(response) => response,
+                ServiceResult::Consumed => panic!("Expected Response variant"),
+            }
+        };
+        let response = options("/users/42");
+        assert_eq!(response.status.as_u16(), 200);
+        assert_eq!(response.headers.get("allow").unwrap(), "GET, POST, OPTIONS");
+        assert_eq!(response.headers.get("accept-post").unwrap(), "application/json, application/xml");
+        let body = b"GET\nPOST accepts=application/json,application/xml auth=Bearer\nOPTIONS\n";
+        assert_eq!(response.body.as_deref(), Some(&body[..]));
+
+        assert_eq!(options("/health").headers.get("allow").unwrap(), "GET, OPTIONS");
+        assert_eq!(options("/missing").status.as_u16(), 404);
     }
 }
@@ -0,0 +1,274 @@
+use super::MiddlewareToggles;
+use feather_runtime::runtime::ServerHandle;
+use std::io;
+use std::net::SocketAddr;
+use std::thread::JoinHandle;
+
+/// Controls a server started with [`App::start`](crate::App::start) or [`App::listen_with_graceful_shutdown`](crate::App::listen_with_graceful_shutdown).
+///
+/// Dropping the handle does **not** stop the server, call [`shutdown`](Self::shutdown) and then
+/// [`wait`](Self::wait) to stop it and block until in-flight requests are drained.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let handle = app.listen_with_graceful_shutdown("127.0.0.1:5050");
+///
+/// // Stop on Ctrl+C / SIGTERM (requires the `signals` feature)
+/// handle.shutdown_on_signal().expect("Failed to install signal handler");
+///
+/// // Block until the server has shut down
+/// handle.wait().expect("Server error");
+/// ```
+pub struct ShutdownHandle {
+    server: ServerHandle,
+    thread: JoinHandle<io::Result<()>>,
+    /// Known upfront when the listener was bound before spawning the server thread
+    bound_addr: Option<SocketAddr>,
+    toggles: MiddlewareToggles,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(server: ServerHandle, thread: JoinHandle<io::Result<()>>, bound_addr: Option<SocketAddr>, toggles: MiddlewareToggles) -> Self {
+        Self {
+            server,
+            thread,
+            bound_addr,
+            toggles,
+        }
+    }
+
+    /// Stop accepting new connections and start draining the in-flight ones.
+    ///
+    /// This returns immediately, use [`wait`](Self::wait) to block until the server is done.
+    pub fn shutdown(&self) {
+        self.server.shutdown();
+    }
+
+    /// Block until the server has shut down and return its result.
+    ///
+    /// The server only stops after [`shutdown`](Self::shutdown) is called (or a signal is
+    /// received if [`shutdown_on_signal`](Self::shutdown_on_signal) was used), so this blocks
+    /// forever otherwise.
+    pub fn wait(self) -> io::Result<()> {
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::other("Server thread panicked")),
+        }
+    }
+
+    /// Shortcut for [`shutdown`](Self::shutdown) followed by [`wait`](Self::wait).
+    pub fn shutdown_and_wait(self) -> io::Result<()> {
+        self.shutdown();
+        self.wait()
+    }
+
+    /// Returns `true` until a shutdown has been requested.
+    pub fn is_running(&self) -> bool {
+        self.server.is_running()
+    }
+
+    /// The address the server is bound to, or `None` if it has not bound yet.
+    ///
+    /// Always `Some` for servers started with [`App::start`](crate::App::start).
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.bound_addr.or_else(|| self.server.local_addr())
+    }
+
+    /// Access the underlying runtime [`ServerHandle`].
+    ///
+    /// It is cloneable, so it can be moved into other threads to trigger the shutdown.
+    pub fn server(&self) -> &ServerHandle {
+        &self.server
+    }
+
+    /// The switches of the middlewares registered with [`App::use_named_middleware`](crate::App::use_named_middleware).
+    ///
+    /// They are the same as [`AppContext::middleware_toggles`](crate::AppContext::middleware_toggles) and can be
+    /// cloned into other threads, so a running server can be changed from outside of its routes.
+    pub fn middleware_toggles(&self) -> &MiddlewareToggles {
+        &self.toggles
+    }
+
+    /// Trigger a graceful shutdown when the process receives SIGINT (Ctrl+C), SIGTERM or SIGHUP.
+    ///
+    /// Only one signal handler can be installed per process.
+    #[cfg(feature = "signals")]
+    pub fn shutdown_on_signal(&self) -> Result<(), ctrlc::Error> {
+        let server = self.server.clone();
+        ctrlc::set_handler(move || server.shutdown())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::App;
+    use crate::internals::Finalizer;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    #[test]
+    fn test_start_reports_bound_port() {
+        let mut app = App::without_logger();
+        app.get("/", |_req: &mut crate::Request, res: &mut crate::Response, _ctx: &crate::AppContext| {
+            res.finish_text("hi")
+        });
+        let handle = app.start("127.0.0.1:0").unwrap();
+        let addr = handle.local_addr().expect("start should know the bound address");
+        assert_ne!(addr.port(), 0);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("hi"));
+
+        handle.shutdown_and_wait().unwrap();
+    }
+
+    #[test]
+    fn test_lifecycle_hooks_run_around_serving() {
+        use std::sync::{Arc, Mutex};
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut app = App::without_logger();
+        let log = events.clone();
+        app.on_start(move |ctx| {
+            ctx.set_state(String::from("warm"));
+            log.lock().unwrap().push("start");
+        });
+        let log = events.clone();
+        app.on_shutdown(move |ctx| {
+            assert_eq!(*ctx.get_state::<String>(), "warm");
+            log.lock().unwrap().push("shutdown");
+        });
+        let log = events.clone();
+        app.get("/", move |_req: &mut crate::Request, res: &mut crate::Response, ctx: &crate::AppContext| {
+            log.lock().unwrap().push("request");
+            res.finish_text(ctx.get_state::<String>().as_str())
+        });
+        let handle = app.start("127.0.0.1:0").unwrap();
+
+        let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("warm"));
+
+        handle.shutdown_and_wait().unwrap();
+        assert_eq!(*events.lock().unwrap(), ["start", "request", "shutdown"]);
+    }
+
+    #[test]
+    fn test_providers_build_state_in_order() {
+        struct Url(String);
+        struct Pool(String);
+
+        let mut app = App::without_logger();
+        app.provide(|_ctx| Ok::<_, String>(Url("postgres://localhost/app".to_string())));
+        app.provide(|ctx| Ok::<_, String>(Pool(format!("pool for {}", ctx.get_state::<Url>().0))));
+        app.get("/", |_req: &mut crate::Request, res: &mut crate::Response, ctx: &crate::AppContext| {
+            res.finish_text(ctx.get_state::<Pool>().0.clone())
+        });
+        let handle = app.start("127.0.0.1:0").unwrap();
+
+        let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("pool for postgres://localhost/app"));
+        handle.shutdown_and_wait().unwrap();
+
+        let mut app = App::without_logger();
+        app.provide(|_ctx| Err::<Url, _>("connection refused"));
+        let Err(error) = app.start("127.0.0.1:0") else {
+            panic!("the failed provider didn't stop the start")
+        };
+        assert!(error.to_string().contains("connection refused"), "{error}");
+
+        // Depending on state that isn't there fails the start instead of the first request
+        let mut app = App::without_logger();
+        app.provide(|ctx| Ok::<_, String>(Pool(ctx.get_state::<Url>().0.clone())));
+        assert!(app.start("127.0.0.1:0").is_err());
+    }
+
+    #[test]
+    fn test_named_middlewares_toggle_from_the_handle() {
+        let mut app = App::without_logger();
+        app.use_named_middleware("maintenance", |_req: &mut crate::Request, res: &mut crate::Response, _ctx: &crate::AppContext| {
+            res.set_status(503).finish_text("maintenance")
+        });
+        app.get("/", |_req: &mut crate::Request, res: &mut crate::Response, _ctx: &crate::AppContext| {
+            res.finish_text("hi")
+        });
+        let handle = app.start("127.0.0.1:0").unwrap();
+        let get = || {
+            let mut stream = TcpStream::connect(handle.local_addr().unwrap()).unwrap();
+            stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let toggles = handle.middleware_toggles();
+        assert_eq!(toggles.list(), [("maintenance".to_string(), true)]);
+        assert!(get().ends_with("maintenance"));
+        assert!(toggles.disable("maintenance"));
+        assert_eq!(toggles.is_enabled("maintenance"), Some(false));
+        assert!(get().ends_with("hi"));
+        assert!(toggles.enable("maintenance"));
+        assert!(get().ends_with("maintenance"));
+        assert!(!toggles.disable("compression"));
+
+        handle.shutdown_and_wait().unwrap();
+    }
+
+    #[test]
+    fn test_listen_until_stops_on_message() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{Arc, mpsc};
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let mut app = App::without_logger();
+        let flag = stopped.clone();
+        app.on_shutdown(move |_ctx| flag.store(true, Ordering::SeqCst));
+        let (stop, receiver) = mpsc::channel();
+        let server = std::thread::spawn(move || app.listen_until("127.0.0.1:0", receiver));
+
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(!stopped.load(Ordering::SeqCst));
+        stop.send(()).unwrap();
+        server.join().unwrap();
+        assert!(stopped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_try_listen_reports_address_in_use() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = taken.local_addr().unwrap();
+        match App::without_logger().try_listen(address) {
+            Err(crate::ServeError::AddrInUse(reported)) => assert_eq!(reported, address.to_string()),
+            other => panic!("expected AddrInUse, got {other:?}"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "jwt")]
+    fn test_start_rejects_weak_jwt_secret() {
+        use crate::Profile;
+        use crate::jwt::JwtManager;
+
+        let mut app = App::without_logger();
+        app.context().set_profile(Profile::Prod);
+        app.context().set_jwt(JwtManager::new("secret".to_string()));
+        let error = app.start("127.0.0.1:0").err().expect("a short secret should be rejected");
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("at least 32"));
+
+        // Dev only warns, so examples can use placeholders
+        let mut app = App::without_logger();
+        app.context().set_profile(Profile::Dev);
+        app.context().set_jwt(JwtManager::new("secret".to_string()));
+        app.start("127.0.0.1:0").unwrap().shutdown_and_wait().unwrap();
+    }
+}
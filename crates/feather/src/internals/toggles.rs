@@ -0,0 +1,77 @@
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Runtime on/off switches for named global middlewares.
+///
+/// Middlewares registered with [`App::use_named_middleware`](crate::App::use_named_middleware)
+/// get an atomic flag here. The flag is checked on every request, so flipping it takes effect
+/// immediately without restarting the server.
+///
+/// The switches are shared by every clone of the [`AppContext`](crate::AppContext), which makes it
+/// easy to expose them through your own admin route.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// app.use_named_middleware("maintenance", maintenance_page);
+/// app.context().middleware_toggles().disable("maintenance");
+///
+/// app.post("/admin/maintenance/:state", middleware!(|req, res, ctx| {
+///     let on = req.param("state") == Some("on");
+///     ctx.middleware_toggles().set_enabled("maintenance", on);
+///     res.finish_text("ok")
+/// }));
+/// ```
+#[derive(Clone, Default)]
+pub struct MiddlewareToggles {
+    flags: Arc<RwLock<HashMap<String, Arc<AtomicBool>>>>,
+}
+
+impl MiddlewareToggles {
+    /// Registers a new switch (enabled by default) and returns its flag.
+    /// Registering the same name twice returns the existing flag.
+    pub(crate) fn register(&self, name: &str) -> Arc<AtomicBool> {
+        let mut flags = self.flags.write();
+        flags.entry(name.to_string()).or_insert_with(|| Arc::new(AtomicBool::new(true))).clone()
+    }
+
+    /// Enables or disables the middleware registered under `name`.
+    ///
+    /// Returns `false` if no middleware with that name was registered.
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> bool {
+        match self.flags.read().get(name) {
+            Some(flag) => {
+                flag.store(enabled, Ordering::Release);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Enables the middleware registered under `name`. Returns `false` if it is unknown.
+    #[inline]
+    pub fn enable(&self, name: &str) -> bool {
+        self.set_enabled(name, true)
+    }
+
+    /// Disables the middleware registered under `name`. Returns `false` if it is unknown.
+    #[inline]
+    pub fn disable(&self, name: &str) -> bool {
+        self.set_enabled(name, false)
+    }
+
+    /// Returns whether the middleware registered under `name` is enabled, or `None` if it is unknown.
+    pub fn is_enabled(&self, name: &str) -> Option<bool> {
+        self.flags.read().get(name).map(|flag| flag.load(Ordering::Acquire))
+    }
+
+    /// Returns the names and current state of every registered switch.
+    pub fn list(&self) -> Vec<(String, bool)> {
+        let flags = self.flags.read();
+        let mut list: Vec<_> = flags.iter().map(|(name, flag)| (name.clone(), flag.load(Ordering::Acquire))).collect();
+        list.sort_by(|a, b| a.0.cmp(&b.0));
+        list
+    }
+}
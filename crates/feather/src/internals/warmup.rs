@@ -0,0 +1,132 @@
+use super::AppContext;
+use super::health::HealthCheck;
+use parking_lot::Mutex;
+use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+pub(crate) type WarmupTask = Box<dyn FnOnce(&AppContext) -> Result<(), Box<dyn Error>> + Send>;
+
+/// The tasks added with [`App::warmup`](crate::App::warmup) and how far they got.
+///
+/// Shared by the clones of the [AppContext], so the health report sees tasks finish.
+#[derive(Clone, Default)]
+pub(crate) struct Warmup(Arc<Mutex<Progress>>);
+
+#[derive(Default)]
+struct Progress {
+    queued: Vec<WarmupTask>,
+    registered: usize,
+    pending: usize,
+    failures: Vec<String>,
+}
+
+impl Warmup {
+    pub(crate) fn add(&self, task: WarmupTask) {
+        let mut progress = self.0.lock();
+        progress.queued.push(task);
+        progress.registered += 1;
+        progress.pending += 1;
+    }
+
+    /// Starts every queued task on its own coroutine.
+    pub(crate) fn run(&self, ctx: &AppContext) {
+        let tasks = std::mem::take(&mut self.0.lock().queued);
+        for task in tasks {
+            let (warmup, ctx) = (self.clone(), ctx.clone());
+            feather_runtime::runtime::spawn(move || {
+                let result = match panic::catch_unwind(AssertUnwindSafe(|| task(&ctx))) {
+                    Ok(result) => result.map_err(|e| e.to_string()),
+                    Err(_) => Err("task panicked".to_string()),
+                };
+                let mut progress = warmup.0.lock();
+                progress.pending -= 1;
+                if let Err(e) = result {
+                    progress.failures.push(e);
+                }
+            });
+        }
+    }
+
+    /// Fails while tasks are still running or if one of them failed, `None` without tasks.
+    pub(crate) fn check(&self) -> Option<HealthCheck> {
+        let progress = self.0.lock();
+        let error = match (progress.registered, progress.pending, progress.failures.as_slice()) {
+            (0, _, _) => return None,
+            (_, _, [_, ..]) => Some(progress.failures.join("; ")),
+            (_, 0, []) => None,
+            (registered, pending, []) => Some(format!("{pending} of {registered} tasks pending")),
+        };
+        Some(HealthCheck::new("warmup", error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::App;
+    use crate::middlewares::builtins::Readiness;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn ready(addr: SocketAddr) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /ready HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_readiness_waits_for_warmup() {
+        let (release, released) = mpsc::channel::<()>();
+        let mut app = App::without_logger();
+        app.use_middleware(Readiness::default());
+        app.warmup(move |ctx| {
+            released.recv()?;
+            ctx.set_state(String::from("primed"));
+            Ok(())
+        });
+        app.warmup(|_| Ok(()));
+        let handle = app.start("127.0.0.1:0").unwrap();
+        let addr = handle.local_addr().unwrap();
+
+        // The second task may or may not be done yet
+        let response = ready(addr);
+        assert!(response.starts_with("HTTP/1.1 503") && response.contains("of 2 tasks pending"), "{response}");
+        release.send(()).unwrap();
+        let mut response = ready(addr);
+        for _ in 0..100 {
+            if response.starts_with("HTTP/1.1 200") {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+            response = ready(addr);
+        }
+        assert!(response.contains("ok   warmup"), "{response}");
+
+        handle.shutdown_and_wait().unwrap();
+    }
+
+    #[test]
+    fn test_failed_warmup_keeps_app_unready() {
+        let mut app = App::without_logger();
+        app.use_middleware(Readiness::default());
+        app.warmup(|_| Err("templates/ not found".into()));
+        let handle = app.start("127.0.0.1:0").unwrap();
+
+        let mut response = ready(handle.local_addr().unwrap());
+        for _ in 0..100 {
+            if response.contains("not found") {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+            response = ready(handle.local_addr().unwrap());
+        }
+        assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+        assert!(response.contains("fail warmup: templates/ not found"), "{response}");
+
+        handle.shutdown_and_wait().unwrap();
+    }
+}
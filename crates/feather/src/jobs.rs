@@ -0,0 +1,372 @@
+//! Work done in the background after responding, see [`AppContext::enqueue`].
+//!
+//! Jobs wait in a queue shared by the clones of the context and are run by a fixed number of worker
+//! coroutines, which start with the server. A job that fails is retried after the backoff of its
+//! [RetryPolicy], and one that keeps failing is reported. On a graceful shutdown the workers finish the
+//! jobs already queued before the state is torn down.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use feather::jobs::{JobHandler, RetryPolicy};
+//!
+//! struct SendWelcome {
+//!     email: String,
+//! }
+//!
+//! impl JobHandler for SendWelcome {
+//!     fn run(&self, ctx: &AppContext) -> Result<(), Box<dyn Error>> {
+//!         ctx.get_state::<Mailer>().send_welcome(&self.email)?;
+//!         Ok(())
+//!     }
+//!
+//!     fn retry(&self) -> RetryPolicy {
+//!         RetryPolicy::exponential(5, Duration::from_secs(2))
+//!     }
+//! }
+//!
+//! app.post("/signup", middleware!(|req, res, ctx| {
+//!     let email = create_account(req)?;
+//!     ctx.enqueue(SendWelcome { email });
+//!     res.finish_text("Signed up")
+//! }));
+//! ```
+
+use crate::internals::{AppContext, Diagnostic, PanicError};
+use feather_runtime::runtime::sync::{Mutex as ChannelLock, mpsc};
+use parking_lot::Mutex;
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// A unit of background work, queued with [`AppContext::enqueue`].
+///
+/// Implemented for closures taking the context, and for structs carrying the data of their job.
+/// Handlers can be run several times when they fail, so they should be safe to retry.
+pub trait JobHandler: Send + Sync + 'static {
+    /// Do the work, an error has the job retried.
+    fn run(&self, ctx: &AppContext) -> Result<(), Box<dyn Error>>;
+
+    /// How often and when the job is retried (default: [RetryPolicy::default]).
+    fn retry(&self) -> RetryPolicy {
+        RetryPolicy::default()
+    }
+}
+
+impl<F, E> JobHandler for F
+where
+    F: Fn(&AppContext) -> Result<(), E> + Send + Sync + 'static,
+    E: Into<Box<dyn Error>>,
+{
+    fn run(&self, ctx: &AppContext) -> Result<(), Box<dyn Error>> {
+        self(ctx).map_err(Into::into)
+    }
+}
+
+/// A [JobHandler] with its retry policy, to override the one of the handler.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// ctx.enqueue(Job::new(move |ctx: &AppContext| ping(&url)).retry(RetryPolicy::never()));
+/// ```
+pub struct Job {
+    name: &'static str,
+    retry: RetryPolicy,
+    handler: Box<dyn JobHandler>,
+}
+
+impl Job {
+    pub fn new<H: JobHandler>(handler: H) -> Self {
+        Self {
+            name: std::any::type_name::<H>(),
+            retry: handler.retry(),
+            handler: Box::new(handler),
+        }
+    }
+
+    /// Retry the job according to `policy`.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Run the job until it succeeds or its attempts are used up, waiting out the backoff in between.
+    fn run(self, ctx: &AppContext) {
+        let mut attempt = 1;
+        loop {
+            let result = match panic::catch_unwind(AssertUnwindSafe(|| self.handler.run(ctx))) {
+                Ok(result) => result.map_err(|e| e.to_string()),
+                Err(payload) => Err(PanicError::from_payload(payload).to_string()),
+            };
+            let Err(error) = result else {
+                return;
+            };
+            if attempt >= self.retry.attempts {
+                Diagnostic::error("background job failed").with("job", self.name).with("attempts", attempt).cause(error).emit();
+                return;
+            }
+            feather_runtime::runtime::sleep(self.retry.delay(attempt));
+            attempt += 1;
+        }
+    }
+}
+
+impl<H: JobHandler> From<H> for Job {
+    fn from(handler: H) -> Self {
+        Job::new(handler)
+    }
+}
+
+impl fmt::Debug for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Job").field("name", &self.name).field("retry", &self.retry).finish()
+    }
+}
+
+/// How often a failing job is attempted, and how long to wait before each retry.
+///
+/// The worker running the job waits out the backoff, so long backoffs keep workers busy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    attempts: u32,
+    delay: Duration,
+    exponential: bool,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Give up after the first failure.
+    pub fn never() -> Self {
+        Self::fixed(1, Duration::ZERO)
+    }
+
+    /// Up to `attempts` attempts, `delay` apart.
+    pub fn fixed(attempts: u32, delay: Duration) -> Self {
+        Self {
+            attempts: attempts.max(1),
+            delay,
+            exponential: false,
+            max_delay: delay,
+        }
+    }
+
+    /// Up to `attempts` attempts, waiting `initial` before the first retry and twice as long before
+    /// every other one, at most a minute unless raised with [max_delay](Self::max_delay).
+    pub fn exponential(attempts: u32, initial: Duration) -> Self {
+        Self {
+            attempts: attempts.max(1),
+            delay: initial,
+            exponential: true,
+            max_delay: Duration::from_secs(60).max(initial),
+        }
+    }
+
+    /// Cap the delay between two attempts at `max`.
+    pub fn max_delay(mut self, max: Duration) -> Self {
+        self.max_delay = max;
+        self
+    }
+
+    /// How many times the job is run at most.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// The delay after the failed `attempt`, counting from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = match self.exponential {
+            true => self.delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1))),
+            false => self.delay,
+        };
+        delay.min(self.max_delay)
+    }
+}
+
+/// Three attempts, one and then two seconds apart.
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::exponential(3, Duration::from_secs(1))
+    }
+}
+
+/// Settings of the job workers, see [`App::configure_jobs`](crate::App::configure_jobs).
+#[derive(Clone, Debug)]
+pub struct JobConfig {
+    /// Number of coroutines running jobs concurrently (default: 4)
+    pub workers: usize,
+    /// How long a graceful shutdown waits for the queued jobs, in seconds (default: 30)
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+enum Message {
+    Run(Job),
+    Stop,
+}
+
+/// The queue of an app's jobs, reached with [`AppContext::jobs`].
+///
+/// Jobs queued before the server starts wait for the workers.
+#[derive(Clone)]
+pub struct JobQueue(Arc<Queue>);
+
+struct Queue {
+    /// Taken on shutdown, when no more jobs are accepted
+    sender: Mutex<Option<mpsc::Sender<Message>>>,
+    receiver: ChannelLock<mpsc::Receiver<Message>>,
+    config: Mutex<JobConfig>,
+    /// Queued and running jobs
+    pending: AtomicUsize,
+    /// Workers that didn't stop yet
+    workers: AtomicUsize,
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self(Arc::new(Queue {
+            sender: Mutex::new(Some(sender)),
+            receiver: ChannelLock::new(receiver),
+            config: Mutex::new(JobConfig::default()),
+            pending: AtomicUsize::new(0),
+            workers: AtomicUsize::new(0),
+        }))
+    }
+}
+
+impl JobQueue {
+    /// Queue `job` for the workers. Jobs queued after the server shut down are dropped with a warning.
+    pub fn enqueue(&self, job: impl Into<Job>) {
+        let job = job.into();
+        let sender = self.0.sender.lock();
+        let Some(sender) = sender.as_ref() else {
+            Diagnostic::warning("job queued after shutdown").with("job", job.name).note("the job is dropped").emit();
+            return;
+        };
+        self.0.pending.fetch_add(1, Ordering::SeqCst);
+        if sender.send(Message::Run(job)).is_err() {
+            self.0.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// How many jobs are queued or running.
+    pub fn pending(&self) -> usize {
+        self.0.pending.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn configure(&self, config: JobConfig) {
+        *self.0.config.lock() = config;
+    }
+
+    /// Starts the workers, each on its own coroutine.
+    pub(crate) fn start(&self, ctx: &AppContext) {
+        let workers = self.0.config.lock().workers.max(1);
+        self.0.workers.store(workers, Ordering::SeqCst);
+        for _ in 0..workers {
+            let (queue, ctx) = (self.0.clone(), ctx.clone());
+            feather_runtime::runtime::spawn(move || {
+                loop {
+                    // The lock is only held while waiting, so the other workers can run their jobs
+                    let message = queue.receiver.lock().map_err(|_| ()).and_then(|receiver| receiver.recv().map_err(|_| ()));
+                    match message {
+                        Ok(Message::Run(job)) => {
+                            job.run(&ctx);
+                            queue.pending.fetch_sub(1, Ordering::SeqCst);
+                        }
+                        Ok(Message::Stop) | Err(()) => break,
+                    }
+                }
+                queue.workers.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    }
+
+    /// Stops accepting jobs and waits for the workers to finish the queued ones.
+    pub(crate) fn close(&self) {
+        let Some(sender) = self.0.sender.lock().take() else {
+            return;
+        };
+        // Behind every queued job, so those still run
+        for _ in 0..self.0.workers.load(Ordering::SeqCst) {
+            let _ = sender.send(Message::Stop);
+        }
+        drop(sender);
+        let deadline = Instant::now() + Duration::from_secs(self.0.config.lock().shutdown_timeout_secs);
+        while self.0.workers.load(Ordering::SeqCst) > 0 {
+            if Instant::now() >= deadline {
+                Diagnostic::warning("shutdown timeout reached with jobs left").with("pending", self.pending()).emit();
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl fmt::Debug for JobQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobQueue").field("pending", &self.pending()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jobs_are_retried_and_drained_on_close() {
+        let ctx = AppContext::new();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicUsize::new(0));
+
+        // Queued before the workers start
+        let (tries, finished) = (attempts.clone(), done.clone());
+        ctx.enqueue(
+            Job::new(move |_ctx: &AppContext| {
+                if tries.fetch_add(1, Ordering::SeqCst) < 2 {
+                    return Err("flaky");
+                }
+                finished.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .retry(RetryPolicy::fixed(3, Duration::from_millis(1))),
+        );
+        ctx.jobs().start(&ctx);
+        for _ in 0..10 {
+            let finished = done.clone();
+            ctx.enqueue(move |_ctx: &AppContext| {
+                finished.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, String>(())
+            });
+        }
+        ctx.enqueue(Job::new(|_ctx: &AppContext| Err::<(), _>("down")).retry(RetryPolicy::never()));
+
+        ctx.jobs().close();
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(done.load(Ordering::SeqCst), 11);
+        assert_eq!(ctx.jobs().pending(), 0);
+
+        ctx.enqueue(|_ctx: &AppContext| Ok::<_, String>(()));
+        assert_eq!(ctx.jobs().pending(), 0);
+    }
+
+    #[test]
+    fn test_retry_delays() {
+        let policy = RetryPolicy::exponential(5, Duration::from_secs(1)).max_delay(Duration::from_secs(5));
+        let delays: Vec<_> = (1..5).map(|attempt| policy.delay(attempt).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 5]);
+        assert_eq!(RetryPolicy::fixed(3, Duration::from_millis(50)).delay(2), Duration::from_millis(50));
+        assert_eq!(RetryPolicy::never().attempts(), 1);
+    }
+}
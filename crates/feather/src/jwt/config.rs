@@ -0,0 +1,85 @@
+//! Settings of a [`JwtManager`](super::JwtManager) read from a config file or the environment.
+
+use jsonwebtoken::Algorithm;
+use serde::Deserialize;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Settings for [`JwtManager::from_config`](super::JwtManager::from_config) and [`App::enable_jwt`](crate::App::enable_jwt).
+///
+/// Exactly one source of keys has to be set: a `secret`, key files or a `jwks_url`. Every field has
+/// an environment variable read by [from_env](Self::from_env).
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct JwtConfig {
+    /// Shared secret signing and verifying tokens with HMAC (env: `JWT_SECRET`)
+    pub secret: Option<String>,
+    /// PEM file of the RSA, EC or Ed25519 key signing tokens (env: `JWT_PRIVATE_KEY_FILE`)
+    pub private_key_file: Option<PathBuf>,
+    /// PEM file of the public key verifying them. Without a private key the manager can only verify (env: `JWT_PUBLIC_KEY_FILE`)
+    pub public_key_file: Option<PathBuf>,
+    /// JWKS endpoint of an identity provider to verify tokens with (env: `JWT_JWKS_URL`)
+    pub jwks_url: Option<String>,
+    /// Signing algorithm, like `HS512` or `ES256` (default: `HS256` with a secret, `RS256` with key files) (env: `JWT_ALGORITHM`)
+    pub algorithm: Option<Algorithm>,
+    /// Issuer put in and required of tokens (env: `JWT_ISSUER`)
+    pub issuer: Option<String>,
+    /// Audience put in and required of tokens (env: `JWT_AUDIENCE`)
+    pub audience: Option<String>,
+    /// Lifetime of tokens from [`JwtManager::generate`](super::JwtManager::generate), in seconds (default: 3600) (env: `JWT_TTL_SECS`)
+    pub ttl_secs: u64,
+    /// Clock skew allowed when checking `exp` and `nbf`, in seconds (default: 60) (env: `JWT_LEEWAY_SECS`)
+    pub leeway_secs: u64,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            secret: None,
+            private_key_file: None,
+            public_key_file: None,
+            jwks_url: None,
+            algorithm: None,
+            issuer: None,
+            audience: None,
+            ttl_secs: 3600,
+            leeway_secs: 60,
+        }
+    }
+}
+
+impl JwtConfig {
+    /// Read the settings from the `JWT_*` environment variables, unset ones keep their default.
+    ///
+    /// # Errors
+    ///
+    /// When `JWT_ALGORITHM`, `JWT_TTL_SECS` or `JWT_LEEWAY_SECS` don't parse.
+    pub fn from_env() -> io::Result<Self> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    pub(crate) fn from_vars(var: impl Fn(&str) -> Option<String>) -> io::Result<Self> {
+        let mut config = Self {
+            secret: var("JWT_SECRET"),
+            private_key_file: var("JWT_PRIVATE_KEY_FILE").map(PathBuf::from),
+            public_key_file: var("JWT_PUBLIC_KEY_FILE").map(PathBuf::from),
+            jwks_url: var("JWT_JWKS_URL"),
+            algorithm: var("JWT_ALGORITHM").map(|value| parse("JWT_ALGORITHM", &value)).transpose()?,
+            issuer: var("JWT_ISSUER"),
+            audience: var("JWT_AUDIENCE"),
+            ..Self::default()
+        };
+        if let Some(value) = var("JWT_TTL_SECS") {
+            config.ttl_secs = parse("JWT_TTL_SECS", &value)?;
+        }
+        if let Some(value) = var("JWT_LEEWAY_SECS") {
+            config.leeway_secs = parse("JWT_LEEWAY_SECS", &value)?;
+        }
+        Ok(config)
+    }
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> io::Result<T> {
+    value.trim().parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("{name} has an invalid value `{value}`")))
+}
@@ -0,0 +1,64 @@
+//! What clients get when a protected route rejects them, see [`JwtManager::with_failure_handler`](super::JwtManager::with_failure_handler).
+
+use super::Error;
+use crate::{Request, Response};
+use std::fmt;
+use std::sync::Arc;
+
+/// Why a protected route turned a request away.
+#[derive(Debug)]
+pub enum JwtFailure {
+    /// No `Authorization: Bearer` header, or `token` query parameter on WebSocket routes.
+    MissingToken,
+    /// The token didn't decode, or its claims didn't [validate](super::Claim::validate).
+    InvalidToken(Error),
+    /// The token is valid but has none of the required roles.
+    Forbidden,
+}
+
+impl JwtFailure {
+    /// The status of the default response, `401` or `403` for [Forbidden](Self::Forbidden).
+    pub fn status(&self) -> u16 {
+        match self {
+            JwtFailure::Forbidden => 403,
+            _ => 401,
+        }
+    }
+
+    /// The `WWW-Authenticate` challenge of the default response, as in RFC 6750.
+    pub fn challenge(&self) -> &'static str {
+        match self {
+            JwtFailure::MissingToken => "Bearer",
+            JwtFailure::InvalidToken(_) => "Bearer error=\"invalid_token\"",
+            JwtFailure::Forbidden => "Bearer error=\"insufficient_scope\"",
+        }
+    }
+
+    /// The text body of the default response.
+    pub fn message(&self) -> &'static str {
+        match self {
+            JwtFailure::MissingToken => "Missing or invalid Authorization header",
+            JwtFailure::InvalidToken(_) => "Invalid or expired token",
+            JwtFailure::Forbidden => "Missing a required role",
+        }
+    }
+
+    /// The default response: the [status](Self::status), the [challenge](Self::challenge) and the [message](Self::message).
+    pub fn respond(&self, response: &mut Response) {
+        response.set_status(self.status());
+        let _ = response.add_header("WWW-Authenticate", self.challenge());
+        response.send_text(self.message());
+    }
+}
+
+pub(crate) type Handler = dyn Fn(&Request, &mut Response, &JwtFailure) + Send + Sync;
+
+/// The handler of a [`JwtManager`](super::JwtManager), which derives `Debug`.
+#[derive(Clone)]
+pub(crate) struct FailureHandler(pub(crate) Arc<Handler>);
+
+impl fmt::Debug for FailureHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FailureHandler")
+    }
+}
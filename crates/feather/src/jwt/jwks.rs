@@ -0,0 +1,335 @@
+//! Verification keys fetched from a JWKS endpoint, see [`JwtManager::from_jwks_url`](super::JwtManager::from_jwks_url).
+
+use super::{Error, ErrorKind};
+use feather_runtime::client::{Client, ClientError, ClientRequest};
+use feather_runtime::{HeaderName, HeaderValue};
+use jsonwebtoken::jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet, PublicKeyUse};
+use jsonwebtoken::{Algorithm, DecodingKey};
+use parking_lot::{Mutex, RwLock};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Fetches the JWKS document at a URL.
+pub(crate) type Fetch = Arc<dyn Fn(&str) -> io::Result<String> + Send + Sync>;
+
+/// Time the built-in fetch may take, from resolving the host to the end of the document.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// JWKS documents are a few kilobytes, a longer body fails the fetch instead of being cut off.
+const MAX_DOCUMENT: u64 = 1024 * 1024;
+
+/// The cached keys of a JWKS endpoint.
+pub(crate) struct Jwks {
+    url: String,
+    pub(crate) ttl: Duration,
+    pub(crate) min_refresh: Duration,
+    pub(crate) fetch: Fetch,
+    keys: RwLock<Keys>,
+    /// When the last fetch started, only locked to check and set it, never while fetching
+    last_fetch: Mutex<Option<Instant>>,
+    /// Whether a fetch is running, callers meanwhile use the cached keys
+    fetching: AtomicBool,
+}
+
+#[derive(Default)]
+struct Keys {
+    keys: Vec<(Option<String>, Arc<Key>)>,
+    fetched_at: Option<Instant>,
+}
+
+/// A verification key with the only algorithm tokens signed with it may use.
+pub(crate) struct Key {
+    pub(crate) decoding: DecodingKey,
+    pub(crate) algorithm: Algorithm,
+}
+
+impl Jwks {
+    pub(crate) fn new(url: String) -> Self {
+        Self {
+            url,
+            ttl: Duration::from_secs(10 * 60),
+            min_refresh: Duration::from_secs(10),
+            fetch: Arc::new(fetch),
+            keys: RwLock::new(Keys::default()),
+            last_fetch: Mutex::new(None),
+            fetching: AtomicBool::new(false),
+        }
+    }
+
+    /// The same endpoint with other settings, `configure` them on an empty cache.
+    pub(crate) fn reconfigured(&self, configure: impl FnOnce(&mut Jwks)) -> Self {
+        let mut jwks = Self::new(self.url.clone());
+        (jwks.ttl, jwks.min_refresh, jwks.fetch) = (self.ttl, self.min_refresh, self.fetch.clone());
+        configure(&mut jwks);
+        jwks
+    }
+
+    /// The key a token with `kid` in its header is verified with, fetching the keys when they expired or
+    /// don't have it. Tokens without a `kid` are accepted when the endpoint has a single key.
+    pub(crate) fn key(&self, kid: Option<&str>) -> Result<Arc<Key>, Error> {
+        {
+            let keys = self.keys.read();
+            let fresh = keys.fetched_at.is_some_and(|at| at.elapsed() < self.ttl);
+            if fresh && let Some(key) = keys.find(kid) {
+                return Ok(key);
+            }
+        }
+        self.refresh();
+        // Keys that couldn't be refreshed keep being used until the endpoint answers again
+        self.keys.read().find(kid).ok_or_else(|| Error::from(ErrorKind::InvalidToken))
+    }
+
+    /// Fetch the keys unless that was tried within `min_refresh`, so tokens with made up `kid`s can't
+    /// flood the endpoint. Callers arriving while a fetch is running use the keys cached so far.
+    fn refresh(&self) {
+        {
+            let mut last_fetch = self.last_fetch.lock();
+            if last_fetch.is_some_and(|at| at.elapsed() < self.min_refresh) || self.fetching.swap(true, Ordering::AcqRel) {
+                return;
+            }
+            *last_fetch = Some(Instant::now());
+        }
+        let _running = Running(&self.fetching);
+        match (self.fetch)(&self.url).and_then(|document| parse(&document)) {
+            Ok(keys) => {
+                *self.keys.write() = Keys {
+                    keys,
+                    fetched_at: Some(Instant::now()),
+                }
+            }
+            Err(e) => {
+                #[cfg(feature = "log")]
+                log::warn!("Failed to fetch the JWKS at {}: {e}", self.url);
+                #[cfg(not(feature = "log"))]
+                drop(e);
+            }
+        }
+    }
+}
+
+/// Clears the running flag once a fetch returned or panicked.
+struct Running<'a>(&'a AtomicBool);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl fmt::Debug for Jwks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Jwks").field("url", &self.url).field("ttl", &self.ttl).field("min_refresh", &self.min_refresh).finish_non_exhaustive()
+    }
+}
+
+impl Keys {
+    fn find(&self, kid: Option<&str>) -> Option<Arc<Key>> {
+        match kid {
+            Some(kid) => self.keys.iter().find(|(id, _)| id.as_deref() == Some(kid)).map(|(_, key)| key.clone()),
+            None if self.keys.len() == 1 => Some(self.keys[0].1.clone()),
+            None => None,
+        }
+    }
+}
+
+/// The signing keys of a JWKS document. Encryption and symmetric keys are skipped, a shared secret
+/// doesn't belong in a public document.
+fn parse(document: &str) -> io::Result<Vec<(Option<String>, Arc<Key>)>> {
+    let set: JwkSet = serde_json::from_str(document).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let keys = set
+        .keys
+        .iter()
+        .filter(|jwk| !matches!(jwk.common.public_key_use, Some(PublicKeyUse::Encryption)))
+        .filter_map(|jwk| {
+            let algorithm = algorithm(jwk)?;
+            let decoding = DecodingKey::from_jwk(jwk).ok()?;
+            Some((
+                jwk.common.key_id.clone(),
+                Arc::new(Key {
+                    decoding,
+                    algorithm,
+                }),
+            ))
+        })
+        .collect();
+    Ok(keys)
+}
+
+/// The `alg` of the key, or the usual one for its type. Pinning it keeps a token from choosing
+/// an algorithm that reads the public key as something else.
+fn algorithm(jwk: &Jwk) -> Option<Algorithm> {
+    if let Some(algorithm) = &jwk.common.key_algorithm {
+        let algorithm = Algorithm::from_str(&algorithm.to_string()).ok()?;
+        return (!matches!(algorithm, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512)).then_some(algorithm);
+    }
+    match &jwk.algorithm {
+        AlgorithmParameters::RSA(_) => Some(Algorithm::RS256),
+        AlgorithmParameters::EllipticCurve(params) if params.curve == EllipticCurve::P256 => Some(Algorithm::ES256),
+        AlgorithmParameters::EllipticCurve(params) if params.curve == EllipticCurve::P384 => Some(Algorithm::ES384),
+        AlgorithmParameters::OctetKeyPair(params) if params.curve == EllipticCurve::Ed25519 => Some(Algorithm::EdDSA),
+        _ => None,
+    }
+}
+
+/// The built-in fetch, a `GET` with the runtime's client over HTTP or, with the `tls` feature, HTTPS.
+fn fetch(url: &str) -> io::Result<String> {
+    let client = Client::new().timeout(FETCH_TIMEOUT).max_body(MAX_DOCUMENT);
+    let request = ClientRequest::get(url).header(HeaderName::from_static("accept"), HeaderValue::from_static("application/json"));
+    let response = client.send(&request).map_err(|e| match e {
+        ClientError::Io(e) => e,
+        ClientError::TimedOut => io::Error::new(io::ErrorKind::TimedOut, e),
+        ClientError::InvalidUrl(_) | ClientError::TlsUnavailable => io::Error::new(io::ErrorKind::InvalidInput, e),
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    })?;
+    if !response.status.is_success() {
+        return Err(io::Error::other(format!("JWKS endpoint answered with status {}", response.status)));
+    }
+    String::from_utf8(response.body.to_vec()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::JwtManager;
+    use crate::jwt::SimpleClaims;
+    use crate::{App, next};
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::time::Duration;
+
+    /// Two Ed25519 keys: the PKCS#8 prefix, the seeds and the public keys.
+    const PKCS8_PREFIX: &str = "302e020100300506032b657004220420";
+    const KEYS: [(&str, &str, &str); 2] = [
+        (
+            "a",
+            "93123a7f62e317ab5847527947b3ef3387248c9913a2f99ebb5447a950f3f0bb",
+            "S7unTCfEADigyBeZW3_d_P0-oUjxzFwxBHQHVAhMtWk",
+        ),
+        (
+            "b",
+            "ee7f9fd20849b91a3805784352c3995a6b41731ea967042949b3476d683a37c0",
+            "Tx4FQVwDh_d71nIYSGmuDanKyf4T3NSn0Il973HymEU",
+        ),
+    ];
+
+    fn jwks(count: usize) -> String {
+        let keys: Vec<String> = KEYS[..count].iter().map(|(kid, _, x)| format!(r#"{{"kty":"OKP","crv":"Ed25519","use":"sig","kid":"{kid}","x":"{x}"}}"#)).collect();
+        format!(r#"{{"keys":[{}]}}"#, keys.join(","))
+    }
+
+    fn token(index: usize) -> String {
+        let (kid, seed, _) = KEYS[index];
+        let hex = format!("{PKCS8_PREFIX}{seed}");
+        let der: Vec<u8> = (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect();
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(kid.to_string());
+        let claims = SimpleClaims {
+            sub: "ada".to_string(),
+            exp: chrono::Utc::now().timestamp() as usize + 60,
+        };
+        jsonwebtoken::encode(&header, &claims, &EncodingKey::from_ed_der(&der)).unwrap()
+    }
+
+    /// A manager whose fetches count and serve the first `published` keys.
+    fn counting(published: &Arc<AtomicUsize>, fetches: &Arc<AtomicUsize>) -> JwtManager {
+        let (published, fetches) = (published.clone(), fetches.clone());
+        JwtManager::from_jwks_url("https://auth.example.com/.well-known/jwks.json").with_jwks_fetcher(move |url| {
+            assert_eq!(url, "https://auth.example.com/.well-known/jwks.json");
+            fetches.fetch_add(1, Ordering::SeqCst);
+            Ok(jwks(published.load(Ordering::SeqCst)))
+        })
+    }
+
+    #[test]
+    fn test_jwks_keys_are_cached_and_refreshed_for_unknown_kids() {
+        let (published, fetches) = (Arc::new(AtomicUsize::new(1)), Arc::new(AtomicUsize::new(0)));
+        let jwt = counting(&published, &fetches).with_jwks_min_refresh(Duration::ZERO);
+
+        assert_eq!(jwt.decode::<SimpleClaims>(&token(0)).unwrap().sub, "ada");
+        assert_eq!(jwt.decode::<SimpleClaims>(&token(0)).unwrap().sub, "ada");
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // The provider rotated in a new key
+        published.store(2, Ordering::SeqCst);
+        assert_eq!(jwt.decode::<SimpleClaims>(&token(1)).unwrap().sub, "ada");
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert!(jwt.encode(&"claims").is_err(), "public keys can't sign");
+    }
+
+    #[test]
+    fn test_unknown_kids_refresh_at_most_once_per_interval() {
+        let (published, fetches) = (Arc::new(AtomicUsize::new(1)), Arc::new(AtomicUsize::new(0)));
+        let jwt = counting(&published, &fetches);
+
+        for _ in 0..3 {
+            assert!(jwt.decode::<SimpleClaims>(&token(1)).is_err());
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(jwt.decode::<SimpleClaims>(&token(0)).unwrap().sub, "ada");
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_a_missing_kid_refetches_once_without_holding_up_other_callers() {
+        let (published, fetches) = (Arc::new(AtomicUsize::new(1)), Arc::new(AtomicUsize::new(0)));
+        let (entered, release) = (Arc::new(Barrier::new(2)), Arc::new(Barrier::new(2)));
+        let (count, keys, gates) = (fetches.clone(), published.clone(), (entered.clone(), release.clone()));
+        let jwt = JwtManager::from_jwks_url("https://auth.example.com/.well-known/jwks.json")
+            .with_jwks_fetcher(move |_| {
+                // The refetch waits until the other callers were answered
+                if count.fetch_add(1, Ordering::SeqCst) == 1 {
+                    gates.0.wait();
+                    gates.1.wait();
+                }
+                Ok(jwks(keys.load(Ordering::SeqCst)))
+            })
+            .with_jwks_min_refresh(Duration::ZERO);
+        assert_eq!(jwt.decode::<SimpleClaims>(&token(0)).unwrap().sub, "ada");
+
+        published.store(2, Ordering::SeqCst);
+        let jwt = Arc::new(jwt);
+        let rotated = {
+            let jwt = jwt.clone();
+            std::thread::spawn(move || jwt.decode::<SimpleClaims>(&token(1)).map(|data| data.sub))
+        };
+        entered.wait();
+        // While the refetch runs, cached keys still verify and the missing one doesn't start another fetch
+        assert_eq!(jwt.decode::<SimpleClaims>(&token(0)).unwrap().sub, "ada");
+        assert!(jwt.decode::<SimpleClaims>(&token(1)).is_err());
+        release.wait();
+
+        assert_eq!(rotated.join().unwrap().unwrap(), "ada");
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(jwt.decode::<SimpleClaims>(&token(1)).unwrap().sub, "ada");
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_jwks_is_fetched_over_http() {
+        let mut app = App::without_logger();
+        app.get("/jwks.json", |_req: &mut crate::Request, res: &mut crate::Response, _ctx: &crate::AppContext| {
+            res.add_header("Content-Type", "application/json").ok();
+            res.send_text(jwks(2));
+            next!()
+        });
+        app.get("/huge.json", |_req: &mut crate::Request, res: &mut crate::Response, _ctx: &crate::AppContext| {
+            res.send_text(" ".repeat(super::MAX_DOCUMENT as usize + 1));
+            next!()
+        });
+        let handle = app.start("127.0.0.1:0").unwrap();
+        let jwt = JwtManager::from_jwks_url(format!("http://{}/jwks.json", handle.local_addr().unwrap()));
+
+        assert_eq!(jwt.decode::<SimpleClaims>(&token(1)).unwrap().sub, "ada");
+        let missing = JwtManager::from_jwks_url(format!("http://{}/missing.json", handle.local_addr().unwrap()));
+        assert!(missing.decode::<SimpleClaims>(&token(1)).is_err());
+        // Too long for a JWKS, an error rather than a document cut off at the limit
+        let error = super::fetch(&format!("http://{}/huge.json", handle.local_addr().unwrap())).unwrap_err();
+        assert!(error.to_string().contains("larger than 1048576 bytes"), "{error}");
+
+        handle.shutdown_and_wait().unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+//! Keys read from PEM files, see [`JwtConfig`](super::JwtConfig).
+//!
+//! `jsonwebtoken` parses the files. It tells the key types apart, but not the curves of EC keys, so those
+//! are checked here against the algorithm.
+
+use super::{Error, ErrorKind};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use simple_asn1::{ASN1Block, OID, oid};
+use std::io;
+use std::path::Path;
+
+/// Read the PEM file at `path` into a key for `algorithm`.
+pub(crate) fn read_key<K>(path: &Path, algorithm: Algorithm, parse: fn(Algorithm, &str) -> Result<K, Error>) -> io::Result<K> {
+    let text = std::fs::read_to_string(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    parse(algorithm, &text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: not a {algorithm:?} key in PEM: {e}", path.display())))
+}
+
+/// A private key: PKCS#8 (`PRIVATE KEY`), or PKCS#1 (`RSA PRIVATE KEY`) for RSA.
+pub(crate) fn encoding_key(algorithm: Algorithm, text: &str) -> Result<EncodingKey, Error> {
+    match Family::of(algorithm) {
+        Family::Hmac => Err(Error::from(ErrorKind::InvalidKeyFormat)),
+        Family::Rsa => EncodingKey::from_rsa_pem(text.as_bytes()),
+        // `openssl pkcs8 -topk8 -nocrypt` converts `EC PRIVATE KEY` files
+        Family::Ec => check_curve(algorithm, text).and_then(|_| EncodingKey::from_ec_pem(text.as_bytes())),
+        Family::Ed => EncodingKey::from_ed_pem(text.as_bytes()),
+    }
+}
+
+/// A public key: `PUBLIC KEY`, or PKCS#1 (`RSA PUBLIC KEY`) for RSA.
+pub(crate) fn decoding_key(algorithm: Algorithm, text: &str) -> Result<DecodingKey, Error> {
+    match Family::of(algorithm) {
+        Family::Hmac => Err(Error::from(ErrorKind::InvalidKeyFormat)),
+        Family::Rsa => DecodingKey::from_rsa_pem(text.as_bytes()),
+        Family::Ec => check_curve(algorithm, text).and_then(|_| DecodingKey::from_ec_pem(text.as_bytes())),
+        Family::Ed => DecodingKey::from_ed_pem(text.as_bytes()),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Family {
+    Hmac,
+    Rsa,
+    Ec,
+    Ed,
+}
+
+impl Family {
+    fn of(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => Family::Hmac,
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 | Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512 => Family::Rsa,
+            Algorithm::ES256 | Algorithm::ES384 => Family::Ec,
+            Algorithm::EdDSA => Family::Ed,
+        }
+    }
+}
+
+/// Whether `algorithm` signs with a shared secret rather than a key pair.
+pub(crate) fn is_hmac(algorithm: Algorithm) -> bool {
+    Family::of(algorithm) == Family::Hmac
+}
+
+/// A P-256 key only makes ES256 signatures and a P-384 key ES384 ones, any other pairing would only fail
+/// once the first token is signed or verified.
+fn check_curve(algorithm: Algorithm, text: &str) -> Result<(), Error> {
+    let invalid = || Error::from(ErrorKind::InvalidKeyFormat);
+    let pem = ::pem::parse(text).map_err(|_| invalid())?;
+    let blocks = simple_asn1::from_der(pem.contents()).map_err(|_| invalid())?;
+    let expected = match algorithm {
+        // prime256v1 1.2.840.10045.3.1.7
+        Algorithm::ES256 => oid!(1, 2, 840, 10045, 3, 1, 7),
+        // secp384r1 1.3.132.0.34
+        _ => oid!(1, 3, 132, 0, 34),
+    };
+    match contains_oid(&blocks, &expected) {
+        true => Ok(()),
+        false => Err(Error::from(ErrorKind::InvalidAlgorithm)),
+    }
+}
+
+/// Whether `oid` is anywhere in `blocks`, the curve is a parameter of the key's algorithm.
+fn contains_oid(blocks: &[ASN1Block], oid: &OID) -> bool {
+    blocks.iter().any(|block| match block {
+        ASN1Block::ObjectIdentifier(_, found) => found == oid,
+        ASN1Block::Sequence(_, blocks) => contains_oid(blocks, oid),
+        _ => false,
+    })
+}
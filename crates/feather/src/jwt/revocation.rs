@@ -0,0 +1,104 @@
+//! Revoked tokens, consulted by [`JwtManager::decode`](super::JwtManager::decode).
+
+use parking_lot::RwLock;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Where revoked tokens are kept, set with [`JwtManager::with_revocation`](super::JwtManager::with_revocation).
+///
+/// Tokens are identified by their `jti` claim, or by their signature when they don't have one.
+/// Implement it over Redis or a database table to share revocations between instances.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// struct RedisRevocations(redis::Client);
+///
+/// impl RevocationStore for RedisRevocations {
+///     fn revoke(&self, id: &str, expires: Option<SystemTime>) {
+///         // SET revoked:{id} 1 EXAT {expires}
+///     }
+///
+///     fn is_revoked(&self, id: &str) -> bool {
+///         // EXISTS revoked:{id}
+///     }
+/// }
+/// ```
+pub trait RevocationStore: Send + Sync {
+    /// Revoke the token with `id`. It can be forgotten after `expires`, when the token is rejected as expired
+    /// anyway, and has to be kept for good when `None`.
+    fn revoke(&self, id: &str, expires: Option<SystemTime>);
+
+    /// Whether the token with `id` was revoked.
+    fn is_revoked(&self, id: &str) -> bool;
+}
+
+/// A [RevocationStore] in memory, for a single instance. Entries are dropped once their token expired.
+#[derive(Default)]
+pub struct MemoryRevocationStore {
+    inner: RwLock<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    expires: HashMap<String, Option<SystemTime>>,
+    /// The size at which expired entries are dropped next
+    purge_at: usize,
+}
+
+impl MemoryRevocationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many tokens are revoked, including expired ones not dropped yet.
+    pub fn len(&self) -> usize {
+        self.inner.read().expires.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl RevocationStore for MemoryRevocationStore {
+    fn revoke(&self, id: &str, expires: Option<SystemTime>) {
+        let mut inner = self.inner.write();
+        inner.expires.insert(id.to_string(), expires);
+        // Doubling the threshold keeps the purges amortized O(1) per revocation
+        if inner.expires.len() >= inner.purge_at {
+            let now = SystemTime::now();
+            inner.expires.retain(|_, expires| expires.is_none_or(|expires| expires > now));
+            inner.purge_at = (inner.expires.len() * 2).max(64);
+        }
+    }
+
+    fn is_revoked(&self, id: &str) -> bool {
+        match self.inner.read().expires.get(id) {
+            Some(Some(expires)) => *expires > SystemTime::now(),
+            Some(None) => true,
+            None => false,
+        }
+    }
+}
+
+/// The store of a [`JwtManager`](super::JwtManager), which derives `Debug`.
+#[derive(Clone)]
+pub(crate) struct Revocations(pub(crate) Arc<dyn RevocationStore>);
+
+impl fmt::Debug for Revocations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Revocations")
+    }
+}
+
+/// The `jti` of the token, or its signature, which no other token shares.
+pub(crate) fn revocation_id<'a>(token: &'a str, claims: &'a Value) -> &'a str {
+    match claims.get("jti").and_then(Value::as_str) {
+        Some(jti) => jti,
+        None => token.rsplit('.').next().unwrap_or(token),
+    }
+}
@@ -32,6 +32,35 @@
 //! - **Built-in Features**: Routing, middleware, state management, error handling, JWT auth.
 //! - **Multithreaded by Default**: Powered by Feather-Runtime for high concurrency.
 //!
+//! ## Cargo Features
+//!
+//! Only `log` is on by default, everything else is opt-in:
+//!
+//! | Feature | Enables |
+//! |---------|---------|
+//! | `log` | Request logging through `log` and `tracing-subscriber` |
+//! | `json` | JSON bodies and responses through `serde` |
+//! | `jwt` | JWT auth, cookie sessions and signed preferences, turns on `json` and `client` |
+//! | `tls` | HTTPS with rustls |
+//! | `ws` | WebSocket routes and hubs |
+//! | `client` | The HTTP/1.1 client and `AppContext::parallel_fetch` |
+//! | `db` | Connection pools through `r2d2` |
+//! | `minijinja`, `tera` | Template engines, turn on `json` |
+//! | `embed` | Serving files embedded in the binary |
+//! | `toml` | TOML configuration files, turns on `json` |
+//! | `signals`, `daemon`, `windows-service` | Shutdown on signals, daemonizing and Windows services |
+//! | `anyhow`, `eyre` | Converting their reports into [`Error`] |
+//!
+//! Features that need a runtime component forward to the matching `feather-runtime` feature, which the build
+//! checks. The minimal profile is plain HTTP/1.1 routing and middleware, for embedded targets and small binaries:
+//!
+//! ```toml
+//! [dependencies]
+//! feather = { version = "0.8", default-features = false }
+//! ```
+//!
+//! There is no `h2` feature: the runtime has no HTTP/2 implementation, put a proxy in front for HTTP/2 clients.
+//!
 //! ## Comprehensive Guides
 //!
 //! Feather comes with detailed guides for every aspect:
@@ -85,11 +114,32 @@
 
 // --- IMPORTS START ---
 
+// Lets the code the macros generate, which names `feather::...`, compile inside this crate as well
+extern crate self as feather;
+
+pub mod cache;
+#[cfg(feature = "json")]
+pub mod config;
+pub mod cookie;
+#[cfg(feature = "db")]
+pub mod db;
+pub mod extract;
 pub mod internals;
+pub mod jobs;
 #[cfg(feature = "jwt")]
 pub mod jwt;
 
 pub mod middlewares;
+#[cfg(feature = "jwt")]
+pub mod prefs;
+pub mod process;
+#[cfg(feature = "jwt")]
+pub mod session;
+#[cfg(feature = "json")]
+pub mod templates;
+pub mod testing;
+#[cfg(feature = "ws")]
+pub mod ws;
 
 /// Comprehensive guides and tutorials for Feather.
 ///
@@ -138,14 +188,32 @@ pub use serde_json::{Value, json};
 #[cfg(feature = "log")]
 pub use log::{info, trace, warn};
 
-use std::error::Error;
 
-pub use crate::internals::State;
+pub use crate::internals::{ReadState, State, WatchState};
 pub use crate::middlewares::MiddlewareResult;
 pub use crate::middlewares::builtins;
-pub use feather_runtime::http::{Request, Response};
+pub use feather_runtime::Bump;
+pub use feather_runtime::http::{Hijacked, Request, Response};
 pub use feather_runtime::runtime::server::ServerConfig;
-pub use internals::{App, AppContext, Finalizer, Router};
+#[cfg(feature = "tls")]
+pub use feather_runtime::runtime::tls::{TlsConfig, TlsError};
+#[cfg(feature = "client")]
+pub use feather_runtime::client;
+#[cfg(feature = "client")]
+pub use internals::FanOutPolicy;
+
+// Every feature forwarded to the runtime must be enabled there, a forward missing in Cargo.toml fails the build here
+const _: () = {
+    assert!(!cfg!(feature = "json") || feather_runtime::features::JSON, "the `json` feature requires `feather-runtime/json`");
+    assert!(!cfg!(feature = "log") || feather_runtime::features::LOG, "the `log` feature requires `feather-runtime/log`");
+    assert!(!cfg!(feature = "tls") || feather_runtime::features::TLS, "the `tls` feature requires `feather-runtime/tls`");
+    assert!(!cfg!(feature = "ws") || feather_runtime::features::WS, "the `ws` feature requires `feather-runtime/ws`");
+    assert!(!cfg!(feature = "client") || feather_runtime::features::CLIENT, "the `client` feature requires `feather-runtime/client`");
+};
+pub use internals::{
+    Annotations, App, AppContext, Controller, Diagnostic, Error, ErrorHandler, Finalizer, HealthReport, HttpError, Metrics, MiddlewareToggles, PanicError, Profile, RequestAnnotations, Resource, RouteDef, RouteHandle, RouteInfo,
+    RouteMeta, Router, ServeError, Severity, ShutdownHandle,
+};
 
 pub mod prelude {
     pub use crate::Outcome;
@@ -160,9 +228,9 @@ pub mod prelude {
 }
 // --- IMPORTS END ---
 
-/// This is just a type alias for `Result<MiddlewareResult, Box<dyn Error>>;`  
+/// This is just a type alias for `Result<MiddlewareResult, feather::Error>;`  
 /// Outcome is used in All middlewares as a return type.
-pub type Outcome = Result<MiddlewareResult, Box<dyn Error>>;
+pub type Outcome = Result<MiddlewareResult, Error>;
 
 /// This macro is just a syntactic sugar over the `Ok(MiddlewareResult::Next)`
 ///
@@ -197,6 +265,43 @@ macro_rules! end {
         Ok($crate::middlewares::MiddlewareResult::End)
     };
 }
+/// Returns a `404 Not Found` [HttpError] from the handler, its message formatted like `format!`.
+///
+/// ```rust,ignore
+/// let Some(user) = users.get(&id) else {
+///     bail_404!("no user {id}");
+/// };
+/// ```
+#[macro_export]
+macro_rules! bail_404 {
+    () => {
+        $crate::bail_404!("Not Found")
+    };
+    ($($arg:tt)+) => {
+        return Err($crate::HttpError::not_found(format!($($arg)+)).into())
+    };
+}
+/// Returns a `403 Forbidden` [HttpError] from the handler unless `condition` holds, with `Forbidden` or a
+/// message formatted like `format!`.
+///
+/// Use it once the client is known, for permission checks. Missing credentials are a
+/// [`HttpError::unauthorized`] instead.
+///
+/// ```rust,ignore
+/// ensure_authorized!(user.is_admin());
+/// ensure_authorized!(post.author == user.id, "only the author can edit post {}", post.id);
+/// ```
+#[macro_export]
+macro_rules! ensure_authorized {
+    ($condition:expr $(,)?) => {
+        $crate::ensure_authorized!($condition, "Forbidden")
+    };
+    ($condition:expr, $($arg:tt)+) => {
+        if !$condition {
+            return Err($crate::HttpError::forbidden(format!($($arg)+)).into());
+        }
+    };
+}
 /// The `middleware!` macro allows you to define middleware functions concisely without repeating type signatures.
 ///
 /// # Usage
@@ -210,16 +315,120 @@ macro_rules! end {
 /// }));
 /// ```
 ///
+/// Parameters the middleware doesn't use can be left out, as long as the ones left are named `req`, `res`
+/// and `ctx` so the macro knows which is which:
+///
+/// ```rust,ignore
+/// app.get("/health", middleware!(|res| res.finish_text("OK")));
+/// app.use_middleware(middleware!(|req, ctx| {
+///     ctx.metrics().counter("requests").increment();
+///     println!("{} {}", req.method, req.uri);
+///     next!()
+/// }));
+/// ```
+///
+/// Prefix the closure with `move` to capture owned values:
+///
+/// ```rust,ignore
+/// let greeting = config.greeting.clone();
+/// app.get("/", middleware!(move |_req, res, _ctx| res.finish_text(greeting.clone())));
+/// ```
+///
 /// This macro expands to a closure with the correct types for Feather's middleware system.
 #[macro_export]
 macro_rules! middleware {
+    (@typed [$($move:tt)?] $req:tt, $res:tt, $ctx:tt, $body:expr) => {
+        $($move)? |$req: &mut $crate::Request, $res: &mut $crate::Response, $ctx: &$crate::AppContext| $body
+    };
+    (@elided [$($move:tt)?] [req] [$req:ident] $body:expr) => { $crate::middleware!(@typed [$($move)?] $req, _, _, $body) };
+    (@elided [$($move:tt)?] [res] [$res:ident] $body:expr) => { $crate::middleware!(@typed [$($move)?] _, $res, _, $body) };
+    (@elided [$($move:tt)?] [ctx] [$ctx:ident] $body:expr) => { $crate::middleware!(@typed [$($move)?] _, _, $ctx, $body) };
+    (@elided [$($move:tt)?] [req res] [$req:ident $res:ident] $body:expr) => { $crate::middleware!(@typed [$($move)?] $req, $res, _, $body) };
+    (@elided [$($move:tt)?] [req ctx] [$req:ident $ctx:ident] $body:expr) => { $crate::middleware!(@typed [$($move)?] $req, _, $ctx, $body) };
+    (@elided [$($move:tt)?] [res ctx] [$res:ident $ctx:ident] $body:expr) => { $crate::middleware!(@typed [$($move)?] _, $res, $ctx, $body) };
+    (@elided [$($move:tt)?] [$($name:ident)+] $($rest:tt)*) => {
+        compile_error!("middleware! parameters are `req, res, ctx` in this order, leave out the ones you don't use or list all three")
+    };
     // Argument form: middleware!(|req, res, ctx| { ... })
-    (|$req:ident, $res:ident, $ctx:ident| $body:block) => {
-        |$req: &mut $crate::Request, $res: &mut $crate::Response, $ctx: &$crate::AppContext| $body
+    (|$req:tt, $res:tt, $ctx:tt| $body:expr) => {
+        $crate::middleware!(@typed [] $req, $res, $ctx, $body)
+    };
+    (move |$req:tt, $res:tt, $ctx:tt| $body:expr) => {
+        $crate::middleware!(@typed [move] $req, $res, $ctx, $body)
+    };
+    // Elided form: middleware!(|res| { ... })
+    (|$($name:ident),+| $body:expr) => {
+        $crate::middleware!(@elided [] [$($name)+] [$($name)+] $body)
+    };
+    (move |$($name:ident),+| $body:expr) => {
+        $crate::middleware!(@elided [move] [$($name)+] [$($name)+] $body)
+    };
+}
+
+/// Registers handlers declared with route attributes like `#[get("/users/:id")]` on an [App] or a [Router].
+///
+/// Each handler is routed with the method and path of its attribute, so the route table stays next to the
+/// handlers instead of in `main`.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[get("/users/:id")]
+/// #[middleware_fn]
+/// fn show_user() {
+///     res.finish_text(format!("User {}", req.param("id").unwrap_or_default()))
+/// }
+///
+/// register!(app, show_user, users::create_user);
+/// ```
+#[macro_export]
+macro_rules! register {
+    ($target:expr, $($handler:path),+ $(,)?) => {{
+        let target = &mut $target;
+        $(
+            target.route(<$handler as $crate::internals::AttributeRoute>::METHOD, <$handler as $crate::internals::AttributeRoute>::PATH, $handler);
+        )+
+    }};
+}
+
+/// Declares a table of routes on an [App] or a [Router], one line per route.
+///
+/// Entries are a method and a path routed to a handler, or `mount` with a prefix and a [Router].
+/// Methods are written like in HTTP, so extension methods like `PURGE` work too. Every entry expands to
+/// the matching `method` or `mount` call, in order.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// routes! { app;
+///     GET "/" => home,
+///     GET "/users/:id" => show_user,
+///     POST "/users" => create_user,
+///     PURGE "/cache" => purge_cache,
+///     mount "/api" => api_router(),
+/// }
+/// ```
+#[macro_export]
+macro_rules! routes {
+    (@entries $target:ident;) => {};
+    (@entries $target:ident; mount $prefix:expr => $router:expr $(, $($rest:tt)*)?) => {
+        $target.mount($prefix, $router);
+        $crate::routes!(@entries $target; $($($rest)*)?);
+    };
+    (@entries $target:ident; $method:ident $path:expr => $handler:expr $(, $($rest:tt)*)?) => {
+        $target.method(stringify!($method), $path, $handler);
+        $crate::routes!(@entries $target; $($($rest)*)?);
     };
+    ($target:expr; $($entries:tt)*) => {{
+        let target = &mut $target;
+        $crate::routes!(@entries target; $($entries)*);
+    }};
 }
 
+pub use feather_macros::handler;
 pub use feather_macros::middleware_fn;
+pub use feather_macros::resource;
+pub use feather_macros::{delete, get, head, options, patch, post, put};
 
 #[cfg(feature = "jwt")]
 pub use feather_macros::Claim;
@@ -1,18 +1,23 @@
 //! Built-in middleware for common functionality.
 //!
-//! This module provides ready-to-use middleware for logging, CORS, and static file serving.
+//! This module provides ready-to-use middleware for logging, slow-request detection, CORS, HSTS, crawler control, cache policies, readiness checks, state inspection, and static file serving from disk or from the binary.
 
 use super::common::Middleware;
+use crate::internals::{Diagnostic, NoRouteMatched, send_error};
 use crate::{Outcome, end, internals::AppContext, next};
 
+use feather_runtime::{HeaderName, HeaderValue, Method};
 use feather_runtime::http::{Request, Response};
 #[cfg(feature = "log")]
 use log::info;
 use std::{
+    collections::HashMap,
     fs::{self, File},
-    io::{self, Read},
+    io::{self, Seek, SeekFrom},
     path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use subtle::ConstantTimeEq;
 
 /// Logs incoming HTTP requests.
 ///
@@ -40,12 +45,72 @@ impl Middleware for Logger {
     }
 }
 
-#[derive(Default)]
+/// Warns about requests that take longer than a threshold to handle.
+///
+/// The duration is measured from the moment this middleware runs until the response is ready,
+/// so register it first to cover the whole chain. Slow requests are logged with their route,
+/// duration and `X-Request-Id` (if the client sent one), and counted in the
+/// `slow_requests` counter of [`AppContext::metrics`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use std::time::Duration;
+/// use feather::{App, middlewares::builtins::SlowRequest};
+///
+/// let mut app = App::new();
+/// app.use_middleware(SlowRequest::new(Duration::from_millis(500)));
+/// ```
+pub struct SlowRequest {
+    threshold: Duration,
+}
+
+/// Marks when [SlowRequest] first saw the request.
+#[derive(Clone, Copy)]
+struct RequestStart(Instant);
+
+impl SlowRequest {
+    /// Name of the counter incremented for every slow request.
+    pub const COUNTER: &'static str = "slow_requests";
+
+    /// Create a slow-request detector that warns about requests slower than `threshold`.
+    #[must_use = "This middleware must be added to the app with use_middleware()"]
+    pub const fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+        }
+    }
+}
+
+impl Middleware for SlowRequest {
+    fn handle(&self, request: &mut Request, _: &mut Response, _: &AppContext) -> Outcome {
+        request.extensions.insert(RequestStart(Instant::now()));
+        next!()
+    }
+
+    fn after(&self, request: &Request, _: &mut Response, ctx: &AppContext) {
+        let Some(RequestStart(start)) = request.extensions.get::<RequestStart>() else {
+            return;
+        };
+        let elapsed = start.elapsed();
+        if elapsed < self.threshold {
+            return;
+        }
+        ctx.metrics().counter(Self::COUNTER).increment();
+        let request_id = request.headers.get("x-request-id").and_then(|v| v.to_str().ok()).unwrap_or("-");
+        Diagnostic::warning("slow request").route(&request.method, request.uri.path()).with("took", format_args!("{elapsed:?}")).with("request id", request_id).emit();
+    }
+}
+
 /// Adds CORS (Cross-Origin Resource Sharing) headers to responses.
 ///
 /// This middleware adds the `Access-Control-Allow-Origin` header to all responses,
 /// allowing browsers to make cross-origin requests to your API.
 ///
+/// [`Cors::default()`] depends on the [`Profile`](crate::Profile): it allows every origin in
+/// development, but outside of it an explicit origin is required and no header is sent.
+/// Use [`Cors::any()`] to allow every origin regardless of the profile.
+///
 /// # Example
 ///
 /// ```rust,ignore
@@ -53,13 +118,26 @@ impl Middleware for Logger {
 ///
 /// let mut app = App::new();
 ///
-/// // Allow all origins
+/// // Allow all origins in development only
 /// app.use_middleware(Cors::default());
 ///
 /// // Allow specific origin
 /// app.use_middleware(Cors::new("https://example.com".to_string()));
+///
+/// // Allow all origins everywhere
+/// app.use_middleware(Cors::any());
+///
+/// // Allow every subdomain
+/// app.use_middleware(Cors::default().allow_wildcard("https://*.example.com"));
+/// app.use_middleware(Cors::default().allow_origin_if(|origin| origin.ends_with(".example.com")));
 /// ```
-pub struct Cors(Option<String>);
+#[derive(Default)]
+pub struct Cors {
+    origin: Option<String>,
+    matchers: Vec<OriginMatcher>,
+}
+
+type OriginMatcher = Box<dyn Fn(&str) -> bool + Send + Sync>;
 
 impl Cors {
     /// Create a CORS middleware for a specific origin.
@@ -76,27 +154,509 @@ impl Cors {
     /// ```
     #[must_use]
     pub const fn new(origin: String) -> Self {
-        Self(Some(origin))
+        Self {
+            origin: Some(origin),
+            matchers: Vec::new(),
+        }
+    }
+
+    /// Create a CORS middleware that allows every origin (`*`), even in production.
+    #[must_use]
+    pub fn any() -> Self {
+        Self::new("*".to_string())
+    }
+
+    /// Also allow the origins accepted by `predicate`.
+    ///
+    /// The request `Origin` is passed to the predicate and, if it matches, reflected in
+    /// `Access-Control-Allow-Origin` together with `Vary: Origin` so caches keep the responses apart.
+    /// Once a predicate is set, the profile default of [`Cors::default()`] no longer applies.
+    #[must_use]
+    pub fn allow_origin_if(mut self, predicate: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.matchers.push(Box::new(predicate));
+        self
+    }
+
+    /// Also allow the origins matching `pattern`, where a single `*` stands for any subdomains.
+    ///
+    /// `https://*.example.com` allows `https://api.example.com` and `https://a.b.example.com`,
+    /// but neither `https://example.com` nor `http://api.example.com`.
+    #[must_use]
+    pub fn allow_wildcard(self, pattern: &str) -> Self {
+        let (prefix, suffix) = pattern.split_once('*').map_or((pattern.to_string(), None), |(prefix, suffix)| (prefix.to_string(), Some(suffix.to_string())));
+        self.allow_origin_if(move |origin| match &suffix {
+            Some(suffix) => origin.len() > prefix.len() + suffix.len() && origin.starts_with(&prefix) && origin.ends_with(suffix.as_str()) && !origin[prefix.len()..origin.len() - suffix.len()].contains(['/', ':']),
+            None => origin == prefix,
+        })
     }
 }
 
 impl Middleware for Cors {
-    fn handle(&self, _: &mut Request, response: &mut Response, _: &AppContext) -> Outcome {
-        response.add_header("Access-Control-Allow-Origin", self.0.as_deref().unwrap_or("*"))?;
+    fn handle(&self, request: &mut Request, response: &mut Response, ctx: &AppContext) -> Outcome {
+        if !self.matchers.is_empty() {
+            // The answer depends on the request origin, even when it doesn't match
+            response.headers.append(HeaderName::from_static("vary"), HeaderValue::from_static("Origin"));
+            let origin = request.headers.get("origin").and_then(|v| v.to_str().ok());
+            if let Some(origin) = origin
+                && self.matchers.iter().any(|matches| matches(origin))
+            {
+                response.add_header("Access-Control-Allow-Origin", origin)?;
+                return next!();
+            }
+        }
+        match (&self.origin, ctx.profile().is_dev() && self.matchers.is_empty()) {
+            (Some(origin), _) => response.add_header("Access-Control-Allow-Origin", origin)?,
+            (None, true) => response.add_header("Access-Control-Allow-Origin", "*")?,
+            (None, false) => {}
+        }
+        next!()
+    }
+}
+
+/// Adds the `Strict-Transport-Security` header, telling browsers to only use HTTPS.
+///
+/// By default the header is only sent outside of [`Profile::Dev`](crate::Profile::Dev), since HSTS on
+/// `localhost` would break plain HTTP development servers. Use [`enabled`](Self::enabled) to override it.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::{App, middlewares::builtins::Hsts};
+///
+/// let mut app = App::new();
+/// app.use_middleware(Hsts::default().include_subdomains(true));
+/// ```
+pub struct Hsts {
+    max_age: u64,
+    include_subdomains: bool,
+    preload: bool,
+    enabled: Option<bool>,
+}
+
+impl Hsts {
+    /// Create a HSTS middleware with the given `max-age` in seconds.
+    #[must_use = "This middleware must be added to the app with use_middleware()"]
+    pub const fn new(max_age: u64) -> Self {
+        Self {
+            max_age,
+            include_subdomains: false,
+            preload: false,
+            enabled: None,
+        }
+    }
+
+    /// Also apply the policy to all subdomains.
+    #[must_use]
+    pub const fn include_subdomains(mut self, include: bool) -> Self {
+        self.include_subdomains = include;
+        self
+    }
+
+    /// Add the `preload` directive, required to get into the browsers' preload lists.
+    #[must_use]
+    pub const fn preload(mut self, preload: bool) -> Self {
+        self.preload = preload;
+        self
+    }
+
+    /// Force sending the header on (`true`) or off (`false`), regardless of the profile.
+    #[must_use]
+    pub const fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = Some(enabled);
+        self
+    }
+}
+
+impl Default for Hsts {
+    /// One year, the value recommended for production sites.
+    fn default() -> Self {
+        Self::new(31_536_000)
+    }
+}
+
+impl Middleware for Hsts {
+    fn handle(&self, _: &mut Request, response: &mut Response, ctx: &AppContext) -> Outcome {
+        if !self.enabled.unwrap_or(!ctx.profile().is_dev()) {
+            return next!();
+        }
+        let mut value = format!("max-age={}", self.max_age);
+        if self.include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        if self.preload {
+            value.push_str("; preload");
+        }
+        response.add_header("Strict-Transport-Security", &value)?;
         next!()
     }
 }
 
+/// Keeps crawlers away from pages that shouldn't be indexed.
+///
+/// Sets the `X-Robots-Tag` header on responses whose path matches one of the configured rules,
+/// and answers `GET /robots.txt` with a file generated from the same rules.
+///
+/// When the [`Profile`](crate::Profile) is set to something else than [`Profile::Prod`](crate::Profile::Prod), with
+/// `FEATHER_ENV` or [`AppContext::set_profile`], every page is marked `noindex, nofollow` and robots.txt disallows
+/// everything, so staging deployments stay out of search indexes. Without a profile pages are indexed, a production
+/// server that forgot `FEATHER_ENV` isn't dropped from search results. Use [`block_all`](Self::block_all) to override that.
+///
+/// Patterns match the request path exactly, or by prefix when they end with `*` (e.g. `/admin/*`).
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::{App, middlewares::builtins::CrawlerControl};
+///
+/// let mut app = App::new();
+/// app.use_middleware(CrawlerControl::new().noindex("/admin/*").noindex("/search").rule("/drafts/*", "noarchive"));
+/// ```
+pub struct CrawlerControl {
+    rules: Vec<(String, String)>,
+    block_all: Option<bool>,
+}
+
+impl CrawlerControl {
+    /// Create a crawler control without rules that blocks everything when the profile is set to dev or staging.
+    #[must_use = "This middleware must be added to the app with use_middleware()"]
+    pub const fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+            block_all: None,
+        }
+    }
+
+    /// Mark the paths matching `pattern` as `noindex, nofollow`.
+    #[must_use]
+    pub fn noindex(self, pattern: impl Into<String>) -> Self {
+        self.rule(pattern, "noindex, nofollow")
+    }
+
+    /// Send `directives` in the `X-Robots-Tag` header for the paths matching `pattern`.
+    ///
+    /// Rules with a `noindex` directive are also listed in robots.txt.
+    #[must_use]
+    pub fn rule(mut self, pattern: impl Into<String>, directives: impl Into<String>) -> Self {
+        self.rules.push((pattern.into(), directives.into()));
+        self
+    }
+
+    /// Force blocking every page on (`true`) or off (`false`), regardless of the profile.
+    #[must_use]
+    pub const fn block_all(mut self, block: bool) -> Self {
+        self.block_all = Some(block);
+        self
+    }
+
+    fn blocks_all(&self, ctx: &AppContext) -> bool {
+        self.blocks_all_in(ctx.explicit_profile())
+    }
+
+    fn blocks_all_in(&self, profile: Option<crate::Profile>) -> bool {
+        self.block_all.unwrap_or_else(|| profile.is_some_and(|profile| !profile.is_prod()))
+    }
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        match pattern.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == pattern,
+        }
+    }
+
+    /// Generate the robots.txt body.
+    pub fn robots_txt(&self, ctx: &AppContext) -> String {
+        let mut body = String::from("User-agent: *\n");
+        if self.blocks_all(ctx) {
+            body.push_str("Disallow: /\n");
+            return body;
+        }
+        let mut disallowed = self.rules.iter().filter(|(_, directives)| directives.contains("noindex")).peekable();
+        if disallowed.peek().is_none() {
+            body.push_str("Disallow:\n");
+        }
+        for (pattern, _) in disallowed {
+            // robots.txt matches by prefix, `$` anchors exact patterns
+            match pattern.strip_suffix('*') {
+                Some(prefix) => body.push_str(&format!("Disallow: {prefix}\n")),
+                None => body.push_str(&format!("Disallow: {pattern}$\n")),
+            }
+        }
+        body
+    }
+}
+
+impl Default for CrawlerControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for CrawlerControl {
+    fn handle(&self, request: &mut Request, response: &mut Response, ctx: &AppContext) -> Outcome {
+        if request.method == Method::GET && request.uri.path() == "/robots.txt" {
+            response.send_text(self.robots_txt(ctx));
+            return end!();
+        }
+        next!()
+    }
+
+    fn after(&self, request: &Request, response: &mut Response, ctx: &AppContext) {
+        let directives = if self.blocks_all(ctx) {
+            Some("noindex, nofollow")
+        } else {
+            self.rules.iter().find(|(pattern, _)| Self::matches(pattern, request.uri.path())).map(|(_, directives)| directives.as_str())
+        };
+        if let Some(directives) = directives {
+            response.add_header("X-Robots-Tag", directives).ok();
+        }
+    }
+}
+
+/// Marks a request as authenticated, so [`CachePolicy`] keeps its response out of shared caches.
+///
+/// The JWT middlewares insert it once a token is accepted. Custom authentication should insert it too:
+///
+/// ```rust,ignore
+/// request.extensions.insert(Authenticated);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Authenticated;
+
+/// Sets `Cache-Control` from the authentication state of the request, so per-user content never ends up in a CDN.
+///
+/// Responses to [`Authenticated`] requests are always `private`: a `public` or `s-maxage` directive set by the
+/// handler is dropped, the other directives are kept. Successful `GET` and `HEAD` responses to anonymous requests
+/// get `public, max-age=<max_age>` unless the handler set a `Cache-Control` header itself.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::{App, middlewares::builtins::CachePolicy};
+///
+/// let mut app = App::new();
+/// app.use_middleware(CachePolicy::new(300));
+/// ```
+pub struct CachePolicy {
+    max_age: u64,
+}
+
+impl CachePolicy {
+    /// Create a cache policy letting shared caches keep anonymous responses for `max_age` seconds.
+    #[must_use = "This middleware must be added to the app with use_middleware()"]
+    pub const fn new(max_age: u64) -> Self {
+        Self {
+            max_age,
+        }
+    }
+}
+
+impl Middleware for CachePolicy {
+    fn handle(&self, _: &mut Request, _: &mut Response, _: &AppContext) -> Outcome {
+        next!()
+    }
+
+    fn after(&self, request: &Request, response: &mut Response, _: &AppContext) {
+        let current = response.headers.get("cache-control").and_then(|value| value.to_str().ok());
+        let value = if request.extensions.get::<Authenticated>().is_some() {
+            let mut directives = vec!["private"];
+            directives.extend(current.into_iter().flat_map(|value| value.split(',')).map(str::trim).filter(|directive| {
+                let name = directive.split('=').next().unwrap_or_default();
+                !directive.is_empty() && !["public", "private", "s-maxage"].iter().any(|shared| name.eq_ignore_ascii_case(shared))
+            }));
+            directives.join(", ")
+        } else if current.is_none() && matches!(request.method, Method::GET | Method::HEAD) && response.status.is_success() {
+            format!("public, max-age={}", self.max_age)
+        } else {
+            return;
+        };
+        response.add_header("Cache-Control", &value).ok();
+    }
+}
+
+/// Answers a readiness endpoint from the health probes of the application state.
+///
+/// `GET` requests to the path run every probe registered with
+/// [`AppContext::set_state_with_health`] and respond with `200 OK` when all of them pass,
+/// or `503 Service Unavailable` otherwise. The body lists the result of each probe.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::{App, middlewares::builtins::Readiness};
+///
+/// let mut app = App::new();
+/// app.context().set_state_with_health(pool, |pool: &Pool| pool.ping());
+/// app.use_middleware(Readiness::new("/ready"));
+/// ```
+pub struct Readiness {
+    path: String,
+}
+
+impl Readiness {
+    /// Serve the readiness report on `path`.
+    #[must_use = "This middleware must be added to the app with use_middleware()"]
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+        }
+    }
+}
+
+impl Default for Readiness {
+    /// Serve the readiness report on `/ready`.
+    fn default() -> Self {
+        Self::new("/ready")
+    }
+}
+
+impl Middleware for Readiness {
+    fn handle(&self, request: &mut Request, response: &mut Response, ctx: &AppContext) -> Outcome {
+        if request.method != Method::GET || request.uri.path() != self.path {
+            return next!();
+        }
+        let report = ctx.health();
+        response.set_status(match report.is_healthy() {
+            true => 200,
+            false => 503,
+        });
+        response.add_header("Cache-Control", "no-store")?;
+        match report.checks() {
+            [] => response.send_text("ok\n"),
+            _ => response.send_text(report.to_string()),
+        }
+        end!()
+    }
+}
+
+/// Lists the types stored in the [`AppContext`], for debugging a `state not found` panic.
+///
+/// `GET` requests to the path are answered with [`AppContext::debug_entries`], one type name per line.
+/// The endpoint only answers in debug builds running the [development profile](crate::Profile::is_dev),
+/// and only to clients on the same machine or, with [token](StateInspector::token), to requests with
+/// `Authorization: Bearer <token>`. Other requests pass through as if it wasn't there.
+///
+/// Behind a reverse proxy on the same machine every request comes from localhost, set a token there.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::{App, middlewares::builtins::StateInspector};
+///
+/// let mut app = App::new();
+/// // curl http://localhost:5050/._feather/state
+/// app.use_middleware(StateInspector::default());
+/// ```
+pub struct StateInspector {
+    path: String,
+    token: Option<String>,
+    debug_build: bool,
+}
+
+impl StateInspector {
+    /// Serve the stored types on `path`.
+    #[must_use = "This middleware must be added to the app with use_middleware()"]
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            token: None,
+            debug_build: cfg!(debug_assertions),
+        }
+    }
+
+    /// Only answer requests with `Authorization: Bearer <token>`, from any address.
+    #[must_use = "This middleware must be added to the app with use_middleware()"]
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    fn allows(&self, request: &Request) -> bool {
+        match &self.token {
+            // In constant time, so the response time doesn't tell how much of a guess was right
+            Some(token) => request.headers.get("Authorization").and_then(|h| h.as_bytes().strip_prefix(b"Bearer ")).is_some_and(|given| bool::from(given.ct_eq(token.as_bytes()))),
+            None => request.remote_addr().ip().is_loopback(),
+        }
+    }
+}
+
+impl Default for StateInspector {
+    /// Serve the stored types on `/._feather/state`.
+    fn default() -> Self {
+        Self::new("/._feather/state")
+    }
+}
+
+impl Middleware for StateInspector {
+    fn handle(&self, request: &mut Request, response: &mut Response, ctx: &AppContext) -> Outcome {
+        if !self.debug_build || request.method != Method::GET || request.uri.path() != self.path || !ctx.profile().is_dev() || !self.allows(request) {
+            return next!();
+        }
+        response.add_header("Cache-Control", "no-store")?;
+        let entries = ctx.debug_entries();
+        response.send_text(entries.iter().map(|name| format!("{name}\n")).collect::<String>());
+        end!()
+    }
+}
+
 /// Serves static files from a directory.
 ///
 /// This middleware serves static files (HTML, CSS, JavaScript, images, etc.) from
 /// a specified directory. It automatically detects content types based on file extensions.
 /// returns HTTP errors for invalid paths.
+///
+/// # Ordering
+///
+/// By default static files are looked up first: a file shadows a route with the same path, and
+/// requests without a matching file fall through to the router. Two options change that:
+///
+/// - [`fallthrough(false)`](Self::fallthrough) answers missing files with a 404 right away instead of trying the routes.
+/// - [`routes_first(true)`](Self::routes_first) only serves a file when no route matched the request.
+///
+/// # Mounting
+///
+/// [`mount("/static")`](Self::mount) only serves the requests under `/static`, looked up without the
+/// prefix, so `GET /static/app.js` serves `app.js` of the directory. The other requests are passed on
+/// without touching the file system, even with [`fallthrough(false)`](Self::fallthrough).
+///
+/// # Directories
+///
+/// A request for a directory serves its index file, the first of [`index`](Self::index) that exists
+/// (`index.html`, then `index.htm`), so `GET /docs/` serves `docs/index.html`. `GET /docs` is redirected
+/// to `/docs/` first, so relative links in the page resolve inside the directory. Directories without an
+/// index file are treated like missing files, their contents are never listed.
+///
+/// # Caching
+///
+/// Files are sent with a weak `ETag` and a `Last-Modified` date. Browsers asking again with `If-None-Match`
+/// or `If-Modified-Since` get `304 Not Modified` while the file is unchanged, without the file being read.
+///
+/// # Precompressed Files
+///
+/// Files compressed ahead of time, like `app.js.br` or `app.js.gz` next to `app.js`, are sent instead of
+/// the file to clients whose `Accept-Encoding` allows it, with its `Content-Encoding`, so nothing is
+/// compressed while serving. Brotli is preferred over gzip. Responses for files that have such a sibling
+/// carry `Vary: Accept-Encoding`. Turned off with [`precompressed(false)`](Self::precompressed).
+///
+/// Files get a `Cache-Control` header when one is configured with [`cache_control`](Self::cache_control),
+/// overridden per extension with [`cache_control_for`](Self::cache_control_for).
+///
+/// # Ranges
+///
+/// A `GET` with a single `Range: bytes=...` range, like the ones video players send to seek or download
+/// managers to resume, gets only those bytes with `206 Partial Content`, and a range past the end of the
+/// file gets `416 Range Not Satisfiable`. Several ranges in one header are answered with the whole file,
+/// and so is a range whose `If-Range` doesn't match the `ETag` or `Last-Modified` date of the file.
+///
+/// # Streaming
+///
+/// Files are never loaded whole: they are read and sent [`chunk_size`](Self::chunk_size) bytes at a
+/// time, 64 KiB by default, so serving a large video costs one chunk of memory per request.
+///
 /// # Security
 ///
 /// - Path traversal attacks are prevented (.. is not allowed)
 /// - Directory listing is disabled
-/// - Only files are served, not directories
+/// - Only files are served, a directory only through its index file
 ///
 /// # Example
 ///
@@ -105,10 +665,110 @@ impl Middleware for Cors {
 ///
 /// let mut app = App::new();
 /// app.use_middleware(ServeStatic::new("./public".to_string()));
+///
+/// // Let routes win over files with the same path
+/// app.use_middleware(ServeStatic::new("./public").routes_first(true));
+///
+/// // Serve `./assets/app.js` at `/static/app.js`
+/// app.use_middleware(ServeStatic::new("./assets").mount("/static"));
 /// ```
 //TODO FIX WIN ERRORS
 pub struct ServeStatic {
     base_path: PathBuf,
+    mount: Option<String>,
+    fallthrough: bool,
+    routes_first: bool,
+    index: Vec<String>,
+    precompressed: bool,
+    cache_control: FileCacheControl,
+    chunk_size: usize,
+}
+
+/// The bytes of a file a request asks for.
+enum ByteRange {
+    /// The whole file.
+    Full,
+    /// The bytes from the first offset to the second, inclusive.
+    Part(u64, u64),
+    /// A range that starts past the end of the file.
+    Unsatisfiable,
+}
+
+/// The `Cache-Control` headers of static files, for all of them and by extension.
+#[derive(Default)]
+struct FileCacheControl {
+    default: Option<HeaderValue>,
+    by_extension: HashMap<String, HeaderValue>,
+}
+
+impl FileCacheControl {
+    #[track_caller]
+    fn value(value: &str) -> HeaderValue {
+        match HeaderValue::from_str(value) {
+            Ok(value) => value,
+            Err(e) => panic!(
+                "{}",
+                Diagnostic::error("invalid Cache-Control value for static files").with("value", format_args!("{value:?}")).cause(e)
+            ),
+        }
+    }
+
+    #[track_caller]
+    fn set_for(&mut self, extension: &str, value: &str) {
+        let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+        self.by_extension.insert(extension, Self::value(value));
+    }
+
+    /// Set the `Cache-Control` header of the file at `path`, if it has one.
+    fn apply(&self, path: &Path, response: &mut Response) {
+        let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+        if let Some(value) = extension.and_then(|extension| self.by_extension.get(&extension)).or(self.default.as_ref()) {
+            response.headers.insert(HeaderName::from_static("cache-control"), value.clone());
+        }
+    }
+}
+
+/// The path of the request below the `mount` prefix, `None` for requests outside of it.
+fn mounted_path<'a>(mount: Option<&str>, request: &'a Request) -> Option<&'a str> {
+    let path = request.uri.path();
+    let Some(mount) = mount else {
+        return Some(path);
+    };
+    // `/static` and `/static/...`, not `/staticfiles`
+    path.strip_prefix(mount).filter(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// A mount prefix as `/prefix`, `None` for the root.
+fn mount_prefix(prefix: &str) -> Option<String> {
+    let prefix = prefix.trim_matches('/');
+    (!prefix.is_empty()).then(|| format!("/{prefix}"))
+}
+
+/// Redirect a request for a directory without the trailing slash to the one with it, so relative links in
+/// its index file resolve inside the directory.
+fn redirect_to_directory(request: &Request, response: &mut Response) {
+    let location = match request.uri.query() {
+        Some(query) => format!("{}/?{query}", request.uri.path()),
+        None => format!("{}/", request.uri.path()),
+    };
+    response.redirect(&location, true);
+}
+
+/// Answer a range that starts past the end of a file of `len` bytes.
+fn range_not_satisfiable(request: &Request, response: &mut Response, len: u64) {
+    response.add_header("Content-Range", &format!("bytes */{len}")).ok();
+    send_error(request, response, 416, "Range Not Satisfiable");
+}
+
+/// The outcome of looking up a request path in the static directory.
+enum Lookup {
+    File(PathBuf),
+    /// A directory with an index file, requested without the trailing slash.
+    Directory,
+    /// No file to serve (it doesn't exist or is a directory without an index file).
+    Missing,
+    Forbidden,
+    Failed(io::Error, PathBuf),
 }
 
 impl ServeStatic {
@@ -128,9 +788,103 @@ impl ServeStatic {
     #[must_use = "This middleware must be added to the app with use_middleware()"]
     pub fn new(directory: impl Into<PathBuf>) -> Self {
         Self{
-            base_path: directory.into()
+            base_path: directory.into(),
+            mount: None,
+            fallthrough: true,
+            routes_first: false,
+            index: vec!["index.html".to_string(), "index.htm".to_string()],
+            precompressed: true,
+            cache_control: FileCacheControl::default(),
+            chunk_size: 64 * 1024,
         }
     }
+
+    /// Only serve the requests under `prefix`, like `/static`, with the prefix stripped from their path.
+    #[must_use]
+    pub fn mount(mut self, prefix: &str) -> Self {
+        self.mount = mount_prefix(prefix);
+        self
+    }
+
+    /// The number of bytes of a file read at a time while it is sent (default: 64 KiB).
+    #[must_use]
+    pub fn chunk_size(mut self, bytes: usize) -> Self {
+        self.chunk_size = bytes;
+        self
+    }
+
+    /// Whether requests without a matching file are passed on to the router (default: `true`).
+    ///
+    /// With `false` they are answered with a 404 immediately. Has no effect with [`routes_first`](Self::routes_first).
+    #[must_use]
+    pub fn fallthrough(mut self, fallthrough: bool) -> Self {
+        self.fallthrough = fallthrough;
+        self
+    }
+
+    /// Only serve files for requests that no route matched (default: `false`).
+    #[must_use]
+    pub fn routes_first(mut self, routes_first: bool) -> Self {
+        self.routes_first = routes_first;
+        self
+    }
+
+    /// The files served for a request to a directory, the first one that exists wins (default:
+    /// `index.html`, `index.htm`).
+    ///
+    /// An empty list turns index files off, directories are then treated like missing files.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let serve = ServeStatic::new("./public").index(["index.html", "default.htm"]);
+    /// ```
+    #[must_use]
+    pub fn index<I: IntoIterator<Item = S>, S: Into<String>>(mut self, files: I) -> Self {
+        self.index = files.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Whether to send the `.br` and `.gz` siblings of files to clients that accept them (default: `true`).
+    #[must_use]
+    pub fn precompressed(mut self, precompressed: bool) -> Self {
+        self.precompressed = precompressed;
+        self
+    }
+
+    /// Send every file with this `Cache-Control` header, like `public, max-age=31536000, immutable` for
+    /// fingerprinted assets. Without it files get no `Cache-Control`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` isn't a valid header value.
+    #[must_use]
+    #[track_caller]
+    pub fn cache_control(mut self, value: &str) -> Self {
+        self.cache_control.default = Some(FileCacheControl::value(value));
+        self
+    }
+
+    /// Send the files with `extension`, like `html` or `.html`, with this `Cache-Control` header instead
+    /// of the one of [cache_control](Self::cache_control).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` isn't a valid header value.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// // Pages are checked again on every visit, the assets they link are cached for a year
+    /// let serve = ServeStatic::new("./public").cache_control("public, max-age=31536000, immutable").cache_control_for("html", "no-cache");
+    /// ```
+    #[must_use]
+    #[track_caller]
+    pub fn cache_control_for(mut self, extension: &str, value: &str) -> Self {
+        self.cache_control.set_for(extension, value);
+        self
+    }
+
     /// Internal Strip the Windows UNC Prefix.
     fn strip_unc(path: &Path) -> &Path {
         if let Some(path_str) = path.to_str(){
@@ -141,24 +895,26 @@ impl ServeStatic {
         path
     }
 
-    fn handle_io_error(&self, e: io::Error, path: &Path, response: &mut Response) {
+    fn handle_io_error(&self, e: io::Error, path: &Path, request: &Request, response: &mut Response) {
         let status_code = match e.kind() {
             io::ErrorKind::PermissionDenied => 403,
             io::ErrorKind::NotFound => 404,
             _ => 500, // Internal Server Error for other IO issues
         };
 
-        eprintln!(
-            "ServeStatic: Error accessing path {:?} (Base: {}): {} - Responding with {}",
-            path, &self.base_path.display(), e, status_code
-        );
+        Diagnostic::error("ServeStatic could not read a file")
+            .with("path", path.display())
+            .with("base", self.base_path.display())
+            .cause(&e)
+            .note(format_args!("responding with {status_code}"))
+            .emit();
 
-        response.set_status(status_code);
-        match status_code {
-            404 => response.send_text("404 Not Found"),
-            403 => response.send_text("403 Forbidden"),
-            _ => response.send_text("500 Internal Server Error"),
+        let message = match status_code {
+            404 => "Not Found",
+            403 => "Forbidden",
+            _ => "Internal Server Error",
         };
+        send_error(request, response, status_code, message);
     }
 
     fn guess_content_type(path: &Path) -> &'static str {
@@ -176,79 +932,861 @@ impl ServeStatic {
             _ => "application/octet-stream", // Default binary type
         }
     }
+
+    /// Resolve the request path to a file inside the base directory.
+    fn lookup(&self, request: &Request) -> Lookup {
+        let Some(requested_path) = mounted_path(self.mount.as_deref(), request) else {
+            return Lookup::Missing;
+        };
+        let requested_path = requested_path.trim_start_matches('/');
+
+        if requested_path.contains("..") {
+            return Lookup::Forbidden; // This is a security risk
+        }
+
+        let full_path = self.base_path.join(requested_path);
+        let Ok(canonical_target) = full_path.canonicalize() else {
+            // File not found, nothing to serve
+            return Lookup::Missing;
+        };
+        let canonical_base = match self.base_path.canonicalize() {
+            Ok(base) => base,
+            Err(e) => return Lookup::Failed(e, self.base_path.clone()),
+        };
+        let clean_target = Self::strip_unc(&canonical_target);
+        let clean_base = Self::strip_unc(&canonical_base);
+
+        if !clean_target.starts_with(clean_base) {
+            return Lookup::Forbidden;
+        }
+
+        match fs::metadata(clean_target) {
+            Ok(metadata) if metadata.is_file() => Lookup::File(clean_target.to_path_buf()),
+            Ok(metadata) if metadata.is_dir() => match self.index_file(clean_target, clean_base) {
+                Some(_) if !request.uri.path().ends_with('/') => Lookup::Directory,
+                Some(index) => Lookup::File(index),
+                None => Lookup::Missing,
+            },
+            Ok(_) => Lookup::Missing,
+            Err(e) => Lookup::Failed(e, clean_target.to_path_buf()),
+        }
+    }
+
+    /// The first index file of `dir` that exists inside the base directory.
+    fn index_file(&self, dir: &Path, base: &Path) -> Option<PathBuf> {
+        self.index.iter().find_map(|name| {
+            let index = dir.join(name).canonicalize().ok()?;
+            let clean_index = Self::strip_unc(&index);
+            // A symlinked index file can't lead out of the base directory either
+            (clean_index.starts_with(base) && clean_index.is_file()).then(|| clean_index.to_path_buf())
+        })
+    }
+
+    /// Whether the client's copy of the file is current, going by `If-None-Match`, or `If-Modified-Since`
+    /// when there is none, against the validators [set_file_headers](Response::set_file_headers) put on the response.
+    fn not_modified(request: &Request, response: &Response, modified: Option<SystemTime>) -> bool {
+        if let Some(tags) = request.headers.get("if-none-match").and_then(|value| value.to_str().ok()) {
+            let Some(etag) = response.headers.get("etag").and_then(|value| value.to_str().ok()) else {
+                return false;
+            };
+            // Weak comparison, the one conditional GETs use
+            let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+            return tags.split(',').any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag));
+        }
+        let Some(since) = request.headers.get("if-modified-since").and_then(|value| value.to_str().ok()).and_then(|value| chrono::DateTime::parse_from_rfc2822(value).ok()) else {
+            return false;
+        };
+        let modified = modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+        // HTTP dates have whole seconds
+        modified.is_some_and(|modified| modified.as_secs() as i64 <= since.timestamp())
+    }
+
+    /// The precompressed sibling of `path` to send instead, with its `Content-Encoding`, if the client
+    /// accepts one. Adds `Vary: Accept-Encoding` to the response when `path` has any sibling.
+    fn precompressed_file(&self, request: &Request, path: &Path, response: &mut Response) -> Option<(PathBuf, &'static str)> {
+        if !self.precompressed {
+            return None;
+        }
+        let mut siblings = [("br", "br"), ("gz", "gzip")]
+            .into_iter()
+            .map(|(extension, encoding)| {
+                let mut sibling = path.as_os_str().to_owned();
+                sibling.push(".");
+                sibling.push(extension);
+                (PathBuf::from(sibling), encoding)
+            })
+            // Only regular files, a symlink could lead out of the base directory
+            .filter(|(sibling, _)| fs::symlink_metadata(sibling).is_ok_and(|metadata| metadata.is_file()))
+            .peekable();
+        siblings.peek()?;
+        response.add_header("Vary", "Accept-Encoding").ok();
+        siblings.find(|(_, encoding)| Self::accepts_encoding(request, encoding))
+    }
+
+    /// Whether `Accept-Encoding` allows `encoding` without `q=0`. An entry naming it wins over `*`, so
+    /// `br;q=0, *` refuses Brotli.
+    fn accepts_encoding(request: &Request, encoding: &str) -> bool {
+        let accepted = request.headers.get_all("accept-encoding").iter().filter_map(|value| value.to_str().ok()).flat_map(|value| value.split(','));
+        let mut wildcard = None;
+        for item in accepted {
+            let mut parts = item.split(';');
+            let name = parts.next().unwrap_or_default().trim();
+            let allowed = !parts.any(|param| param.trim().strip_prefix("q=").and_then(|q| q.trim().parse::<f32>().ok()) == Some(0.0));
+            if name.eq_ignore_ascii_case(encoding) {
+                return allowed;
+            }
+            if name == "*" {
+                wildcard = Some(allowed);
+            }
+        }
+        wildcard.unwrap_or(false)
+    }
+
+    /// The range of a file of `len` bytes that a `Range` header asks for. Headers that aren't a single
+    /// byte range are ignored, like a range whose `If-Range` doesn't match the file.
+    fn byte_range(request: &Request, response: &Response, len: u64) -> ByteRange {
+        let Some(range) = request.headers.get("range").and_then(|value| value.to_str().ok()) else {
+            return ByteRange::Full;
+        };
+        if let Some(validator) = request.headers.get("if-range") {
+            // The client's copy changed, it gets the whole file again
+            if response.headers.get("etag") != Some(validator) && response.headers.get("last-modified") != Some(validator) {
+                return ByteRange::Full;
+            }
+        }
+        let Some((start, end)) = range.trim().strip_prefix("bytes=").and_then(|range| range.split_once('-')) else {
+            return ByteRange::Full;
+        };
+        let (start, end) = match (start.trim(), end.trim()) {
+            // The last `suffix` bytes
+            ("", suffix) => match suffix.parse::<u64>() {
+                Ok(0) => return ByteRange::Unsatisfiable,
+                Ok(suffix) => (len.saturating_sub(suffix), u64::MAX),
+                Err(_) => return ByteRange::Full,
+            },
+            (start, "") => match start.parse() {
+                Ok(start) => (start, u64::MAX),
+                Err(_) => return ByteRange::Full,
+            },
+            (start, end) => match (start.parse(), end.parse()) {
+                (Ok(start), Ok(end)) if start <= end => (start, end),
+                _ => return ByteRange::Full,
+            },
+        };
+        if start >= len {
+            return ByteRange::Unsatisfiable;
+        }
+        ByteRange::Part(start, end.min(len - 1))
+    }
+
+    /// Fill the response from a lookup. Returns `false` if there was nothing to serve.
+    fn respond(&self, lookup: Lookup, request: &Request, response: &mut Response) -> bool {
+        match lookup {
+            Lookup::File(path) => {
+                let encoded = self.precompressed_file(request, &path, response);
+                self.send_file(&path, encoded, request, response);
+            }
+            Lookup::Directory => redirect_to_directory(request, response),
+            Lookup::Missing => return false,
+            Lookup::Forbidden => send_error(request, response, 403, "Forbidden"),
+            Lookup::Failed(e, path) => self.handle_io_error(e, &path, request, response),
+        }
+        true
+    }
+
+    /// Send the file at `path`, or its precompressed sibling with its `Content-Encoding` if given.
+    ///
+    /// The file is streamed while the response is written. `HEAD` requests only get the file metadata, the
+    /// file itself is never read. Neither is it for a conditional request whose copy is current, answered
+    /// with `304 Not Modified`.
+    fn send_file(&self, path: &Path, encoded: Option<(PathBuf, &str)>, request: &Request, response: &mut Response) {
+        let read = encoded.as_ref().map_or(path, |(encoded, _)| encoded);
+        let (metadata, mut file) = match File::open(read).and_then(|file| Ok((file.metadata()?, file))) {
+            Ok(opened) => opened,
+            Err(e) => return self.handle_io_error(e, read, request, response),
+        };
+        response.add_header("Content-Type", Self::guess_content_type(path)).ok();
+        if let Some((_, encoding)) = encoded {
+            response.add_header("Content-Encoding", encoding).ok();
+        }
+        self.cache_control.apply(path, response);
+        response.set_file_headers(&metadata);
+        if matches!(request.method, Method::GET | Method::HEAD) && Self::not_modified(request, response, metadata.modified().ok()) {
+            response.set_status(304);
+            return;
+        }
+        if request.method == Method::HEAD {
+            return;
+        }
+        let len = metadata.len();
+        // Ranges only apply to GET
+        let range = if request.method == Method::GET {
+            Self::byte_range(request, response, len)
+        } else {
+            ByteRange::Full
+        };
+        let sent = match range {
+            ByteRange::Full => {
+                response.send_reader(file, len, self.chunk_size);
+                Ok(())
+            }
+            ByteRange::Part(start, end) => file.seek(SeekFrom::Start(start)).map(|_| {
+                response.set_status(206);
+                response.add_header("Content-Range", &format!("bytes {start}-{end}/{len}")).ok();
+                response.send_reader(file, end - start + 1, self.chunk_size);
+            }),
+            ByteRange::Unsatisfiable => {
+                range_not_satisfiable(request, response, len);
+                Ok(())
+            }
+        };
+        if let Err(e) = sent {
+            self.handle_io_error(e, read, request, response);
+        }
+    }
 }
 
 impl Middleware for ServeStatic {
     fn handle(&self, request: &mut Request, response: &mut Response, _: &AppContext) -> Outcome {
-        let requested_path = request.uri.path().trim_start_matches('/');
-        
-        if requested_path.contains("..") {
-            response.set_status(403);
-            response.send_text("403 Forbidden");
-            return end!(); // Cut of Execution, this is a security risk
+        if self.routes_first || mounted_path(self.mount.as_deref(), request).is_none() {
+            // With routes first, files are served in `after` once we know no route matched
+            return next!();
+        }
+        if self.respond(self.lookup(request), request, response) {
+            // We filled the response, return end!() so the Router doesn't overwrite us with a 404.
+            return end!();
+        }
+        if self.fallthrough {
+            // Give control back to the router so if user has defined a handler for the path it will still execute.
+            return next!();
         }
+        send_error(request, response, 404, "Not Found");
+        end!()
+    }
 
-        let full_path = self.base_path.join(requested_path);
+    fn after(&self, request: &Request, response: &mut Response, _: &AppContext) {
+        if !self.routes_first || request.extensions.get::<NoRouteMatched>().is_none() {
+            return;
+        }
+        let lookup = self.lookup(request);
+        if matches!(lookup, Lookup::Missing) {
+            // Keep the router's 404
+            return;
+        }
+        *response = Response::default();
+        self.respond(lookup, request, response);
+    }
+}
 
-        match full_path.canonicalize() {
-            Ok(canonical_target) => {
-                match self.base_path.canonicalize() {
-                    Ok(canonical_base) => {
-                        let clean_target = Self::strip_unc(&canonical_target);
-                        let clean_base = Self::strip_unc(&canonical_base);
-
-                        if !clean_target.starts_with(clean_base) {
-                            response.set_status(403);
-                            response.send_text("403 Forbidden");
-                            return end!(); 
-                        }
-
-                        match fs::metadata(clean_target) {
-                            Ok(metadata) => {
-                                if metadata.is_file() {
-                                    match File::open(clean_target) {
-                                        Ok(mut file) => {
-                                            let mut buffer = Vec::new();
-                                            if file.read_to_end(&mut buffer).is_ok() {
-                                                let ct = Self::guess_content_type(clean_target);
-                                                response.add_header("Content-Type", ct)?;
-                                                response.add_header("Content-Length", &buffer.len().to_string())?;
-                                                response.send_bytes(buffer);
-                                                // We found the file and filled the response.
-                                                // We return end!() so the Router doesn't overwrite us with a 404.
-                                                return end!(); 
-                                            }
-                                        }
-                                        Err(e) => {
-                                            self.handle_io_error(e, clean_target, response);
-                                            return end!();
-                                        }
-                                    }
-                                } else if metadata.is_dir() {
-                                    // We Return next here ServeStatic Can't serve directories.
-                                    // So give control back to the router so if user has defined a handler for the path it will still execute.
-                                    return next!();
-                                }
-                            }
-                            Err(e) => {
-                                self.handle_io_error(e, clean_target, response);
-                                return end!();
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        self.handle_io_error(e, &self.base_path, response);
-                        return end!();
-                    }
+/// Serves static files embedded in the binary, so a deployment is a single executable with its frontend.
+///
+/// The files are served like the ones of [ServeStatic]: with the content type of their extension, a strong
+/// `ETag` answered with `304 Not Modified`, byte ranges and the `Cache-Control` of
+/// [`cache_control`](Self::cache_control). Directories serve their [index file](Self::index) and
+/// [`mount`](Self::mount) serves the files under a prefix. Embedded files have no modification time, so
+/// there is no `Last-Modified`.
+///
+/// A directory is embedded with [`include_dir!`](https://docs.rs/include_dir) and the `embed` feature,
+/// the `include_dir` crate must be a dependency of the app too. Single files are embedded with
+/// [`include_bytes!`] and [from_files](Self::from_files), without the feature.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::middlewares::builtins::ServeEmbedded;
+/// use include_dir::{Dir, include_dir};
+///
+/// static PUBLIC: Dir = include_dir!("$CARGO_MANIFEST_DIR/public");
+///
+/// app.use_middleware(ServeEmbedded::new(&PUBLIC).cache_control("public, max-age=3600"));
+///
+/// // A single page
+/// app.use_middleware(ServeEmbedded::from_files([("index.html", include_bytes!("../public/index.html").as_slice())]));
+/// ```
+pub struct ServeEmbedded {
+    files: HashMap<String, EmbeddedFile>,
+    mount: Option<String>,
+    fallthrough: bool,
+    index: Vec<String>,
+    cache_control: FileCacheControl,
+}
+
+struct EmbeddedFile {
+    contents: &'static [u8],
+    etag: HeaderValue,
+}
+
+impl ServeEmbedded {
+    /// Serve the files of a directory embedded with `include_dir!`, by their path inside it. Requires the
+    /// `embed` feature.
+    #[cfg(feature = "embed")]
+    #[must_use = "This middleware must be added to the app with use_middleware()"]
+    pub fn new(dir: &'static include_dir::Dir<'static>) -> Self {
+        fn walk(dir: &'static include_dir::Dir<'static>, files: &mut Vec<(String, &'static [u8])>) {
+            for entry in dir.entries() {
+                match entry {
+                    include_dir::DirEntry::Dir(dir) => walk(dir, files),
+                    // Paths are relative to the embedded directory, with `/` on every platform
+                    include_dir::DirEntry::File(file) => files.push((file.path().to_string_lossy().replace('\\', "/"), file.contents())),
                 }
             }
-            Err(_) => {
-                // File not found?
-                // Just give control back to the Router so it can try match!
-                return next!();
+        }
+        let mut files = Vec::new();
+        walk(dir, &mut files);
+        Self::from_files(files)
+    }
+
+    /// Serve `files`, pairs of a path like `css/site.css` and the contents of the file.
+    #[must_use = "This middleware must be added to the app with use_middleware()"]
+    pub fn from_files<P: Into<String>>(files: impl IntoIterator<Item = (P, &'static [u8])>) -> Self {
+        let files = files
+            .into_iter()
+            .map(|(path, contents)| {
+                let mut hasher = std::hash::DefaultHasher::new();
+                std::hash::Hash::hash(contents, &mut hasher);
+                let etag = format!("\"{:x}-{:016x}\"", contents.len(), std::hash::Hasher::finish(&hasher));
+                let file = EmbeddedFile {
+                    contents,
+                    etag: HeaderValue::from_str(&etag).expect("hex digits are a valid header value"),
+                };
+                (path.into().trim_start_matches('/').to_string(), file)
+            })
+            .collect();
+        Self {
+            files,
+            mount: None,
+            fallthrough: true,
+            index: vec!["index.html".to_string(), "index.htm".to_string()],
+            cache_control: FileCacheControl::default(),
+        }
+    }
+
+    /// Only serve the requests under `prefix`, like `/static`, with the prefix stripped from their path.
+    #[must_use]
+    pub fn mount(mut self, prefix: &str) -> Self {
+        self.mount = mount_prefix(prefix);
+        self
+    }
+
+    /// Whether requests without a matching file are passed on to the router (default: `true`).
+    ///
+    /// With `false` they are answered with a 404 immediately.
+    #[must_use]
+    pub fn fallthrough(mut self, fallthrough: bool) -> Self {
+        self.fallthrough = fallthrough;
+        self
+    }
+
+    /// The files served for a request to a directory, the first one that exists wins (default:
+    /// `index.html`, `index.htm`).
+    #[must_use]
+    pub fn index<I: IntoIterator<Item = S>, S: Into<String>>(mut self, files: I) -> Self {
+        self.index = files.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Send every file with this `Cache-Control` header. Without it files get no `Cache-Control`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` isn't a valid header value.
+    #[must_use]
+    #[track_caller]
+    pub fn cache_control(mut self, value: &str) -> Self {
+        self.cache_control.default = Some(FileCacheControl::value(value));
+        self
+    }
+
+    /// Send the files with `extension`, like `html` or `.html`, with this `Cache-Control` header instead
+    /// of the one of [cache_control](Self::cache_control).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` isn't a valid header value.
+    #[must_use]
+    #[track_caller]
+    pub fn cache_control_for(mut self, extension: &str, value: &str) -> Self {
+        self.cache_control.set_for(extension, value);
+        self
+    }
+
+    /// Fill the response with the file of the request. Returns `false` if there is none.
+    fn respond(&self, request: &Request, response: &mut Response) -> bool {
+        let Some(path) = mounted_path(self.mount.as_deref(), request) else {
+            return false;
+        };
+        let path = path.trim_start_matches('/');
+        if let Some(file) = self.files.get(path) {
+            self.send_file(path, file, request, response);
+            return true;
+        }
+        let dir = path.trim_end_matches('/');
+        let index = self.index.iter().find_map(|name| {
+            let index = if dir.is_empty() {
+                name.clone()
+            } else {
+                format!("{dir}/{name}")
+            };
+            self.files.get_key_value(&index)
+        });
+        match index {
+            Some((index, file)) if request.uri.path().ends_with('/') => self.send_file(index, file, request, response),
+            Some(_) => redirect_to_directory(request, response),
+            None => return false,
+        }
+        true
+    }
+
+    fn send_file(&self, path: &str, file: &EmbeddedFile, request: &Request, response: &mut Response) {
+        let path = Path::new(path);
+        response.add_header("Content-Type", ServeStatic::guess_content_type(path)).ok();
+        self.cache_control.apply(path, response);
+        response.headers.insert(HeaderName::from_static("etag"), file.etag.clone());
+        response.headers.insert(HeaderName::from_static("accept-ranges"), HeaderValue::from_static("bytes"));
+        if matches!(request.method, Method::GET | Method::HEAD) && ServeStatic::not_modified(request, response, None) {
+            response.set_status(304);
+            return;
+        }
+        let len = file.contents.len() as u64;
+        // Ranges only apply to GET
+        let range = if request.method == Method::GET {
+            ServeStatic::byte_range(request, response, len)
+        } else {
+            ByteRange::Full
+        };
+        match range {
+            ByteRange::Full => response.send_bytes(file.contents),
+            ByteRange::Part(start, end) => {
+                response.set_status(206);
+                response.add_header("Content-Range", &format!("bytes {start}-{end}/{len}")).ok();
+                response.send_bytes(&file.contents[start as usize..=end as usize]);
             }
+            ByteRange::Unsatisfiable => range_not_satisfiable(request, response, len),
         }
+    }
+}
 
-        next!()
+impl Middleware for ServeEmbedded {
+    fn handle(&self, request: &mut Request, response: &mut Response, _: &AppContext) -> Outcome {
+        if self.respond(request, response) {
+            return end!();
+        }
+        if self.fallthrough || mounted_path(self.mount.as_deref(), request).is_none() {
+            return next!();
+        }
+        send_error(request, response, 404, "Not Found");
+        end!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Profile;
+    use crate::middlewares::MiddlewareResult;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    fn cors_origin(cors: &Cors, origin: &str) -> Option<String> {
+        let mut request = Request::builder().header("Origin", origin).build().unwrap();
+        let mut response = Response::default();
+        cors.handle(&mut request, &mut response, &AppContext::new()).unwrap();
+        assert_eq!(response.headers.get("vary").unwrap(), "Origin");
+        response.headers.get("access-control-allow-origin").map(|v| v.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn test_cors_wildcard_origins() {
+        let cors = Cors::default().allow_wildcard("https://*.example.com").allow_origin_if(|origin| origin == "http://localhost:3000");
+        assert_eq!(cors_origin(&cors, "https://api.example.com").as_deref(), Some("https://api.example.com"));
+        assert_eq!(cors_origin(&cors, "http://localhost:3000").as_deref(), Some("http://localhost:3000"));
+        assert_eq!(cors_origin(&cors, "https://example.com"), None);
+        assert_eq!(cors_origin(&cors, "https://evil.com/.example.com"), None);
+        assert_eq!(cors_origin(&cors, "http://api.example.com"), None);
+    }
+
+    /// The value of `header` after running `middleware` in `profile`.
+    fn header_in(middleware: &impl Middleware, profile: Profile, header: &str) -> Option<String> {
+        let mut ctx = AppContext::new();
+        ctx.set_profile(profile);
+        let mut request = Request::builder().build().unwrap();
+        let mut response = Response::default();
+        middleware.handle(&mut request, &mut response, &ctx).unwrap();
+        response.headers.get(header).map(|v| v.to_str().unwrap().to_string())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_cors_defaults_follow_the_profile() {
+        let allowed = |cors: &Cors, profile| header_in(cors, profile, "access-control-allow-origin");
+        assert_eq!(allowed(&Cors::default(), Profile::Dev).as_deref(), Some("*"));
+        assert_eq!(allowed(&Cors::default(), Profile::Staging), None);
+        assert_eq!(allowed(&Cors::default(), Profile::Prod), None);
+        assert_eq!(allowed(&Cors::any(), Profile::Prod).as_deref(), Some("*"));
+        let site = Cors::new("https://example.com".to_string());
+        assert_eq!(allowed(&site, Profile::Dev).as_deref(), Some("https://example.com"));
+        assert_eq!(allowed(&site, Profile::Prod).as_deref(), Some("https://example.com"));
+    }
+
+    #[test]
+    fn test_hsts_is_sent_outside_of_dev() {
+        let hsts = |middleware: &Hsts, profile| header_in(middleware, profile, "strict-transport-security");
+        assert_eq!(hsts(&Hsts::default(), Profile::Dev), None);
+        assert_eq!(hsts(&Hsts::default(), Profile::Prod).as_deref(), Some("max-age=31536000"));
+        let preloaded = Hsts::new(60).include_subdomains(true).preload(true);
+        assert_eq!(hsts(&preloaded, Profile::Staging).as_deref(), Some("max-age=60; includeSubDomains; preload"));
+        assert_eq!(hsts(&Hsts::default().enabled(true), Profile::Dev).as_deref(), Some("max-age=31536000"));
+        assert_eq!(hsts(&Hsts::default().enabled(false), Profile::Prod), None);
+    }
+
+    #[test]
+    fn test_crawler_control_only_blocks_set_profiles_outside_of_prod() {
+        let crawl = |crawler: &CrawlerControl, profile: Profile, path: &str| {
+            let mut ctx = AppContext::new();
+            ctx.set_profile(profile);
+            let mut request = Request::builder().uri(path).build().unwrap();
+            let mut response = Response::default();
+            crawler.handle(&mut request, &mut response, &ctx).unwrap();
+            crawler.after(&request, &mut response, &ctx);
+            let robots = response.body.map(|body| String::from_utf8(body.to_vec()).unwrap());
+            robots.or_else(|| response.headers.get("x-robots-tag").map(|v| v.to_str().unwrap().to_string()))
+        };
+        let crawler = CrawlerControl::new().noindex("/admin/*");
+        for profile in [Profile::Dev, Profile::Staging] {
+            assert_eq!(crawl(&crawler, profile, "/robots.txt").as_deref(), Some("User-agent: *\nDisallow: /\n"));
+            assert_eq!(crawl(&crawler, profile, "/").as_deref(), Some("noindex, nofollow"));
+        }
+        assert_eq!(crawl(&crawler, Profile::Prod, "/robots.txt").as_deref(), Some("User-agent: *\nDisallow: /admin/\n"));
+        assert_eq!(crawl(&crawler, Profile::Prod, "/"), None);
+        assert_eq!(crawl(&crawler, Profile::Prod, "/admin/users").as_deref(), Some("noindex, nofollow"));
+        assert_eq!(crawl(&crawler.block_all(true), Profile::Prod, "/").as_deref(), Some("noindex, nofollow"));
+        let unblocked = CrawlerControl::new().block_all(false);
+        assert_eq!(crawl(&unblocked, Profile::Staging, "/robots.txt").as_deref(), Some("User-agent: *\nDisallow:\n"));
+
+        // Without FEATHER_ENV or set_profile the profile is dev, but pages are indexed
+        let crawler = CrawlerControl::new();
+        assert!(!crawler.blocks_all_in(None));
+        assert!(crawler.blocks_all_in(Some(Profile::Dev)));
+        assert!(!crawler.blocks_all_in(Some(Profile::Prod)));
+        assert!(crawler.block_all(true).blocks_all_in(None));
+    }
+
+    #[test]
+    fn test_slow_request_counts_requests_over_the_threshold() {
+        use crate::testing::TestClient;
+        use crate::{App, Finalizer, middleware};
+
+        let mut app = App::without_logger();
+        // Turned away before the detector ran, so never measured
+        app.use_middleware(middleware!(|req, res| if req.uri.path() == "/blocked" {
+            res.set_status(403).finish_text("no")
+        } else {
+            next!()
+        }));
+        app.use_middleware(SlowRequest::new(Duration::from_millis(20)));
+        app.get("/fast", middleware!(|res| res.finish_text("fast")));
+        app.get(
+            "/slow",
+            middleware!(|res| {
+                std::thread::sleep(Duration::from_millis(30));
+                res.finish_text("slow")
+            }),
+        );
+        let client = TestClient::new(app);
+        let slow = || client.context().metrics().counter(SlowRequest::COUNTER).get();
+
+        client.get("/fast").send();
+        assert_eq!(slow(), 0);
+        client.get("/slow").send();
+        assert_eq!(slow(), 1);
+        assert_eq!(client.get("/blocked").send().status.as_u16(), 403);
+        assert_eq!(slow(), 1);
+    }
+
+    #[test]
+    fn test_readiness_reports_probes() {
+        let ctx = AppContext::new();
+        ctx.set_state_with_health(String::from("up"), |state: &String| (state == "up").then_some(()).ok_or("down"));
+        let readiness = Readiness::default();
+        let check = |ctx: &AppContext| {
+            let mut request = Request::builder().uri("/ready").build().unwrap();
+            let mut response = Response::default();
+            readiness.handle(&mut request, &mut response, ctx).unwrap();
+            (response.status.as_u16(), String::from_utf8(response.body.unwrap().to_vec()).unwrap())
+        };
+        assert_eq!(check(&ctx), (200, "ok   alloc::string::String\n".to_string()));
+
+        // Replacing the state keeps its probe
+        ctx.set_state(String::from("booting"));
+        assert_eq!(check(&ctx), (503, "fail alloc::string::String: down\n".to_string()));
+        ctx.remove_state::<String>();
+        assert_eq!(check(&ctx), (200, "ok\n".to_string()));
+    }
+
+    fn inspect(inspector: &StateInspector, ctx: &AppContext, from: IpAddr, authorization: Option<&str>) -> Option<String> {
+        let request = Request::builder().uri("/._feather/state").remote_addr(SocketAddr::new(from, 0));
+        let request = match authorization {
+            Some(value) => request.header("Authorization", value),
+            None => request,
+        };
+        let mut request = request.build().unwrap();
+        let mut response = Response::default();
+        inspector.handle(&mut request, &mut response, ctx).unwrap();
+        response.body.map(|body| String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn test_state_inspector_lists_types_in_dev() {
+        let mut ctx = AppContext::new();
+        ctx.set_state(String::from("config"));
+        ctx.set_state(42u32);
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        ctx.set_profile(Profile::Dev);
+        let inspector = StateInspector::default();
+        assert_eq!(inspect(&inspector, &ctx, localhost, None).as_deref(), Some("alloc::string::String\nu32\n"));
+        ctx.set_profile(Profile::Prod);
+        assert_eq!(inspect(&inspector, &ctx, localhost, None), None);
+    }
+
+    #[test]
+    fn test_state_inspector_needs_a_debug_build_and_a_local_or_authorized_client() {
+        let mut ctx = AppContext::new();
+        ctx.set_profile(Profile::Dev);
+        ctx.set_state(42u32);
+        let (localhost, remote) = (IpAddr::V4(Ipv4Addr::LOCALHOST), IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)));
+
+        let release = StateInspector {
+            debug_build: false,
+            ..StateInspector::default()
+        };
+        assert_eq!(inspect(&release, &ctx, localhost, None), None);
+        assert_eq!(inspect(&release.token("secret"), &ctx, remote, Some("Bearer secret")), None);
+
+        assert_eq!(inspect(&StateInspector::default(), &ctx, remote, None), None);
+        let token = StateInspector::default().token("secret");
+        assert_eq!(inspect(&token, &ctx, remote, Some("Bearer secret")).as_deref(), Some("u32\n"));
+        assert_eq!(inspect(&token, &ctx, remote, Some("Bearer wrong")), None);
+        assert_eq!(inspect(&token, &ctx, remote, Some("Bearer secre")), None);
+        assert_eq!(inspect(&token, &ctx, remote, Some("Bearer secrets")), None);
+        assert_eq!(inspect(&token, &ctx, localhost, None), None);
+    }
+
+    #[test]
+    fn test_cache_policy_follows_authentication() {
+        let cache_control = |authenticated: bool, set: Option<&str>| {
+            let mut request = Request::builder().uri("/feed").build().unwrap();
+            if authenticated {
+                request.extensions.insert(Authenticated);
+            }
+            let mut response = Response::default();
+            if let Some(value) = set {
+                response.add_header("Cache-Control", value).unwrap();
+            }
+            CachePolicy::new(60).after(&request, &mut response, &AppContext::new());
+            response.headers.get("cache-control").map(|v| v.to_str().unwrap().to_string())
+        };
+        assert_eq!(cache_control(false, None).as_deref(), Some("public, max-age=60"));
+        assert_eq!(cache_control(false, Some("no-store")).as_deref(), Some("no-store"));
+        assert_eq!(cache_control(true, None).as_deref(), Some("private"));
+        let shared = cache_control(true, Some("public, max-age=600, s-maxage=3600"));
+        assert_eq!(shared.as_deref(), Some("private, max-age=600"));
+    }
+
+    /// A directory of static files for one test, removed when dropped.
+    struct StaticDir(PathBuf);
+
+    impl StaticDir {
+        fn new(name: &str, files: &[(&str, &[u8])]) -> Self {
+            let dir = std::env::temp_dir().join(format!("feather-{name}-{}", std::process::id()));
+            for (path, contents) in files {
+                let path = dir.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, contents).unwrap();
+            }
+            Self(dir)
+        }
+    }
+
+    impl Drop for StaticDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    fn static_get(serve: &impl Middleware, path: &str, headers: &[(&str, &str)]) -> Response {
+        let request = headers.iter().fold(Request::builder().uri(path), |request, (name, value)| request.header(name, value));
+        crate::testing::run_middleware(serve, request.build().unwrap()).0
+    }
+
+    #[test]
+    fn test_static_files_answer_conditional_requests() {
+        let dir = StaticDir::new("conditional", &[("app.js", b"console.log(1)")]);
+        let serve = ServeStatic::new(&dir.0);
+
+        let response = static_get(&serve, "/app.js", &[]);
+        assert_eq!(response.status.as_u16(), 200);
+        let etag = response.headers.get("etag").unwrap().to_str().unwrap().to_string();
+        let modified = response.headers.get("last-modified").unwrap().to_str().unwrap().to_string();
+
+        let response = static_get(&serve, "/app.js", &[("If-None-Match", &format!("\"other\", {}", etag.trim_start_matches("W/")))]);
+        assert_eq!(response.status.as_u16(), 304);
+        assert_eq!(response.body, None);
+        assert_eq!(static_get(&serve, "/app.js", &[("If-None-Match", "\"other\"")]).status.as_u16(), 200);
+        assert_eq!(static_get(&serve, "/app.js", &[("If-Modified-Since", &modified)]).status.as_u16(), 304);
+        assert_eq!(
+            static_get(&serve, "/app.js", &[("If-Modified-Since", "Thu, 01 Jan 1970 00:00:00 GMT")]).status.as_u16(),
+            200
+        );
+        // If-None-Match wins over If-Modified-Since
+        assert_eq!(
+            static_get(&serve, "/app.js", &[("If-None-Match", "\"other\""), ("If-Modified-Since", &modified)]).status.as_u16(),
+            200
+        );
+    }
+
+    #[test]
+    fn test_static_files_answer_range_requests() {
+        let dir = StaticDir::new("range", &[("video.mp4", b"0123456789")]);
+        let serve = ServeStatic::new(&dir.0);
+        let range = |range: &str| {
+            let response = static_get(&serve, "/video.mp4", &[("Range", range)]);
+            let content_range = response.headers.get("content-range").map(|value| value.to_str().unwrap().to_string());
+            (response.status.as_u16(), content_range, response.body)
+        };
+
+        assert_eq!(range("bytes=2-4"), (206, Some("bytes 2-4/10".to_string()), Some("234".into())));
+        assert_eq!(range("bytes=7-"), (206, Some("bytes 7-9/10".to_string()), Some("789".into())));
+        assert_eq!(range("bytes=-3"), (206, Some("bytes 7-9/10".to_string()), Some("789".into())));
+        assert_eq!(range("bytes=8-100"), (206, Some("bytes 8-9/10".to_string()), Some("89".into())));
+        assert_eq!(range("bytes=10-"), (416, Some("bytes */10".to_string()), Some("Range Not Satisfiable".into())));
+        // Several ranges or a malformed one get the whole file
+        assert_eq!(range("bytes=0-1, 4-5").0, 200);
+        assert_eq!(range("bytes=5-2").0, 200);
+        let response = static_get(&serve, "/video.mp4", &[("Range", "bytes=2-4")]);
+        assert_eq!(response.headers.get("content-length").unwrap(), "3");
+
+        let etag = static_get(&serve, "/video.mp4", &[]).headers.get("etag").unwrap().to_str().unwrap().to_string();
+        let unchanged = static_get(&serve, "/video.mp4", &[("Range", "bytes=2-4"), ("If-Range", &etag)]);
+        assert_eq!(unchanged.status.as_u16(), 206);
+        let changed = static_get(&serve, "/video.mp4", &[("Range", "bytes=2-4"), ("If-Range", "W/\"old\"")]);
+        assert_eq!(changed.status.as_u16(), 200);
+        assert_eq!(changed.body.as_deref(), Some(&b"0123456789"[..]));
+    }
+
+    #[test]
+    fn test_directories_serve_their_index_file() {
+        let files: &[(&str, &[u8])] = &[("docs/index.html", b"<h1>Docs</h1>"), ("blog/index.htm", b"blog"), ("empty/notes.txt", b"notes")];
+        let dir = StaticDir::new("index", files);
+        let serve = ServeStatic::new(&dir.0);
+
+        let response = static_get(&serve, "/docs/", &[]);
+        assert_eq!(response.body.as_deref(), Some(&b"<h1>Docs</h1>"[..]));
+        assert_eq!(response.headers.get("content-type").unwrap(), "text/html; charset=utf-8");
+        assert_eq!(static_get(&serve, "/blog/", &[]).body.as_deref(), Some(&b"blog"[..]));
+        let response = static_get(&serve, "/docs?page=2", &[]);
+        assert_eq!(response.status.as_u16(), 301);
+        assert_eq!(response.headers.get("location").unwrap(), "/docs/?page=2");
+        // Without an index file the request falls through to the router
+        let (_, result) = crate::testing::run_middleware(&serve, Request::builder().uri("/empty/").build().unwrap());
+        assert_eq!(result, MiddlewareResult::Next);
+
+        let serve = ServeStatic::new(&dir.0).index(["index.htm"]);
+        assert_eq!(static_get(&serve, "/blog/", &[]).body.as_deref(), Some(&b"blog"[..]));
+        let (_, result) = crate::testing::run_middleware(&serve, Request::builder().uri("/docs/").build().unwrap());
+        assert_eq!(result, MiddlewareResult::Next);
+    }
+
+    #[test]
+    fn test_precompressed_files_are_sent_to_clients_that_accept_them() {
+        let files: &[(&str, &[u8])] = &[("app.js", b"plain"), ("app.js.br", b"brotli"), ("app.js.gz", b"gzip"), ("style.css", b"css")];
+        let dir = StaticDir::new("precompressed", files);
+        let serve = ServeStatic::new(&dir.0);
+
+        let response = static_get(&serve, "/app.js", &[("Accept-Encoding", "gzip, deflate, br")]);
+        assert_eq!(response.body.as_deref(), Some(&b"brotli"[..]));
+        assert_eq!(response.headers.get("content-encoding").unwrap(), "br");
+        assert_eq!(response.headers.get("content-type").unwrap(), "application/javascript; charset=utf-8");
+        assert_eq!(response.headers.get("vary").unwrap(), "Accept-Encoding");
+        let response = static_get(&serve, "/app.js", &[("Accept-Encoding", "gzip, br;q=0")]);
+        assert_eq!(response.body.as_deref(), Some(&b"gzip"[..]));
+        assert_eq!(response.headers.get("content-encoding").unwrap(), "gzip");
+        // An entry naming the coding wins over `*`, in any order
+        let response = static_get(&serve, "/app.js", &[("Accept-Encoding", "br;q=0, *")]);
+        assert_eq!(response.headers.get("content-encoding").unwrap(), "gzip");
+        let response = static_get(&serve, "/app.js", &[("Accept-Encoding", "*;q=0, br")]);
+        assert_eq!(response.headers.get("content-encoding").unwrap(), "br");
+        let response = static_get(&serve, "/app.js", &[]);
+        assert_eq!(response.body.as_deref(), Some(&b"plain"[..]));
+        assert!(response.headers.get("content-encoding").is_none());
+        assert_eq!(response.headers.get("vary").unwrap(), "Accept-Encoding");
+        // No sibling, nothing varies
+        assert!(static_get(&serve, "/style.css", &[("Accept-Encoding", "br")]).headers.get("vary").is_none());
+
+        let serve = ServeStatic::new(&dir.0).precompressed(false);
+        assert_eq!(static_get(&serve, "/app.js", &[("Accept-Encoding", "br")]).body.as_deref(), Some(&b"plain"[..]));
+    }
+
+    #[test]
+    fn test_static_files_get_their_cache_control() {
+        let dir = StaticDir::new("cache-control", &[("index.html", b"page"), ("app.JS", b"js"), ("logo.png", b"png")]);
+        let serve = ServeStatic::new(&dir.0).cache_control("public, max-age=31536000, immutable").cache_control_for(".html", "no-cache").cache_control_for("js", "public, max-age=60");
+        let cache_control = |path: &str| static_get(&serve, path, &[]).headers.get("cache-control").map(|value| value.to_str().unwrap().to_string());
+
+        assert_eq!(cache_control("/logo.png").as_deref(), Some("public, max-age=31536000, immutable"));
+        assert_eq!(cache_control("/index.html").as_deref(), Some("no-cache"));
+        assert_eq!(cache_control("/app.JS").as_deref(), Some("public, max-age=60"));
+        assert_eq!(static_get(&ServeStatic::new(&dir.0), "/logo.png", &[]).headers.get("cache-control"), None);
+    }
+
+    #[test]
+    fn test_mounted_files_are_served_under_the_prefix() {
+        let dir = StaticDir::new("mount", &[("app.js", b"js"), ("index.html", b"home")]);
+        let serve = ServeStatic::new(&dir.0).mount("/static/").fallthrough(false);
+
+        assert_eq!(static_get(&serve, "/static/app.js", &[]).body.as_deref(), Some(&b"js"[..]));
+        assert_eq!(static_get(&serve, "/static/", &[]).body.as_deref(), Some(&b"home"[..]));
+        assert_eq!(static_get(&serve, "/static", &[]).headers.get("location").unwrap(), "/static/");
+        assert_eq!(static_get(&serve, "/static/missing.js", &[]).status.as_u16(), 404);
+        // Outside of the mount requests are passed on, even without fallthrough
+        for path in ["/app.js", "/staticapp.js", "/api/users"] {
+            let (_, result) = crate::testing::run_middleware(&serve, Request::builder().uri(path).build().unwrap());
+            assert_eq!(result, MiddlewareResult::Next, "{path}");
+        }
+    }
+
+    #[test]
+    fn test_embedded_files_are_served_like_static_files() {
+        let files: [(&str, &'static [u8]); 3] = [("index.html", b"home"), ("/css/site.css", b"body {}"), ("docs/index.html", b"docs")];
+        let serve = ServeEmbedded::from_files(files).mount("/app").cache_control("public, max-age=3600").fallthrough(false);
+
+        let response = static_get(&serve, "/app/css/site.css", &[]);
+        assert_eq!(response.body.as_deref(), Some(&b"body {}"[..]));
+        assert_eq!(response.headers.get("content-type").unwrap(), "text/css; charset=utf-8");
+        assert_eq!(response.headers.get("cache-control").unwrap(), "public, max-age=3600");
+        let etag = response.headers.get("etag").unwrap().to_str().unwrap().to_string();
+        assert_eq!(static_get(&serve, "/app/css/site.css", &[("If-None-Match", &etag)]).status.as_u16(), 304);
+        let response = static_get(&serve, "/app/css/site.css", &[("Range", "bytes=-2")]);
+        assert_eq!((response.status.as_u16(), response.body.as_deref()), (206, Some(&b"{}"[..])));
+
+        assert_eq!(static_get(&serve, "/app/", &[]).body.as_deref(), Some(&b"home"[..]));
+        assert_eq!(static_get(&serve, "/app/docs/", &[]).body.as_deref(), Some(&b"docs"[..]));
+        assert_eq!(static_get(&serve, "/app/docs", &[]).headers.get("location").unwrap(), "/app/docs/");
+        assert_eq!(static_get(&serve, "/app/missing.js", &[]).status.as_u16(), 404);
+        let (_, result) = crate::testing::run_middleware(&serve, Request::builder().uri("/api/users").build().unwrap());
+        assert_eq!(result, MiddlewareResult::Next);
+    }
+
+    #[test]
+    fn test_static_files_are_streamed() {
+        let dir = StaticDir::new("stream", &[("big.bin", &[7; 10_000])]);
+        let serve = ServeStatic::new(&dir.0).chunk_size(1024);
+        let mut request = Request::builder().uri("/big.bin").build().unwrap();
+        let mut response = Response::default();
+        serve.handle(&mut request, &mut response, &AppContext::new()).unwrap();
+
+        assert_eq!(response.body, None);
+        assert_eq!(response.headers.get("content-length").unwrap(), "10000");
+        let mut written = Vec::new();
+        response.take_stream().unwrap().write_to(&mut written).unwrap();
+        assert_eq!(written, vec![7; 10_000]);
+    }
+}
@@ -29,9 +29,18 @@ pub trait Middleware: Send + Sync {
     /// - Access application state via `ctx`
     /// - Control flow with the return value
     fn handle(&self, request: &mut Request, response: &mut Response, ctx: &AppContext) -> Outcome;
+
+    /// Post-response hook, called once the response for the request is ready.
+    ///
+    /// Only called for global middlewares (registered with `use_middleware`), in reverse
+    /// registration order, after the route handler has run. A middleware whose `handle` didn't
+    /// run, because an earlier one ended the request or it was turned off, isn't called.
+    /// Use it for timing, logging or adding headers that depend on the final response.
+    /// Does nothing by default.
+    fn after(&self, _request: &Request, _response: &mut Response, _ctx: &AppContext) {}
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MiddlewareResult {
     /// Continue to the next middleware in the chain.
     Next,
@@ -124,3 +133,32 @@ macro_rules! chain {
     }};
 }
 pub use chain;
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::TestClient;
+    use crate::{App, Finalizer, middleware, next};
+
+    #[test]
+    fn test_middleware_macro_forms() {
+        let mut app = App::without_logger();
+        app.context().set_state(String::from("Ada"));
+        let greeting = String::from("Hello");
+        app.use_middleware(middleware!(|req, ctx| {
+            ctx.metrics().counter("seen").increment();
+            req.extensions.insert(1u8);
+            next!()
+        }));
+        app.get("/health", middleware!(|res| res.finish_text("OK")));
+        app.get("/name", middleware!(|res, ctx| res.finish_text(ctx.get_state::<String>().as_str())));
+        app.get("/users/:id", middleware!(|req, res| res.finish_text(req.param("id").unwrap_or_default())));
+        app.get("/greet", middleware!(move |_req, res, _ctx| res.finish_text(greeting.clone())));
+        let client = TestClient::new(app);
+
+        assert_eq!(client.get("/health").send().body.as_deref(), Some(&b"OK"[..]));
+        assert_eq!(client.get("/name").send().body.as_deref(), Some(&b"Ada"[..]));
+        assert_eq!(client.get("/users/7").send().body.as_deref(), Some(&b"7"[..]));
+        assert_eq!(client.get("/greet").send().body.as_deref(), Some(&b"Hello"[..]));
+        assert_eq!(client.context().metrics().counter("seen").get(), 4);
+    }
+}
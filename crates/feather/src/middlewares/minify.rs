@@ -0,0 +1,364 @@
+//! Minification of HTML, CSS and JavaScript responses.
+//!
+//! [`Minify`] is a [`BodyTransform`] for apps that render or serve text assets without a build pipeline.
+//! The minifiers are deliberately conservative: they strip comments and redundant whitespace but never
+//! rename or reorder anything, so the output behaves exactly like the input.
+
+use super::transform::BodyTransform;
+use bytes::Bytes;
+use feather_runtime::http::{Request, Response};
+use std::error::Error;
+
+/// Minifies `text/html`, `text/css` and `application/javascript` responses.
+///
+/// Each content type can be toggled on its own, and bodies smaller than [`min_size`](Self::min_size)
+/// are left alone since the savings wouldn't be worth the work.
+/// Responses that already have a `Content-Encoding` (e.g. compressed) are never touched.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::middlewares::minify::Minify;
+///
+/// // Minify everything above 1KB
+/// app.use_transform(Minify::default());
+///
+/// // Only HTML, no matter the size
+/// app.use_transform(Minify::new().css(false).js(false).min_size(0));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Minify {
+    html: bool,
+    css: bool,
+    js: bool,
+    min_size: usize,
+}
+
+impl Default for Minify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The kinds of content [Minify] knows how to handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Html,
+    Css,
+    Js,
+}
+
+impl Minify {
+    /// Minify HTML, CSS and JavaScript bodies of at least 1KB.
+    pub const fn new() -> Self {
+        Self {
+            html: true,
+            css: true,
+            js: true,
+            min_size: 1024,
+        }
+    }
+
+    /// Enable or disable minification of `text/html` responses.
+    pub const fn html(mut self, enabled: bool) -> Self {
+        self.html = enabled;
+        self
+    }
+
+    /// Enable or disable minification of `text/css` responses.
+    pub const fn css(mut self, enabled: bool) -> Self {
+        self.css = enabled;
+        self
+    }
+
+    /// Enable or disable minification of `application/javascript` (and `text/javascript`) responses.
+    pub const fn js(mut self, enabled: bool) -> Self {
+        self.js = enabled;
+        self
+    }
+
+    /// Only minify bodies of at least `bytes` bytes.
+    pub const fn min_size(mut self, bytes: usize) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    fn kind(&self, response: &Response) -> Option<Kind> {
+        let content_type = response.headers.get("content-type")?.to_str().ok()?;
+        let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        match mime.as_str() {
+            "text/html" if self.html => Some(Kind::Html),
+            "text/css" if self.css => Some(Kind::Css),
+            "application/javascript" | "text/javascript" if self.js => Some(Kind::Js),
+            _ => None,
+        }
+    }
+}
+
+impl BodyTransform for Minify {
+    fn applies(&self, _request: &Request, response: &Response) -> bool {
+        // Streamed bodies, like static files, by their announced length
+        let len = response.body.as_ref().map(Bytes::len).or_else(|| response.headers.get("content-length")?.to_str().ok()?.parse().ok());
+        let large_enough = len.is_some_and(|len| len >= self.min_size);
+        large_enough && !response.headers.contains_key("content-encoding") && self.kind(response).is_some()
+    }
+
+    fn transform(&self, body: Bytes, response: &mut Response) -> Result<Bytes, Box<dyn Error>> {
+        let minified = match self.kind(response) {
+            Some(Kind::Html) => minify_html(&body),
+            Some(Kind::Css) => minify_css(&body),
+            Some(Kind::Js) => minify_js(&body),
+            None => return Ok(body),
+        };
+        Ok(Bytes::from(minified))
+    }
+
+    /// HTML, CSS and JavaScript files are small enough to minify in memory.
+    fn buffers_streams(&self) -> bool {
+        true
+    }
+}
+
+/// Elements whose content must be copied verbatim.
+const RAW_ELEMENTS: [&[u8]; 4] = [b"pre", b"textarea", b"script", b"style"];
+
+/// Strips comments (except conditional comments) and collapses whitespace between tags.
+/// Tags themselves and the content of `pre`, `textarea`, `script` and `style` are kept as is.
+pub fn minify_html(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        let c = input[i];
+        if c == b'<' {
+            if input[i..].starts_with(b"<!--") && !input[i..].starts_with(b"<!--[if") {
+                i = find(input, b"-->", i + 4).map_or(input.len(), |end| end + 3);
+                continue;
+            }
+            if let Some(name) = RAW_ELEMENTS.iter().find(|name| starts_tag(input, i, name)) {
+                let mut closing = b"</".to_vec();
+                closing.extend_from_slice(name);
+                let end = find_ci(input, &closing, i + 1).and_then(|at| find(input, b">", at)).map_or(input.len(), |at| at + 1);
+                out.extend_from_slice(&input[i..end]);
+                i = end;
+                continue;
+            }
+            let end = find(input, b">", i).map_or(input.len(), |at| at + 1);
+            out.extend_from_slice(&input[i..end]);
+            i = end;
+        } else if c.is_ascii_whitespace() {
+            while i < input.len() && input[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            // A removed comment can leave two runs of whitespace next to each other
+            if out.last() != Some(&b' ') {
+                out.push(b' ');
+            }
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Strips comments and whitespace that CSS doesn't need. Strings are kept as is.
+pub fn minify_css(input: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        let c = input[i];
+        match c {
+            b'"' | b'\'' => {
+                let end = string_end(input, i);
+                out.extend_from_slice(&input[i..end]);
+                i = end;
+            }
+            b'/' if input.get(i + 1) == Some(&b'*') => {
+                i = find(input, b"*/", i + 2).map_or(input.len(), |end| end + 2);
+            }
+            c if c.is_ascii_whitespace() => {
+                while i < input.len() && input[i].is_ascii_whitespace() {
+                    i += 1;
+                }
+                let prev = out.last().copied();
+                let next = input.get(i).copied();
+                let around_punct = |b: Option<u8>| matches!(b, None | Some(b'{' | b'}' | b';' | b',' | b'>'));
+                if !around_punct(prev) && !around_punct(next) && prev != Some(b':') {
+                    out.push(b' ');
+                }
+            }
+            b'}' => {
+                if out.last() == Some(&b';') {
+                    out.pop();
+                }
+                out.push(c);
+                i += 1;
+            }
+            _ => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Strips comments, indentation and blank lines from JavaScript.
+///
+/// Line breaks are kept so automatic semicolon insertion still works, and strings,
+/// template literals and regex literals are copied untouched.
+pub fn minify_js(input: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        let c = input[i];
+        match c {
+            b'"' | b'\'' | b'`' => {
+                let end = string_end(input, i);
+                out.extend_from_slice(&input[i..end]);
+                i = end;
+            }
+            b'/' if input.get(i + 1) == Some(&b'/') => {
+                i = find(input, b"\n", i).unwrap_or(input.len());
+            }
+            b'/' if input.get(i + 1) == Some(&b'*') => {
+                i = find(input, b"*/", i + 2).map_or(input.len(), |end| end + 2);
+                // `a/* */b` must not become `ab`
+                if out.last().is_some_and(|b| !b.is_ascii_whitespace()) {
+                    out.push(b' ');
+                }
+            }
+            b'/' if regex_allowed(&out) => {
+                let end = regex_end(input, i);
+                out.extend_from_slice(&input[i..end]);
+                i = end;
+            }
+            c if c.is_ascii_whitespace() => {
+                let mut newline = false;
+                while i < input.len() && input[i].is_ascii_whitespace() {
+                    newline |= input[i] == b'\n';
+                    i += 1;
+                }
+                while out.last().is_some_and(|b| *b == b' ') {
+                    out.pop();
+                }
+                match out.last() {
+                    None => {}
+                    Some(b'\n') if newline => {}
+                    _ if newline => out.push(b'\n'),
+                    _ => out.push(b' '),
+                }
+            }
+            _ => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    while out.last().is_some_and(|b| b.is_ascii_whitespace()) {
+        out.pop();
+    }
+    out
+}
+
+/// Keywords after which a `/` starts a regex literal.
+const REGEX_KEYWORDS: [&[u8]; 9] = [b"return", b"typeof", b"case", b"do", b"else", b"in", b"throw", b"void", b"yield"];
+
+/// A `/` starts a regex literal (rather than a division) when it follows an operator, a keyword or nothing at all.
+fn regex_allowed(out: &[u8]) -> bool {
+    let trimmed = out.trim_ascii_end();
+    match trimmed.last() {
+        None => true,
+        Some(b) if b"(,=:[!&|?{};+-*%<>~^".contains(b) => true,
+        Some(_) => {
+            let word_start = trimmed.iter().rposition(|b| !(b.is_ascii_alphanumeric() || *b == b'_' || *b == b'$')).map_or(0, |at| at + 1);
+            REGEX_KEYWORDS.contains(&&trimmed[word_start..])
+        }
+    }
+}
+
+/// Returns the index just past the regex literal starting at `start`.
+fn regex_end(input: &[u8], start: usize) -> usize {
+    let mut i = start + 1;
+    let mut in_class = false;
+    while i < input.len() {
+        match input[i] {
+            b'\\' => i += 1,
+            b'[' => in_class = true,
+            b']' => in_class = false,
+            b'/' if !in_class => return i + 1,
+            b'\n' => return i,
+            _ => {}
+        }
+        i += 1;
+    }
+    input.len()
+}
+
+/// Returns the index just past the string starting at `start`, whose first byte is the quote.
+fn string_end(input: &[u8], start: usize) -> usize {
+    let quote = input[start];
+    let mut i = start + 1;
+    while i < input.len() {
+        match input[i] {
+            b'\\' => i += 1,
+            b if b == quote => return i + 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    input.len()
+}
+
+/// Whether an opening tag named `name` starts at `at`.
+fn starts_tag(input: &[u8], at: usize, name: &[u8]) -> bool {
+    let rest = &input[at + 1..];
+    rest.len() > name.len() && rest[..name.len()].eq_ignore_ascii_case(name) && matches!(rest[name.len()], b'>' | b'/' | b' ' | b'\t' | b'\n' | b'\r')
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack.get(from..)?.windows(needle.len()).position(|w| w == needle).map(|at| at + from)
+}
+
+fn find_ci(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack.get(from..)?.windows(needle.len()).position(|w| w.eq_ignore_ascii_case(needle)).map(|at| at + from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn html(s: &str) -> String {
+        String::from_utf8(minify_html(s.as_bytes())).unwrap()
+    }
+    fn css(s: &str) -> String {
+        String::from_utf8(minify_css(s.as_bytes())).unwrap()
+    }
+    fn js(s: &str) -> String {
+        String::from_utf8(minify_js(s.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn test_html_collapses_whitespace_and_comments() {
+        let input = "<div  class=\"a  b\">\n    <!-- note -->\n    <p>Hello   world</p>\n</div>";
+        assert_eq!(html(input), "<div  class=\"a  b\"> <p>Hello world</p> </div>");
+    }
+
+    #[test]
+    fn test_html_keeps_raw_elements() {
+        let input = "<PRE>  keep\n  this </PRE>  <script>if (a  <  b) {}</script>";
+        assert_eq!(html(input), "<PRE>  keep\n  this </PRE> <script>if (a  <  b) {}</script>");
+    }
+
+    #[test]
+    fn test_css() {
+        let input = "/* header */\nbody {\n  margin : 0;\n  font-family: \"Open  Sans\", sans-serif;\n}\na > b , c d { color: red; }";
+        assert_eq!(css(input), "body{margin :0;font-family:\"Open  Sans\",sans-serif}a>b,c d{color:red}");
+    }
+
+    #[test]
+    fn test_js() {
+        let input = "// setup\nconst url = \"http://example.com\"; /* inline */\n\n    let re = /\\/\\//g;\n    let x = a / b; // divide\nreturn /a b/;";
+        assert_eq!(js(input), "const url = \"http://example.com\";\nlet re = /\\/\\//g;\nlet x = a / b;\nreturn /a b/;");
+    }
+}
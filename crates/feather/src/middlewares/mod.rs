@@ -8,6 +8,9 @@
 //! - [`Middleware`] - Trait for implementing request handlers
 //! - [`MiddlewareResult`] - Enum controlling request flow
 //! - [`builtins`] - Pre-built middleware for common tasks
+//! - [`transform`] - Post-processing of response bodies
+//! - [`minify`] - HTML/CSS/JS minification transform
+//! - [`Stack`] - Reusable bundles of middleware
 //!
 //! # Using Middleware
 //!
@@ -31,5 +34,11 @@
 
 pub mod builtins;
 pub mod common;
+pub mod minify;
+pub mod stack;
+pub mod transform;
 
 pub use common::{Middleware, MiddlewareResult, chain};
+pub use minify::Minify;
+pub use stack::Stack;
+pub use transform::BodyTransform;
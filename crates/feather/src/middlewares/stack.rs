@@ -0,0 +1,114 @@
+//! Reusable bundles of middleware.
+//!
+//! A [Stack] runs its middleware in order and is a [Middleware] itself, so a bundle like logging, CORS and
+//! authentication is defined once and used on every route, [Router](crate::Router) or [App](crate::App)
+//! that needs it. Unlike [`chain!`](crate::middlewares::chain), a stack is a value: it can be cloned,
+//! returned from a function and extended later.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use feather::middlewares::Stack;
+//! use feather::middlewares::builtins::{Cors, Logger};
+//!
+//! fn api_stack() -> Stack {
+//!     Stack::new().with(Logger).with(Cors::default()).with(require_token)
+//! }
+//!
+//! app.use_middleware(api_stack());
+//! admin.use_middleware(api_stack().with(require_admin));
+//! ```
+
+use super::{Middleware, MiddlewareResult};
+use crate::{Outcome, internals::AppContext};
+use feather_runtime::http::{Request, Response};
+use std::sync::Arc;
+
+/// Middleware run in the order they were added, stopping at the first one that doesn't return
+/// [`MiddlewareResult::Next`].
+///
+/// Clones share the middleware, so cloning a stack is cheap. The [after](Middleware::after) hooks run in
+/// reverse order, like the ones of global middleware.
+#[derive(Clone, Default)]
+pub struct Stack {
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl Stack {
+    /// An empty stack, which passes every request on.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `middleware` to the end of the stack.
+    pub fn with(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// The number of middleware in the stack.
+    pub fn len(&self) -> usize {
+        self.middleware.len()
+    }
+
+    /// Whether the stack has no middleware.
+    pub fn is_empty(&self) -> bool {
+        self.middleware.is_empty()
+    }
+}
+
+impl Middleware for Stack {
+    fn handle(&self, request: &mut Request, response: &mut Response, ctx: &AppContext) -> Outcome {
+        for middleware in &self.middleware {
+            match middleware.handle(request, response, ctx)? {
+                MiddlewareResult::Next => continue,
+                result => return Ok(result),
+            }
+        }
+        Ok(MiddlewareResult::Next)
+    }
+
+    fn after(&self, request: &Request, response: &mut Response, ctx: &AppContext) {
+        for middleware in self.middleware.iter().rev() {
+            middleware.after(request, response, ctx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestClient;
+    use crate::{App, Finalizer, end, middleware, next};
+
+    #[test]
+    fn test_stacks_are_reused_across_routes() {
+        let auth = Stack::new()
+            .with(middleware!(|res| {
+                res.add_header("X-Stack", "1")?;
+                next!()
+            }))
+            .with(middleware!(|req, res| {
+                if req.headers.get("authorization").is_none() {
+                    res.set_status(401);
+                    return end!();
+                }
+                next!()
+            }));
+        assert_eq!(auth.len(), 2);
+
+        let mut app = App::without_logger();
+        app.get("/users", auth.clone().with(middleware!(|res| res.finish_text("users"))));
+        app.get("/orders", auth.with(middleware!(|res| res.finish_text("orders"))));
+        let client = TestClient::new(app);
+
+        let response = client.get("/users").header("Authorization", "Bearer token").send();
+        assert_eq!(response.body.as_deref(), Some(&b"users"[..]));
+        assert_eq!(response.headers.get("x-stack").unwrap(), "1");
+        assert_eq!(
+            client.get("/orders").header("Authorization", "Bearer token").send().body.as_deref(),
+            Some(&b"orders"[..])
+        );
+        assert_eq!(client.get("/orders").send().status.as_u16(), 401);
+    }
+}
@@ -0,0 +1,180 @@
+//! Response body post-processing.
+//!
+//! Body transforms run after the middleware chain has produced a response and rewrite its body:
+//! compression, minification, snippet injection, encryption and so on.
+//! They are registered with [`App::use_transform`](crate::App::use_transform) and applied in the order they were added.
+
+use bytes::Bytes;
+use feather_runtime::http::{Request, Response};
+use feather_runtime::{HeaderName, HeaderValue};
+use std::error::Error;
+use std::sync::Arc;
+
+/// A post-processing step that rewrites the response body.
+///
+/// Implement this instead of writing a middleware that re-implements body rewriting.
+/// The pipeline takes care of calling transforms in declared order, skipping responses without a body
+/// and keeping the `Content-Length` header in sync with the rewritten body.
+///
+/// Streamed bodies, like the files of [`ServeStatic`](crate::middlewares::builtins::ServeStatic), are sent
+/// as they are unless a transform [buffers streams](Self::buffers_streams). The first one that does and
+/// applies reads the stream into memory, and the transforms after it see that body like any other.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::middlewares::transform::BodyTransform;
+///
+/// struct Banner;
+///
+/// impl BodyTransform for Banner {
+///     fn applies(&self, _req: &Request, res: &Response) -> bool {
+///         res.headers.get("content-type").is_some_and(|ct| ct.as_bytes().starts_with(b"text/html"))
+///     }
+///
+///     fn transform(&self, body: Bytes, _res: &mut Response) -> Result<Bytes, Box<dyn Error>> {
+///         let mut out = b"<!-- served by feather -->".to_vec();
+///         out.extend_from_slice(&body);
+///         Ok(Bytes::from(out))
+///     }
+/// }
+///
+/// app.use_transform(Banner);
+/// ```
+pub trait BodyTransform: Send + Sync {
+    /// Decide whether this transform should run for the given response.
+    ///
+    /// Defaults to `true`. Check things like the status code or `Content-Type` here.
+    fn applies(&self, _request: &Request, _response: &Response) -> bool {
+        true
+    }
+
+    /// Rewrite the body. The response is passed along so headers like `Content-Encoding` can be adjusted.
+    fn transform(&self, body: Bytes, response: &mut Response) -> Result<Bytes, Box<dyn Error>>;
+
+    /// Whether a streamed body is read into memory to be transformed, checked before [applies](Self::applies).
+    ///
+    /// Defaults to `false`, so large files keep being streamed. Return `true` for transforms that only apply
+    /// to bodies small enough to hold in memory, `applies` sees the length in the `Content-Length` header.
+    fn buffers_streams(&self) -> bool {
+        false
+    }
+}
+
+/// Automatically implement `BodyTransform` for closures.
+///
+/// ```rust,ignore
+/// app.use_transform(|body: Bytes, _res: &mut Response| -> Result<Bytes, Box<dyn Error>> {
+///     Ok(body)
+/// });
+/// ```
+impl<F> BodyTransform for F
+where
+    F: Fn(Bytes, &mut Response) -> Result<Bytes, Box<dyn Error>> + Send + Sync,
+{
+    fn transform(&self, body: Bytes, response: &mut Response) -> Result<Bytes, Box<dyn Error>> {
+        self(body, response)
+    }
+}
+
+/// Runs the transforms over the response body in order.
+pub(crate) fn apply_transforms(transforms: &[Arc<dyn BodyTransform>], request: &Request, response: &mut Response) -> Result<(), Box<dyn Error>> {
+    for transform in transforms {
+        let has_body = response.body.is_some() || (response.has_stream() && transform.buffers_streams());
+        if !has_body || !transform.applies(request, response) {
+            continue;
+        }
+        if let Some(stream) = response.take_stream() {
+            response.body = Some(stream.into_bytes()?);
+        }
+        let body = response.body.take().unwrap_or_default();
+        let body = transform.transform(body, response)?;
+        response.headers.insert(HeaderName::from_static("content-length"), HeaderValue::from(body.len()));
+        response.body = Some(body);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middlewares::minify::Minify;
+    use crate::testing::TestClient;
+    use crate::{App, Finalizer, middleware};
+    use std::io::Cursor;
+
+    fn suffix(suffix: &'static str) -> impl Fn(Bytes, &mut Response) -> Result<Bytes, Box<dyn Error>> + Send + Sync {
+        move |body: Bytes, _: &mut Response| Ok(Bytes::from([&body[..], suffix.as_bytes()].concat()))
+    }
+
+    /// Upper-cases the body, streamed ones included.
+    struct Shout;
+
+    impl BodyTransform for Shout {
+        fn transform(&self, body: Bytes, _response: &mut Response) -> Result<Bytes, Box<dyn Error>> {
+            Ok(Bytes::from(body.to_ascii_uppercase()))
+        }
+
+        fn buffers_streams(&self) -> bool {
+            true
+        }
+    }
+
+    fn routes() -> App {
+        let mut app = App::without_logger();
+        app.get("/buffered", middleware!(|res| res.finish_text("body")));
+        app.get(
+            "/streamed",
+            middleware!(|res| {
+                res.send_reader(Cursor::new(b"stream".to_vec()), 6, 2);
+                crate::next!()
+            }),
+        );
+        app
+    }
+
+    #[test]
+    fn test_transforms_run_in_declared_order() {
+        let mut app = routes();
+        app.use_transform(suffix("-a"));
+        app.use_transform(suffix("-b"));
+        let response = TestClient::new(app).get("/buffered").send();
+        assert_eq!(response.body.as_deref(), Some(&b"body-a-b"[..]));
+        assert_eq!(response.headers.get("content-length").unwrap(), "8");
+    }
+
+    #[test]
+    fn test_streamed_bodies_are_only_transformed_when_buffered() {
+        let mut app = routes();
+        app.use_transform(suffix("-a"));
+        let response = TestClient::new(app).get("/streamed").send();
+        assert_eq!(response.body.as_deref(), Some(&b"stream"[..]));
+        assert_eq!(response.headers.get("content-length").unwrap(), "6");
+
+        // Once a transform buffered the stream, the ones after it see the body
+        let mut app = routes();
+        app.use_transform(suffix("-a"));
+        app.use_transform(Shout);
+        app.use_transform(suffix("-b"));
+        let response = TestClient::new(app).get("/streamed").send();
+        assert_eq!(response.body.as_deref(), Some(&b"STREAM-b"[..]));
+        assert_eq!(response.headers.get("content-length").unwrap(), "8");
+    }
+
+    #[test]
+    fn test_minify_buffers_streamed_assets() {
+        let mut app = App::without_logger();
+        app.get(
+            "/page.html",
+            middleware!(|res| {
+                let page = b"<p>  a  </p>\n\n<p>b</p>".to_vec();
+                res.add_header("Content-Type", "text/html")?;
+                res.send_reader(Cursor::new(page), 22, 4);
+                crate::next!()
+            }),
+        );
+        app.use_transform(Minify::new().min_size(10));
+        let response = TestClient::new(app).get("/page.html").send();
+        assert_eq!(response.body.as_deref(), Some(&b"<p> a </p> <p>b</p>"[..]));
+    }
+}
@@ -0,0 +1,206 @@
+//! User preferences persisted in a signed cookie.
+//!
+//! Small per-user settings like the theme or the locale don't need a session store: [`Preferences`]
+//! keeps them in a cookie signed with the secret of the app's [`JwtManager`](crate::jwt::JwtManager),
+//! so clients can't tamper with them. Requires the `jwt` feature.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use feather::prefs::{Preferences, RequestPrefs, ResponsePrefs};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Default, Serialize, Deserialize)]
+//! struct Settings {
+//!     theme: String,
+//!     locale: String,
+//! }
+//!
+//! impl Preferences for Settings {}
+//!
+//! app.post("/theme/:name", middleware!(|req, res, ctx| {
+//!     let mut settings = req.prefs::<Settings>(ctx);
+//!     settings.theme = req.param("name").unwrap();
+//!     res.save_prefs(ctx, &settings)?;
+//!     res.finish_text("saved")
+//! }));
+//! ```
+
+use crate::cookie::{Cookie, RequestCookies, ResponseCookies};
+use crate::{AppContext, Error};
+use feather_runtime::http::{Request, Response};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A preferences struct stored in a signed cookie, see the [module docs](self).
+///
+/// Missing, tampered or unreadable cookies read as [`Default::default`]. When the struct changes,
+/// bump [`VERSION`](Self::VERSION) and implement [`migrate`](Self::migrate) to carry old cookies over.
+pub trait Preferences: Serialize + DeserializeOwned + Default {
+    /// Name of the cookie. Defaults to `prefs`.
+    const COOKIE: &'static str = "prefs";
+    /// Version of the stored format. Defaults to `1`.
+    const VERSION: u32 = 1;
+    /// How long the browser keeps the cookie, in seconds. Defaults to one year.
+    const MAX_AGE: u64 = 365 * 24 * 60 * 60;
+
+    /// Convert preferences stored with an older `version`. Returning `None` falls back to the default.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// // Version 1 stored a `dark: bool`, version 2 a theme name
+    /// fn migrate(version: u32, old: Value) -> Option<Self> {
+    ///     match version {
+    ///         1 => Some(Settings {
+    ///             theme: if old["dark"].as_bool()? { "dark" } else { "light" }.to_string(),
+    ///             ..Default::default()
+    ///         }),
+    ///         _ => None,
+    ///     }
+    /// }
+    /// ```
+    fn migrate(version: u32, old: Value) -> Option<Self> {
+        let _ = (version, old);
+        None
+    }
+}
+
+/// What the cookie holds: the preferences and the version they were stored with. `kind` keeps other
+/// tokens signed with the same secret from passing as preferences.
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    kind: String,
+    v: u32,
+    prefs: Value,
+}
+
+const KIND: &str = "prefs";
+
+/// Preferences access for [`Request`].
+pub trait RequestPrefs {
+    /// Read the preferences of type `T` sent with the request, or their default.
+    fn prefs<T: Preferences>(&self, ctx: &AppContext) -> T;
+}
+
+impl RequestPrefs for Request {
+    fn prefs<T: Preferences>(&self, ctx: &AppContext) -> T {
+        let stored = self.cookie(T::COOKIE).and_then(|token| ctx.jwt().decode_unexpiring::<Stored>(token).ok());
+        let Some(stored) = stored.filter(|stored| stored.kind == KIND) else {
+            return T::default();
+        };
+        let prefs = if stored.v == T::VERSION {
+            serde_json::from_value(stored.prefs).ok()
+        } else {
+            T::migrate(stored.v, stored.prefs)
+        };
+        prefs.unwrap_or_default()
+    }
+}
+
+/// Preferences writing for [`Response`].
+pub trait ResponsePrefs {
+    /// Sign `prefs` and send them back in their cookie.
+    fn save_prefs<T: Preferences>(&mut self, ctx: &AppContext, prefs: &T) -> Result<(), Error>;
+}
+
+impl ResponsePrefs for Response {
+    fn save_prefs<T: Preferences>(&mut self, ctx: &AppContext, prefs: &T) -> Result<(), Error> {
+        let stored = Stored {
+            kind: KIND.to_string(),
+            v: T::VERSION,
+            prefs: serde_json::to_value(prefs)?,
+        };
+        let token = ctx.jwt().encode(&stored)?;
+        self.set_cookie(ctx, Cookie::new(T::COOKIE, token).max_age(T::MAX_AGE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jwt::JwtManager;
+    use crate::testing::run_middleware_with;
+    use crate::{Outcome, next};
+    use serde_json::json;
+
+    #[derive(Default, Serialize, Deserialize, Debug, PartialEq)]
+    struct Settings {
+        theme: String,
+    }
+
+    impl Preferences for Settings {
+        const VERSION: u32 = 2;
+
+        fn migrate(version: u32, old: Value) -> Option<Self> {
+            match version {
+                1 => Some(Settings {
+                    theme: if old["dark"].as_bool()? {
+                        "dark"
+                    } else {
+                        "light"
+                    }
+                    .to_string(),
+                }),
+                _ => None,
+            }
+        }
+    }
+
+    fn context() -> AppContext {
+        let mut ctx = AppContext::new();
+        ctx.set_jwt(JwtManager::new("secret".to_string()));
+        ctx
+    }
+
+    fn request(cookie: &str) -> Request {
+        Request::builder().uri("/").header("Cookie", cookie).build().unwrap()
+    }
+
+    fn token(ctx: &AppContext, stored: serde_json::Value) -> String {
+        format!("prefs={}", ctx.jwt().encode(&stored).unwrap())
+    }
+
+    #[test]
+    fn test_prefs_round_trip() {
+        let ctx = context();
+        let save = |_: &mut Request, response: &mut Response, ctx: &AppContext| -> Outcome {
+            response.save_prefs(
+                ctx,
+                &Settings {
+                    theme: "dark".to_string(),
+                },
+            )?;
+            next!()
+        };
+        let (response, _) = run_middleware_with(&save, request(""), &ctx);
+        let header = response.headers.get("set-cookie").unwrap().to_str().unwrap();
+        let cookie = header.split(';').next().unwrap();
+        assert_eq!(request(cookie).prefs::<Settings>(&ctx).theme, "dark");
+
+        // A cookie signed with another secret is ignored
+        let mut other = AppContext::new();
+        other.set_jwt(JwtManager::new("other".to_string()));
+        assert_eq!(request(cookie).prefs::<Settings>(&other), Settings::default());
+    }
+
+    #[test]
+    fn test_other_tokens_are_not_prefs() {
+        let ctx = context();
+        // Signed with the same secret, but not by save_prefs
+        let cookie = token(&ctx, json!({ "v": 2, "prefs": { "theme": "dark" } }));
+        assert_eq!(request(&cookie).prefs::<Settings>(&ctx), Settings::default());
+        let cookie = token(&ctx, json!({ "kind": "session", "v": 2, "prefs": { "theme": "dark" } }));
+        assert_eq!(request(&cookie).prefs::<Settings>(&ctx), Settings::default());
+    }
+
+    #[test]
+    fn test_old_versions_are_migrated() {
+        let ctx = context();
+        let cookie = token(&ctx, json!({ "kind": "prefs", "v": 1, "prefs": { "dark": true } }));
+        assert_eq!(request(&cookie).prefs::<Settings>(&ctx).theme, "dark");
+        let cookie = token(&ctx, json!({ "kind": "prefs", "v": 0, "prefs": null }));
+        assert_eq!(request(&cookie).prefs::<Settings>(&ctx), Settings::default());
+    }
+}
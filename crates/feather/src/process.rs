@@ -0,0 +1,353 @@
+//! Running Feather as a background process without a service manager.
+//!
+//! [`PidFile`] records the process id and locks the file, so a second instance refuses to start.
+//! [`daemonize`] (Unix only, `daemon` feature) detaches the process from the terminal.
+//! [`run_as_service`] (Windows only, `windows-service` feature) runs the server under the Service Control Manager.
+//!
+//! Combined with the `signals` feature, `kill $(cat app.pid)` shuts the server down gracefully.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use feather::App;
+//! use feather::process::{DaemonOptions, daemonize};
+//!
+//! fn main() {
+//!     // Must happen before anything spawns a thread, the server included
+//!     let _pid_file = daemonize(DaemonOptions::new().pid_file("/var/run/app.pid").stderr("/var/log/app.err")).expect("Failed to daemonize");
+//!
+//!     let app = App::new();
+//!     let handle = app.listen_with_graceful_shutdown("0.0.0.0:8080");
+//!     handle.shutdown_on_signal().expect("Failed to install signal handler");
+//!     handle.wait().expect("Server error");
+//! }
+//! ```
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// A locked file holding the id of the running process.
+///
+/// The lock is held and the file removed when the `PidFile` is dropped, so keep it alive for as long
+/// as the server runs.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl PidFile {
+    /// Create (or take over a stale) pid file and write the current process id to it.
+    ///
+    /// Fails with [`io::ErrorKind::AlreadyExists`] if another running process holds the lock.
+    pub fn create(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} is locked by another running process", path.display()),
+                ));
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        // Removed on drop, possibly after the working directory changed
+        let path = fs::canonicalize(&path).unwrap_or(path);
+        let mut pid_file = Self {
+            path,
+            file,
+        };
+        pid_file.write_pid()?;
+        Ok(pid_file)
+    }
+
+    /// The path of the pid file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Replace the content with the current process id.
+    fn write_pid(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.rewind()?;
+        writeln!(self.file, "{}", std::process::id())?;
+        self.file.sync_all()
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Options for [`daemonize`].
+#[cfg(all(unix, feature = "daemon"))]
+#[derive(Clone, Debug)]
+pub struct DaemonOptions {
+    working_dir: PathBuf,
+    pid_file: Option<PathBuf>,
+    stdout: Option<PathBuf>,
+    stderr: Option<PathBuf>,
+    umask: u32,
+}
+
+#[cfg(all(unix, feature = "daemon"))]
+impl Default for DaemonOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(unix, feature = "daemon"))]
+impl DaemonOptions {
+    /// Run from `/` with a `027` umask, no pid file and output discarded.
+    pub fn new() -> Self {
+        Self {
+            working_dir: PathBuf::from("/"),
+            pid_file: None,
+            stdout: None,
+            stderr: None,
+            umask: 0o027,
+        }
+    }
+
+    /// Directory to change into. Relative paths in the other options are resolved before the change.
+    pub fn working_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.working_dir = dir.into();
+        self
+    }
+
+    /// Write and lock a [`PidFile`].
+    pub fn pid_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.pid_file = Some(path.into());
+        self
+    }
+
+    /// Append standard output to a file instead of discarding it.
+    pub fn stdout(mut self, path: impl Into<PathBuf>) -> Self {
+        self.stdout = Some(path.into());
+        self
+    }
+
+    /// Append standard error to a file instead of discarding it.
+    pub fn stderr(mut self, path: impl Into<PathBuf>) -> Self {
+        self.stderr = Some(path.into());
+        self
+    }
+
+    /// File mode creation mask of the daemon.
+    pub fn umask(mut self, umask: u32) -> Self {
+        self.umask = umask;
+        self
+    }
+}
+
+/// Detach the process from the terminal and keep running in the background.
+///
+/// The usual double fork: the calling process exits, the daemon runs in a new session with its
+/// standard input read from `/dev/null` and its output sent to the configured files.
+/// The pid file is locked and the output files are opened before forking, so those errors still
+/// reach the terminal.
+///
+/// Call it at the very beginning of `main`: forking a process that already runs other threads
+/// (like the server workers) leaves only the calling thread alive in the daemon.
+#[cfg(all(unix, feature = "daemon"))]
+pub fn daemonize(options: DaemonOptions) -> io::Result<Option<PidFile>> {
+    use std::os::fd::AsRawFd;
+
+    let mut pid_file = options.pid_file.map(PidFile::create).transpose()?;
+    let open_output = |path: Option<PathBuf>| match path {
+        Some(path) => OpenOptions::new().create(true).append(true).open(path),
+        None => OpenOptions::new().write(true).open("/dev/null"),
+    };
+    let stdin = File::open("/dev/null")?;
+    let stdout = open_output(options.stdout)?;
+    let stderr = open_output(options.stderr)?;
+
+    // SAFETY: `fork`, `setsid` and `umask` have no memory safety requirements. The parents leave with `_exit`
+    // so they don't run destructors, the pid file belongs to the daemon.
+    unsafe {
+        for step in 0..2 {
+            match libc::fork() {
+                -1 => return Err(io::Error::last_os_error()),
+                0 => {}
+                _ => libc::_exit(0),
+            }
+            // Leave the terminal's session, the second fork makes sure we can never acquire one again
+            if step == 0 && libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        libc::umask(options.umask as libc::mode_t);
+    }
+    std::env::set_current_dir(&options.working_dir)?;
+
+    for (file, fd) in [(&stdin, 0), (&stdout, 1), (&stderr, 2)] {
+        // SAFETY: both file descriptors are valid for the duration of the call.
+        if unsafe { libc::dup2(file.as_raw_fd(), fd) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    // The daemon has a new pid
+    if let Some(pid_file) = &mut pid_file {
+        pid_file.write_pid()?;
+    }
+    Ok(pid_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_file_is_exclusive() {
+        let path = std::env::temp_dir().join(format!("feather-{}.pid", std::process::id()));
+        let pid_file = PidFile::create(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().trim(), std::process::id().to_string());
+        assert_eq!(PidFile::create(&path).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+}
+
+#[cfg(all(windows, feature = "windows-service"))]
+pub use self::windows::run_as_service;
+
+#[cfg(all(windows, feature = "windows-service"))]
+mod windows {
+    use std::ffi::OsString;
+    use std::io;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex, PoisonError, mpsc};
+    use std::time::Duration;
+
+    use windows_service::service::{ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType};
+    use windows_service::service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle};
+    use windows_service::{define_windows_service, service_dispatcher};
+
+    use crate::{App, AppContext, Diagnostic, Finalizer, Outcome, Request, Response};
+
+    /// Handed from [`run_as_service`] to the service main, which the dispatcher calls on another thread.
+    struct PendingService {
+        name: String,
+        app: App,
+        address: String,
+    }
+
+    static PENDING: Mutex<Option<PendingService>> = Mutex::new(None);
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    /// Run the application as a native Windows service, requires the `windows-service` feature.
+    ///
+    /// Call it from `main` of a binary registered with the Service Control Manager (e.g. `sc.exe create`).
+    /// It blocks until the service is stopped:
+    ///
+    /// - **Stop** and system **shutdown** stop accepting connections and drain the in-flight requests,
+    ///   like [`ShutdownHandle::shutdown_and_wait`](crate::ShutdownHandle::shutdown_and_wait).
+    /// - **Pause** keeps the server running but answers every request with `503 Service Unavailable`,
+    ///   **continue** resumes normal handling.
+    ///
+    /// Fails when the process was not started by the Service Control Manager.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// use feather::App;
+    /// use feather::process::run_as_service;
+    ///
+    /// fn main() -> windows_service::Result<()> {
+    ///     let app = App::new();
+    ///     run_as_service("feather-app", app, "0.0.0.0:8080")
+    /// }
+    /// ```
+    pub fn run_as_service(name: &str, app: App, address: impl Into<String>) -> windows_service::Result<()> {
+        *PENDING.lock().unwrap_or_else(PoisonError::into_inner) = Some(PendingService {
+            name: name.to_string(),
+            app,
+            address: address.into(),
+        });
+        service_dispatcher::start(name, ffi_service_main)
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        let Some(service) = PENDING.lock().unwrap_or_else(PoisonError::into_inner).take() else {
+            return;
+        };
+        if let Err(e) = run(service) {
+            Diagnostic::error("Windows service failed").cause(e).emit();
+        }
+    }
+
+    fn run(service: PendingService) -> windows_service::Result<()> {
+        let (events, controls) = mpsc::channel();
+        let status = service_control_handler::register(&service.name, move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown | ServiceControl::Pause | ServiceControl::Continue => {
+                let _ = events.send(control);
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+
+        let mut app = service.app;
+        let stop_hint = app.shutdown_timeout();
+        let paused = Arc::new(AtomicBool::new(false));
+        app.prepend_gate(paused.clone(), |_req: &mut Request, res: &mut Response, _ctx: &AppContext| -> Outcome {
+            res.set_status(503);
+            res.finish_text("Service paused")
+        });
+
+        report(status, ServiceState::StartPending, Duration::from_secs(5), 0)?;
+        let handle = match app.start(service.address) {
+            Ok(handle) => handle,
+            Err(e) => return report(status, ServiceState::Stopped, Duration::ZERO, exit_code(&e)),
+        };
+        report(status, ServiceState::Running, Duration::ZERO, 0)?;
+
+        // The sender lives in the control handler, which is never unregistered
+        for control in controls {
+            match control {
+                ServiceControl::Pause => {
+                    paused.store(true, Ordering::Release);
+                    report(status, ServiceState::Paused, Duration::ZERO, 0)?;
+                }
+                ServiceControl::Continue => {
+                    paused.store(false, Ordering::Release);
+                    report(status, ServiceState::Running, Duration::ZERO, 0)?;
+                }
+                _ => break,
+            }
+        }
+
+        report(status, ServiceState::StopPending, stop_hint, 0)?;
+        let code = handle.shutdown_and_wait().err().map_or(0, |e| exit_code(&e));
+        report(status, ServiceState::Stopped, Duration::ZERO, code)
+    }
+
+    fn exit_code(error: &io::Error) -> u32 {
+        // Errors without an OS code are reported as ERROR_INVALID_FUNCTION
+        error.raw_os_error().map_or(1, |code| code as u32)
+    }
+
+    fn report(status: ServiceStatusHandle, state: ServiceState, wait_hint: Duration, exit_code: u32) -> windows_service::Result<()> {
+        let controls_accepted = match state {
+            ServiceState::Running | ServiceState::Paused => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN | ServiceControlAccept::PAUSE_CONTINUE,
+            _ => ServiceControlAccept::empty(),
+        };
+        status.set_service_status(ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(exit_code),
+            checkpoint: 0,
+            wait_hint,
+            process_id: None,
+        })
+    }
+}
@@ -0,0 +1,228 @@
+//! Logins kept in a signed session cookie.
+//!
+//! For browser apps, where a cookie is easier to handle than a bearer token: [`ctx.login`](ContextSession::login)
+//! signs the user into a session cookie with the secret of the app's [`JwtManager`](crate::jwt::JwtManager),
+//! [`SessionAuth`] guards the routes behind it and [`current_user`](RequestSession::current_user) reads the
+//! user back. Requires the `jwt` feature.
+//!
+//! The context is shared by every request, so it hands out the cookie and the handler sets it on its response.
+//! Sessions are stateless: the cookie of [`ctx.logout`](ContextSession::logout) deletes the session from the
+//! browser, but a copy of it stays valid until it expires. Rotate the JWT secret, or keep lifetimes short,
+//! when that matters.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use feather::cookie::ResponseCookies;
+//! use feather::session::{ContextSession, RequestSession, SessionAuth};
+//!
+//! app.post("/login", middleware!(|req, res, ctx| {
+//!     let user_id = check_password(req)?;
+//!     res.set_cookie(ctx, ctx.login(&user_id)?)?;
+//!     res.redirect("/account", false);
+//!     next!()
+//! }));
+//!
+//! let mut account = Router::new();
+//! account.use_middleware(SessionAuth::new().redirect_to("/login"));
+//! account.get("/", middleware!(|req, res, _ctx| {
+//!     let user_id: u64 = req.current_user().unwrap();
+//!     res.finish_text(format!("Hello, user {user_id}"))
+//! }));
+//! app.mount("/account", account);
+//! ```
+
+use crate::cookie::{Cookie, RequestCookies};
+use crate::internals::send_error;
+use crate::middlewares::Middleware;
+use crate::middlewares::builtins::Authenticated;
+use crate::{AppContext, Error, Outcome, Request, Response, end, next};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name of the session cookie.
+pub const SESSION_COOKIE: &str = "session";
+
+/// How long a [login](ContextSession::login) lasts by default: a week.
+pub const SESSION_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// What the cookie holds. `kind` keeps other tokens signed with the same secret from passing as sessions.
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    kind: String,
+    user: Value,
+    exp: u64,
+}
+
+const KIND: &str = "session";
+
+/// The user of a valid session, inserted by [SessionAuth].
+#[derive(Clone, Debug)]
+struct SessionUser(Value);
+
+/// Guards routes behind a [login](ContextSession::login), use it on the router of a protected group.
+///
+/// Requests without a valid session get `401 Unauthorized`, or are redirected to the login page with
+/// [redirect_to](Self::redirect_to). Valid ones are marked [Authenticated] and carry their user for
+/// [`current_user`](RequestSession::current_user).
+#[derive(Clone, Debug, Default)]
+pub struct SessionAuth {
+    login_path: Option<String>,
+}
+
+impl SessionAuth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Redirect to `path` instead of answering `401`. The page asked for is passed in the `next` query
+    /// parameter, so the login handler can send the user back.
+    pub fn redirect_to(mut self, path: impl Into<String>) -> Self {
+        self.login_path = Some(path.into());
+        self
+    }
+}
+
+impl Middleware for SessionAuth {
+    fn handle(&self, request: &mut Request, response: &mut Response, ctx: &AppContext) -> Outcome {
+        if let Some(user) = session(request, ctx) {
+            request.extensions.insert(SessionUser(user));
+            request.extensions.insert(Authenticated);
+            return next!();
+        }
+        match &self.login_path {
+            Some(path) => {
+                let asked = request.uri.path_and_query().map_or("/", |asked| asked.as_str());
+                response.redirect(&format!("{path}?next={}", urlencoding::encode(asked)), false);
+            }
+            None => send_error(request, response, 401, "Login required"),
+        }
+        end!()
+    }
+}
+
+/// Session access for [`Request`].
+pub trait RequestSession {
+    /// The user stored by [login](ContextSession::login), on routes behind [SessionAuth]. `None` elsewhere,
+    /// or when the user doesn't deserialize into `T`.
+    fn current_user<T: DeserializeOwned>(&self) -> Option<T>;
+}
+
+impl RequestSession for Request {
+    fn current_user<T: DeserializeOwned>(&self) -> Option<T> {
+        let SessionUser(user) = self.extensions.get::<SessionUser>()?;
+        T::deserialize(user).ok()
+    }
+}
+
+/// Logging in and out through the [`AppContext`], which signs the session cookies.
+pub trait ContextSession {
+    /// The cookie of a session for `user`, usually its id, lasting [SESSION_LIFETIME]. Set it with
+    /// [`set_cookie`](crate::cookie::ResponseCookies::set_cookie).
+    fn login<T: Serialize>(&self, user: &T) -> Result<Cookie, Error>;
+    /// The cookie of a session for `user` lasting `lifetime`.
+    fn login_for<T: Serialize>(&self, user: &T, lifetime: Duration) -> Result<Cookie, Error>;
+    /// The cookie deleting the session from the browser.
+    fn logout(&self) -> Cookie;
+}
+
+impl ContextSession for AppContext {
+    fn login<T: Serialize>(&self, user: &T) -> Result<Cookie, Error> {
+        self.login_for(user, SESSION_LIFETIME)
+    }
+
+    fn login_for<T: Serialize>(&self, user: &T, lifetime: Duration) -> Result<Cookie, Error> {
+        let stored = Stored {
+            kind: KIND.to_string(),
+            user: serde_json::to_value(user)?,
+            exp: (SystemTime::now() + lifetime).duration_since(UNIX_EPOCH)?.as_secs(),
+        };
+        Ok(Cookie::new(SESSION_COOKIE, self.jwt().encode(&stored)?).max_age(lifetime.as_secs()))
+    }
+
+    fn logout(&self) -> Cookie {
+        Cookie::new(SESSION_COOKIE, "").max_age(0)
+    }
+}
+
+/// The user of the request's session cookie, if it's signed by us and not expired.
+fn session(request: &Request, ctx: &AppContext) -> Option<Value> {
+    let stored: Stored = ctx.jwt().decode_unexpiring(request.cookie(SESSION_COOKIE)?).ok()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    (stored.kind == KIND && stored.exp > now).then_some(stored.user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Profile;
+    use crate::jwt::JwtManager;
+    use crate::middlewares::MiddlewareResult;
+    use crate::testing::{TestClient, run_middleware_with};
+    use crate::{App, Finalizer, Router, middleware};
+
+    fn context() -> AppContext {
+        let mut ctx = AppContext::new();
+        ctx.set_jwt(JwtManager::new("secret".to_string()));
+        ctx
+    }
+
+    fn request(path: &str, token: &str) -> Request {
+        Request::builder().uri(path).header("Cookie", &format!("{SESSION_COOKIE}={token}")).build().unwrap()
+    }
+
+    #[test]
+    fn test_logins_pass_session_auth() {
+        let mut app = App::without_logger();
+        app.context().set_jwt(JwtManager::new("secret".to_string()));
+        let mut account = Router::new();
+        account.use_middleware(SessionAuth::new());
+        account.get(
+            "/",
+            middleware!(|req, res| {
+                assert!(req.extensions.get::<Authenticated>().is_some());
+                res.finish_text(req.current_user::<u64>().unwrap().to_string())
+            }),
+        );
+        app.mount("/account", account);
+        let client = TestClient::new(app);
+
+        let login = client.context().login(&42u64).unwrap();
+        assert!(login.to_header_value(Profile::Dev).unwrap().contains(&format!("; Max-Age={}", SESSION_LIFETIME.as_secs())));
+        let response = client.get("/account").header("Cookie", &format!("{SESSION_COOKIE}={}", login.value())).send();
+        assert_eq!(response.body.as_deref(), Some(&b"42"[..]));
+        assert_eq!(client.get("/account").send().status.as_u16(), 401);
+
+        let logout = client.context().logout().to_header_value(Profile::Dev).unwrap();
+        assert!(logout.starts_with(&format!("{SESSION_COOKIE}=;")) && logout.contains("; Max-Age=0"), "{logout}");
+    }
+
+    #[test]
+    fn test_other_tokens_are_not_sessions() {
+        let ctx = context();
+        // Signed with the same secret, but not by login
+        let token = ctx.jwt().generate_simple("42", 1).unwrap();
+        let (response, result) = run_middleware_with(&SessionAuth::new().redirect_to("/login"), request("/account/orders?page=2", &token), &ctx);
+        assert_eq!(result, MiddlewareResult::End);
+        assert_eq!(response.status.as_u16(), 302);
+        assert_eq!(response.headers.get("location").unwrap(), "/login?next=%2Faccount%2Forders%3Fpage%3D2");
+    }
+
+    #[test]
+    fn test_expired_sessions_answer_401() {
+        let ctx = context();
+        let token = ctx
+            .jwt()
+            .encode(&Stored {
+                kind: KIND.to_string(),
+                user: Value::from(42),
+                exp: 1,
+            })
+            .unwrap();
+        let (response, result) = run_middleware_with(&SessionAuth::new(), request("/account", &token), &ctx);
+        assert_eq!(result, MiddlewareResult::End);
+        assert_eq!(response.status.as_u16(), 401);
+    }
+}
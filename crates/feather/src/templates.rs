@@ -0,0 +1,220 @@
+//! Server-rendered HTML pages from templates.
+//!
+//! A [TemplateEngine] is registered once with [`AppContext::set_templates`](crate::AppContext::set_templates)
+//! and handlers render pages with [`res.render`](ResponseTemplates::render), which serializes the data,
+//! renders the template and sends it as HTML. Requires the `json` feature.
+//!
+//! Adapters for [MiniJinja](https://docs.rs/minijinja) and [Tera](https://docs.rs/tera) come with the
+//! `minijinja` and `tera` features. In debug builds they read the templates again before every render, so
+//! an edited template shows on the next reload of the page without restarting the server.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use feather::templates::{MiniJinja, ResponseTemplates};
+//!
+//! let mut app = App::new();
+//! app.context().set_templates(MiniJinja::new("templates"));
+//!
+//! app.get("/users/:id", middleware!(|req, res, ctx| {
+//!     let user = ctx.get_state::<Users>().find(req.param("id"))?;
+//!     res.render(ctx, "profile.html", &user)?;
+//!     next!()
+//! }));
+//! ```
+
+use crate::{AppContext, Error, Response};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Renders named templates with data, registered with [`AppContext::set_templates`](crate::AppContext::set_templates).
+///
+/// Implement it to use another template library.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// struct Handlebars(handlebars::Handlebars<'static>);
+///
+/// impl TemplateEngine for Handlebars {
+///     fn render(&self, name: &str, data: &Value) -> Result<String, Error> {
+///         Ok(self.0.render(name, data)?)
+///     }
+/// }
+/// ```
+pub trait TemplateEngine: Send + Sync + 'static {
+    /// Render the template `name` with `data`.
+    fn render(&self, name: &str, data: &Value) -> Result<String, Error>;
+}
+
+/// The engine of the app, kept in the state of the context.
+pub(crate) struct Templates(pub(crate) Arc<dyn TemplateEngine>);
+
+/// Rendering templates for [`Response`].
+pub trait ResponseTemplates {
+    /// Render the template `name` with `data` with the engine of the app and send it as HTML.
+    ///
+    /// # Errors
+    ///
+    /// When `data` doesn't serialize, or the template is missing or fails to render.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no engine was registered with [`AppContext::set_templates`](crate::AppContext::set_templates).
+    fn render<T: Serialize>(&mut self, ctx: &AppContext, name: &str, data: &T) -> Result<(), Error>;
+}
+
+impl ResponseTemplates for Response {
+    #[track_caller]
+    fn render<T: Serialize>(&mut self, ctx: &AppContext, name: &str, data: &T) -> Result<(), Error> {
+        let engine = ctx.templates();
+        let html = engine.render(name, &serde_json::to_value(data)?)?;
+        self.send_html(html);
+        Ok(())
+    }
+}
+
+/// A [TemplateEngine] backed by [MiniJinja](https://docs.rs/minijinja). Requires the `minijinja` feature.
+#[cfg(feature = "minijinja")]
+pub struct MiniJinja {
+    env: parking_lot::RwLock<minijinja::Environment<'static>>,
+    reload: bool,
+}
+
+#[cfg(feature = "minijinja")]
+impl MiniJinja {
+    /// Load the templates from the files under `dir`, named by their path relative to it, like
+    /// `users/profile.html`.
+    pub fn new(dir: impl AsRef<std::path::Path>) -> Self {
+        let mut env = minijinja::Environment::new();
+        env.set_loader(minijinja::path_loader(dir));
+        Self::from_env(env)
+    }
+
+    /// Render with an environment set up by hand, with its own filters, globals or loader.
+    pub fn from_env(env: minijinja::Environment<'static>) -> Self {
+        Self {
+            env: parking_lot::RwLock::new(env),
+            reload: cfg!(debug_assertions),
+        }
+    }
+
+    /// Whether to read the templates again before every render, on by default in debug builds.
+    pub fn hot_reload(mut self, enabled: bool) -> Self {
+        self.reload = enabled;
+        self
+    }
+}
+
+#[cfg(feature = "minijinja")]
+impl TemplateEngine for MiniJinja {
+    fn render(&self, name: &str, data: &Value) -> Result<String, Error> {
+        if self.reload {
+            // Templates are loaded again on their next use
+            self.env.write().clear_templates();
+        }
+        Ok(self.env.read().get_template(name)?.render(data)?)
+    }
+}
+
+/// A [TemplateEngine] backed by [Tera](https://docs.rs/tera). Requires the `tera` feature.
+#[cfg(feature = "tera")]
+pub struct Tera {
+    tera: parking_lot::RwLock<tera::Tera>,
+    reload: bool,
+}
+
+#[cfg(feature = "tera")]
+impl Tera {
+    /// Load the templates matching the glob `pattern`, like `templates/**/*.html`, named by their path
+    /// relative to the directory before the first wildcard.
+    ///
+    /// # Errors
+    ///
+    /// When the pattern is invalid or a template fails to parse.
+    pub fn new(pattern: &str) -> Result<Self, Error> {
+        Ok(Self::from_tera(tera::Tera::new(pattern)?))
+    }
+
+    /// Render with templates set up by hand, with their own filters or functions.
+    pub fn from_tera(tera: tera::Tera) -> Self {
+        Self {
+            tera: parking_lot::RwLock::new(tera),
+            reload: cfg!(debug_assertions),
+        }
+    }
+
+    /// Whether to read the templates again before every render, on by default in debug builds. Only
+    /// templates loaded from a glob are read again.
+    pub fn hot_reload(mut self, enabled: bool) -> Self {
+        self.reload = enabled;
+        self
+    }
+}
+
+#[cfg(feature = "tera")]
+impl TemplateEngine for Tera {
+    fn render(&self, name: &str, data: &Value) -> Result<String, Error> {
+        if self.reload {
+            self.tera.write().full_reload()?;
+        }
+        Ok(self.tera.read().render(name, &tera::Context::from_serialize(data)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestClient;
+    use crate::{App, middleware, next};
+
+    /// Replaces `{{ key }}` with the string field `key` of the data.
+    struct Placeholders;
+
+    impl TemplateEngine for Placeholders {
+        fn render(&self, name: &str, data: &Value) -> Result<String, Error> {
+            let template = match name {
+                "profile.html" => "<h1>{{ name }}</h1>",
+                _ => return Err(Error::msg(format!("template `{name}` not found"))),
+            };
+            let name = data["name"].as_str().unwrap_or_default();
+            Ok(template.replace("{{ name }}", name))
+        }
+    }
+
+    #[derive(Serialize)]
+    struct User {
+        name: &'static str,
+    }
+
+    const ADA: User = User {
+        name: "Ada",
+    };
+
+    #[test]
+    fn test_pages_are_rendered_as_html() {
+        let mut app = App::without_logger();
+        app.context().set_templates(Placeholders);
+        app.get(
+            "/profile",
+            middleware!(|res, ctx| {
+                res.render(ctx, "profile.html", &ADA)?;
+                next!()
+            }),
+        );
+        app.get(
+            "/missing",
+            middleware!(|res, ctx| {
+                res.render(ctx, "missing.html", &ADA)?;
+                next!()
+            }),
+        );
+        let client = TestClient::new(app);
+
+        let response = client.get("/profile").send();
+        assert_eq!(response.headers.get("content-type").unwrap(), "text/html");
+        assert_eq!(response.body.as_deref(), Some(&b"<h1>Ada</h1>"[..]));
+        assert_eq!(client.get("/missing").send().status.as_u16(), 500);
+    }
+}
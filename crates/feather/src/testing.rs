@@ -0,0 +1,297 @@
+//! Test the routes of an app without starting a server.
+//!
+//! A [TestClient] hands requests straight to the app's middleware and routes, in the same order a
+//! server would, and returns the [Response] for assertions. No socket is bound, so tests run fast and
+//! in parallel without racing for ports.
+//!
+//! A single middleware is tested without an app at all with [run_middleware].
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use feather::testing::TestClient;
+//!
+//! #[test]
+//! fn test_get_user() {
+//!     let client = TestClient::new(build_app());
+//!     let response = client.get("/users/1").header("Authorization", "Bearer token").send();
+//!     assert_eq!(response.status.as_u16(), 200);
+//!     assert_eq!(response.body.as_deref(), Some(&b"Ada"[..]));
+//! }
+//! ```
+
+use crate::internals::{AppService, Diagnostic};
+use crate::middlewares::{Middleware, MiddlewareResult};
+use crate::{App, AppContext};
+use bytes::Bytes;
+use feather_runtime::Method;
+use feather_runtime::http::{Request, RequestBuilder, Response};
+use feather_runtime::runtime::Service;
+use feather_runtime::runtime::service::ServiceResult;
+
+/// Run `middleware` on `request` with a new [AppContext] and return the response and what it returned.
+///
+/// The middleware runs like a global one: [handle](Middleware::handle), then its [after](Middleware::after)
+/// hook. An error is answered like an app without error handlers would, with its status, and returned as
+/// [`MiddlewareResult::End`]. A body sent with [`Response::send_reader`] is read into [`Response::body`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use feather::testing::run_middleware;
+///
+/// #[test]
+/// fn test_requests_without_token_are_rejected() {
+///     let request = Request::builder().uri("/admin").build().unwrap();
+///     let (response, result) = run_middleware(&RequireToken, request);
+///     assert_eq!(result, MiddlewareResult::End);
+///     assert_eq!(response.status.as_u16(), 401);
+/// }
+/// ```
+#[track_caller]
+pub fn run_middleware(middleware: &impl Middleware, request: Request) -> (Response, MiddlewareResult) {
+    run_middleware_with(middleware, request, &AppContext::new())
+}
+
+/// Like [run_middleware], with a context set up for the middleware, like the state it reads.
+#[track_caller]
+pub fn run_middleware_with(middleware: &impl Middleware, mut request: Request, ctx: &AppContext) -> (Response, MiddlewareResult) {
+    let mut response = Response::default();
+    let result = match middleware.handle(&mut request, &mut response, ctx) {
+        Ok(result) => result,
+        Err(e) => {
+            e.respond(&request, &mut response);
+            MiddlewareResult::End
+        }
+    };
+    middleware.after(&request, &mut response, ctx);
+    read_stream(&mut response);
+    (response, result)
+}
+
+/// Read a body sent with [`Response::send_reader`] into [`Response::body`], for assertions.
+#[track_caller]
+fn read_stream(response: &mut Response) {
+    if let Some(stream) = response.take_stream() {
+        match stream.into_bytes() {
+            Ok(body) => response.body = Some(body),
+            Err(e) => panic!("{}", Diagnostic::error("failed to read the streamed body").cause(e)),
+        }
+    }
+}
+
+/// Sends requests to an [App] in the same process.
+///
+/// The app starts like it would in a server: its [providers](App::provide) run, then the
+/// [start hooks](App::on_start), [warmup tasks](App::warmup) and job workers. Dropping the client
+/// shuts it down like a graceful shutdown would.
+pub struct TestClient {
+    service: AppService,
+}
+
+impl TestClient {
+    /// Start `app` for testing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the app can't start, e.g. because a [provider](App::provide) failed.
+    #[track_caller]
+    pub fn new(app: App) -> Self {
+        let service = match app.into_service() {
+            Ok(service) => service,
+            Err(e) => panic!("{}", Diagnostic::error("failed to start the app for testing").cause(e)),
+        };
+        service.on_start();
+        Self {
+            service,
+        }
+    }
+
+    /// The context of the app, to set up or check its state.
+    pub fn context(&self) -> &AppContext {
+        &self.service.context
+    }
+
+    /// Start a request with `method` to `path`, which can have a query string.
+    pub fn request(&self, method: Method, path: &str) -> TestRequest<'_> {
+        TestRequest {
+            client: self,
+            builder: Request::builder().method(method).uri(path).header("Host", "localhost"),
+        }
+    }
+
+    /// Start a `GET` request to `path`.
+    pub fn get(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::GET, path)
+    }
+
+    /// Start a `POST` request to `path`.
+    pub fn post(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::POST, path)
+    }
+
+    /// Start a `PUT` request to `path`.
+    pub fn put(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::PUT, path)
+    }
+
+    /// Start a `PATCH` request to `path`.
+    pub fn patch(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::PATCH, path)
+    }
+
+    /// Start a `DELETE` request to `path`.
+    pub fn delete(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::DELETE, path)
+    }
+}
+
+impl Drop for TestClient {
+    fn drop(&mut self) {
+        self.service.on_shutdown();
+    }
+}
+
+/// A request being built by a [TestClient], sent with [send](Self::send).
+///
+/// The request is a [RequestBuilder] underneath, an invalid path or header fails the test when it's sent.
+pub struct TestRequest<'a> {
+    client: &'a TestClient,
+    builder: RequestBuilder,
+}
+
+impl TestRequest<'_> {
+    /// Add a header, keeping the ones with the same name.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.builder = self.builder.header(name, value);
+        self
+    }
+
+    /// Set the body.
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.builder = self.builder.body(body);
+        self
+    }
+
+    /// Set the body to `value` as JSON, with its `Content-Type`. Requires the `json` feature.
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize>(mut self, value: &T) -> Self {
+        self.builder = self.builder.json(value);
+        self
+    }
+
+    /// Send the request through the app and return its response, with a body sent with
+    /// [`Response::send_reader`] read into [`Response::body`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a part of the request is invalid, or if the app takes the connection over like WebSocket
+    /// routes do.
+    #[track_caller]
+    pub fn send(self) -> Response {
+        let request = match self.builder.build() {
+            Ok(request) => request,
+            Err(e) => panic!("{}", Diagnostic::error("invalid test request").cause(e)),
+        };
+        let is_head = request.method == Method::HEAD;
+        let mut response = match self.client.service.handle(request, None) {
+            Ok(ServiceResult::Response(response)) if !response.is_hijacked() => response,
+            Ok(_) => panic!(
+                "{}",
+                Diagnostic::error("the app took over the test connection").help("upgraded connections like WebSockets need a server, see `App::start`")
+            ),
+            Err(e) => panic!("{}", Diagnostic::error("the app failed to handle the test request").cause(e)),
+        };
+        read_stream(&mut response);
+        // Like the server, HEAD gets the headers of the GET response but no body
+        if is_head {
+            response.body = None;
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internals::Finalizer;
+    use crate::middlewares::builtins::Cors;
+    use crate::{end, middleware, next};
+
+    #[test]
+    fn test_requests_go_through_middleware_and_routes() {
+        let mut app = App::without_logger();
+        app.on_start(|ctx| ctx.set_state(String::from("Ada")));
+        app.use_middleware(middleware!(|req, res, _ctx| {
+            if req.headers.get("authorization").is_none() {
+                res.set_status(401);
+                return crate::end!();
+            }
+            next!()
+        }));
+        app.get(
+            "/users/:id",
+            middleware!(|req, res, ctx| {
+                let id = req.param("id").unwrap_or_default();
+                res.finish_text(format!("{} {id}", ctx.get_state::<String>()))
+            }),
+        );
+        app.post(
+            "/echo",
+            middleware!(|req, res, _ctx| {
+                res.add_header(
+                    "Content-Type",
+                    req.headers.get("content-type").map_or("", |value| value.to_str().unwrap_or_default()),
+                )?;
+                res.send_bytes(req.body.to_vec());
+                next!()
+            }),
+        );
+        let client = TestClient::new(app);
+
+        let response = client.get("/users/1").header("Authorization", "Bearer token").send();
+        assert_eq!(response.status.as_u16(), 200);
+        assert_eq!(response.body.as_deref(), Some(&b"Ada 1"[..]));
+        assert_eq!(client.get("/users/1").send().status.as_u16(), 401);
+        assert_eq!(client.get("/missing").header("Authorization", "Bearer token").send().status.as_u16(), 404);
+
+        let response = client.post("/echo").header("Authorization", "Bearer token").header("Content-Type", "application/json").body(r#"{"name":"Ada"}"#).send();
+        assert_eq!(response.headers.get("content-type").unwrap(), "application/json");
+        assert_eq!(response.body.as_deref(), Some(&br#"{"name":"Ada"}"#[..]));
+    }
+
+    #[test]
+    fn test_middleware_run_without_an_app() {
+        let guard = middleware!(|req, res, ctx| {
+            if req.headers.get("authorization").is_none() {
+                res.set_status(401);
+                return end!();
+            }
+            req.extensions.insert(ctx.get_state::<String>().to_string());
+            next!()
+        });
+        let ctx = AppContext::new();
+        ctx.set_state(String::from("Ada"));
+
+        let request = Request::builder().uri("/admin").header("Authorization", "Bearer token").build().unwrap();
+        let (response, result) = run_middleware_with(&guard, request, &ctx);
+        assert_eq!(result, MiddlewareResult::Next);
+        assert_eq!(response.status.as_u16(), 200);
+        let (response, result) = run_middleware_with(&guard, Request::builder().uri("/admin").build().unwrap(), &ctx);
+        assert_eq!(result, MiddlewareResult::End);
+        assert_eq!(response.status.as_u16(), 401);
+
+        let failing = middleware!(|_req, _res, _ctx| Err(crate::HttpError::forbidden("admins only").into()));
+        let (response, result) = run_middleware(&failing, Request::builder().uri("/admin").build().unwrap());
+        assert_eq!(result, MiddlewareResult::End);
+        assert_eq!(response.status.as_u16(), 403);
+        assert_eq!(response.body.as_deref(), Some(&b"admins only"[..]));
+
+        let preflight = Request::builder().method(Method::OPTIONS).uri("/admin").header("Origin", "https://example.com").build().unwrap();
+        // Cors::default() allows every origin in dev only, FEATHER_ENV mustn't decide the outcome
+        let mut ctx = AppContext::new();
+        ctx.set_profile(crate::Profile::Dev);
+        let (response, _) = run_middleware_with(&Cors::default(), preflight, &ctx);
+        assert_eq!(response.headers.get("access-control-allow-origin").unwrap(), "*");
+    }
+}
@@ -0,0 +1,755 @@
+//! WebSocket routes. Requires the `ws` feature.
+//!
+//! [`App::ws`](crate::App::ws) registers a handler that receives the socket together with the upgrade
+//! request and the [`AppContext`], so it can authenticate the client and reach shared state like a normal route.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use feather::ws::Message;
+//!
+//! app.ws("/chat/:room", |mut socket, req, ctx| {
+//!     let room = req.param("room").unwrap_or("lobby").to_string();
+//!     let history = ctx.get_state::<State<History>>();
+//!     while let Some(message) = socket.recv()? {
+//!         if let Message::Text(text) = message {
+//!             history.lock().push(&room, &text);
+//!             socket.send_text(&text)?;
+//!         }
+//!     }
+//!     Ok(())
+//! });
+//! ```
+//!
+//! # Rooms
+//!
+//! Every socket of an [`App::ws`](crate::App::ws) route is tracked by the [`WsHub`] of
+//! [`AppContext::ws_hub`] until its handler returns. Put sockets in rooms to message them together:
+//!
+//! ```rust,ignore
+//! app.ws("/chat", |mut socket, _req, ctx| {
+//!     let hub = ctx.ws_hub();
+//!     hub.join(socket.id(), "lobby");
+//!     while let Some(message) = socket.recv()? {
+//!         if let Message::Text(_) = message {
+//!             hub.to_room("lobby").except(socket.id()).send(message);
+//!         }
+//!     }
+//!     Ok(())
+//! });
+//! ```
+//!
+//! # Monitoring
+//!
+//! The hub keeps the [`Metrics`](crate::Metrics) gauges `ws_connections{route="/chat/:room"}` and
+//! `ws_room_clients{room="lobby"}` up to date, and [`WsHub::connections`] and [`WsHub::close`] list
+//! and drop clients from an admin route.
+
+pub use feather_runtime::runtime::ShutdownSignal;
+pub use feather_runtime::ws::{CloseFrame, Heartbeat, Message, Overflow, TrySendError, WebSocket, WsSender, accept_key};
+
+#[cfg(feature = "jwt")]
+use crate::jwt::{Claim, JwtFailure};
+use crate::middlewares::Middleware;
+#[cfg(feature = "jwt")]
+use crate::middlewares::builtins::Authenticated;
+use crate::{AppContext, Metrics, Outcome, Request, Response};
+use parking_lot::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+#[cfg(feature = "jwt")]
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+type Listener = Arc<dyn Fn(&HubEvent) + Send + Sync>;
+
+/// The pattern of a WebSocket route its connections are counted under, updated when its router is mounted.
+#[derive(Clone)]
+pub(crate) struct RouteLabel(Arc<RwLock<String>>);
+
+impl RouteLabel {
+    pub(crate) fn new(path: &str) -> Self {
+        Self(Arc::new(RwLock::new(path.to_string())))
+    }
+
+    pub(crate) fn set(&self, path: &str) {
+        *self.0.write() = path.to_string();
+    }
+}
+
+/// The route behind [`App::ws`](crate::App::ws): answers the handshake and hands the socket to the handler.
+pub(crate) struct WsRoute<F>(pub(crate) Arc<F>, pub(crate) RouteLabel);
+
+impl<F> Middleware for WsRoute<F>
+where
+    F: Fn(WebSocket, &Request, &AppContext) -> Result<(), Box<dyn Error>> + Send + Sync + 'static,
+{
+    fn handle(&self, request: &mut Request, response: &mut Response, ctx: &AppContext) -> Outcome {
+        let handler = self.0.clone();
+        upgrade(request, response, ctx, &self.1, move |socket, upgrade, ctx| handler(socket, upgrade, ctx))
+    }
+}
+
+/// The route behind [`App::ws_protected`](crate::App::ws_protected): refuses the upgrade with `401`
+/// unless the client sends a valid token.
+#[cfg(feature = "jwt")]
+pub(crate) struct ProtectedWsRoute<C, F>(pub(crate) Arc<F>, pub(crate) RouteLabel, pub(crate) PhantomData<fn() -> C>);
+
+#[cfg(feature = "jwt")]
+impl<C, F> Middleware for ProtectedWsRoute<C, F>
+where
+    C: Claim + Send + 'static,
+    F: Fn(WebSocket, &Request, &AppContext, C) -> Result<(), Box<dyn Error>> + Send + Sync + 'static,
+{
+    fn handle(&self, request: &mut Request, response: &mut Response, ctx: &AppContext) -> Outcome {
+        let claims = match token(request).map(|token| ctx.jwt().decode::<C>(&token)) {
+            Some(Ok(claims)) => claims,
+            Some(Err(e)) => {
+                ctx.jwt().reject(request, response, JwtFailure::InvalidToken(e));
+                return crate::end!();
+            }
+            None => {
+                ctx.jwt().reject(request, response, JwtFailure::MissingToken);
+                return crate::end!();
+            }
+        };
+        request.extensions.insert(Authenticated);
+        let handler = self.0.clone();
+        upgrade(request, response, ctx, &self.1, move |socket, upgrade, ctx| handler(socket, upgrade, ctx, claims))
+    }
+}
+
+/// The token of the `Authorization: Bearer` header, or of the `token` query parameter for browsers,
+/// which can't set headers on WebSockets.
+#[cfg(feature = "jwt")]
+fn token(request: &Request) -> Option<String> {
+    match request.headers.get("Authorization").and_then(|h| h.to_str().ok()).and_then(|h| h.strip_prefix("Bearer ")) {
+        Some(token) => Some(token.to_string()),
+        None => request.query().ok()?.remove("token"),
+    }
+}
+
+/// Answers the handshake and runs `handler` with the socket, tracked by the hub.
+fn upgrade<H>(request: &mut Request, response: &mut Response, ctx: &AppContext, route: &RouteLabel, handler: H) -> Outcome
+where
+    H: FnOnce(WebSocket, &Request, &AppContext) -> Result<(), Box<dyn Error>> + Send + 'static,
+{
+    let (upgrade, ctx, route) = (request.clone(), ctx.clone(), route.0.read().clone());
+    // Refused handshakes are already answered with the error
+    feather_runtime::ws::accept(request, response, move |mut socket| {
+        if let Some(frame) = ctx.ws_hub().shutdown_close.read().clone() {
+            socket.set_shutdown_close(frame.code, &frame.reason);
+        }
+        let _registration = Registration::new(ctx.ws_hub(), &socket, route, upgrade.remote_addr());
+        if let Err(e) = handler(socket, &upgrade, &ctx) {
+            #[cfg(feature = "log")]
+            log::warn!("WebSocket handler for {upgrade} failed: {e}");
+            #[cfg(not(feature = "log"))]
+            drop(e);
+        }
+    });
+    crate::end!()
+}
+
+/// Keeps a socket in the hub while its handler runs, even if it panics.
+struct Registration<'a>(&'a WsHub, u64);
+
+impl<'a> Registration<'a> {
+    fn new(hub: &'a WsHub, socket: &WebSocket, route: String, peer: SocketAddr) -> Self {
+        hub.connect(socket, route, peer);
+        Self(hub, socket.id())
+    }
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.0.disconnect(self.1);
+    }
+}
+
+/// A connected client of the [`WsHub`], from [`WsHub::connections`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WsConnection {
+    /// The [`WebSocket::id`] of the client.
+    pub id: u64,
+    /// The pattern of the route it connected to, like `/chat/:room`.
+    pub route: String,
+    pub peer: SocketAddr,
+    pub connected_at: SystemTime,
+    /// The rooms it's in, sorted.
+    pub rooms: Vec<String>,
+}
+
+/// A change in the [`WsHub`], see [`WsHub::on_event`]. Clients are named by their [`WebSocket::id`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HubEvent {
+    /// A socket was accepted, before its handler runs.
+    Connected(u64),
+    /// The handler of a socket returned, after the socket left its rooms.
+    Disconnected(u64),
+    Joined {
+        client: u64,
+        room: String,
+    },
+    Left {
+        client: u64,
+        room: String,
+    },
+}
+
+/// The connected sockets of the [`App::ws`](crate::App::ws) routes and the rooms they're in.
+///
+/// Sockets are added when accepted and removed with their rooms when their handler returns. Sending
+/// goes through each socket's [`WsSender::try_send`], so it works while the handlers wait in [`WebSocket::recv`]
+/// and a client that doesn't read can't hold up the others, its [`Overflow`] policy applies instead.
+/// Clients that can't be reached are skipped, the sending methods return how many got the message queued.
+///
+/// The hub of [`AppContext::ws_hub`] counts the clients of each route and room in the app's [`Metrics`],
+/// see the [module docs](self#monitoring).
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let hub = ctx.ws_hub();
+/// hub.on_event(|event| {
+///     if let HubEvent::Joined { client, room } = event {
+///         log::info!("{client} joined {room}");
+///     }
+/// });
+/// hub.broadcast(Message::Text("Maintenance in 5 minutes".into()));
+/// ```
+#[derive(Clone, Default)]
+pub struct WsHub {
+    clients: Arc<RwLock<Clients>>,
+    listeners: Arc<RwLock<Vec<Listener>>>,
+    shutdown_close: Arc<RwLock<Option<CloseFrame>>>,
+    metrics: Metrics,
+}
+
+#[derive(Default)]
+struct Clients {
+    clients: HashMap<u64, Client>,
+    /// Rooms without members are removed
+    rooms: HashMap<String, HashSet<u64>>,
+}
+
+struct Client {
+    sender: WsSender,
+    route: String,
+    peer: SocketAddr,
+    connected_at: SystemTime,
+}
+
+impl WsHub {
+    /// A hub counting its clients in `metrics`.
+    pub(crate) fn with_metrics(metrics: Metrics) -> Self {
+        Self {
+            metrics,
+            ..Self::default()
+        }
+    }
+
+    /// Call `listener` on every connect, disconnect, join and leave.
+    ///
+    /// Listeners run on the coroutine that caused the event, after the hub is updated, so they can use the hub.
+    pub fn on_event(&self, listener: impl Fn(&HubEvent) + Send + Sync + 'static) {
+        self.listeners.write().push(Arc::new(listener));
+    }
+
+    /// Send `message` to every connected client.
+    pub fn broadcast(&self, message: Message) -> usize {
+        let senders: Vec<WsSender> = self.clients.read().clients.values().map(|client| client.sender.clone()).collect();
+        send_all(senders, message)
+    }
+
+    /// The clients in `room`, to send to them.
+    pub fn to_room(&self, room: &str) -> Room<'_> {
+        Room {
+            hub: self,
+            name: room.to_string(),
+            except: None,
+        }
+    }
+
+    /// The sender of a single client, `None` if it isn't connected.
+    pub fn client(&self, client: u64) -> Option<WsSender> {
+        self.clients.read().clients.get(&client).map(|client| client.sender.clone())
+    }
+
+    /// Every connected client, sorted by id.
+    ///
+    /// ```rust,ignore
+    /// app.get("/admin/sockets", middleware!(|_req, res, ctx| {
+    ///     let lines: Vec<String> = ctx.ws_hub().connections().iter().map(|c| format!("{} {} {}", c.id, c.peer, c.route)).collect();
+    ///     res.send_text(lines.join("\n"));
+    ///     next!()
+    /// }));
+    /// ```
+    pub fn connections(&self) -> Vec<WsConnection> {
+        let clients = self.clients.read();
+        let mut connections: Vec<WsConnection> = clients
+            .clients
+            .iter()
+            .map(|(id, client)| WsConnection {
+                id: *id,
+                route: client.route.clone(),
+                peer: client.peer,
+                connected_at: client.connected_at,
+                rooms: Vec::new(),
+            })
+            .collect();
+        connections.sort_by_key(|connection| connection.id);
+        for (room, members) in &clients.rooms {
+            for connection in connections.iter_mut().filter(|connection| members.contains(&connection.id)) {
+                connection.rooms.push(room.clone());
+            }
+        }
+        for connection in &mut connections {
+            connection.rooms.sort();
+        }
+        connections
+    }
+
+    /// Close the connection of `client` with `code` and `reason`, `false` if it isn't connected or already closing.
+    ///
+    /// The close frame is queued like [`WsSender::try_send`], so a client that stopped reading is dropped by its
+    /// [`Overflow`] policy or its heartbeat instead of holding up the caller. Its handler sees the connection end
+    /// in [`WebSocket::recv`] and the client leaves the hub when the handler returns.
+    pub fn close(&self, client: u64, code: u16, reason: &str) -> bool {
+        let Some(sender) = self.client(client) else {
+            return false;
+        };
+        let frame = CloseFrame {
+            code,
+            reason: reason.to_string(),
+        };
+        match sender.try_send(Message::Close(Some(frame))) {
+            Ok(()) | Err(TrySendError::Overflowed) => true,
+            Err(TrySendError::Closed | TrySendError::TooLarge) => false,
+        }
+    }
+
+    /// Add `client` to `room`, creating the room. `false` if the client isn't connected or already in the room.
+    pub fn join(&self, client: u64, room: &str) -> bool {
+        let joined = {
+            let mut clients = self.clients.write();
+            let joined = clients.clients.contains_key(&client) && clients.rooms.entry(room.to_string()).or_default().insert(client);
+            if joined {
+                // Under the lock, a concurrent leave emptying the room removes the gauge
+                self.metrics.gauge(&room_gauge(room)).increment();
+            }
+            joined
+        };
+        if joined {
+            self.emit(HubEvent::Joined {
+                client,
+                room: room.to_string(),
+            });
+        }
+        joined
+    }
+
+    /// Remove `client` from `room`. `false` if it wasn't in it.
+    pub fn leave(&self, client: u64, room: &str) -> bool {
+        let left = {
+            let mut clients = self.clients.write();
+            let (left, emptied) = clients.remove_from(client, room);
+            if emptied {
+                self.metrics.remove_gauge(&room_gauge(room));
+            } else if left {
+                self.metrics.gauge(&room_gauge(room)).decrement();
+            }
+            left
+        };
+        if left {
+            self.emit(HubEvent::Left {
+                client,
+                room: room.to_string(),
+            });
+        }
+        left
+    }
+
+    /// Change the close frame sockets accepted from now on send when the server shuts down,
+    /// see [`WebSocket::set_shutdown_close`].
+    pub fn set_shutdown_close(&self, code: u16, reason: &str) {
+        *self.shutdown_close.write() = Some(CloseFrame {
+            code,
+            reason: reason.to_string(),
+        });
+    }
+
+    /// The rooms `client` is in.
+    pub fn rooms_of(&self, client: u64) -> Vec<String> {
+        self.clients.read().rooms.iter().filter(|(_, members)| members.contains(&client)).map(|(room, _)| room.clone()).collect()
+    }
+
+    /// How many clients are connected.
+    pub fn len(&self) -> usize {
+        self.clients.read().clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn connect(&self, socket: &WebSocket, route: String, peer: SocketAddr) {
+        self.metrics.gauge(&route_gauge(&route)).increment();
+        let client = Client {
+            sender: socket.sender(),
+            route,
+            peer,
+            connected_at: SystemTime::now(),
+        };
+        self.clients.write().clients.insert(socket.id(), client);
+        self.emit(HubEvent::Connected(socket.id()));
+    }
+
+    fn disconnect(&self, client: u64) {
+        for room in self.rooms_of(client) {
+            self.leave(client, &room);
+        }
+        let removed = self.clients.write().clients.remove(&client);
+        if let Some(removed) = removed {
+            self.metrics.gauge(&route_gauge(&removed.route)).decrement();
+            self.emit(HubEvent::Disconnected(client));
+        }
+    }
+
+    fn emit(&self, event: HubEvent) {
+        // Listeners may add listeners
+        let listeners = self.listeners.read().clone();
+        for listener in listeners {
+            listener(&event);
+        }
+    }
+}
+
+impl Clients {
+    /// Whether `client` was in `room` and whether that emptied the room.
+    fn remove_from(&mut self, client: u64, room: &str) -> (bool, bool) {
+        let Some(members) = self.rooms.get_mut(room) else {
+            return (false, false);
+        };
+        let removed = members.remove(&client);
+        let emptied = members.is_empty();
+        if emptied {
+            self.rooms.remove(room);
+        }
+        (removed, emptied)
+    }
+}
+
+/// The clients of a room, from [`WsHub::to_room`].
+pub struct Room<'a> {
+    hub: &'a WsHub,
+    name: String,
+    except: Option<u64>,
+}
+
+impl Room<'_> {
+    /// Leave `client` out, usually the one whose message is being relayed.
+    pub fn except(mut self, client: u64) -> Self {
+        self.except = Some(client);
+        self
+    }
+
+    /// Send `message` to the clients of the room.
+    pub fn send(&self, message: Message) -> usize {
+        send_all(self.senders(), message)
+    }
+
+    /// The ids of the clients in the room.
+    pub fn clients(&self) -> Vec<u64> {
+        let clients = self.hub.clients.read();
+        clients.rooms.get(&self.name).map_or_else(Vec::new, |members| members.iter().copied().filter(|id| Some(*id) != self.except).collect())
+    }
+
+    fn senders(&self) -> Vec<WsSender> {
+        let clients = self.hub.clients.read();
+        let Some(members) = clients.rooms.get(&self.name) else {
+            return Vec::new();
+        };
+        members.iter().filter(|id| Some(**id) != self.except).filter_map(|id| clients.clients.get(id).map(|client| client.sender.clone())).collect()
+    }
+}
+
+fn route_gauge(route: &str) -> String {
+    format!("ws_connections{{route=\"{route}\"}}")
+}
+
+fn room_gauge(room: &str) -> String {
+    format!("ws_room_clients{{room=\"{room}\"}}")
+}
+
+/// Queues `message` for every sender, called outside the hub's lock.
+fn send_all(senders: Vec<WsSender>, message: Message) -> usize {
+    senders.iter().filter(|sender| sender.try_send(message.clone()).is_ok()).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HubEvent, Message};
+    use crate::{App, AppContext, Request, Response, Router};
+    use parking_lot::Mutex;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    const UPGRADE: &str = "Upgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n";
+
+    /// Opens a WebSocket on `path` and returns the stream after the `101` head.
+    fn open(addr: SocketAddr, path: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n{UPGRADE}").as_bytes()).unwrap();
+        let mut head = Vec::new();
+        let mut byte = [0u8];
+        while !head.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        assert!(head.starts_with(b"HTTP/1.1 101"), "{}", String::from_utf8_lossy(&head));
+        stream
+    }
+
+    /// Sends a short masked text frame.
+    fn send_text(stream: &mut TcpStream, text: &str) {
+        let mut frame = vec![0x81, 0x80 | text.len() as u8, 0, 0, 0, 0];
+        frame.extend_from_slice(text.as_bytes());
+        stream.write_all(&frame).unwrap();
+    }
+
+    /// Reads a short text frame.
+    fn read_text(stream: &mut TcpStream) -> String {
+        let mut head = [0u8; 2];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(head[0], 0x81);
+        let mut payload = vec![0u8; head[1] as usize];
+        stream.read_exact(&mut payload).unwrap();
+        String::from_utf8(payload).unwrap()
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        for _ in 0..500 {
+            if condition() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("timed out");
+    }
+
+    /// Opens a WebSocket on `path` and returns the first text message the server sends before closing.
+    fn first_message(addr: SocketAddr, path: &str, headers: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n{headers}{UPGRADE}");
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).unwrap();
+        assert!(received.starts_with(b"HTTP/1.1 101"), "{}", String::from_utf8_lossy(&received));
+        let head_end = received.windows(4).position(|window| window == b"\r\n\r\n").unwrap() + 4;
+        let frame = &received[head_end..];
+        assert_eq!(frame[0], 0x81);
+        let len = frame[1] as usize;
+        assert_eq!(frame[2 + len], 0x88, "the server closes after the message");
+        String::from_utf8(frame[2..2 + len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_ws_handler_sees_request_and_context() {
+        let mut app = App::without_logger();
+        app.context().set_state(String::from("welcome"));
+        app.ws("/rooms/:room", |mut socket, req, ctx| {
+            let user = req.headers.get("x-user").and_then(|user| user.to_str().ok()).unwrap_or("anonymous");
+            socket.send_text(&format!("{} {user} to {}", ctx.get_state::<String>(), req.param("room").unwrap()))?;
+            socket.close(1000, "")?;
+            Ok(())
+        });
+        let handle = app.start("127.0.0.1:0").unwrap();
+
+        assert_eq!(first_message(handle.local_addr().unwrap(), "/rooms/rust", "X-User: ada\r\n"), "welcome ada to rust");
+
+        handle.shutdown_and_wait().unwrap();
+    }
+
+    #[test]
+    fn test_mounted_ws_route_sees_prefix_params_and_middleware() {
+        let mut router = Router::new();
+        router.use_middleware(|req: &mut Request, _: &mut Response, _: &AppContext| -> crate::Outcome {
+            req.extensions.insert("tagged");
+            crate::next!()
+        });
+        router.ws("/rooms/:room", |mut socket, req, _ctx| {
+            let tag = req.extensions.get::<&str>().copied().unwrap_or("untagged");
+            socket.send_text(&format!("{}/{} {tag}", req.param("tenant").unwrap(), req.param("room").unwrap()))?;
+            socket.close(1000, "")?;
+            Ok(())
+        });
+        let mut app = App::without_logger();
+        app.mount("/tenants/:tenant", router);
+        let handle = app.start("127.0.0.1:0").unwrap();
+
+        assert_eq!(
+            first_message(handle.local_addr().unwrap(), "/tenants/acme/rooms/general", ""),
+            "acme/general tagged"
+        );
+
+        handle.shutdown_and_wait().unwrap();
+    }
+
+    #[test]
+    fn test_hub_relays_to_room_and_reports_events() {
+        let mut app = App::without_logger();
+        let ctx = app.context().clone();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        ctx.ws_hub().on_event(move |event| recorded.lock().push(event.clone()));
+        app.ws("/chat/:room", |mut socket, req, ctx| {
+            let (hub, room) = (ctx.ws_hub(), req.param("room").unwrap());
+            hub.join(socket.id(), room);
+            while let Some(message) = socket.recv()? {
+                if let Message::Text(_) = message {
+                    hub.to_room(room).except(socket.id()).send(message);
+                }
+            }
+            Ok(())
+        });
+        let handle = app.start("127.0.0.1:0").unwrap();
+        let addr = handle.local_addr().unwrap();
+
+        let mut ada = open(addr, "/chat/lobby");
+        let mut bob = open(addr, "/chat/lobby");
+        let mut eve = open(addr, "/chat/kitchen");
+        wait_until(|| ctx.ws_hub().to_room("lobby").clients().len() == 2 && ctx.ws_hub().to_room("kitchen").clients().len() == 1);
+
+        send_text(&mut ada, "hi bob");
+        assert_eq!(read_text(&mut bob), "hi bob");
+        assert_eq!(ctx.ws_hub().broadcast(Message::Text("bye".into())), 3);
+        for stream in [&mut ada, &mut bob, &mut eve] {
+            assert_eq!(read_text(stream), "bye");
+        }
+
+        drop(ada);
+        wait_until(|| ctx.ws_hub().len() == 2);
+        let events = events.lock().clone();
+        let ada_id = match events.last() {
+            Some(HubEvent::Disconnected(id)) => *id,
+            _ => panic!("{events:?}"),
+        };
+        let left = HubEvent::Left {
+            client: ada_id,
+            room: "lobby".into(),
+        };
+        assert!(events.ends_with(&[left, HubEvent::Disconnected(ada_id)]), "{events:?}");
+        assert_eq!(events.iter().filter(|event| matches!(event, HubEvent::Joined { .. })).count(), 3);
+        assert_eq!(ctx.ws_hub().rooms_of(ada_id), Vec::<String>::new());
+
+        drop((bob, eve));
+        handle.shutdown_and_wait().unwrap();
+    }
+
+    #[test]
+    fn test_hub_counts_lists_and_closes_connections() {
+        let mut router = Router::new();
+        router.ws("/rooms/:room", |mut socket, req, ctx| {
+            ctx.ws_hub().join(socket.id(), req.param("room").unwrap());
+            while socket.recv()?.is_some() {}
+            Ok(())
+        });
+        let mut app = App::without_logger();
+        app.mount("/chat", router);
+        let ctx = app.context().clone();
+        let handle = app.start("127.0.0.1:0").unwrap();
+        let addr = handle.local_addr().unwrap();
+        let gauge = |name: &str| ctx.metrics().snapshot().into_iter().find(|(metric, _)| metric == name).map(|(_, value)| value);
+
+        let mut ada = open(addr, "/chat/rooms/lobby");
+        let bob = open(addr, "/chat/rooms/lobby");
+        wait_until(|| gauge(r#"ws_room_clients{room="lobby"}"#) == Some(2));
+        assert_eq!(gauge(r#"ws_connections{route="/chat/rooms/:room"}"#), Some(2));
+        let connections = ctx.ws_hub().connections();
+        assert_eq!(connections.len(), 2);
+        assert!(connections.iter().all(|connection| connection.route == "/chat/rooms/:room" && connection.rooms == ["lobby"]));
+        let ada_id = connections.iter().find(|connection| connection.peer == ada.local_addr().unwrap()).unwrap().id;
+
+        assert!(ctx.ws_hub().close(ada_id, 4000, "kicked"));
+        let mut frame = [0u8; 10];
+        ada.read_exact(&mut frame).unwrap();
+        assert_eq!((frame[0], &frame[2..4], &frame[4..]), (0x88, &4000u16.to_be_bytes()[..], &b"kicked"[..]));
+        ada.write_all(&[0x88, 0x80, 0, 0, 0, 0]).unwrap();
+        wait_until(|| ctx.ws_hub().len() == 1);
+        assert!(!ctx.ws_hub().close(ada_id, 4000, "kicked"));
+        assert_eq!(gauge(r#"ws_connections{route="/chat/rooms/:room"}"#), Some(1));
+        assert_eq!(gauge(r#"ws_room_clients{room="lobby"}"#), Some(1));
+
+        drop((ada, bob));
+        wait_until(|| ctx.ws_hub().is_empty());
+        assert_eq!(gauge(r#"ws_connections{route="/chat/rooms/:room"}"#), Some(0));
+        assert_eq!(gauge(r#"ws_room_clients{room="lobby"}"#), None);
+        handle.shutdown_and_wait().unwrap();
+    }
+
+    #[cfg(feature = "jwt")]
+    #[test]
+    fn test_protected_ws_route_requires_a_valid_token() {
+        use crate::jwt::{JwtManager, SimpleClaims};
+
+        let jwt = JwtManager::new("a-secret-that-is-at-least-32-bytes-long".to_string());
+        let token = jwt.generate_simple("ada", 1).unwrap();
+        let mut app = App::without_logger();
+        app.context().set_jwt(jwt);
+        app.ws_protected("/feed", |mut socket, _req, _ctx, claims: SimpleClaims| {
+            socket.send_text(&format!("hello {}", claims.sub))?;
+            socket.close(1000, "")?;
+            Ok(())
+        });
+        let handle = app.start("127.0.0.1:0").unwrap();
+        let addr = handle.local_addr().unwrap();
+
+        assert_eq!(first_message(addr, "/feed", &format!("Authorization: Bearer {token}\r\n")), "hello ada");
+        assert_eq!(first_message(addr, &format!("/feed?token={token}"), ""), "hello ada");
+        for path in ["/feed", "/feed?token=forged"] {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n{UPGRADE}").as_bytes()).unwrap();
+            let mut response = [0u8; 1024];
+            let n = stream.read(&mut response).unwrap();
+            assert!(response.starts_with(b"HTTP/1.1 401"), "{}", String::from_utf8_lossy(&response[..n]));
+        }
+
+        handle.shutdown_and_wait().unwrap();
+    }
+
+    #[test]
+    fn test_shutdown_sends_configured_close_frame() {
+        let mut app = App::without_logger();
+        let ctx = app.context().clone();
+        ctx.ws_hub().set_shutdown_close(1012, "restarting");
+        let signalled = Arc::new(AtomicBool::new(false));
+        let seen = signalled.clone();
+        app.ws("/live", move |mut socket, _req, _ctx| {
+            while socket.recv()?.is_some() {}
+            seen.store(socket.shutdown_signal().is_triggered(), Ordering::SeqCst);
+            Ok(())
+        });
+        let handle = app.start("127.0.0.1:0").unwrap();
+        let mut stream = open(handle.local_addr().unwrap(), "/live");
+        wait_until(|| ctx.ws_hub().len() == 1);
+
+        let shutdown = std::thread::spawn(move || handle.shutdown_and_wait());
+        let mut head = [0u8; 2];
+        stream.read_exact(&mut head).unwrap();
+        let mut payload = vec![0u8; head[1] as usize];
+        stream.read_exact(&mut payload).unwrap();
+        assert_eq!((head[0], &payload[..2], &payload[2..]), (0x88, &1012u16.to_be_bytes()[..], &b"restarting"[..]));
+        stream.write_all(&[0x88, 0x80, 0, 0, 0, 0]).unwrap();
+
+        shutdown.join().unwrap().unwrap();
+        assert!(signalled.load(Ordering::SeqCst));
+        assert!(ctx.ws_hub().is_empty());
+    }
+}
@@ -2,7 +2,6 @@
 /// NOTE: This example requires the SQLite installed on your system.
 // Import Our Dependencies
 use feather::{App, info, middleware_fn, next, warn};
-use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use r2d2_sqlite::rusqlite::Result;
 use serde_json::json;
@@ -10,11 +9,12 @@ use serde_json::json;
 fn main() -> Result<()> {
     // Create a new App
     let mut app = App::new();
-    let manager = SqliteConnectionManager::file(":memory:");
-    let pool: Pool<SqliteConnectionManager> = r2d2::Pool::new(manager).unwrap();
+    // Every connection to `:memory:` would open its own database, so the connections share one by name
+    let manager = SqliteConnectionManager::file("file:people?mode=memory&cache=shared");
+    app.with_pool(manager, 8); // Store a pool of 8 connections inside of our context
 
     // Create a person table
-    pool.get().unwrap().execute(
+    app.context().db::<SqliteConnectionManager>().unwrap().execute(
         "
     CREATE TABLE person (
         id    INTEGER PRIMARY KEY,
@@ -22,9 +22,7 @@ fn main() -> Result<()> {
     )",
         [],
     )?;
-    app.context().set_state(pool); // Store the connection inside of our context
-
-    // from now on conn is only accesible inside the context
+    // from now on connections are checked out of the context
     app.post("/login", login);
 
     app.get("/user", get_user);
@@ -49,8 +47,7 @@ fn login() -> Outcome {
         }
     };
 
-    let db = ctx.get_state::<Pool<SqliteConnectionManager>>();
-    let conn = db.get().unwrap(); // Keep connection alive
+    let conn = ctx.db::<SqliteConnectionManager>()?; // Returned to the pool when dropped
     match conn.execute("INSERT INTO person (name) VALUES (?1)", [username]) {
         Ok(rows_changed) => res.set_status(200).send_json(&json!({
             "success": true,
@@ -66,8 +63,7 @@ fn login() -> Outcome {
 // Get Route for listing users
 #[middleware_fn]
 fn get_user() -> Outcome {
-    let db = ctx.get_state::<Pool<SqliteConnectionManager>>();
-    let conn = db.get().unwrap(); // Keep connection alive
+    let conn = ctx.db::<SqliteConnectionManager>()?;
     let mut stmt = conn.prepare("SELECT name FROM person")?;
     let users = stmt.query_map([], |row| row.get::<_, String>(0))?.filter_map(Result::ok).collect::<Vec<_>>();
     res.set_status(200).send_json(&json!({ "users": users }));
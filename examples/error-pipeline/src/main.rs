@@ -14,13 +14,15 @@ fn main() {
     );
 
     // if there is no Custom Error handler set Framework will catch the error log it and send a 500 back to the client
-    // We can attach a custom error handler with this function
+    // We can attach a handler for each type of error with this function
+    app.on_error::<io::Error>(|err, _req, res| {
+        warn!("Error is a IO error {err}");
+        res.set_status(500).send_text("Missing data on the server, Internal Error");
+    });
+    // Errors of any other type end up here
     app.set_error_handler(Box::new(|err, _req, res| {
-        warn!("A Error Accured");
-        if err.is::<io::Error>() {
-            warn!("Error is a IO error{err}");
-            res.set_status(500).send_text("Missing data on the server, Internal Error");
-        }
+        warn!("A Error Accured {err}");
+        res.set_status(500).send_text("Internal Error");
     }));
     // This way we can handle Errors Gracefully and safely.
     app.listen("127.0.0.1:5050");
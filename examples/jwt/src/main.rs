@@ -55,6 +55,7 @@ fn main() {
     );
 
     app.get("/protected2", protected2);
+    app.get("/admin", admin_only);
 
     // Of course lets listen on port 5050
     app.listen("127.0.0.1:5050")
@@ -79,3 +80,20 @@ fn protected2(claims: MyClaim) -> feather::Outcome {
     res.send_text(format!("Hello {}", claims.name));
     next!()
 }
+
+// Claims can carry roles, mark the field with #[roles]
+#[derive(Claim, Deserialize, Serialize)]
+struct StaffClaims {
+    #[required]
+    sub: String,
+    #[roles]
+    roles: Vec<String>,
+}
+
+// Tokens without the admin role get a 403
+#[jwt_required(roles("admin"))]
+#[middleware_fn]
+fn admin_only(claims: StaffClaims) -> feather::Outcome {
+    res.send_text(format!("Welcome to the admin area {}", claims.sub));
+    next!()
+}
@@ -1,5 +1,5 @@
 use feather::internals::Router;
-use feather::{Finalizer, json, middleware, middleware_fn, next};
+use feather::{Finalizer, get, json, middleware, middleware_fn, next, register};
 
 pub fn api_router() -> Router {
     let mut router = Router::new();
@@ -10,11 +10,13 @@ pub fn api_router() -> Router {
         next!()
     }));
 
-    router.get("/data", get_data);
+    // Routes declared with an attribute on their handler
+    register!(router, get_data);
 
     router
 }
 
+#[get("/data")]
 #[middleware_fn]
 fn get_data() {
     res.finish_json(&json!({ "status": "success", "data": [1, 2, 3] }))